@@ -0,0 +1,269 @@
+use crate::disassembler::{is_two_words, Instruction};
+use crate::system::System;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Program memory words
+const FLASH_WORDS: usize = 0x4000;
+
+/// Disassembled instructions of the loaded program
+fn instructions(system: &System) -> impl Iterator<Item = &Instruction> {
+	system
+		.disassembler
+		.assembly
+		.iter()
+		.flat_map(|assembly| assembly.values())
+}
+
+/// Whether the opcode is a conditional branch or skips the next instruction on a
+/// condition: `brbs`, `brbc`, `cpse`, `sbrc`, `sbrs`, `sbic` and `sbis`
+fn is_conditional(opcode: u16) -> bool {
+	matches!(opcode, 0xF000..=0xF7FF | 0xFC00..=0xFFFF | 0x1000..=0x13FF)
+		|| opcode & 0xFD00 == 0x9900
+}
+
+/// How often a conditional branch or skip went each way, a skip is taken when it skips
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCount {
+	pub taken: u64,
+	pub not_taken: u64,
+}
+
+/// Coverage totals over the disassembled program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+	pub instructions: usize,
+	pub executed: usize,
+	/// Outcomes of conditional branches and skips, two for each of them
+	pub branches: usize,
+	pub branches_seen: usize,
+}
+
+impl Summary {
+	/// Share of the instructions executed at least once, in percent
+	pub fn instruction_percent(&self) -> f64 {
+		percent(self.executed, self.instructions)
+	}
+
+	pub fn branch_percent(&self) -> f64 {
+		percent(self.branches_seen, self.branches)
+	}
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+	match total {
+		0 => 100.0,
+		_ => part as f64 / total as f64 * 100.0,
+	}
+}
+
+impl fmt::Display for Summary {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} of {} instructions executed ({:.1}%), {} of {} branch outcomes seen ({:.1}%)",
+			self.executed,
+			self.instructions,
+			self.instruction_percent(),
+			self.branches_seen,
+			self.branches,
+			self.branch_percent()
+		)
+	}
+}
+
+/// Lines, branches and functions of one source file in an lcov report
+#[derive(Default)]
+struct SourceFile {
+	/// Executions of each line, the most any of its instructions was executed
+	lines: BTreeMap<u32, u64>,
+	/// Branch counts by line, `None` for branches that were never reached
+	branches: BTreeMap<u32, Vec<Option<BranchCount>>>,
+	/// Start line, name and calls of each function
+	functions: Vec<(u32, String, u64)>,
+}
+
+/// Counts how often each flash word was executed and which way each conditional
+/// branch or skip went
+#[derive(Debug, Clone)]
+pub struct Coverage {
+	hits: Vec<u64>,
+	branches: BTreeMap<u16, BranchCount>,
+}
+
+impl Default for Coverage {
+	fn default() -> Self {
+		Self {
+			hits: vec![0; FLASH_WORDS],
+			branches: BTreeMap::new(),
+		}
+	}
+}
+
+impl Coverage {
+	pub fn clear(&mut self) {
+		*self = Self::default();
+	}
+
+	/// Times the word at the address was executed, as an instruction or its operand
+	pub fn hits(&self, address: u16) -> u64 {
+		self.hits.get(address as usize).copied().unwrap_or(0)
+	}
+
+	/// Outcomes of the conditional branch or skip at the address, if it was executed
+	pub fn branch(&self, address: u16) -> Option<BranchCount> {
+		self.branches.get(&address).copied()
+	}
+
+	/// Counts the instruction at `pc`, which left the program counter at `next`.
+	/// Called by [`Cpu::step`](crate::cpu::Cpu::step) for each instruction executed.
+	pub(crate) fn record(&mut self, pc: u16, opcode: u16, next: u16) {
+		let words = if is_two_words(opcode) { 2 } else { 1 };
+		for address in pc..pc + words {
+			if let Some(hits) = self.hits.get_mut(address as usize) {
+				*hits += 1;
+			}
+		}
+
+		if is_conditional(opcode) {
+			let branch = self.branches.entry(pc).or_default();
+			if next == pc.wrapping_add(1) {
+				branch.not_taken += 1;
+			} else {
+				branch.taken += 1;
+			}
+		}
+	}
+
+	pub fn summary(&self, system: &System) -> Summary {
+		let mut summary = Summary::default();
+		for instruction in instructions(system) {
+			summary.instructions += 1;
+			if self.hits(instruction.address) > 0 {
+				summary.executed += 1;
+			}
+			if is_conditional(instruction.opcode) {
+				let branch = self.branch(instruction.address).unwrap_or_default();
+				summary.branches += 2;
+				summary.branches_seen +=
+					(branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+			}
+		}
+		summary
+	}
+
+	/// Writes an lcov report when the firmware has line information and an annotated
+	/// disassembly otherwise
+	pub fn write_report(&self, system: &System, output: &mut impl Write) -> io::Result<()> {
+		if system.lines.is_empty() {
+			self.write_annotated(system, output)
+		} else {
+			self.write_lcov(system, output)
+		}
+	}
+
+	/// Writes the line, branch and function coverage of each source file in the lcov
+	/// tracefile format, read by `genhtml` and most CI coverage tools
+	pub fn write_lcov(&self, system: &System, output: &mut impl Write) -> io::Result<()> {
+		let mut files: BTreeMap<usize, SourceFile> = BTreeMap::new();
+		for instruction in instructions(system) {
+			let Some(location) = system.lines.location(instruction.address) else {
+				continue;
+			};
+			let file = files.entry(location.file).or_default();
+			let hits = self.hits(instruction.address);
+			let line = file.lines.entry(location.line).or_default();
+			*line = (*line).max(hits);
+
+			if is_conditional(instruction.opcode) {
+				let branch =
+					(hits > 0).then(|| self.branch(instruction.address).unwrap_or_default());
+				file.branches.entry(location.line).or_default().push(branch);
+			}
+		}
+		for label in system.symbols.functions() {
+			if let Some(location) = system.lines.location(label.address) {
+				let calls = self.hits(label.address);
+				if let Some(file) = files.get_mut(&location.file) {
+					file.functions
+						.push((location.line, label.name.clone(), calls));
+				}
+			}
+		}
+
+		writeln!(output, "TN:")?;
+		for (index, file) in &files {
+			writeln!(
+				output,
+				"SF:{}",
+				system.lines.file(*index).unwrap_or_default()
+			)?;
+
+			for (line, name, _) in &file.functions {
+				writeln!(output, "FN:{},{}", line, name)?;
+			}
+			for (_, name, calls) in &file.functions {
+				writeln!(output, "FNDA:{},{}", calls, name)?;
+			}
+			let called = file.functions.iter().filter(|(_, _, calls)| *calls > 0);
+			writeln!(output, "FNF:{}", file.functions.len())?;
+			writeln!(output, "FNH:{}", called.count())?;
+
+			let (mut found, mut hit) = (0, 0);
+			for (line, branches) in &file.branches {
+				for (block, branch) in branches.iter().enumerate() {
+					let counts = branch.map(|branch| [branch.taken, branch.not_taken]);
+					for outcome in 0..2 {
+						let count =
+							counts.map_or("-".to_string(), |counts| counts[outcome].to_string());
+						writeln!(output, "BRDA:{},{},{},{}", line, block, outcome, count)?;
+						found += 1;
+						hit += counts.is_some_and(|counts| counts[outcome] > 0) as usize;
+					}
+				}
+			}
+			writeln!(output, "BRF:{}", found)?;
+			writeln!(output, "BRH:{}", hit)?;
+
+			for (line, hits) in &file.lines {
+				writeln!(output, "DA:{},{}", line, hits)?;
+			}
+			writeln!(output, "LF:{}", file.lines.len())?;
+			writeln!(
+				output,
+				"LH:{}",
+				file.lines.values().filter(|hits| **hits > 0).count()
+			)?;
+			writeln!(output, "end_of_record")?;
+		}
+		Ok(())
+	}
+
+	/// Writes the disassembly with how often each instruction was executed, `#####` for
+	/// those never executed, and the outcomes of conditional branches and skips
+	pub fn write_annotated(&self, system: &System, output: &mut impl Write) -> io::Result<()> {
+		for instruction in instructions(system) {
+			if let Some(label) = &instruction.label {
+				writeln!(output, "\n{}:", label)?;
+			}
+			let hits = match self.hits(instruction.address) {
+				0 => "#####".to_string(),
+				hits => hits.to_string(),
+			};
+			let mut line = format!(
+				"{:>10}  {:04X}  {:<7} {}",
+				hits, instruction.address, instruction.instruction, instruction.operands
+			);
+			if is_conditional(instruction.opcode) {
+				let branch = self.branch(instruction.address).unwrap_or_default();
+				line = format!(
+					"{:<60} taken {}, not taken {}",
+					line, branch.taken, branch.not_taken
+				);
+			}
+			writeln!(output, "{}", line.trim_end())?;
+		}
+		writeln!(output, "\n{}", self.summary(system))
+	}
+}
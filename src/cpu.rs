@@ -1,6 +1,25 @@
-use crate::memory::{Memory, Sram, RAMEND};
+use crate::coverage::Coverage;
+use crate::debugger::{Breakpoints, History, WatchHit, Watchpoints};
+use crate::disassembler::{is_two_words, Disassembler};
+use crate::memory::{Access, AccessKind, Memory, Sram, RAMEND};
+use crate::peripherals::sleep::SleepMode;
+use crate::peripherals::Peripherals;
+use crate::profiler::Profiler;
 use crate::system::System;
-use crate::utils::{bits_u16, bits_u8, high_byte};
+use crate::trace::{Event, Tracer};
+use crate::utils::{bit, bits_u16, bits_u8, high_byte, low_byte, to_u16};
+
+const SPL: u16 = 0x5D;
+const SPH: u16 = 0x5E;
+const SREG: u16 = 0x5F;
+
+/// Low registers of the X, Y and Z pointers
+const X: usize = 26;
+const Y: usize = 28;
+const Z: usize = 30;
+
+/// `rjmp .-2`, a jump to itself
+const IDLE_LOOP: u16 = 0xCFFF;
 
 #[derive(Default, Debug, Clone)]
 #[allow(non_snake_case)]
@@ -52,30 +71,264 @@ impl Sreg {
 pub struct Cpu {
 	pub system: System,
 	pub sram: Sram,
+	pub peripherals: Peripherals,
 	pub sp: u16,
 	pub status: Sreg,
 	pub pc: u16,
 	pub cycles: usize,
 	pub opcode: u16,
+	/// Skip ahead to the next peripheral event while sleeping or spinning in an idle loop
+	pub fast_forward: bool,
+	/// Set by the `break` instruction, cleared by whoever stops on it
+	pub break_hit: bool,
+	pub breakpoints: Breakpoints,
+	/// Set when a step ends at a breakpoint that stops, cleared by whoever stops on it
+	pub breakpoint_hit: bool,
+	/// Whether a breakpoint at the entry point was checked or a step made since reset
+	pub entry_checked: bool,
+	pub watchpoints: Watchpoints,
+	/// Set when a step makes an access a watchpoint stops on, cleared by whoever stops on it
+	pub watch_hit: Option<WatchHit>,
+	/// Collect the data space and EEPROM accesses of each step, as done for watchpoints
+	pub record_accesses: bool,
+	/// Data space accesses of the last step, while recording
+	pub accesses: Vec<Access>,
+	/// Records each step so that execution can go back, `None` to run without
+	pub history: Option<History>,
+	/// Logs the instructions executed, `None` to run without
+	pub trace: Option<Tracer>,
+	/// Attributes cycles to functions, `None` to run without
+	pub profiler: Option<Profiler>,
+	/// Counts the instructions executed and branches taken, `None` to run without
+	pub coverage: Option<Coverage>,
 }
 
 impl Cpu {
 	pub fn init() -> Self {
-		Self {
+		let mut cpu = Self {
 			system: System::default(),
 			sram: Sram::default(),
+			peripherals: Peripherals::default(),
 			sp: RAMEND,
 			status: Sreg::default(),
 			pc: 0x0000,
 			cycles: 0,
 			opcode: 0x0000,
-		}
+			fast_forward: false,
+			break_hit: false,
+			breakpoints: Breakpoints::default(),
+			breakpoint_hit: false,
+			entry_checked: false,
+			watchpoints: Watchpoints::default(),
+			watch_hit: None,
+			record_accesses: false,
+			accesses: Vec::new(),
+			history: None,
+			trace: None,
+			profiler: None,
+			coverage: None,
+		};
+		cpu.peripherals.clock.configure(&cpu.system.fuses);
+		cpu.peripherals.watchdog.configure(&cpu.system.fuses);
+		cpu.peripherals.reset(&mut cpu.sram);
+		cpu
 	}
 
 	pub fn reset(&mut self) {
 		self.sp = RAMEND;
-		self.pc = 0x0000;
+		self.pc = self.system.entry_point;
 		self.cycles = 0;
+		self.break_hit = false;
+		self.breakpoint_hit = false;
+		self.entry_checked = false;
+		self.watch_hit = None;
+		if let Some(history) = &mut self.history {
+			history.clear();
+		}
+		self.peripherals.clock.configure(&self.system.fuses);
+		self.peripherals.watchdog.configure(&self.system.fuses);
+		self.peripherals.reset(&mut self.sram);
+	}
+
+	/// Restarts the program after a watchdog time-out, the cycle count keeps running
+	fn watchdog_reset(&mut self) {
+		self.sp = RAMEND;
+		self.pc = 0x0000;
+		self.status = Sreg::default();
+		self.peripherals.watchdog_reset(&mut self.sram);
+	}
+
+	fn is_recording(&self) -> bool {
+		self.record_accesses || !self.watchpoints.is_empty() || self.trace.is_some()
+	}
+
+	fn record_read(&mut self, address: u16, value: u8) {
+		if self.is_recording() {
+			self.accesses.push(Access {
+				address,
+				kind: AccessKind::Read,
+				value,
+				previous: value,
+			});
+		}
+	}
+
+	fn record_write(&mut self, address: u16, value: u8) {
+		if self.is_recording() {
+			let previous = match address {
+				0..=RAMEND => self.peek_data(address),
+				_ => value,
+			};
+			self.accesses.push(Access {
+				address,
+				kind: AccessKind::Write,
+				value,
+				previous,
+			});
+		}
+	}
+
+	/// Reads a byte from data space, routing I/O registers to their peripherals
+	pub fn read_data(&mut self, address: u16) -> u8 {
+		let value = match address {
+			SPL => low_byte(self.sp) as u8,
+			SPH => high_byte(self.sp) as u8,
+			SREG => self.status.byte(),
+			_ => self.peripherals.read(&mut self.sram, address),
+		};
+		self.record_read(address, value);
+		value
+	}
+
+	/// Writes a byte to data space, routing I/O registers to their peripherals
+	pub fn write_data(&mut self, address: u16, data: u8) {
+		self.record_write(address, data);
+		match address {
+			SPL => self.sp = to_u16(high_byte(self.sp) as u8, data),
+			SPH => self.sp = to_u16(data, low_byte(self.sp) as u8),
+			SREG => self.status.set_byte(data),
+			_ if self.peripherals.eeprom.handles(address) => {
+				self.peripherals.write_eeprom(
+					&mut self.sram,
+					&mut self.system.eeprom_memory,
+					address,
+					data,
+				);
+				self.cycles += self.peripherals.eeprom.take_stall_cycles();
+			}
+			_ => self.peripherals.write(&mut self.sram, address, data),
+		}
+	}
+
+	/// Reads the word at the program counter and moves past it, wrapping at the end of
+	/// program memory
+	fn fetch(&mut self) -> u16 {
+		let word = self.system.program_memory.read(self.pc);
+		self.pc = self.pc.wrapping_add(1) & 0x3FFF;
+		word
+	}
+
+	fn pointer(&self, low: usize) -> u16 {
+		to_u16(self.sram.registers[low + 1], self.sram.registers[low])
+	}
+
+	fn set_pointer(&mut self, low: usize, value: u16) {
+		self.set_register(low as u8, low_byte(value) as u8);
+		self.set_register(low as u8 + 1, high_byte(value) as u8);
+	}
+
+	/// Address of `ld` and `st` through a pointer, post-incrementing or pre-decrementing
+	/// it as selected by the low two bits of the opcode
+	fn indirect_address(&mut self, pointer: usize) -> u16 {
+		let address = self.pointer(pointer);
+		match self.opcode & 0x3 {
+			1 => {
+				self.set_pointer(pointer, address.wrapping_add(1));
+				address
+			}
+			2 => {
+				let address = address.wrapping_sub(1);
+				self.set_pointer(pointer, address);
+				address
+			}
+			_ => address,
+		}
+	}
+
+	/// Address of `ldd` and `std`, Y or Z plus the displacement in `10q0 qqsd dddd yqqq`
+	fn displaced_address(&self) -> u16 {
+		let q = (self.opcode & 0x7) | ((self.opcode >> 7) & 0x18) | ((self.opcode >> 8) & 0x20);
+		let pointer = if self.opcode & 0x8 != 0 { Y } else { Z };
+		self.pointer(pointer).wrapping_add(q)
+	}
+
+	/// Reads a byte from data space without side effects on the peripherals, for debuggers
+	pub fn peek_data(&mut self, address: u16) -> u8 {
+		match address {
+			SPL => low_byte(self.sp) as u8,
+			SPH => high_byte(self.sp) as u8,
+			SREG => self.status.byte(),
+			_ => self.sram.read(address) as u8,
+		}
+	}
+
+	/// Writes a byte to data space without side effects on the peripherals, for debuggers
+	pub fn poke_data(&mut self, address: u16, data: u8) {
+		match address {
+			SPL => self.sp = to_u16(high_byte(self.sp) as u8, data),
+			SPH => self.sp = to_u16(data, low_byte(self.sp) as u8),
+			SREG => self.status.set_byte(data),
+			_ => self.sram.write(address, data as u16),
+		}
+	}
+
+	/// Writes a register, recorded like any other data space write
+	fn set_register(&mut self, register: u8, value: u8) {
+		self.record_write(register as u16, value);
+		self.sram.registers[register as usize] = value;
+	}
+
+	fn push_byte(&mut self, data: u8) {
+		self.record_write(self.sp, data);
+		self.sram.write(self.sp, data as u16);
+		self.sp = self.sp.wrapping_sub(1);
+	}
+
+	fn pop_byte(&mut self) -> u8 {
+		self.sp = self.sp.wrapping_add(1);
+		let value = self.sram.read(self.sp) as u8;
+		self.record_read(self.sp, value);
+		value
+	}
+
+	fn push_pc(&mut self) {
+		self.push_byte(low_byte(self.pc) as u8);
+		self.push_byte(high_byte(self.pc) as u8);
+	}
+
+	fn pop_pc(&mut self) {
+		let high = self.pop_byte();
+		let low = self.pop_byte();
+		self.pc = to_u16(high, low);
+	}
+
+	/// Jumps to the highest priority pending interrupt, returns false if none was taken
+	fn service_interrupt(&mut self) -> bool {
+		if !self.status.I {
+			return false;
+		}
+
+		let interrupt = match self.peripherals.pending_interrupt(&mut self.sram) {
+			Some(interrupt) => interrupt,
+			None => return false,
+		};
+
+		self.peripherals.acknowledge(&mut self.sram, interrupt);
+		self.push_pc();
+		self.status.I = false;
+		self.pc = interrupt.vector_address();
+		self.cycles += 4;
+		true
 	}
 
 	// Arithmetic and Logic Instruction
@@ -112,7 +365,7 @@ impl Cpu {
 		self.status.C =
 			(rd_bits.7 & rr_bits.7 | rr_bits.7 & !r_bits.7 | !r_bits.7 & rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -150,7 +403,7 @@ impl Cpu {
 		self.status.C =
 			(rd_bits.7 & rr_bits.7 | rr_bits.7 & !r_bits.7 | !r_bits.7 & rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -181,8 +434,8 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.C = (!r_bits.15 & rdh_bits.7) == 1;
 
-		self.sram.registers[d as usize] = result_low;
-		self.sram.registers[(d + 1) as usize] = result_high;
+		self.set_register(d, result_low);
+		self.set_register(d + 1, result_high);
 
 		self.cycles += 2;
 	}
@@ -220,7 +473,7 @@ impl Cpu {
 		self.status.C =
 			(!rd_bits.7 & rr_bits.7 | rr_bits.7 & r_bits.7 | r_bits.7 & !rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -245,7 +498,7 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.C = (!rd_bits.7 & k_bits.7 | k_bits.7 & r_bits.7 | r_bits.7 & !rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -284,7 +537,7 @@ impl Cpu {
 		self.status.C =
 			(!rd_bits.7 & rr_bits.7 | rr_bits.7 & r_bits.7 | r_bits.7 & !rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -311,7 +564,7 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.C = (!rd_bits.7 & k_bits.7 | k_bits.7 & r_bits.7 | r_bits.7 & !rd_bits.7) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -343,8 +596,8 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.C = r_bits.15 & !rdh_bits.7 == 1;
 
-		self.sram.registers[d as usize] = result_low;
-		self.sram.registers[(d + 1) as usize] = result_high;
+		self.set_register(d, result_low);
+		self.set_register(d + 1, result_high);
 
 		self.cycles += 2;
 	}
@@ -374,7 +627,7 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -395,7 +648,7 @@ impl Cpu {
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 		self.status.Z = result == 0;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -425,7 +678,7 @@ impl Cpu {
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 		self.status.Z = result == 0;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -446,7 +699,7 @@ impl Cpu {
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 		self.status.Z = result == 0;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -476,7 +729,7 @@ impl Cpu {
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 		self.status.Z = result == 0;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -499,7 +752,7 @@ impl Cpu {
 		self.status.Z = result == 0;
 		self.status.C = true;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -518,11 +771,12 @@ impl Cpu {
 		let rd_bits = bits_u8(rd);
 
 		self.status.H = (r_bits.3 | !rd_bits.3) == 1;
-		self.status.V = (r_bits.7
-			& !r_bits.6 & !r_bits.5
-			& !r_bits.4 & !r_bits.3
-			& !r_bits.2 & !r_bits.1
-			& !r_bits.0) == 1;
+		self.status.V =
+			(r_bits.7
+				& !r_bits.6 & !r_bits.5
+				& !r_bits.4 & !r_bits.3
+				& !r_bits.2 & !r_bits.1
+				& !r_bits.0) == 1;
 		self.status.N = r_bits.7 == 1;
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
 		self.status.Z = result == 0;
@@ -530,7 +784,7 @@ impl Cpu {
 			(r_bits.7 | r_bits.6 | r_bits.5 | r_bits.4 | r_bits.3 | r_bits.2 | r_bits.1 | r_bits.0)
 				== 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -558,20 +812,22 @@ impl Cpu {
 		let result = self.sram.registers[rd as usize].wrapping_add(1);
 		let r_bits = bits_u8(result);
 
-		self.status.V = (r_bits.7
-			& !r_bits.6 & !r_bits.5
-			& !r_bits.4 & !r_bits.3
-			& !r_bits.2 & !r_bits.1
-			& !r_bits.0) == 1;
+		self.status.V =
+			(r_bits.7
+				& !r_bits.6 & !r_bits.5
+				& !r_bits.4 & !r_bits.3
+				& !r_bits.2 & !r_bits.1
+				& !r_bits.0) == 1;
 		self.status.N = r_bits.7 == 1;
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
-		self.status.Z = (!r_bits.7
-			& !r_bits.6 & !r_bits.5
-			& !r_bits.4 & !r_bits.3
-			& !r_bits.2 & !r_bits.1
-			& !r_bits.0) == 1;
+		self.status.Z =
+			(!r_bits.7
+				& !r_bits.6 & !r_bits.5
+				& !r_bits.4 & !r_bits.3
+				& !r_bits.2 & !r_bits.1
+				& !r_bits.0) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -588,20 +844,22 @@ impl Cpu {
 		let result = self.sram.registers[rd as usize].wrapping_sub(1);
 		let r_bits = bits_u8(result);
 
-		self.status.V = (!r_bits.7
-			& r_bits.6 & r_bits.5
-			& r_bits.4 & r_bits.3
-			& r_bits.2 & r_bits.1
-			& r_bits.0) == 1;
+		self.status.V =
+			(!r_bits.7
+				& r_bits.6 & r_bits.5
+				& r_bits.4 & r_bits.3
+				& r_bits.2 & r_bits.1
+				& r_bits.0) == 1;
 		self.status.N = r_bits.7 == 1;
 		self.status.S = ((self.status.N as u8) ^ (self.status.V as u8)) == 1;
-		self.status.Z = (!r_bits.7
-			& !r_bits.6 & !r_bits.5
-			& !r_bits.4 & !r_bits.3
-			& !r_bits.2 & !r_bits.1
-			& !r_bits.0) == 1;
+		self.status.Z =
+			(!r_bits.7
+				& !r_bits.6 & !r_bits.5
+				& !r_bits.4 & !r_bits.3
+				& !r_bits.2 & !r_bits.1
+				& !r_bits.0) == 1;
 
-		self.sram.registers[rd as usize] = result;
+		self.set_register(rd, result);
 
 		self.cycles += 1;
 	}
@@ -658,8 +916,8 @@ impl Cpu {
 		self.status.C = r_bits.15 == 1;
 		self.status.Z = result == 1;
 
-		self.sram.registers[0] = result_low;
-		self.sram.registers[1] = result_high;
+		self.set_register(0, result_low);
+		self.set_register(1, result_high);
 
 		self.cycles += 1;
 	}
@@ -681,8 +939,8 @@ impl Cpu {
 		self.status.C = r_bits.15 == 1;
 		self.status.Z = result == 1;
 
-		self.sram.registers[0] = result_low;
-		self.sram.registers[1] = result_high;
+		self.set_register(0, result_low);
+		self.set_register(1, result_high);
 
 		self.cycles += 1;
 	}
@@ -705,8 +963,8 @@ impl Cpu {
 		self.status.C = r_bits.15 == 1;
 		self.status.Z = result == 1;
 
-		self.sram.registers[0] = result_low;
-		self.sram.registers[1] = result_high;
+		self.set_register(0, result_low);
+		self.set_register(1, result_high);
 
 		self.cycles += 1;
 	}
@@ -719,37 +977,191 @@ impl Cpu {
 
 	// Branch Instructions
 
-	fn rjmp(&mut self) {}
+	fn rjmp(&mut self) {
+		// 1100 kkkk kkkk kkkk
+
+		let k = ((self.opcode << 4) as i16) >> 4;
+		self.pc = self.pc.wrapping_add(k as u16) & 0x3FFF;
+
+		self.cycles += 2;
+	}
+
+	fn ijmp(&mut self) {
+		// 1001 0100 0000 1001
+
+		self.pc = self.pointer(Z) & 0x3FFF;
+
+		self.cycles += 2;
+	}
+
+	fn jmp(&mut self) {
+		// 1001 010k kkkk 110k kkkk kkkk kkkk kkkk
+
+		let k = self.fetch();
+		self.pc = k & 0x3FFF;
+
+		self.cycles += 3;
+	}
 
-	fn ijmp(&mut self) {}
+	fn rcall(&mut self) {
+		// 1101 kkkk kkkk kkkk
 
-	fn jmp(&mut self) {}
+		let k = ((self.opcode << 4) as i16) >> 4;
+		self.push_pc();
+		self.pc = self.pc.wrapping_add(k as u16) & 0x3FFF;
 
-	fn rcall(&mut self) {}
+		self.cycles += 3;
+	}
 
-	fn icall(&mut self) {}
+	fn icall(&mut self) {
+		// 1001 0101 0000 1001
 
-	fn call(&mut self) {}
+		self.push_pc();
+		self.pc = self.pointer(Z) & 0x3FFF;
 
-	fn ret(&mut self) {}
+		self.cycles += 3;
+	}
 
-	fn reti(&mut self) {}
+	fn call(&mut self) {
+		// 1001 010k kkkk 111k kkkk kkkk kkkk kkkk
 
-	fn cpse(&mut self) {}
+		let k = self.fetch();
+		self.push_pc();
+		self.pc = k & 0x3FFF;
 
-	fn cp(&mut self) {}
+		self.cycles += 4;
+	}
 
-	fn cpc(&mut self) {}
+	fn ret(&mut self) {
+		self.pop_pc();
+		self.cycles += 4;
+	}
 
-	fn cpi(&mut self) {}
+	fn reti(&mut self) {
+		self.pop_pc();
+		self.status.I = true;
+		self.cycles += 4;
+	}
 
-	fn sbrc(&mut self) {}
+	/// Registers of a two-register instruction, `rd` and `rr` from `oooo oord dddd rrrr`
+	fn two_registers(&self) -> (u8, u8) {
+		let rd = ((self.opcode >> 4) & 0x1F) as u8;
+		let rr = ((self.opcode & 0xF) | ((self.opcode >> 5) & 0x10)) as u8;
+		(rd, rr)
+	}
 
-	fn sbrs(&mut self) {}
+	/// Sets the flags of `rd - rr - carry` as cp, cpc and cpi do, without storing it
+	fn compare(&mut self, rd: u8, rr: u8, carry: bool) {
+		let result = rd.wrapping_sub(rr).wrapping_sub(carry as u8);
+		// bits 3 and 7 are the borrows out of the low nibble and the byte
+		let borrows = !rd & rr | rr & result | result & !rd;
+		let overflow = rd & !rr & !result | !rd & rr & result;
+
+		self.status.H = borrows & 0x08 != 0;
+		self.status.V = overflow & 0x80 != 0;
+		self.status.N = result & 0x80 != 0;
+		self.status.S = self.status.N ^ self.status.V;
+		// with a carry in, a zero result only keeps Z from the previous byte
+		self.status.Z = result == 0 && (!carry || self.status.Z);
+		self.status.C = borrows & 0x80 != 0;
+	}
+
+	/// Skips the next instruction, one or two words long, when `condition` holds
+	fn skip_if(&mut self, condition: bool) {
+		if condition {
+			let next = self.system.program_memory.read(self.pc);
+			let words = if is_two_words(next) { 2 } else { 1 };
+			self.pc = self.pc.wrapping_add(words) & 0x3FFF;
+			self.cycles += 1 + words as usize;
+		} else {
+			self.cycles += 1;
+		}
+	}
+
+	/// Jumps by the 7-bit offset of `oooo ookk kkkk ksss` when `condition` holds
+	fn branch_if(&mut self, condition: bool) {
+		if condition {
+			let k = ((self.opcode << 6) as i16) >> 9;
+			self.pc = self.pc.wrapping_add(k as u16) & 0x3FFF;
+			self.cycles += 2;
+		} else {
+			self.cycles += 1;
+		}
+	}
 
-	fn sbic(&mut self) {}
+	fn cpse(&mut self) {
+		// 0001 00rd dddd rrrr
 
-	fn sbis(&mut self) {}
+		let (rd, rr) = self.two_registers();
+		let equal = self.sram.registers[rd as usize] == self.sram.registers[rr as usize];
+		self.skip_if(equal);
+	}
+
+	fn cp(&mut self) {
+		// 0001 01rd dddd rrrr
+
+		let (rd, rr) = self.two_registers();
+		let (rd, rr) = (
+			self.sram.registers[rd as usize],
+			self.sram.registers[rr as usize],
+		);
+		self.compare(rd, rr, false);
+
+		self.cycles += 1;
+	}
+
+	fn cpc(&mut self) {
+		// 0000 01rd dddd rrrr
+
+		let (rd, rr) = self.two_registers();
+		let (rd, rr) = (
+			self.sram.registers[rd as usize],
+			self.sram.registers[rr as usize],
+		);
+		self.compare(rd, rr, self.status.C);
+
+		self.cycles += 1;
+	}
+
+	fn cpi(&mut self) {
+		// 0011 KKKK dddd KKKK
+
+		let rd = (((self.opcode & 0xF0) >> 4) + 16) as usize;
+		let k = ((((self.opcode >> 8) & 0xF) << 4) | (self.opcode & 0xF)) as u8;
+		self.compare(self.sram.registers[rd], k, false);
+
+		self.cycles += 1;
+	}
+
+	fn sbrc(&mut self) {
+		// 1111 110r rrrr 0bbb
+
+		let rr = ((self.opcode >> 4) & 0x1F) as usize;
+		let set = bit(self.sram.registers[rr], (self.opcode & 0x7) as u8) != 0;
+		self.skip_if(!set);
+	}
+
+	fn sbrs(&mut self) {
+		// 1111 111r rrrr 0bbb
+
+		let rr = ((self.opcode >> 4) & 0x1F) as usize;
+		let set = bit(self.sram.registers[rr], (self.opcode & 0x7) as u8) != 0;
+		self.skip_if(set);
+	}
+
+	fn sbic(&mut self) {
+		// 1001 1001 AAAA Abbb
+
+		let value = self.read_data(((self.opcode >> 3) & 0x1F) + 0x20);
+		self.skip_if(bit(value, (self.opcode & 0x7) as u8) == 0);
+	}
+
+	fn sbis(&mut self) {
+		// 1001 1011 AAAA Abbb
+
+		let value = self.read_data(((self.opcode >> 3) & 0x1F) + 0x20);
+		self.skip_if(bit(value, (self.opcode & 0x7) as u8) != 0);
+	}
 
 	#[allow(dead_code)]
 	fn brbs(&mut self) {
@@ -775,13 +1187,21 @@ impl Cpu {
 		// brbc 7, <label> -> brid <address>
 	}
 
-	fn breq(&mut self) {}
+	fn breq(&mut self) {
+		self.branch_if(self.status.Z);
+	}
 
-	fn brne(&mut self) {}
+	fn brne(&mut self) {
+		self.branch_if(!self.status.Z);
+	}
 
-	fn brcs(&mut self) {}
+	fn brcs(&mut self) {
+		self.branch_if(self.status.C);
+	}
 
-	fn brcc(&mut self) {}
+	fn brcc(&mut self) {
+		self.branch_if(!self.status.C);
+	}
 
 	#[allow(dead_code)]
 	fn brsh(&mut self) {
@@ -793,35 +1213,85 @@ impl Cpu {
 		// brlo <label> -> brbs 0, <label> -> brcs <address>
 	}
 
-	fn brmi(&mut self) {}
+	fn brmi(&mut self) {
+		self.branch_if(self.status.N);
+	}
 
-	fn brpl(&mut self) {}
+	fn brpl(&mut self) {
+		self.branch_if(!self.status.N);
+	}
 
-	fn brge(&mut self) {}
+	fn brge(&mut self) {
+		self.branch_if(!self.status.S);
+	}
 
-	fn brlt(&mut self) {}
+	fn brlt(&mut self) {
+		self.branch_if(self.status.S);
+	}
 
-	fn brhs(&mut self) {}
+	fn brhs(&mut self) {
+		self.branch_if(self.status.H);
+	}
 
-	fn brhc(&mut self) {}
+	fn brhc(&mut self) {
+		self.branch_if(!self.status.H);
+	}
 
-	fn brts(&mut self) {}
+	fn brts(&mut self) {
+		self.branch_if(self.status.T);
+	}
 
-	fn brtc(&mut self) {}
+	fn brtc(&mut self) {
+		self.branch_if(!self.status.T);
+	}
 
-	fn brvs(&mut self) {}
+	fn brvs(&mut self) {
+		self.branch_if(self.status.V);
+	}
 
-	fn brvc(&mut self) {}
+	fn brvc(&mut self) {
+		self.branch_if(!self.status.V);
+	}
 
-	fn brie(&mut self) {}
+	fn brie(&mut self) {
+		self.branch_if(self.status.I);
+	}
 
-	fn brid(&mut self) {}
+	fn brid(&mut self) {
+		self.branch_if(!self.status.I);
+	}
 
 	// Bit and Bit-Test Instructions
 
-	fn sbi(&mut self) {}
+	fn sbi(&mut self) {
+		// 1001 1010 AAAA Abbb
+
+		let a = ((self.opcode >> 3) & 0x1F) + 0x20;
+		let mask = 1 << (self.opcode & 0x7);
+		let value = if Peripherals::writes_single_bits(a) {
+			mask
+		} else {
+			self.read_data(a) | mask
+		};
+		self.write_data(a, value);
+
+		self.cycles += 2;
+	}
+
+	fn cbi(&mut self) {
+		// 1001 1000 AAAA Abbb
 
-	fn cbi(&mut self) {}
+		let a = ((self.opcode >> 3) & 0x1F) + 0x20;
+		let mask = 1 << (self.opcode & 0x7);
+		let value = if Peripherals::writes_single_bits(a) {
+			0x00
+		} else {
+			self.read_data(a) & !mask
+		};
+		self.write_data(a, value);
+
+		self.cycles += 2;
+	}
 
 	#[allow(dead_code)]
 	fn lsl(&mut self) {
@@ -951,9 +1421,22 @@ impl Cpu {
 
 	// Data Transfer Instructions
 
-	fn mov(&mut self) {}
+	fn mov(&mut self) {
+		// 0010 11rd dddd rrrr
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let rr = (self.opcode & 0xF) | ((self.opcode & 0x200) >> 5);
+		self.set_register(rd, self.sram.registers[rr as usize]);
+		self.cycles += 1;
+	}
 
-	fn movw(&mut self) {}
+	fn movw(&mut self) {
+		// 0000 0001 dddd rrrr
+		let d = (((self.opcode & 0xF0) >> 4) * 2) as u8;
+		let r = ((self.opcode & 0xF) * 2) as usize;
+		self.set_register(d, self.sram.registers[r]);
+		self.set_register(d + 1, self.sram.registers[r + 1]);
+		self.cycles += 1;
+	}
 
 	fn ldi(&mut self) {
 		// 1110 kkkk dddd kkkk
@@ -962,47 +1445,166 @@ impl Cpu {
 		rd += 16;
 
 		let k = ((((self.opcode >> 8) & 0xF) << 4) | (self.opcode & 0xF)) as u8;
-		self.sram.registers[rd as usize] = k;
+		self.set_register(rd, k);
 		self.cycles += 1;
 	}
 
-	fn ld_x(&mut self) {}
+	fn ld_x(&mut self) {
+		// 1001 000d dddd 11oo
 
-	fn ld_y(&mut self) {}
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let address = self.indirect_address(X);
+		let value = self.read_data(address);
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
-	fn ld_z(&mut self) {}
+	fn ld_y(&mut self) {
+		// 1001 000d dddd 10oo
 
-	fn ldd(&mut self) {}
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let address = self.indirect_address(Y);
+		let value = self.read_data(address);
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
-	fn lds(&mut self) {}
+	fn ld_z(&mut self) {
+		// 1001 000d dddd 00oo
 
-	fn st_x(&mut self) {}
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let address = self.indirect_address(Z);
+		let value = self.read_data(address);
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
-	fn st_y(&mut self) {}
+	/// Also `ld` through Y and Z without a displacement
+	fn ldd(&mut self) {
+		// 10q0 qq0d dddd yqqq
 
-	fn st_z(&mut self) {}
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let address = self.displaced_address();
+		let value = self.read_data(address);
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
-	fn std(&mut self) {}
+	fn lds(&mut self) {
+		// 1001 000d dddd 0000 kkkk kkkk kkkk kkkk
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let k = self.fetch();
+		let value = self.read_data(k);
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
-	fn sts(&mut self) {}
+	fn st_x(&mut self) {
+		// 1001 001r rrrr 11oo
 
-	fn lpm(&mut self) {}
+		let rr = (self.opcode & 0x1F0) >> 4;
+		let data = self.sram.registers[rr as usize];
+		let address = self.indirect_address(X);
+		self.write_data(address, data);
+		self.cycles += 2;
+	}
+
+	fn st_y(&mut self) {
+		// 1001 001r rrrr 10oo
+
+		let rr = (self.opcode & 0x1F0) >> 4;
+		let data = self.sram.registers[rr as usize];
+		let address = self.indirect_address(Y);
+		self.write_data(address, data);
+		self.cycles += 2;
+	}
+
+	fn st_z(&mut self) {
+		// 1001 001r rrrr 00oo
+
+		let rr = (self.opcode & 0x1F0) >> 4;
+		let data = self.sram.registers[rr as usize];
+		let address = self.indirect_address(Z);
+		self.write_data(address, data);
+		self.cycles += 2;
+	}
+
+	/// Also `st` through Y and Z without a displacement
+	fn std(&mut self) {
+		// 10q0 qq1r rrrr yqqq
+
+		let rr = (self.opcode & 0x1F0) >> 4;
+		let address = self.displaced_address();
+		self.write_data(address, self.sram.registers[rr as usize]);
+		self.cycles += 2;
+	}
+
+	fn sts(&mut self) {
+		// 1001 001d dddd 0000 kkkk kkkk kkkk kkkk
+		let rr = (self.opcode & 0x1F0) >> 4;
+		let k = self.fetch();
+		self.write_data(k, self.sram.registers[rr as usize]);
+		self.cycles += 2;
+	}
+
+	fn lpm(&mut self) {
+		// 1001 0101 1100 1000, 1001 000d dddd 010i
+
+		let (rd, post_increment) = if self.opcode == 0x95C8 {
+			(0, false)
+		} else {
+			((self.opcode & 0x1F0) >> 4, self.opcode & 0x1 != 0)
+		};
+
+		// Z is a byte address into program memory
+		let z = self.pointer(Z);
+		let word = self.system.program_memory.read((z >> 1) & 0x3FFF);
+		let byte = if z & 1 == 0 {
+			low_byte(word)
+		} else {
+			high_byte(word)
+		};
+		self.set_register(rd as u8, byte as u8);
+		if post_increment {
+			self.set_pointer(Z, z.wrapping_add(1));
+		}
+
+		self.cycles += 3;
+	}
 
 	fn spm(&mut self) {}
 
-	fn in_(&mut self) {}
+	fn in_(&mut self) {
+		// 1011 0AAd dddd AAAA
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let a = (self.opcode & 0xF) | ((self.opcode & 0x600) >> 5);
+		let value = self.read_data(a + 0x20);
+		self.set_register(rd, value);
+		self.cycles += 1;
+	}
 
 	fn out(&mut self) {
 		// 1011 1AAr rrrr AAAA
 		let rr = (self.opcode & 0x1F0) >> 4;
 		let a = (self.opcode & 0xF) | ((self.opcode & 0x600) >> 5);
-		self.sram.io_registers[a as usize] = self.sram.registers[rr as usize];
+		self.write_data(a + 0x20, self.sram.registers[rr as usize]);
 		self.cycles += 1;
 	}
 
-	fn push(&mut self) {}
+	fn push(&mut self) {
+		// 1001 001r rrrr 1111
+		let rr = (self.opcode & 0x1F0) >> 4;
+		self.push_byte(self.sram.registers[rr as usize]);
+		self.cycles += 2;
+	}
 
-	fn pop(&mut self) {}
+	fn pop(&mut self) {
+		// 1001 000d dddd 1111
+		let rd = ((self.opcode & 0x1F0) >> 4) as u8;
+		let value = self.pop_byte();
+		self.set_register(rd, value);
+		self.cycles += 2;
+	}
 
 	// MCU Control Instructions
 
@@ -1011,23 +1613,182 @@ impl Cpu {
 	}
 
 	fn sleep(&mut self) {
+		if let Some(mode) = SleepMode::from_smcr(&mut self.sram) {
+			self.peripherals
+				.sleep(&mut self.sram, &mut self.system.eeprom_memory, mode);
+		}
 		self.cycles += 1;
 	}
 
 	fn wdr(&mut self) {
+		self.peripherals.restart_watchdog(&mut self.sram);
 		self.cycles += 1;
 	}
 
-	fn break_(&mut self) {}
+	/// Without an on-chip debugger attached this is where a host would take over, the
+	/// emulator flags it and carries on with the next instruction
+	fn break_(&mut self) {
+		self.break_hit = true;
+		self.cycles += 1;
+	}
+
+	pub fn is_sleeping(&self) -> bool {
+		self.peripherals.sleep_mode.is_some()
+	}
+
+	/// Stays asleep for a cycle unless an interrupt able to wake the current mode is
+	/// pending, in which case the CPU is halted 4 cycles before jumping to its vector
+	fn wait_for_wake_up(&mut self) {
+		let waking = self.status.I && self.peripherals.pending_interrupt(&mut self.sram).is_some();
+
+		if waking {
+			self.peripherals
+				.wake(&mut self.sram, &mut self.system.eeprom_memory);
+			self.cycles += 4;
+			self.service_interrupt();
+		} else {
+			self.cycles += self.idle_cycles(1);
+		}
+	}
+
+	/// Cycles to let pass while idle, repeating `period` up to the next peripheral event
+	/// when fast forwarding
+	fn idle_cycles(&mut self, period: usize) -> usize {
+		match self.peripherals.scheduler.next_event() {
+			Some(cycles) if self.fast_forward => cycles.div_ceil(period).max(1) * period,
+			_ => period,
+		}
+	}
 
 	fn reserved(&mut self) {
 		println!("Reserved opcode: 0x{:04X}", self.opcode);
 		self.cycles += 1;
 	}
 
+	/// Undoes the last step, false if there is no history to go back in
+	pub fn step_back(&mut self) -> bool {
+		self.with_history(|history, cpu| history.step_back(cpu))
+			.unwrap_or(false)
+	}
+
+	/// Goes back to the last breakpoint passed, true if there was one in the history
+	pub fn reverse_continue(&mut self) -> bool {
+		self.with_history(|history, cpu| history.reverse_continue(cpu))
+			.unwrap_or(false)
+	}
+
+	/// Goes back or forward in the history to a cycle count
+	pub fn seek(&mut self, cycles: usize) {
+		self.with_history(|history, cpu| history.seek(cpu, cycles));
+	}
+
+	fn with_history<T>(&mut self, f: impl FnOnce(&mut History, &mut Cpu) -> T) -> Option<T> {
+		let mut history = self.history.take()?;
+		let result = f(&mut history, self);
+		self.history = Some(history);
+		Some(result)
+	}
+
 	pub fn step(&mut self) {
-		self.opcode = self.system.program_memory.read(self.pc);
-		self.pc += 1;
+		if self.history.is_some() {
+			// the history executes the step itself, recording what it changes
+			self.with_history(|history, cpu| history.step(cpu));
+			return;
+		}
+
+		let start_cycles = self.cycles;
+		let (pc, opcode) = (self.pc, self.system.program_memory.read(self.pc));
+		self.accesses.clear();
+		self.peripherals.eeprom.accesses.clear();
+		self.peripherals.eeprom.record_accesses = self.is_recording();
+		if let Some(trace) = &mut self.trace {
+			trace.begin(&self.sram.registers, self.status.byte(), self.sp);
+		}
+
+		self.entry_checked = true;
+		// whether an instruction or interrupt moved the program counter, rather than
+		// the CPU sleeping or idling in place
+		let mut landed = true;
+		let event = if self.is_sleeping() {
+			self.wait_for_wake_up();
+			landed = !self.is_sleeping();
+			Event::Sleep
+		} else if self.service_interrupt() {
+			Event::Interrupt
+		} else {
+			if self.fast_forward && self.system.program_memory.read(self.pc) == IDLE_LOOP {
+				self.cycles += self.idle_cycles(2);
+				landed = false;
+			} else {
+				self.execute();
+			}
+			Event::Instruction
+		};
+
+		let elapsed = self.cycles - start_cycles;
+		self.peripherals
+			.step(&mut self.sram, &mut self.system.eeprom_memory, elapsed);
+
+		if let Some(mut trace) = self.trace.take() {
+			trace.end(self, event, start_cycles, pc);
+			self.trace = Some(trace);
+		}
+		if let Some(mut profiler) = self.profiler.take() {
+			profiler.step(self, event, start_cycles, pc);
+			self.profiler = Some(profiler);
+		}
+		if let (Event::Instruction, Some(coverage)) = (event, &mut self.coverage) {
+			coverage.record(pc, opcode, self.pc);
+		}
+
+		if self.peripherals.watchdog.take_reset_request() {
+			self.watchdog_reset();
+		}
+
+		if !self.watchpoints.is_empty() && self.watch_hit.is_none() {
+			let system = &mut self.system;
+			self.watch_hit = self.watchpoints.check(
+				&self.accesses,
+				&self.peripherals.eeprom.accesses,
+				pc,
+				|| {
+					let memory = &mut system.program_memory;
+					let next_word = is_two_words(opcode).then(|| memory.read(pc.wrapping_add(1)));
+					let instruction =
+						Disassembler::default().decode(pc, opcode, next_word, &system.symbols);
+					format!("{} {}", instruction.instruction, instruction.operands)
+						.trim_end()
+						.to_string()
+				},
+			);
+		}
+
+		if landed {
+			self.check_breakpoints();
+		}
+	}
+
+	/// Counts a hit on a breakpoint at the entry point, which no step lands on, if
+	/// nothing was executed since reset. Called when a run starts, true if it stops.
+	pub fn check_entry_breakpoint(&mut self) -> bool {
+		if !self.entry_checked {
+			self.entry_checked = true;
+			self.check_breakpoints();
+		}
+		self.breakpoint_hit
+	}
+
+	fn check_breakpoints(&mut self) {
+		if !self.breakpoints.is_empty() {
+			// conditions read the CPU the breakpoints belong to
+			let mut breakpoints = std::mem::take(&mut self.breakpoints);
+			self.breakpoint_hit |= breakpoints.check(self);
+			self.breakpoints = breakpoints;
+		}
+	}
+
+	fn execute(&mut self) {
+		self.opcode = self.fetch();
 
 		let low_byte = (self.opcode & 0xF) as u8;
 		let high_byte = ((self.opcode >> 4) & 0xF) as u8;
@@ -1068,11 +1829,7 @@ impl Cpu {
 			0x5000..=0x5FFF => self.subi(),
 			0x6000..=0x6FFF => self.ori(),
 			0x7000..=0x7FFF => self.andi(),
-			0x8000..=0x81FF => {
-				self.ld_z();
-				self.ld_y();
-				self.ldd();
-			}
+			0x8000..=0x81FF => self.ldd(),
 			0x8200..=0x83FF => self.std(),
 			0x8400..=0x85FF => self.ldd(),
 			0x8600..=0x87FF => self.std(),
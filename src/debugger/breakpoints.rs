@@ -0,0 +1,89 @@
+use super::Condition;
+use crate::cpu::Cpu;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+	/// Word address of the instruction to stop before
+	pub address: u16,
+	pub enabled: bool,
+	/// Only stops while this holds
+	pub condition: Option<Condition>,
+	/// Hits to let pass before stopping
+	pub ignore_count: u32,
+	/// Times execution reached the address with the condition met
+	pub hits: u32,
+}
+
+impl Breakpoint {
+	pub fn new(address: u16) -> Self {
+		Self {
+			address,
+			enabled: true,
+			condition: None,
+			ignore_count: 0,
+			hits: 0,
+		}
+	}
+}
+
+/// Breakpoints by word address, checked by [`Cpu::step`] whenever an instruction or
+/// interrupt lands on one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+	points: BTreeMap<u16, Breakpoint>,
+}
+
+impl Breakpoints {
+	pub fn is_empty(&self) -> bool {
+		self.points.is_empty()
+	}
+
+	/// Adds a breakpoint, replacing any other at its address
+	pub fn insert(&mut self, breakpoint: Breakpoint) {
+		self.points.insert(breakpoint.address, breakpoint);
+	}
+
+	pub fn remove(&mut self, address: u16) -> Option<Breakpoint> {
+		self.points.remove(&address)
+	}
+
+	/// Adds an unconditional breakpoint, or removes the one already there
+	pub fn toggle(&mut self, address: u16) {
+		if self.remove(address).is_none() {
+			self.insert(Breakpoint::new(address));
+		}
+	}
+
+	pub fn get(&self, address: u16) -> Option<&Breakpoint> {
+		self.points.get(&address)
+	}
+
+	pub fn get_mut(&mut self, address: u16) -> Option<&mut Breakpoint> {
+		self.points.get_mut(&address)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+		self.points.values()
+	}
+
+	pub fn clear(&mut self) {
+		self.points.clear();
+	}
+
+	/// Counts a hit if an enabled breakpoint at the program counter has its condition
+	/// met, and tells whether to stop
+	pub fn check(&mut self, cpu: &Cpu) -> bool {
+		let breakpoint = match self.points.get_mut(&cpu.pc) {
+			Some(breakpoint) if breakpoint.enabled => breakpoint,
+			_ => return false,
+		};
+		if let Some(condition) = &breakpoint.condition {
+			if !condition.evaluate(cpu) {
+				return false;
+			}
+		}
+		breakpoint.hits += 1;
+		breakpoint.hits > breakpoint.ignore_count
+	}
+}
@@ -0,0 +1,283 @@
+use crate::cpu::Cpu;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+	Or,
+	And,
+	Equal,
+	NotEqual,
+	Less,
+	LessEqual,
+	Greater,
+	GreaterEqual,
+}
+
+impl Operator {
+	fn apply(&self, left: i64, right: i64) -> bool {
+		match self {
+			Operator::Or => left != 0 || right != 0,
+			Operator::And => left != 0 && right != 0,
+			Operator::Equal => left == right,
+			Operator::NotEqual => left != right,
+			Operator::Less => left < right,
+			Operator::LessEqual => left <= right,
+			Operator::Greater => left > right,
+			Operator::GreaterEqual => left >= right,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expression {
+	Number(i64),
+	Register(usize),
+	/// Register pair as `r25:r24`, high register first
+	Pair(usize, usize),
+	/// SREG bit
+	Flag(u8),
+	Sreg,
+	Sp,
+	/// Word address
+	Pc,
+	Not(Box<Expression>),
+	Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+	fn evaluate(&self, cpu: &Cpu) -> i64 {
+		match self {
+			Expression::Number(value) => *value,
+			Expression::Register(index) => cpu.sram.registers[*index] as i64,
+			Expression::Pair(high, low) => {
+				(cpu.sram.registers[*high] as i64) << 8 | cpu.sram.registers[*low] as i64
+			}
+			Expression::Flag(bit) => (cpu.status.byte() >> bit & 1) as i64,
+			Expression::Sreg => cpu.status.byte() as i64,
+			Expression::Sp => cpu.sp as i64,
+			Expression::Pc => cpu.pc as i64,
+			Expression::Not(operand) => (operand.evaluate(cpu) == 0) as i64,
+			Expression::Binary(operator, left, right) => {
+				operator.apply(left.evaluate(cpu), right.evaluate(cpu)) as i64
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	Number(i64),
+	Name(String),
+	Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Token::Number(value) => write!(f, "{}", value),
+			Token::Name(name) => write!(f, "{}", name),
+			Token::Symbol(symbol) => write!(f, "{}", symbol),
+		}
+	}
+}
+
+/// Longest first, so `<=` is not read as `<`
+const SYMBOLS: [&str; 13] = [
+	"==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", ":", "=",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+	let mut tokens = Vec::new();
+	let mut rest = text.trim_start();
+
+	while !rest.is_empty() {
+		if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+			if *symbol == "=" {
+				return Err("Use == to compare".to_string());
+			}
+			tokens.push(Token::Symbol(symbol));
+			rest = &rest[symbol.len()..];
+		} else {
+			let length = rest
+				.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+				.unwrap_or(rest.len());
+			if length == 0 {
+				let character = rest.chars().next().unwrap_or_default();
+				return Err(format!("Unexpected character: {}", character));
+			}
+			let word = &rest[..length];
+			tokens.push(match parse_number(word) {
+				Some(value) => Token::Number(value),
+				None if word.starts_with(|c: char| c.is_ascii_digit()) => {
+					return Err(format!("Invalid number: {}", word))
+				}
+				None => Token::Name(word.to_string()),
+			});
+			rest = &rest[length..];
+		}
+		rest = rest.trim_start();
+	}
+	Ok(tokens)
+}
+
+/// Decimal, `0x` hexadecimal or `0b` binary
+fn parse_number(word: &str) -> Option<i64> {
+	if let Some(digits) = word.strip_prefix("0x") {
+		i64::from_str_radix(digits, 16).ok()
+	} else if let Some(digits) = word.strip_prefix("0b") {
+		i64::from_str_radix(digits, 2).ok()
+	} else {
+		word.parse().ok()
+	}
+}
+
+fn register(name: &str) -> Option<usize> {
+	name.strip_prefix(['r', 'R'])?
+		.parse()
+		.ok()
+		.filter(|index| *index < 32)
+}
+
+/// Recursive descent over `||`, `&&`, comparisons and `!`, in increasing precedence
+struct Parser {
+	tokens: Vec<Token>,
+	position: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn accept(&mut self, symbol: &str) -> bool {
+		let found = matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol);
+		if found {
+			self.position += 1;
+		}
+		found
+	}
+
+	fn binary(
+		&mut self,
+		operators: &[(&str, Operator)],
+		operand: fn(&mut Self) -> Result<Expression, String>,
+	) -> Result<Expression, String> {
+		let mut left = operand(self)?;
+		'outer: loop {
+			for (symbol, operator) in operators {
+				if self.accept(symbol) {
+					let right = operand(self)?;
+					left = Expression::Binary(*operator, Box::new(left), Box::new(right));
+					continue 'outer;
+				}
+			}
+			return Ok(left);
+		}
+	}
+
+	fn or(&mut self) -> Result<Expression, String> {
+		self.binary(&[("||", Operator::Or)], Self::and)
+	}
+
+	fn and(&mut self) -> Result<Expression, String> {
+		self.binary(&[("&&", Operator::And)], Self::comparison)
+	}
+
+	fn comparison(&mut self) -> Result<Expression, String> {
+		self.binary(
+			&[
+				("==", Operator::Equal),
+				("!=", Operator::NotEqual),
+				("<=", Operator::LessEqual),
+				(">=", Operator::GreaterEqual),
+				("<", Operator::Less),
+				(">", Operator::Greater),
+			],
+			Self::unary,
+		)
+	}
+
+	fn unary(&mut self) -> Result<Expression, String> {
+		if self.accept("!") {
+			return Ok(Expression::Not(Box::new(self.unary()?)));
+		}
+		if self.accept("(") {
+			let expression = self.or()?;
+			if !self.accept(")") {
+				return Err("Missing )".to_string());
+			}
+			return Ok(expression);
+		}
+
+		let token = self.peek().cloned();
+		self.position += 1;
+		match token {
+			Some(Token::Number(value)) => Ok(Expression::Number(value)),
+			Some(Token::Name(name)) => self.name(&name),
+			Some(Token::Symbol(symbol)) => Err(format!("Unexpected {}", symbol)),
+			None => Err("Unexpected end of condition".to_string()),
+		}
+	}
+
+	fn name(&mut self, name: &str) -> Result<Expression, String> {
+		if let Some(high) = register(name) {
+			if !self.accept(":") {
+				return Ok(Expression::Register(high));
+			}
+			return match self.peek() {
+				Some(Token::Name(low)) => {
+					let low = register(low).ok_or_else(|| format!("Unknown register: {}", low))?;
+					self.position += 1;
+					Ok(Expression::Pair(high, low))
+				}
+				_ => Err("Expected a register after :".to_string()),
+			};
+		}
+
+		let flags = "CZNVSHTI";
+		match name.to_ascii_uppercase().as_str() {
+			"SREG" => Ok(Expression::Sreg),
+			"SP" => Ok(Expression::Sp),
+			"PC" => Ok(Expression::Pc),
+			flag if flag.len() == 1 && flags.contains(flag) => {
+				Ok(Expression::Flag(flags.find(flag).unwrap() as u8))
+			}
+			_ => Err(format!("Unknown name: {}", name)),
+		}
+	}
+}
+
+/// Expression over registers and flags that decides whether a breakpoint stops, e.g.
+/// `r24 == 0x10 && Z` or `r25:r24 >= 1000 || !I`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+	text: String,
+	expression: Expression,
+}
+
+impl Condition {
+	pub fn parse(text: &str) -> Result<Self, String> {
+		let mut parser = Parser {
+			tokens: tokenize(text)?,
+			position: 0,
+		};
+		let expression = parser.or()?;
+		if let Some(token) = parser.peek() {
+			return Err(format!("Unexpected {}", token));
+		}
+		Ok(Self {
+			text: text.trim().to_string(),
+			expression,
+		})
+	}
+
+	pub fn evaluate(&self, cpu: &Cpu) -> bool {
+		self.expression.evaluate(cpu) != 0
+	}
+}
+
+impl fmt::Display for Condition {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.text)
+	}
+}
@@ -0,0 +1,259 @@
+use crate::cpu::Cpu;
+use crate::memory::{Access, AccessKind, Memory, RAMEND};
+use crate::peripherals::Peripherals;
+use std::collections::VecDeque;
+
+/// Steps between checkpoints of the peripheral state
+pub const CHECKPOINT_INTERVAL: usize = 1024;
+
+/// Steps kept by [`History::default`]
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// What a step changed, restoring it puts the CPU back to where it was before the step
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Undo {
+	pc: u16,
+	sp: u16,
+	sreg: u8,
+	cycles: usize,
+	opcode: u16,
+	/// Data space bytes the step wrote, with their previous contents
+	data: Vec<(u16, u8)>,
+	/// EEPROM bytes the step programmed, with their previous contents
+	eeprom: Vec<(u16, u8)>,
+	/// What the SPI and TWI devices answered, fed back when the step is replayed
+	devices: (Vec<u8>, Vec<u8>),
+}
+
+/// Peripheral state and I/O registers before a step. The undo log only covers what the
+/// CPU writes, not what the peripherals update on their own.
+#[derive(Clone)]
+struct Checkpoint {
+	step: usize,
+	peripherals: Peripherals,
+	io_registers: Vec<u8>,
+	ext_io_registers: Vec<u8>,
+}
+
+/// Undo log of the steps a CPU executed, with periodic checkpoints of the peripheral
+/// state. Going back undoes steps down to the checkpoint before the target and replays
+/// from there. Replayed steps get the answers the devices attached outside the chip gave
+/// the first time, and the devices don't see the traffic again.
+pub struct History {
+	/// Steps kept, older ones are dropped a checkpoint interval at a time
+	capacity: usize,
+	/// Step number of the oldest entry in `log`
+	first: usize,
+	log: VecDeque<Undo>,
+	checkpoints: VecDeque<Checkpoint>,
+	/// Device answers of the undone steps up to `end`, next step first
+	redo: VecDeque<(Vec<u8>, Vec<u8>)>,
+	/// Furthest step reached and its cycle count, seeking forward replays up to there
+	end: (usize, usize),
+}
+
+impl Default for History {
+	fn default() -> Self {
+		Self::new(DEFAULT_CAPACITY)
+	}
+}
+
+impl History {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity: capacity.max(CHECKPOINT_INTERVAL),
+			first: 0,
+			log: VecDeque::new(),
+			checkpoints: VecDeque::new(),
+			redo: VecDeque::new(),
+			end: (0, 0),
+		}
+	}
+
+	/// Steps recorded since the history was created or cleared
+	pub fn current(&self) -> usize {
+		self.first + self.log.len()
+	}
+
+	pub fn can_step_back(&self) -> bool {
+		!self.log.is_empty()
+	}
+
+	/// Cycle count of the oldest step that can be gone back to
+	pub fn start_cycles(&self, cpu: &Cpu) -> usize {
+		self.log.front().map_or(cpu.cycles, |undo| undo.cycles)
+	}
+
+	/// Cycle count of the furthest step reached
+	pub fn end_cycles(&self, cpu: &Cpu) -> usize {
+		self.end.1.max(cpu.cycles)
+	}
+
+	pub fn clear(&mut self) {
+		self.first = 0;
+		self.log.clear();
+		self.checkpoints.clear();
+		self.redo.clear();
+		self.end = (0, 0);
+	}
+
+	/// Executes and records a step, called by [`Cpu::step`] with the history taken out
+	pub fn step(&mut self, cpu: &mut Cpu) {
+		let step = self.current();
+		if step.is_multiple_of(CHECKPOINT_INTERVAL) {
+			self.checkpoints.push_back(Checkpoint {
+				step,
+				peripherals: cpu.peripherals.clone(),
+				io_registers: cpu.sram.io_registers.clone(),
+				ext_io_registers: cpu.sram.ext_io_registers.clone(),
+			});
+		}
+
+		let (pc, sp, sreg) = (cpu.pc, cpu.sp, cpu.status.byte());
+		let (cycles, opcode) = (cpu.cycles, cpu.opcode);
+
+		// the writes of the step are collected the same way as for watchpoints
+		let recording = std::mem::replace(&mut cpu.record_accesses, true);
+		cpu.peripherals.log_devices(self.redo.pop_front());
+		cpu.step();
+		cpu.record_accesses = recording;
+		let devices = cpu.peripherals.take_device_answers();
+
+		self.log.push_back(Undo {
+			pc,
+			sp,
+			sreg,
+			cycles,
+			opcode,
+			data: writes(&cpu.accesses)
+				.filter(|(address, _)| *address <= RAMEND)
+				.collect(),
+			eeprom: writes(&cpu.peripherals.eeprom.accesses).collect(),
+			devices,
+		});
+
+		if self.current() >= self.end.0 {
+			self.end = (self.current(), cpu.cycles);
+		}
+		// keeps at least `capacity` steps
+		let second = self.checkpoints.get(1).map(|checkpoint| checkpoint.step);
+		if let Some(oldest) = second.filter(|step| self.current() - step >= self.capacity) {
+			self.checkpoints.pop_front();
+			self.log.drain(..oldest - self.first);
+			self.first = oldest;
+		}
+	}
+
+	/// Puts the CPU back to before its last step, false if there is none
+	pub fn step_back(&mut self, cpu: &mut Cpu) -> bool {
+		if !self.can_step_back() {
+			return false;
+		}
+		self.rewind(cpu, self.current() - 1);
+		true
+	}
+
+	/// Goes back to the last step that started at an enabled breakpoint with its condition
+	/// met, or to the oldest step. Hit counts are left alone. True if a breakpoint stopped it.
+	pub fn reverse_continue(&mut self, cpu: &mut Cpu) -> bool {
+		// memory and registers are exact after undoing, which is all conditions read
+		while self.undo(cpu) {
+			let stop = cpu.breakpoints.get(cpu.pc).is_some_and(|breakpoint| {
+				breakpoint.enabled
+					&& (breakpoint.condition.as_ref()).is_none_or(|c| c.evaluate(cpu))
+			});
+			if stop {
+				self.rewind(cpu, self.current());
+				return true;
+			}
+		}
+		self.rewind(cpu, self.current());
+		false
+	}
+
+	/// Moves back to the last step starting at or before `cycles`, or replays forward
+	/// until reaching it or the furthest step reached
+	pub fn seek(&mut self, cpu: &mut Cpu, cycles: usize) {
+		if cycles < cpu.cycles {
+			let step = self.first + self.log.partition_point(|undo| undo.cycles <= cycles);
+			self.rewind(cpu, step.saturating_sub(1).max(self.first));
+		} else {
+			let end = self.end.0;
+			self.replay(cpu, |cpu, step| step >= end || cpu.cycles >= cycles);
+		}
+	}
+
+	/// Undoes steps down to the checkpoint at or before `step`, restores the peripherals
+	/// and replays up to `step`
+	fn rewind(&mut self, cpu: &mut Cpu, step: usize) {
+		let Some(index) = (self.checkpoints.iter()).rposition(|checkpoint| checkpoint.step <= step)
+		else {
+			return;
+		};
+		while self.current() > self.checkpoints[index].step {
+			self.undo(cpu);
+		}
+		// the replay takes the checkpoint again
+		self.checkpoints.truncate(index + 1);
+		if let Some(checkpoint) = self.checkpoints.pop_back() {
+			cpu.peripherals = checkpoint.peripherals;
+			cpu.sram.io_registers = checkpoint.io_registers;
+			cpu.sram.ext_io_registers = checkpoint.ext_io_registers;
+		}
+		self.replay(cpu, |_, current| current >= step);
+	}
+
+	/// Undoes the last step, keeping its device answers for the replay
+	fn undo(&mut self, cpu: &mut Cpu) -> bool {
+		let Some(undo) = self.log.pop_back() else {
+			return false;
+		};
+		undo.restore(cpu);
+		self.redo.push_front(undo.devices);
+		true
+	}
+
+	/// Steps until `done` holds, without counting breakpoint hits, stopping on
+	/// watchpoints, or tracing, profiling and covering the steps again
+	fn replay(&mut self, cpu: &mut Cpu, done: impl Fn(&Cpu, usize) -> bool) {
+		let breakpoints = std::mem::take(&mut cpu.breakpoints);
+		let watchpoints = std::mem::take(&mut cpu.watchpoints);
+		let trace = cpu.trace.take();
+		let profiler = cpu.profiler.take();
+		let coverage = cpu.coverage.take();
+		while !done(cpu, self.current()) {
+			self.step(cpu);
+		}
+		cpu.breakpoints = breakpoints;
+		cpu.watchpoints = watchpoints;
+		cpu.trace = trace;
+		cpu.profiler = profiler;
+		cpu.coverage = coverage;
+		cpu.break_hit = false;
+		cpu.breakpoint_hit = false;
+		cpu.watch_hit = None;
+	}
+}
+
+impl Undo {
+	fn restore(&self, cpu: &mut Cpu) {
+		for (address, previous) in self.data.iter().rev() {
+			cpu.sram.write(*address, *previous as u16);
+		}
+		for (address, previous) in self.eeprom.iter().rev() {
+			cpu.system.eeprom_memory.write(*address, *previous as u16);
+		}
+		cpu.pc = self.pc;
+		cpu.sp = self.sp;
+		cpu.status.set_byte(self.sreg);
+		cpu.cycles = self.cycles;
+		cpu.opcode = self.opcode;
+	}
+}
+
+/// Addresses written in `accesses`, with their contents before the write
+fn writes(accesses: &[Access]) -> impl Iterator<Item = (u16, u8)> + '_ {
+	(accesses.iter())
+		.filter(|access| access.kind == AccessKind::Write)
+		.map(|access| (access.address, access.previous))
+}
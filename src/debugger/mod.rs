@@ -0,0 +1,11 @@
+pub mod breakpoints;
+pub mod condition;
+pub mod history;
+pub mod stepping;
+pub mod watchpoints;
+
+pub use breakpoints::{Breakpoint, Breakpoints};
+pub use condition::Condition;
+pub use history::History;
+pub use stepping::{SourceStep, StepKind};
+pub use watchpoints::{WatchHit, WatchKind, WatchSpace, Watchpoint, Watchpoints};
@@ -0,0 +1,75 @@
+use crate::cpu::Cpu;
+use crate::disassembler::is_call;
+use crate::dwarf::Location;
+use crate::memory::Memory;
+
+const RET: u16 = 0x9508;
+const RETI: u16 = 0x9518;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+	/// Into the next line, entering called functions that have line information
+	Line,
+	/// To the next line of the current function, running calls to completion
+	Over,
+	/// Until the current function returns
+	Out,
+}
+
+/// A source-level step in progress. Calls are told apart from pushes and the `rcall .+0`
+/// reserving stack space by their opcode, and interrupts by SP moving two bytes while
+/// the I flag gets cleared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStep {
+	kind: StepKind,
+	start: Option<Location>,
+	/// Calls entered and not yet returned from, negative once the starting function returns
+	depth: i32,
+}
+
+impl SourceStep {
+	pub fn new(kind: StepKind, cpu: &Cpu) -> Self {
+		Self {
+			kind,
+			start: cpu.system.lines.location(cpu.pc),
+			depth: 0,
+		}
+	}
+
+	/// Executes one instruction, true once the step is complete or a breakpoint or
+	/// watchpoint is hit. Watchpoint hits are left in `cpu.watch_hit` to be reported.
+	pub fn step(&mut self, cpu: &mut Cpu) -> bool {
+		let sp = cpu.sp;
+		let interrupts_enabled = cpu.status.I;
+		let opcode = cpu.system.program_memory.read(cpu.pc);
+		cpu.step();
+
+		if cpu.breakpoint_hit {
+			cpu.breakpoint_hit = false;
+			return true;
+		}
+		if cpu.watch_hit.is_some() {
+			return true;
+		}
+		let interrupt = interrupts_enabled && !cpu.status.I;
+		if cpu.sp == sp.wrapping_sub(2) && (is_call(opcode) || interrupt) {
+			self.depth += 1;
+		} else if cpu.sp == sp.wrapping_add(2) && matches!(opcode, RET | RETI) {
+			self.depth -= 1;
+		}
+
+		match self.kind {
+			StepKind::Out => self.depth < 0,
+			StepKind::Over if self.depth > 0 => false,
+			StepKind::Line | StepKind::Over => {
+				let lines = &cpu.system.lines;
+				lines.is_statement(cpu.pc) && lines.location(cpu.pc) != self.start
+			}
+		}
+	}
+
+	/// Executes up to `limit` instructions, true once the step is complete
+	pub fn run(&mut self, cpu: &mut Cpu, limit: usize) -> bool {
+		(0..limit).any(|_| self.step(cpu))
+	}
+}
@@ -0,0 +1,196 @@
+use crate::memory::{Access, AccessKind, REGISTER_NAMES};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+	Read,
+	Write,
+	/// Reads and writes
+	Access,
+	/// Writes that store a different value
+	Change,
+}
+
+impl WatchKind {
+	pub const ALL: [WatchKind; 4] = [
+		WatchKind::Read,
+		WatchKind::Write,
+		WatchKind::Access,
+		WatchKind::Change,
+	];
+
+	fn matches(&self, access: &Access) -> bool {
+		match self {
+			WatchKind::Read => access.kind == AccessKind::Read,
+			WatchKind::Write => access.kind == AccessKind::Write,
+			WatchKind::Access => true,
+			WatchKind::Change => {
+				access.kind == AccessKind::Write && access.value != access.previous
+			}
+		}
+	}
+}
+
+impl fmt::Display for WatchKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			WatchKind::Read => write!(f, "read"),
+			WatchKind::Write => write!(f, "write"),
+			WatchKind::Access => write!(f, "access"),
+			WatchKind::Change => write!(f, "change"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchSpace {
+	/// Registers, I/O registers and SRAM
+	Data,
+	Eeprom,
+}
+
+impl fmt::Display for WatchSpace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			WatchSpace::Data => write!(f, "data"),
+			WatchSpace::Eeprom => write!(f, "EEPROM"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+	pub space: WatchSpace,
+	pub address: u16,
+	/// Bytes watched from `address` on
+	pub length: u16,
+	pub kind: WatchKind,
+	pub enabled: bool,
+}
+
+impl Watchpoint {
+	pub fn new(space: WatchSpace, address: u16, length: u16, kind: WatchKind) -> Self {
+		Self {
+			space,
+			address,
+			length: length.max(1),
+			kind,
+			enabled: true,
+		}
+	}
+
+	/// An I/O register by its datasheet name such as `PORTB`, ignoring case
+	pub fn register(name: &str, kind: WatchKind) -> Option<Self> {
+		REGISTER_NAMES
+			.iter()
+			.find(|(_, register)| register.eq_ignore_ascii_case(name) && *register != "Reserved")
+			.map(|(address, _)| Self::new(WatchSpace::Data, *address as u16, 1, kind))
+	}
+
+	fn contains(&self, address: u16) -> bool {
+		address.wrapping_sub(self.address) < self.length
+	}
+}
+
+/// An access that stopped execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+	pub space: WatchSpace,
+	pub kind: WatchKind,
+	pub access: Access,
+	/// Word address of the instruction that made the access
+	pub pc: u16,
+	/// Disassembly of that instruction
+	pub instruction: String,
+}
+
+impl fmt::Display for WatchHit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let access = &self.access;
+		write!(
+			f,
+			"{} watchpoint on {} 0x{:04X} at PC 0x{:04X} ({}): ",
+			self.kind, self.space, access.address, self.pc, self.instruction
+		)?;
+		match access.kind {
+			AccessKind::Read => write!(f, "read 0x{:02X}", access.value),
+			AccessKind::Write => write!(f, "0x{:02X} -> 0x{:02X}", access.previous, access.value),
+		}
+	}
+}
+
+/// Watchpoints on data space and EEPROM addresses, checked by [`Cpu::step`] against the
+/// accesses the instruction made
+///
+/// [`Cpu::step`]: crate::cpu::Cpu::step
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Watchpoints {
+	points: Vec<Watchpoint>,
+}
+
+impl Watchpoints {
+	pub fn is_empty(&self) -> bool {
+		self.points.is_empty()
+	}
+
+	pub fn insert(&mut self, watchpoint: Watchpoint) {
+		self.points.push(watchpoint);
+	}
+
+	/// Removes a watchpoint equal to `watchpoint`, true if there was one
+	pub fn remove(&mut self, watchpoint: &Watchpoint) -> bool {
+		match self.points.iter().position(|point| point == watchpoint) {
+			Some(index) => {
+				self.points.remove(index);
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+		self.points.iter()
+	}
+
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Watchpoint> {
+		self.points.iter_mut()
+	}
+
+	pub fn clear(&mut self) {
+		self.points.clear();
+	}
+
+	/// First access of a step that an enabled watchpoint stops on, `disassemble` gives
+	/// the instruction at `pc` once there is a hit
+	pub fn check(
+		&self,
+		data: &[Access],
+		eeprom: &[Access],
+		pc: u16,
+		disassemble: impl FnOnce() -> String,
+	) -> Option<WatchHit> {
+		let accesses = data
+			.iter()
+			.map(|access| (WatchSpace::Data, access))
+			.chain(eeprom.iter().map(|access| (WatchSpace::Eeprom, access)));
+
+		for (space, access) in accesses {
+			let hit = self.points.iter().find(|point| {
+				point.enabled
+					&& point.space == space
+					&& point.contains(access.address)
+					&& point.kind.matches(access)
+			});
+			if let Some(point) = hit {
+				return Some(WatchHit {
+					space,
+					kind: point.kind,
+					access: *access,
+					pc,
+					instruction: disassemble(),
+				});
+			}
+		}
+		None
+	}
+}
@@ -0,0 +1,482 @@
+use crate::elf::{self, Elf};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0B;
+const DW_FORM_STRP: u64 = 0x0E;
+const DW_FORM_UDATA: u64 = 0x0F;
+const DW_FORM_DATA16: u64 = 0x1E;
+const DW_FORM_LINE_STRP: u64 = 0x1F;
+
+/// Byte addresses past flash are not code
+const FLASH_BYTES: u64 = 0x8000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	/// A line program extends past the end of its section
+	Truncated,
+	UnsupportedVersion(u16),
+	/// Form of a DWARF 5 directory or file attribute that can't be decoded
+	UnsupportedForm(u64),
+	/// Header values the line program can't be run with
+	InvalidHeader,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Truncated => write!(f, "line program is truncated"),
+			Error::UnsupportedVersion(version) => {
+				write!(f, "DWARF version {} is not supported", version)
+			}
+			Error::UnsupportedForm(form) => write!(f, "unsupported attribute form 0x{:X}", form),
+			Error::InvalidHeader => write!(f, "invalid line program header"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+	/// Index into the files of the line table
+	pub file: usize,
+	pub line: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+	location: Location,
+	/// Recommended breakpoint position, the start of a statement
+	statement: bool,
+}
+
+/// Source file and line of each flash address, from the `.debug_line` section
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+	files: Vec<String>,
+	/// Rows by the word address they start at, `None` where a sequence ends
+	rows: BTreeMap<u16, Option<Row>>,
+}
+
+impl LineTable {
+	/// Line table of an ELF file, empty when it has no debug information
+	pub fn from_elf(elf: &Elf) -> Result<Self> {
+		let contents = |name| {
+			elf.section(name)
+				.map_or(&[][..], |section| &section.data[..])
+		};
+		Self::parse(
+			contents(".debug_line"),
+			contents(".debug_line_str"),
+			contents(".debug_str"),
+		)
+	}
+
+	/// Runs the line programs of every compilation unit in `.debug_line`, DWARF 5 file
+	/// names may refer to `.debug_line_str` and `.debug_str`
+	pub fn parse(debug_line: &[u8], line_strings: &[u8], strings: &[u8]) -> Result<Self> {
+		let mut table = LineTable::default();
+		let strings = Strings {
+			line: line_strings,
+			debug: strings,
+		};
+
+		let mut cursor = Cursor::new(debug_line);
+		while !cursor.is_empty() {
+			table.unit(&mut cursor, &strings)?;
+		}
+		Ok(table)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rows.is_empty()
+	}
+
+	/// Path of a file as recorded by the compiler
+	pub fn file(&self, index: usize) -> Option<&str> {
+		self.files.get(index).map(String::as_str)
+	}
+
+	/// Source line the instruction at the word address belongs to
+	pub fn location(&self, address: u16) -> Option<Location> {
+		self.rows
+			.range(..=address)
+			.next_back()
+			.and_then(|(_, row)| row.map(|row| row.location))
+	}
+
+	/// Whether a statement starts at the word address
+	pub fn is_statement(&self, address: u16) -> bool {
+		matches!(self.rows.get(&address), Some(Some(row)) if row.statement)
+	}
+
+	fn intern(&mut self, path: String) -> usize {
+		match self.files.iter().position(|file| *file == path) {
+			Some(index) => index,
+			None => {
+				self.files.push(path);
+				self.files.len() - 1
+			}
+		}
+	}
+
+	fn add_row(&mut self, address: u64, row: Option<Row>) {
+		if address >= FLASH_BYTES {
+			return;
+		}
+		let address = (address / 2) as u16;
+		match row {
+			// the last row for an address is the one that applies
+			Some(_) => {
+				self.rows.insert(address, row);
+			}
+			// another sequence may start where this one ends
+			None => {
+				self.rows.entry(address).or_insert(None);
+			}
+		}
+	}
+
+	fn unit(&mut self, cursor: &mut Cursor, strings: &Strings) -> Result<()> {
+		let (length, offset_size) = match cursor.u32()? {
+			0xFFFF_FFFF => (cursor.unsigned(8)?, 8),
+			length => (length as u64, 4),
+		};
+		let mut unit = Cursor::new(cursor.take(length)?);
+
+		let version = unit.u16()?;
+		if !(2..=5).contains(&version) {
+			return Err(Error::UnsupportedVersion(version));
+		}
+		if version >= 5 {
+			// address and segment selector size
+			unit.take(2)?;
+		}
+		let header_length = unit.unsigned(offset_size)?;
+		let mut header = Cursor::new(unit.take(header_length)?);
+
+		let minimum_length = header.u8()? as u64;
+		if version >= 4 {
+			// maximum operations per instruction, only for VLIW
+			header.u8()?;
+		}
+		let default_statement = header.u8()? != 0;
+		let line_base = header.u8()? as i8;
+		let line_range = header.u8()?;
+		let opcode_base = header.u8()?;
+		if line_range == 0 || opcode_base == 0 {
+			return Err(Error::InvalidHeader);
+		}
+		let opcode_lengths = header.take(opcode_base as u64 - 1)?.to_vec();
+
+		// file registers count from one before DWARF 5
+		let (mut files, first_file) = if version >= 5 {
+			let directories = entries(&mut header, strings, offset_size)?;
+			let directory = |index: u64| match directories.get(index as usize) {
+				Some((path, _)) => path.as_str(),
+				None => "",
+			};
+			let files = entries(&mut header, strings, offset_size)?
+				.into_iter()
+				.map(|(path, index)| self.intern(join(directory(index), &path)))
+				.collect::<Vec<usize>>();
+			(files, 0)
+		} else {
+			let mut directories = Vec::new();
+			loop {
+				let directory = header.string()?;
+				if directory.is_empty() {
+					break;
+				}
+				directories.push(directory);
+			}
+			let mut files = Vec::new();
+			loop {
+				let path = header.string()?;
+				if path.is_empty() {
+					break;
+				}
+				let index = file_entry(&mut header)?;
+				files.push(self.intern(join_v4(&directories, index, &path)));
+			}
+			(files, 1)
+		};
+
+		let mut address: u64 = 0;
+		let mut file: u64 = 1;
+		let mut line: u32 = 1;
+		let mut statement = default_statement;
+
+		while !unit.is_empty() {
+			let row = |file: u64, line: u32, statement: bool| {
+				files
+					.get(file.wrapping_sub(first_file) as usize)
+					.map(|file| Row {
+						location: Location { file: *file, line },
+						statement,
+					})
+			};
+
+			let opcode = unit.u8()?;
+			if opcode >= opcode_base {
+				let adjusted = opcode - opcode_base;
+				address = address.wrapping_add((adjusted / line_range) as u64 * minimum_length);
+				line = line.wrapping_add_signed(line_base as i32 + (adjusted % line_range) as i32);
+				if let Some(row) = row(file, line, statement) {
+					self.add_row(address, Some(row));
+				}
+				continue;
+			}
+
+			match opcode {
+				0 => {
+					let length = unit.uleb()?;
+					let mut extended = Cursor::new(unit.take(length)?);
+					match extended.u8()? {
+						DW_LNE_END_SEQUENCE => {
+							self.add_row(address, None);
+							address = 0;
+							file = 1;
+							line = 1;
+							statement = default_statement;
+						}
+						DW_LNE_SET_ADDRESS => address = extended.unsigned(length - 1)?,
+						DW_LNE_DEFINE_FILE => {
+							let path = extended.string()?;
+							file_entry(&mut extended)?;
+							files.push(self.intern(path));
+						}
+						_ => {}
+					}
+				}
+				DW_LNS_COPY => {
+					if let Some(row) = row(file, line, statement) {
+						self.add_row(address, Some(row));
+					}
+				}
+				DW_LNS_ADVANCE_PC => {
+					address = address.wrapping_add(unit.uleb()?.wrapping_mul(minimum_length))
+				}
+				DW_LNS_ADVANCE_LINE => line = line.wrapping_add(unit.sleb()? as u32),
+				DW_LNS_SET_FILE => file = unit.uleb()?,
+				DW_LNS_NEGATE_STMT => statement = !statement,
+				DW_LNS_CONST_ADD_PC => {
+					let adjusted = 255 - opcode_base;
+					address = address.wrapping_add((adjusted / line_range) as u64 * minimum_length);
+				}
+				DW_LNS_FIXED_ADVANCE_PC => address = address.wrapping_add(unit.u16()? as u64),
+				_ => {
+					// operands of opcodes without effect on the address or line
+					for _ in 0..opcode_lengths[opcode as usize - 1] {
+						unit.uleb()?;
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+struct Strings<'a> {
+	line: &'a [u8],
+	debug: &'a [u8],
+}
+
+/// Skips the directory index, modification time and length of a DWARF 4 file entry,
+/// returning the directory index
+fn file_entry(cursor: &mut Cursor) -> Result<u64> {
+	let directory = cursor.uleb()?;
+	cursor.uleb()?;
+	cursor.uleb()?;
+	Ok(directory)
+}
+
+/// DWARF 5 directory or file entries as `(path, directory index)`
+fn entries(cursor: &mut Cursor, strings: &Strings, offset_size: u64) -> Result<Vec<(String, u64)>> {
+	let format_count = cursor.u8()?;
+	let format = (0..format_count)
+		.map(|_| Ok((cursor.uleb()?, cursor.uleb()?)))
+		.collect::<Result<Vec<(u64, u64)>>>()?;
+	let count = cursor.uleb()?;
+	if format.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let mut entries = Vec::new();
+	for _ in 0..count {
+		let mut path = String::new();
+		let mut directory = 0;
+		for (content, form) in &format {
+			let (text, number) = match *form {
+				DW_FORM_STRING => (Some(cursor.string()?), 0),
+				DW_FORM_LINE_STRP => (
+					Some(elf::name(strings.line, cursor.offset(offset_size)?)),
+					0,
+				),
+				DW_FORM_STRP => (
+					Some(elf::name(strings.debug, cursor.offset(offset_size)?)),
+					0,
+				),
+				DW_FORM_UDATA => (None, cursor.uleb()?),
+				DW_FORM_DATA1 => (None, cursor.unsigned(1)?),
+				DW_FORM_DATA2 => (None, cursor.unsigned(2)?),
+				DW_FORM_DATA4 => (None, cursor.unsigned(4)?),
+				DW_FORM_DATA8 => (None, cursor.unsigned(8)?),
+				DW_FORM_DATA16 => (None, cursor.take(16).map(|_| 0)?),
+				DW_FORM_BLOCK => {
+					let length = cursor.uleb()?;
+					(None, cursor.take(length).map(|_| 0)?)
+				}
+				form => return Err(Error::UnsupportedForm(form)),
+			};
+			match *content {
+				DW_LNCT_PATH => path = text.unwrap_or_default(),
+				DW_LNCT_DIRECTORY_INDEX => directory = number,
+				_ => {}
+			}
+		}
+		entries.push((path, directory));
+	}
+	Ok(entries)
+}
+
+fn join(directory: &str, path: &str) -> String {
+	Path::new(directory)
+		.join(path)
+		.to_string_lossy()
+		.into_owned()
+}
+
+/// Directory zero is the compilation directory, which is only named in `.debug_info`
+fn join_v4(directories: &[String], index: u64, path: &str) -> String {
+	match index
+		.checked_sub(1)
+		.and_then(|index| directories.get(index as usize))
+	{
+		Some(directory) => join(directory, path),
+		None => path.to_string(),
+	}
+}
+
+struct Cursor<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Cursor<'a> {
+	fn new(bytes: &'a [u8]) -> Self {
+		Self { bytes, position: 0 }
+	}
+
+	fn is_empty(&self) -> bool {
+		self.position >= self.bytes.len()
+	}
+
+	fn take(&mut self, length: u64) -> Result<&'a [u8]> {
+		let end = usize::try_from(length)
+			.ok()
+			.and_then(|length| self.position.checked_add(length))
+			.filter(|end| *end <= self.bytes.len())
+			.ok_or(Error::Truncated)?;
+		let bytes = &self.bytes[self.position..end];
+		self.position = end;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16> {
+		Ok(self.unsigned(2)? as u16)
+	}
+
+	fn u32(&mut self) -> Result<u32> {
+		Ok(self.unsigned(4)? as u32)
+	}
+
+	/// Little endian value of up to 8 bytes
+	fn unsigned(&mut self, size: u64) -> Result<u64> {
+		if size > 8 {
+			return Err(Error::InvalidHeader);
+		}
+		let bytes = self.take(size)?;
+		Ok(bytes
+			.iter()
+			.rev()
+			.fold(0, |value, byte| value << 8 | *byte as u64))
+	}
+
+	/// Section offset, 4 or 8 bytes depending on the unit format
+	fn offset(&mut self, size: u64) -> Result<usize> {
+		Ok(self.unsigned(size)? as usize)
+	}
+
+	fn uleb(&mut self) -> Result<u64> {
+		let mut value = 0;
+		let mut shift = 0;
+		loop {
+			let byte = self.u8()?;
+			if shift < 64 {
+				value |= ((byte & 0x7F) as u64) << shift;
+			}
+			shift += 7;
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+	}
+
+	fn sleb(&mut self) -> Result<i64> {
+		let mut value = 0;
+		let mut shift = 0;
+		loop {
+			let byte = self.u8()?;
+			if shift < 64 {
+				value |= ((byte & 0x7F) as i64) << shift;
+			}
+			shift += 7;
+			if byte & 0x80 == 0 {
+				if shift < 64 && byte & 0x40 != 0 {
+					value |= -1 << shift;
+				}
+				return Ok(value);
+			}
+		}
+	}
+
+	fn string(&mut self) -> Result<String> {
+		let rest = &self.bytes[self.position.min(self.bytes.len())..];
+		let length = rest
+			.iter()
+			.position(|byte| *byte == 0)
+			.ok_or(Error::Truncated)?;
+		let text = String::from_utf8_lossy(&rest[..length]).into_owned();
+		self.position += length + 1;
+		Ok(text)
+	}
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"\x7FELF";
+const CLASS_32: u8 = 1;
+const LITTLE_ENDIAN: u8 = 1;
+const MACHINE_AVR: u16 = 83;
+
+const HEADER_SIZE: usize = 52;
+const SECTION_HEADER_SIZE: usize = 40;
+const PROGRAM_HEADER_SIZE: usize = 32;
+const SYMBOL_SIZE: usize = 16;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u32 = 0x2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	NotElf,
+	/// 64 bit or big endian files
+	UnsupportedFormat,
+	/// Built for another architecture, holds `e_machine`
+	WrongMachine(u16),
+	/// A header or section extends past the end of the file
+	Truncated,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::NotElf => write!(f, "not an ELF file"),
+			Error::UnsupportedFormat => write!(f, "only 32 bit little endian ELF is supported"),
+			Error::WrongMachine(machine) => {
+				write!(f, "built for machine {} rather than AVR", machine)
+			}
+			Error::Truncated => write!(f, "file is truncated"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+	pub name: String,
+	/// Address the section runs at, data space sections are offset by 0x800000
+	pub address: u32,
+	/// Address the section is stored at, `.data` is copied from flash at start up
+	pub load_address: u32,
+	/// Empty for sections without file contents such as `.bss`
+	pub data: Vec<u8>,
+	/// Whether the section occupies memory on the target
+	pub allocated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+	Function,
+	/// A variable, or constant data in flash
+	Object,
+	Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+	pub name: String,
+	/// Byte address, offset by 0x800000 for data space
+	pub value: u32,
+	pub size: u32,
+	pub kind: SymbolKind,
+}
+
+/// Contents of an ELF32 AVR executable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Elf {
+	/// Byte address of the first instruction
+	pub entry: u32,
+	pub sections: Vec<Section>,
+	/// Named entries of `.symtab`, empty for stripped files
+	pub symbols: Vec<Symbol>,
+}
+
+impl Elf {
+	pub fn section(&self, name: &str) -> Option<&Section> {
+		self.sections.iter().find(|section| section.name == name)
+	}
+
+	/// `(load address, byte)` pairs of every allocated section with contents, the
+	/// layout avr-objcopy would write out
+	pub fn image(&self) -> Vec<(u32, u8)> {
+		self.sections
+			.iter()
+			.filter(|section| section.allocated)
+			.flat_map(|section| {
+				section
+					.data
+					.iter()
+					.enumerate()
+					.map(|(offset, byte)| (section.load_address + offset as u32, *byte))
+			})
+			.collect()
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+	fn slice(&self, offset: usize, length: usize) -> Result<&[u8]> {
+		offset
+			.checked_add(length)
+			.and_then(|end| self.bytes.get(offset..end))
+			.ok_or(Error::Truncated)
+	}
+
+	fn u16(&self, offset: usize) -> Result<u16> {
+		let bytes = self.slice(offset, 2)?;
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+
+	fn u32(&self, offset: usize) -> Result<u32> {
+		let bytes = self.slice(offset, 4)?;
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+}
+
+struct SectionHeader {
+	name: usize,
+	kind: u32,
+	flags: u32,
+	address: u32,
+	offset: usize,
+	size: usize,
+	link: usize,
+}
+
+struct ProgramHeader {
+	kind: u32,
+	offset: usize,
+	physical_address: u32,
+	file_size: usize,
+}
+
+fn section_headers(reader: &Reader) -> Result<Vec<SectionHeader>> {
+	let table = reader.u32(32)? as usize;
+	let entry_size = reader.u16(46)? as usize;
+	let count = reader.u16(48)? as usize;
+	if count > 0 && entry_size < SECTION_HEADER_SIZE {
+		return Err(Error::Truncated);
+	}
+
+	(0..count)
+		.map(|index| {
+			let header = table + index * entry_size;
+			Ok(SectionHeader {
+				name: reader.u32(header)? as usize,
+				kind: reader.u32(header + 4)?,
+				flags: reader.u32(header + 8)?,
+				address: reader.u32(header + 12)?,
+				offset: reader.u32(header + 16)? as usize,
+				size: reader.u32(header + 20)? as usize,
+				link: reader.u32(header + 24)? as usize,
+			})
+		})
+		.collect()
+}
+
+fn program_headers(reader: &Reader) -> Result<Vec<ProgramHeader>> {
+	let table = reader.u32(28)? as usize;
+	let entry_size = reader.u16(42)? as usize;
+	let count = reader.u16(44)? as usize;
+	if count > 0 && entry_size < PROGRAM_HEADER_SIZE {
+		return Err(Error::Truncated);
+	}
+
+	(0..count)
+		.map(|index| {
+			let header = table + index * entry_size;
+			Ok(ProgramHeader {
+				kind: reader.u32(header)?,
+				offset: reader.u32(header + 4)? as usize,
+				physical_address: reader.u32(header + 12)?,
+				file_size: reader.u32(header + 16)? as usize,
+			})
+		})
+		.collect()
+}
+
+/// NUL terminated string at `offset` in a string table
+pub(crate) fn name(strings: &[u8], offset: usize) -> String {
+	let bytes = strings.get(offset..).unwrap_or_default();
+	let end = bytes
+		.iter()
+		.position(|byte| *byte == 0)
+		.unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn symbols(reader: &Reader, headers: &[SectionHeader]) -> Result<Vec<Symbol>> {
+	let table = match headers.iter().find(|header| header.kind == SHT_SYMTAB) {
+		Some(table) => table,
+		None => return Ok(Vec::new()),
+	};
+	let strings = match headers.get(table.link) {
+		Some(strings) => reader.slice(strings.offset, strings.size)?,
+		None => &[],
+	};
+
+	let mut symbols = Vec::new();
+	for index in 0..table.size / SYMBOL_SIZE {
+		let entry = table.offset + index * SYMBOL_SIZE;
+		let name = name(strings, reader.u32(entry)? as usize);
+		if name.is_empty() {
+			continue;
+		}
+
+		let kind = match reader.slice(entry + 12, 1)?[0] & 0xF {
+			STT_FUNC => SymbolKind::Function,
+			STT_OBJECT => SymbolKind::Object,
+			_ => SymbolKind::Other,
+		};
+		symbols.push(Symbol {
+			name,
+			value: reader.u32(entry + 4)?,
+			size: reader.u32(entry + 8)?,
+			kind,
+		});
+	}
+	Ok(symbols)
+}
+
+/// Decodes the header, sections, load addresses and symbols of an ELF32 AVR file
+pub fn parse(bytes: &[u8]) -> Result<Elf> {
+	let reader = Reader { bytes };
+
+	if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+		return Err(Error::NotElf);
+	}
+	if bytes[4] != CLASS_32 || bytes[5] != LITTLE_ENDIAN {
+		return Err(Error::UnsupportedFormat);
+	}
+	let machine = reader.u16(18)?;
+	if machine != MACHINE_AVR {
+		return Err(Error::WrongMachine(machine));
+	}
+
+	let headers = section_headers(&reader)?;
+	let segments = program_headers(&reader)?;
+	let strings = match headers.get(reader.u16(50)? as usize) {
+		Some(table) => reader.slice(table.offset, table.size)?,
+		None => &[],
+	};
+
+	let sections = headers
+		.iter()
+		.map(|header| {
+			let data = if header.kind == SHT_NOBITS {
+				Vec::new()
+			} else {
+				reader.slice(header.offset, header.size)?.to_vec()
+			};
+
+			// the segment holding the section's bytes gives its load address
+			let load_address = segments
+				.iter()
+				.find(|segment| {
+					segment.kind == PT_LOAD
+						&& header.kind != SHT_NOBITS
+						&& segment.offset <= header.offset
+						&& header.offset + header.size <= segment.offset + segment.file_size
+				})
+				.map_or(header.address, |segment| {
+					segment.physical_address + (header.offset - segment.offset) as u32
+				});
+
+			Ok(Section {
+				name: name(strings, header.name),
+				address: header.address,
+				load_address,
+				data,
+				allocated: header.flags & SHF_ALLOC != 0,
+			})
+		})
+		.collect::<Result<Vec<Section>>>()?;
+
+	Ok(Elf {
+		entry: reader.u32(24)?,
+		sections,
+		symbols: symbols(&reader, &headers)?,
+	})
+}
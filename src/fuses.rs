@@ -0,0 +1,76 @@
+use crate::utils::bit;
+
+// Low fuse bits
+const CKDIV8: u8 = 7;
+const CKSEL: u8 = 0x0F;
+
+// High fuse bits
+const WDTON: u8 = 4;
+const EESAVE: u8 = 3;
+
+/// Device signature of the ATmega328P
+pub const SIGNATURE: [u8; 3] = [0x1E, 0x95, 0x0F];
+
+/// Fuse bytes, a bit reads 0 when the fuse is programmed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fuses {
+	pub low: u8,
+	pub high: u8,
+	pub extended: u8,
+	/// Lock bits, not enforced as there is no programming interface
+	pub lock: u8,
+}
+
+impl Default for Fuses {
+	/// Factory values from the datasheet
+	fn default() -> Self {
+		Self {
+			low: 0x62,
+			high: 0xD9,
+			extended: 0xFF,
+			lock: 0xFF,
+		}
+	}
+}
+
+impl Fuses {
+	/// CKSEL3:0, the clock source selected at reset
+	pub fn clock_select(&self) -> u8 {
+		self.low & CKSEL
+	}
+
+	pub fn set_clock_select(&mut self, cksel: u8) {
+		self.low = (self.low & !CKSEL) | (cksel & CKSEL);
+	}
+
+	/// The system clock starts divided by 8 when CKDIV8 is programmed
+	pub fn ckdiv8(&self) -> bool {
+		bit(self.low, CKDIV8) == 0
+	}
+
+	pub fn set_ckdiv8(&mut self, divide: bool) {
+		if divide {
+			self.low &= !(1 << CKDIV8);
+		} else {
+			self.low |= 1 << CKDIV8;
+		}
+	}
+
+	/// The watchdog is always on in system reset mode when WDTON is programmed
+	pub fn wdton(&self) -> bool {
+		bit(self.high, WDTON) == 0
+	}
+
+	/// EEPROM is preserved through chip erase when EESAVE is programmed
+	pub fn eesave(&self) -> bool {
+		bit(self.high, EESAVE) == 0
+	}
+
+	pub fn set_eesave(&mut self, preserve: bool) {
+		if preserve {
+			self.high &= !(1 << EESAVE);
+		} else {
+			self.high |= 1 << EESAVE;
+		}
+	}
+}
@@ -0,0 +1,526 @@
+use crate::cpu::Cpu;
+use crate::debugger::{WatchHit, WatchKind, WatchSpace, Watchpoint};
+use crate::memory::{Memory, EEPROM_SIZE, RAMEND};
+use crate::symbols::DATA_OFFSET;
+use crate::system::EEPROM_IMAGE_OFFSET;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Byte address one past the end of flash
+const FLASH_END: u32 = 0x8000;
+
+/// General purpose registers, SREG, SP and PC
+const REGISTER_COUNT: usize = 35;
+const SREG_REGISTER: usize = 32;
+const SP_REGISTER: usize = 33;
+const PC_REGISTER: usize = 34;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// Instructions executed between checks for an interrupt from GDB
+const POLL_INTERVAL: usize = 1024;
+
+/// Stop reason reported to GDB, the inverse of the `Z2` to `Z4` packet types. Value
+/// change watchpoints have no GDB type, they are only set from the GUI.
+fn watch_reason(kind: WatchKind) -> Option<&'static str> {
+	match kind {
+		WatchKind::Write => Some("watch"),
+		WatchKind::Read => Some("rwatch"),
+		WatchKind::Access => Some("awatch"),
+		WatchKind::Change => None,
+	}
+}
+
+/// What the connection does after a packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+	Reply(String),
+	/// Run until something stops the target, a single instruction if `step` is set
+	Resume {
+		step: bool,
+	},
+	/// GDB detached or killed the target, the session ends
+	Close(String),
+}
+
+/// Breakpoints and packet handling of a GDB remote serial protocol session. The
+/// breakpoints are kept apart from the CPU's own, which still stop a resume, while
+/// watchpoints go to the CPU.
+#[derive(Debug, Clone, Default)]
+pub struct GdbServer {
+	/// Word addresses
+	breakpoints: BTreeSet<u16>,
+}
+
+impl GdbServer {
+	/// Answers a packet, `packet` is the unescaped contents between `$` and `#`
+	pub fn handle(&mut self, cpu: &mut Cpu, packet: &[u8]) -> Action {
+		let text = String::from_utf8_lossy(packet);
+		let reply = match packet.first() {
+			Some(b'?') => stop_reply(SIGTRAP),
+			Some(b'g') => hex(&registers(cpu)),
+			Some(b'G') => ok(decode_hex(&packet[1..]).and_then(|bytes| set_registers(cpu, &bytes))),
+			Some(b'p') => match parse_number(&text[1..]).and_then(|n| register(cpu, n as usize)) {
+				Some(bytes) => hex(&bytes),
+				None => error(),
+			},
+			Some(b'P') => ok(text[1..].split_once('=').and_then(|(index, value)| {
+				let index = parse_number(index)? as usize;
+				set_register(cpu, index, &decode_hex(value.as_bytes())?)
+			})),
+			Some(b'm') => {
+				match address_length(&text[1..]).and_then(|(a, l)| read_memory(cpu, a, l)) {
+					Some(bytes) => hex(&bytes),
+					None => error(),
+				}
+			}
+			Some(b'M') => ok(text[1..].split_once(':').and_then(|(range, data)| {
+				let (address, _) = address_length(range)?;
+				write_memory(cpu, address, &decode_hex(data.as_bytes())?)
+			})),
+			Some(b'X') => ok(packet
+				.iter()
+				.position(|byte| *byte == b':')
+				.and_then(|colon| {
+					let (address, _) = address_length(&text[1..colon])?;
+					write_memory(cpu, address, &packet[colon + 1..])
+				})),
+			Some(b'Z') => self.insert(cpu, &text[1..]),
+			Some(b'z') => self.remove(cpu, &text[1..]),
+			Some(b'c') | Some(b's') => {
+				if let Some(address) = parse_number(&text[1..]) {
+					cpu.pc = (address / 2) as u16;
+				}
+				return Action::Resume {
+					step: packet[0] == b's',
+				};
+			}
+			Some(b'D') => return Action::Close("OK".to_string()),
+			Some(b'k') => return Action::Close(String::new()),
+			Some(b'H') => "OK".to_string(),
+			Some(b'q') => query(&text),
+			_ => String::new(),
+		};
+		Action::Reply(reply)
+	}
+
+	/// Steps `cpu` until a breakpoint, watchpoint, `break` instruction or firmware exit,
+	/// or `interrupted` returns true, and gives the stop reply
+	pub fn resume(
+		&mut self,
+		cpu: &mut Cpu,
+		step: bool,
+		mut interrupted: impl FnMut() -> bool,
+	) -> String {
+		cpu.breakpoint_hit = false;
+		cpu.watch_hit = None;
+		if !step && cpu.check_entry_breakpoint() {
+			cpu.breakpoint_hit = false;
+			return stop_reply(SIGTRAP);
+		}
+
+		let mut count: usize = 0;
+		loop {
+			count += 1;
+			cpu.step();
+
+			if let Some(code) = cpu.peripherals.debug.exit_code() {
+				return format!("W{:02x}", code);
+			}
+			if cpu.break_hit {
+				cpu.break_hit = false;
+				return stop_reply(SIGTRAP);
+			}
+			if let Some(hit) = cpu.watch_hit.take() {
+				return watch_reply(&hit);
+			}
+			if step || cpu.breakpoint_hit || self.breakpoints.contains(&cpu.pc) {
+				cpu.breakpoint_hit = false;
+				return stop_reply(SIGTRAP);
+			}
+			if count.is_multiple_of(POLL_INTERVAL) && interrupted() {
+				return stop_reply(SIGINT);
+			}
+		}
+	}
+
+	/// `Z` packets, `type,address,kind`
+	fn insert(&mut self, cpu: &mut Cpu, arguments: &str) -> String {
+		match self.point(arguments) {
+			Some(Point::Breakpoint(address)) => {
+				self.breakpoints.insert(address);
+				"OK".to_string()
+			}
+			Some(Point::Watchpoint(watch)) => {
+				cpu.watchpoints.insert(watch);
+				"OK".to_string()
+			}
+			None => String::new(),
+		}
+	}
+
+	fn remove(&mut self, cpu: &mut Cpu, arguments: &str) -> String {
+		match self.point(arguments) {
+			Some(Point::Breakpoint(address)) => {
+				self.breakpoints.remove(&address);
+				"OK".to_string()
+			}
+			Some(Point::Watchpoint(watch)) => {
+				cpu.watchpoints.remove(&watch);
+				"OK".to_string()
+			}
+			None => String::new(),
+		}
+	}
+
+	/// Breakpoints go in flash and watchpoints in data space or the EEPROM, anything else
+	/// is unsupported
+	fn point(&self, arguments: &str) -> Option<Point> {
+		let mut fields = arguments.split(',');
+		let kind = fields.next()?;
+		let address = parse_number(fields.next()?)?;
+		let length = parse_number(fields.next()?)?;
+
+		let watch = |kind| {
+			let (space, offset) = if address >= EEPROM_IMAGE_OFFSET {
+				let offset = address - EEPROM_IMAGE_OFFSET;
+				(offset < EEPROM_SIZE as u32).then_some((WatchSpace::Eeprom, offset))?
+			} else {
+				let offset = address.checked_sub(DATA_OFFSET)?;
+				(offset <= RAMEND as u32).then_some((WatchSpace::Data, offset))?
+			};
+			let length = length.clamp(1, u16::MAX as u32) as u16;
+			Some(Point::Watchpoint(Watchpoint::new(
+				space,
+				offset as u16,
+				length,
+				kind,
+			)))
+		};
+
+		match kind {
+			"0" | "1" if address < FLASH_END => Some(Point::Breakpoint((address / 2) as u16)),
+			"2" => watch(WatchKind::Write),
+			"3" => watch(WatchKind::Read),
+			"4" => watch(WatchKind::Access),
+			_ => None,
+		}
+	}
+}
+
+enum Point {
+	Breakpoint(u16),
+	Watchpoint(Watchpoint),
+}
+
+fn watch_reply(hit: &WatchHit) -> String {
+	let Some(reason) = watch_reason(hit.kind) else {
+		return stop_reply(SIGTRAP);
+	};
+	let offset = match hit.space {
+		WatchSpace::Data => DATA_OFFSET,
+		WatchSpace::Eeprom => EEPROM_IMAGE_OFFSET,
+	};
+	let address = offset + hit.access.address as u32;
+	format!("T{:02x}{}:{:x};", SIGTRAP, reason, address)
+}
+
+fn query(text: &str) -> String {
+	let name = text.split([':', ',']).next().unwrap_or_default();
+	match name {
+		"qSupported" => "PacketSize=4000;QStartNoAckMode+".to_string(),
+		"qAttached" => "1".to_string(),
+		"qfThreadInfo" => "m1".to_string(),
+		"qsThreadInfo" => "l".to_string(),
+		"qC" => "QC1".to_string(),
+		"qSymbol" => "OK".to_string(),
+		_ => String::new(),
+	}
+}
+
+fn stop_reply(signal: u8) -> String {
+	format!("S{:02x}", signal)
+}
+
+fn ok(result: Option<()>) -> String {
+	match result {
+		Some(()) => "OK".to_string(),
+		None => error(),
+	}
+}
+
+fn error() -> String {
+	"E01".to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &[u8]) -> Option<Vec<u8>> {
+	if !text.len().is_multiple_of(2) {
+		return None;
+	}
+	text.chunks(2)
+		.map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+		.collect()
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+	u32::from_str_radix(text, 16).ok()
+}
+
+/// `address,length` of memory packets
+fn address_length(text: &str) -> Option<(u32, u32)> {
+	let (address, length) = text.split_once(',')?;
+	Some((parse_number(address)?, parse_number(length)?))
+}
+
+/// The avr-gdb register file, SP is 2 bytes and PC a 4 byte byte address
+fn registers(cpu: &mut Cpu) -> Vec<u8> {
+	(0..REGISTER_COUNT)
+		.flat_map(|index| register(cpu, index).unwrap_or_default())
+		.collect()
+}
+
+fn register(cpu: &mut Cpu, index: usize) -> Option<Vec<u8>> {
+	match index {
+		0..=31 => Some(vec![cpu.sram.registers[index]]),
+		SREG_REGISTER => Some(vec![cpu.status.byte()]),
+		SP_REGISTER => Some(cpu.sp.to_le_bytes().to_vec()),
+		PC_REGISTER => Some((cpu.pc as u32 * 2).to_le_bytes().to_vec()),
+		_ => None,
+	}
+}
+
+fn set_registers(cpu: &mut Cpu, bytes: &[u8]) -> Option<()> {
+	let mut offset = 0;
+	for index in 0..REGISTER_COUNT {
+		let size = register(cpu, index)?.len();
+		set_register(cpu, index, bytes.get(offset..offset + size)?)?;
+		offset += size;
+	}
+	Some(())
+}
+
+fn set_register(cpu: &mut Cpu, index: usize, bytes: &[u8]) -> Option<()> {
+	let value = bytes
+		.iter()
+		.rev()
+		.fold(0u32, |value, byte| value << 8 | *byte as u32);
+	match index {
+		0..=31 => cpu.sram.registers[index] = value as u8,
+		SREG_REGISTER => cpu.status.set_byte(value as u8),
+		SP_REGISTER => cpu.sp = value as u16,
+		PC_REGISTER => cpu.pc = (value / 2) as u16,
+		_ => return None,
+	}
+	Some(())
+}
+
+/// Flash from zero, data space from 0x800000 and the EEPROM from 0x810000
+fn read_memory(cpu: &mut Cpu, address: u32, length: u32) -> Option<Vec<u8>> {
+	let bytes: Vec<u8> = (0..length)
+		.map_while(|offset| read_byte(cpu, address.checked_add(offset)?))
+		.collect();
+	// GDB retries shorter reads, only nothing at all is an error
+	(bytes.len() as u32 == length || !bytes.is_empty()).then_some(bytes)
+}
+
+fn read_byte(cpu: &mut Cpu, address: u32) -> Option<u8> {
+	if address < FLASH_END {
+		let word = cpu.system.program_memory.read((address / 2) as u16);
+		Some(word.to_le_bytes()[address as usize % 2])
+	} else if let Some(offset) = data_offset(address) {
+		Some(cpu.peek_data(offset))
+	} else {
+		eeprom_offset(address).map(|offset| cpu.system.eeprom_memory.read(offset) as u8)
+	}
+}
+
+fn write_memory(cpu: &mut Cpu, address: u32, bytes: &[u8]) -> Option<()> {
+	for (offset, byte) in bytes.iter().enumerate() {
+		let address = address.checked_add(offset as u32)?;
+		if address < FLASH_END {
+			let word_address = (address / 2) as u16;
+			let mut word = cpu.system.program_memory.read(word_address).to_le_bytes();
+			word[address as usize % 2] = *byte;
+			cpu.system
+				.program_memory
+				.write(word_address, u16::from_le_bytes(word));
+		} else if let Some(offset) = data_offset(address) {
+			cpu.poke_data(offset, *byte);
+		} else {
+			let offset = eeprom_offset(address)?;
+			cpu.system.eeprom_memory.write(offset, *byte as u16);
+		}
+	}
+	Some(())
+}
+
+fn data_offset(address: u32) -> Option<u16> {
+	address
+		.checked_sub(DATA_OFFSET)
+		.filter(|offset| *offset <= RAMEND as u32)
+		.map(|offset| offset as u16)
+}
+
+fn eeprom_offset(address: u32) -> Option<u16> {
+	address
+		.checked_sub(EEPROM_IMAGE_OFFSET)
+		.filter(|offset| *offset < EEPROM_SIZE as u32)
+		.map(|offset| offset as u16)
+}
+
+enum Packet {
+	Data(Vec<u8>),
+	/// Ctrl-C sent while the target runs
+	Interrupt,
+}
+
+/// Packet framing over a TCP stream
+struct Connection {
+	stream: TcpStream,
+	buffer: Vec<u8>,
+	acknowledge: bool,
+}
+
+impl Connection {
+	/// Takes the next complete packet out of the buffer, acknowledging it
+	fn take_packet(&mut self) -> io::Result<Option<Packet>> {
+		loop {
+			match self.buffer.first() {
+				None => return Ok(None),
+				Some(0x03) => {
+					self.buffer.remove(0);
+					return Ok(Some(Packet::Interrupt));
+				}
+				Some(b'$') => break,
+				// acknowledgements and line noise
+				Some(_) => {
+					self.buffer.remove(0);
+				}
+			}
+		}
+
+		let end = match self.buffer.iter().position(|byte| *byte == b'#') {
+			Some(end) if self.buffer.len() >= end + 3 => end,
+			_ => return Ok(None),
+		};
+		let frame: Vec<u8> = self.buffer.drain(..end + 3).collect();
+		let data = &frame[1..end];
+
+		let checksum = std::str::from_utf8(&frame[end + 1..])
+			.ok()
+			.and_then(|text| u8::from_str_radix(text, 16).ok());
+		let valid = checksum == Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+		if self.acknowledge {
+			self.stream.write_all(if valid { b"+" } else { b"-" })?;
+		}
+		if !valid {
+			return Ok(None);
+		}
+
+		// `}` escapes the next byte, xored with 0x20
+		let mut packet = Vec::with_capacity(data.len());
+		let mut bytes = data.iter();
+		while let Some(byte) = bytes.next() {
+			match byte {
+				b'}' => packet.extend(bytes.next().map(|byte| byte ^ 0x20)),
+				_ => packet.push(*byte),
+			}
+		}
+		Ok(Some(Packet::Data(packet)))
+	}
+
+	/// Blocks until a packet arrives, `None` once GDB hung up
+	fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+		loop {
+			if let Some(packet) = self.take_packet()? {
+				return Ok(Some(packet));
+			}
+			let mut chunk = [0; 1024];
+			let count = self.stream.read(&mut chunk)?;
+			if count == 0 {
+				return Ok(None);
+			}
+			self.buffer.extend_from_slice(&chunk[..count]);
+		}
+	}
+
+	/// Whether GDB asked to stop the running target, without waiting
+	fn interrupted(&mut self) -> bool {
+		let mut chunk = [0; 64];
+		let _ = self.stream.set_nonblocking(true);
+		if let Ok(count) = self.stream.read(&mut chunk) {
+			self.buffer.extend_from_slice(&chunk[..count]);
+		}
+		let _ = self.stream.set_nonblocking(false);
+
+		match self.buffer.iter().position(|byte| *byte == 0x03) {
+			Some(index) => {
+				self.buffer.remove(index);
+				true
+			}
+			None => false,
+		}
+	}
+
+	fn send(&mut self, reply: &str) -> io::Result<()> {
+		let checksum = reply.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+		write!(self.stream, "${}#{:02x}", reply, checksum)?;
+		self.stream.flush()
+	}
+}
+
+/// Serves one GDB session on an accepted connection until GDB detaches or hangs up
+pub fn serve_connection(cpu: &mut Cpu, stream: TcpStream) -> io::Result<()> {
+	stream.set_nodelay(true)?;
+	let mut connection = Connection {
+		stream,
+		buffer: Vec::new(),
+		acknowledge: true,
+	};
+	let mut server = GdbServer::default();
+
+	while let Some(packet) = connection.read_packet()? {
+		let packet = match packet {
+			Packet::Data(packet) => packet,
+			// the target is already stopped
+			Packet::Interrupt => {
+				connection.send(&stop_reply(SIGINT))?;
+				continue;
+			}
+		};
+
+		if packet == b"QStartNoAckMode" {
+			connection.send("OK")?;
+			connection.acknowledge = false;
+			continue;
+		}
+
+		match server.handle(cpu, &packet) {
+			Action::Reply(reply) => connection.send(&reply)?,
+			Action::Resume { step } => {
+				let reply = server.resume(cpu, step, || connection.interrupted());
+				connection.send(&reply)?;
+			}
+			Action::Close(reply) => {
+				if !reply.is_empty() {
+					connection.send(&reply)?;
+				}
+				break;
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Waits for GDB on a local TCP port and serves a single session
+pub fn serve(cpu: &mut Cpu, port: u16) -> io::Result<()> {
+	let listener = TcpListener::bind(("127.0.0.1", port))?;
+	let (stream, _) = listener.accept()?;
+	serve_connection(cpu, stream)
+}
@@ -1,27 +1,152 @@
+use crate::debugger::{Breakpoints, Condition};
 use crate::disassembler::Instruction;
+use crate::symbols::SymbolTable;
 use egui_extras::{Column, TableBuilder};
 use std::collections::BTreeMap;
 
+/// Matches listed below the search box
+const MAX_MATCHES: usize = 8;
+
 #[derive(Default)]
-pub struct AssemblyView {}
+pub struct AssemblyView {
+	search: String,
+	/// Address to scroll to on the next frame
+	jump_to: Option<u16>,
+	/// Breakpoint whose condition is being edited, with the text typed so far
+	editing: Option<(u16, String)>,
+	condition_error: Option<String>,
+}
 
 impl AssemblyView {
+	fn search_ui(&mut self, ui: &mut egui::Ui, symbols: &SymbolTable) {
+		ui.horizontal(|ui| {
+			ui.label("Symbol:");
+			let response = ui.text_edit_singleline(&mut self.search);
+			if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+				if let Some(label) = symbols.search(&self.search).next() {
+					self.jump_to = Some(label.address);
+				}
+			}
+		});
+
+		if self.search.is_empty() {
+			return;
+		}
+
+		ui.horizontal_wrapped(|ui| {
+			for label in symbols.search(&self.search).take(MAX_MATCHES) {
+				if ui.small_button(&label.name).clicked() {
+					self.jump_to = Some(label.address);
+				}
+			}
+		});
+	}
+
+	/// Gutter of an instruction row, clicking toggles a breakpoint and right clicking
+	/// edits it
+	fn breakpoint_ui(&mut self, ui: &mut egui::Ui, breakpoints: &mut Breakpoints, address: u16) {
+		let marker = match breakpoints.get(address) {
+			Some(breakpoint) if breakpoint.enabled => {
+				egui::RichText::new("●").color(egui::Color32::RED)
+			}
+			Some(_) => egui::RichText::new("○").color(egui::Color32::RED),
+			None => egui::RichText::new("○").weak(),
+		};
+
+		let response = ui.add(egui::Label::new(marker).sense(egui::Sense::click()));
+		if response.clicked() {
+			breakpoints.toggle(address);
+		}
+
+		let breakpoint = match breakpoints.get_mut(address) {
+			Some(breakpoint) => breakpoint,
+			None => return,
+		};
+		response.context_menu(|ui| {
+			if self.editing.as_ref().map(|(editing, _)| *editing) != Some(address) {
+				let text = breakpoint
+					.condition
+					.as_ref()
+					.map(ToString::to_string)
+					.unwrap_or_default();
+				self.editing = Some((address, text));
+				self.condition_error = None;
+			}
+
+			ui.checkbox(&mut breakpoint.enabled, "Enabled");
+			ui.horizontal(|ui| {
+				ui.label("Condition:");
+				if let Some((_, text)) = &mut self.editing {
+					let response = ui.text_edit_singleline(text);
+					if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+						let text = text.trim();
+						match Condition::parse(text) {
+							_ if text.is_empty() => breakpoint.condition = None,
+							Ok(condition) => breakpoint.condition = Some(condition),
+							Err(error) => self.condition_error = Some(error),
+						}
+					}
+				}
+			});
+			if let Some(error) = &self.condition_error {
+				ui.colored_label(egui::Color32::LIGHT_RED, error);
+			}
+			ui.horizontal(|ui| {
+				ui.label("Ignore count:");
+				ui.add(egui::DragValue::new(&mut breakpoint.ignore_count));
+			});
+			ui.horizontal(|ui| {
+				ui.label(format!("Hits: {}", breakpoint.hits));
+				if ui.small_button("Reset").clicked() {
+					breakpoint.hits = 0;
+				}
+			});
+		});
+	}
+
 	pub fn ui(
 		&mut self,
 		ui: &mut egui::Ui,
 		assembly: &BTreeMap<u16, Instruction>,
+		symbols: &SymbolTable,
+		breakpoints: &mut Breakpoints,
 		program_counter: &u16,
 	) {
-		let table = TableBuilder::new(ui)
+		if !symbols.is_empty() {
+			self.search_ui(ui, symbols);
+			ui.separator();
+		}
+
+		// a function label takes a row of its own above the instruction
+		let mut rows = Vec::new();
+		for instruction in assembly.values() {
+			if instruction.label.is_some() {
+				rows.push((instruction, true));
+			}
+			rows.push((instruction, false));
+		}
+
+		let mut table = TableBuilder::new(ui)
 			.striped(true)
 			.cell_layout(egui::Layout::left_to_right(egui::Align::LEFT))
+			.column(Column::exact(16.0))
 			.column(Column::exact(60.0))
 			.column(Column::exact(60.0))
 			.column(Column::remainder())
 			.resizable(false);
 
+		if let Some(address) = self.jump_to.take() {
+			if let Some(row) = rows
+				.iter()
+				.position(|(instruction, _)| instruction.address >= address)
+			{
+				table = table.scroll_to_row(row, Some(egui::Align::TOP));
+			}
+		}
+
 		table
 			.header(20.0, |mut header| {
+				header.col(|_| {});
 				header.col(|ui| {
 					ui.label("Address");
 				});
@@ -32,25 +157,38 @@ impl AssemblyView {
 					ui.label("Instruction");
 				});
 			})
-			.body(|mut body| {
-				for (_, instruction) in assembly {
-					body.row(18.0, |mut row| {
-						row.col(|ui| {
-							if &instruction.address == program_counter {
-								ui.code(format!("0x{:04X}", &instruction.address));
-							} else {
-								ui.label(format!("0x{:04X}", &instruction.address));
-							}
-						});
+			.body(|body| {
+				body.rows(18.0, rows.len(), |index, mut row| {
+					let (instruction, is_label) = rows[index];
+					if is_label {
+						row.col(|_| {});
+						row.col(|_| {});
+						row.col(|_| {});
 						row.col(|ui| {
-							ui.label(format!("0x{:04X}", &instruction.opcode));
-						});
-						row.col(|ui| {
-							ui.colored_label(egui::Color32::LIGHT_RED, &instruction.instruction);
-							ui.label(&instruction.operands);
+							let label = instruction.label.as_deref().unwrap_or_default();
+							ui.strong(format!("<{}>:", label));
 						});
+						return;
+					}
+
+					row.col(|ui| {
+						self.breakpoint_ui(ui, breakpoints, instruction.address);
 					});
-				}
+					row.col(|ui| {
+						if &instruction.address == program_counter {
+							ui.code(format!("0x{:04X}", &instruction.address));
+						} else {
+							ui.label(format!("0x{:04X}", &instruction.address));
+						}
+					});
+					row.col(|ui| {
+						ui.label(format!("0x{:04X}", &instruction.opcode));
+					});
+					row.col(|ui| {
+						ui.colored_label(egui::Color32::LIGHT_RED, &instruction.instruction);
+						ui.label(&instruction.operands);
+					});
+				});
 			});
 	}
 }
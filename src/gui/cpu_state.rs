@@ -1,6 +1,7 @@
 use crate::{
 	cpu::Cpu,
 	memory::{Memory, REGISTER_NAMES},
+	peripherals::power::Module,
 };
 use egui_extras::{Column, TableBuilder};
 
@@ -14,6 +15,26 @@ fn status_color(set: bool) -> egui::Color32 {
 	}
 }
 
+fn format_frequency(hertz: f64) -> String {
+	if hertz >= 1e6 {
+		format!("{} MHz", hertz / 1e6)
+	} else if hertz >= 1e3 {
+		format!("{} kHz", hertz / 1e3)
+	} else {
+		format!("{} Hz", hertz)
+	}
+}
+
+fn format_time(seconds: f64) -> String {
+	if seconds >= 1.0 {
+		format!("{:.6} s", seconds)
+	} else if seconds >= 1e-3 {
+		format!("{:.3} ms", seconds * 1e3)
+	} else {
+		format!("{:.3} µs", seconds * 1e6)
+	}
+}
+
 #[derive(PartialEq, Eq)]
 enum Tab {
 	Registers,
@@ -25,7 +46,7 @@ enum Tab {
 struct RegisterTab {}
 
 impl RegisterTab {
-	fn ui(&mut self, ui: &mut egui::Ui, registers: &Vec<u8>, start_index: u8, subract: u8) {
+	fn ui(&mut self, ui: &mut egui::Ui, registers: &[u8], start_index: u8, subract: u8) {
 		let table = TableBuilder::new(ui)
 			.striped(true)
 			.cell_layout(egui::Layout::left_to_right(egui::Align::LEFT))
@@ -77,6 +98,8 @@ impl RegisterTab {
 pub struct CpuState {
 	selected_tab: Tab,
 	register_tab: RegisterTab,
+	/// Emulated time when the stop watch was last cleared
+	stop_watch_start: f64,
 }
 
 impl Default for CpuState {
@@ -84,6 +107,7 @@ impl Default for CpuState {
 		Self {
 			selected_tab: Tab::Registers,
 			register_tab: RegisterTab::default(),
+			stop_watch_start: 0.0,
 		}
 	}
 }
@@ -157,10 +181,33 @@ impl CpuState {
 				ui.end_row();
 
 				ui.label("Frequency:");
+				ui.label(format_frequency(cpu.peripherals.clock.frequency()));
 
 				ui.end_row();
 
+				let seconds = cpu.peripherals.clock.seconds();
+				if seconds < self.stop_watch_start {
+					// the CPU was reset
+					self.stop_watch_start = 0.0;
+				}
+
 				ui.label("Stop Watch:");
+				ui.horizontal(|ui| {
+					ui.label(format_time(seconds - self.stop_watch_start));
+					if ui.small_button("Clear").clicked() {
+						self.stop_watch_start = seconds;
+					}
+				});
+
+				ui.end_row();
+
+				ui.label("Power:");
+				ui.horizontal(|ui| {
+					for module in Module::ALL {
+						let powered = cpu.peripherals.power.is_powered(&mut cpu.sram, module);
+						ui.colored_label(status_color(powered), module.name());
+					}
+				});
 			});
 
 		ui.separator();
@@ -1,4 +1,9 @@
-use crate::{cpu::Cpu, memory::Memory};
+use super::profiler_view::ProfilerView;
+use crate::{
+	cpu::Cpu,
+	debugger::{WatchKind, WatchSpace, Watchpoint, Watchpoints},
+	memory::Memory,
+};
 use std::ops::Range;
 
 const PADDING_SIZE: f32 = 4.0;
@@ -8,6 +13,8 @@ enum Tab {
 	ProgramFlash,
 	DataMemory,
 	Eeprom,
+	Watchpoints,
+	Profiler,
 }
 
 struct MemoryTab {
@@ -30,7 +37,7 @@ impl MemoryTab {
 		let row_height = ui.text_style_height(&text_style);
 
 		let address_range = memory.address_range().clone();
-		let total_rows = (address_range.len() + self.column_count - 1) / self.column_count;
+		let total_rows = address_range.len().div_ceil(self.column_count);
 
 		scroll.show_rows(ui, row_height, total_rows, |ui, row_range| {
 			egui::Grid::new("memory_dump").striped(true).show(ui, |ui| {
@@ -113,9 +120,111 @@ impl MemoryTab {
 	}
 }
 
+/// Adds watchpoints by address or I/O register name and lists the ones set
+struct WatchpointTab {
+	space: WatchSpace,
+	/// Hexadecimal address, or a register name in data space
+	address: String,
+	length: u16,
+	kind: WatchKind,
+	error: Option<String>,
+}
+
+impl Default for WatchpointTab {
+	fn default() -> Self {
+		Self {
+			space: WatchSpace::Data,
+			address: String::new(),
+			length: 1,
+			kind: WatchKind::Write,
+			error: None,
+		}
+	}
+}
+
+impl WatchpointTab {
+	fn ui(&mut self, ui: &mut egui::Ui, watchpoints: &mut Watchpoints) {
+		ui.horizontal(|ui| {
+			egui::ComboBox::from_id_source("watch_space")
+				.selected_text(self.space.to_string())
+				.show_ui(ui, |ui| {
+					for space in [WatchSpace::Data, WatchSpace::Eeprom] {
+						ui.selectable_value(&mut self.space, space, space.to_string());
+					}
+				});
+			ui.add(
+				egui::TextEdit::singleline(&mut self.address)
+					.hint_text("0x0100 or PORTB")
+					.desired_width(120.0),
+			);
+			ui.label("Length");
+			ui.add(egui::DragValue::new(&mut self.length).clamp_range(1..=u16::MAX));
+			egui::ComboBox::from_id_source("watch_kind")
+				.selected_text(self.kind.to_string())
+				.show_ui(ui, |ui| {
+					for kind in WatchKind::ALL {
+						ui.selectable_value(&mut self.kind, kind, kind.to_string());
+					}
+				});
+			if ui.button("Add").clicked() {
+				match self.watchpoint() {
+					Ok(watchpoint) => {
+						watchpoints.insert(watchpoint);
+						self.address.clear();
+						self.error = None;
+					}
+					Err(error) => self.error = Some(error),
+				}
+			}
+			if let Some(error) = &self.error {
+				ui.colored_label(egui::Color32::RED, error);
+			}
+		});
+
+		ui.separator();
+
+		let mut removed = None;
+		egui::Grid::new("watchpoints").striped(true).show(ui, |ui| {
+			for watchpoint in watchpoints.iter_mut() {
+				ui.checkbox(&mut watchpoint.enabled, "");
+				ui.label(format!(
+					"{} 0x{:04X}..0x{:04X}",
+					watchpoint.space,
+					watchpoint.address,
+					watchpoint.address as u32 + watchpoint.length as u32
+				));
+				ui.label(watchpoint.kind.to_string());
+				if ui.button("Remove").clicked() {
+					removed = Some(watchpoint.clone());
+				}
+				ui.end_row();
+			}
+		});
+		if let Some(watchpoint) = removed {
+			watchpoints.remove(&watchpoint);
+		}
+	}
+
+	fn watchpoint(&self) -> Result<Watchpoint, String> {
+		let text = self.address.trim();
+		if self.space == WatchSpace::Data {
+			if let Some(watchpoint) = Watchpoint::register(text, self.kind) {
+				return Ok(watchpoint);
+			}
+		}
+		let digits = text.trim_start_matches("0x");
+		match u16::from_str_radix(digits, 16) {
+			Ok(address) => Ok(Watchpoint::new(self.space, address, self.length, self.kind)),
+			Err(_) => Err(format!("Unknown address: {}", text)),
+		}
+	}
+}
+
 pub struct MemoryView {
 	selected_tab: Tab,
 	memory_tab: MemoryTab,
+	watchpoint_tab: WatchpointTab,
+	profiler_view: ProfilerView,
 }
 
 impl Default for MemoryView {
@@ -123,6 +232,8 @@ impl Default for MemoryView {
 		Self {
 			selected_tab: Tab::ProgramFlash,
 			memory_tab: MemoryTab::default(),
+			watchpoint_tab: WatchpointTab::default(),
+			profiler_view: ProfilerView::default(),
 		}
 	}
 }
@@ -134,6 +245,8 @@ impl MemoryView {
 			ui.selectable_value(&mut self.selected_tab, Tab::ProgramFlash, "Program Flash");
 			ui.selectable_value(&mut self.selected_tab, Tab::DataMemory, "Data Memory");
 			ui.selectable_value(&mut self.selected_tab, Tab::Eeprom, "EEPROM");
+			ui.selectable_value(&mut self.selected_tab, Tab::Watchpoints, "Watchpoints");
+			ui.selectable_value(&mut self.selected_tab, Tab::Profiler, "Profiler");
 		});
 
 		ui.separator();
@@ -148,6 +261,12 @@ impl MemoryView {
 			Tab::Eeprom => {
 				self.memory_tab.ui(ui, &mut cpu.system.eeprom_memory);
 			}
+			Tab::Watchpoints => {
+				self.watchpoint_tab.ui(ui, &mut cpu.watchpoints);
+			}
+			Tab::Profiler => {
+				self.profiler_view.ui(ui, cpu);
+			}
 		}
 	}
 }
@@ -1,27 +1,49 @@
 use std::path::PathBuf;
 
-use crate::system::System;
+use crate::cpu::Cpu;
+use crate::debugger::History;
+use crate::profiler::Profiler;
+use crate::snapshot;
 
-fn find_hex_files() -> glob::Paths {
+/// Save state slots offered in the State menu
+const STATE_SLOTS: usize = 4;
+
+/// Save state file of a slot, in the working directory so it can be shared
+fn slot_path(slot: usize) -> PathBuf {
+	PathBuf::from(format!("slot{}.state", slot))
+}
+
+fn find_program_files(pattern: &str) -> glob::Paths {
 	let exe_path = std::env::current_exe();
 	let programs_path = exe_path.unwrap().parent().unwrap().join("../../programs");
-	glob::glob(programs_path.join("**/*.hex").to_str().unwrap()).unwrap()
+	glob::glob(programs_path.join(pattern).to_str().unwrap()).unwrap()
 }
 
 pub struct MenuBar {
 	programs: Vec<PathBuf>,
+	eeprom_images: Vec<PathBuf>,
 }
 
 impl Default for MenuBar {
 	fn default() -> Self {
-		let programs = find_hex_files().map(|res| res.unwrap()).collect();
-		Self { programs }
+		let programs = find_program_files("**/*.hex")
+			.chain(find_program_files("**/*.elf"))
+			.map(|res| res.unwrap())
+			.collect();
+		let eeprom_images = find_program_files("**/*.eep")
+			.map(|res| res.unwrap())
+			.collect();
+		Self {
+			programs,
+			eeprom_images,
+		}
 	}
 }
 
 impl MenuBar {
-	pub fn ui(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame, system: &mut System) {
+	pub fn ui(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame, cpu: &mut Cpu) {
 		egui::menu::bar(ui, |ui| {
+			let system = &mut cpu.system;
 			egui::widgets::global_dark_light_mode_switch(ui);
 			ui.separator();
 
@@ -29,9 +51,100 @@ impl MenuBar {
 				for program_file in &self.programs {
 					let filename = program_file.file_name().unwrap().to_str().unwrap();
 					if ui.button(filename).clicked() {
-						system.flash_from_hex_file(program_file);
+						if let Err(error) = system.load_program(program_file) {
+							println!("Unable to load {}: {}", filename, error);
+						}
+					}
+				}
+			});
+
+			ui.menu_button("EEPROM", |ui| {
+				for image in &self.eeprom_images {
+					let filename = image.file_name().unwrap().to_str().unwrap();
+					if ui.button(filename).clicked() {
+						if let Err(error) = system.persist_eeprom(image.clone()) {
+							println!("Unable to load EEPROM: {}", error);
+						}
+					}
+				}
+				ui.separator();
+
+				if ui
+					.add_enabled(system.eeprom_file.is_some(), egui::Button::new("Save"))
+					.clicked()
+				{
+					if let Err(error) = system.save_persisted_eeprom() {
+						println!("Unable to save EEPROM: {}", error);
+					}
+				}
+
+				let mut eesave = system.fuses.eesave();
+				if ui.checkbox(&mut eesave, "Preserve on chip erase").changed() {
+					system.fuses.set_eesave(eesave);
+				}
+
+				if ui.button("Chip Erase").clicked() {
+					system.chip_erase();
+				}
+			});
+
+			ui.menu_button("Clock", |ui| {
+				ui.label("Fuses take effect on reset");
+				ui.separator();
+
+				let mut cksel = system.fuses.clock_select();
+				let sources = [
+					(0xF, "16 MHz crystal"),
+					(0x2, "8 MHz internal RC"),
+					(0x3, "128 kHz internal"),
+				];
+				for (value, name) in sources {
+					if ui.radio_value(&mut cksel, value, name).changed() {
+						system.fuses.set_clock_select(cksel);
 					}
 				}
+
+				let mut ckdiv8 = system.fuses.ckdiv8();
+				if ui.checkbox(&mut ckdiv8, "Divide by 8 (CKDIV8)").changed() {
+					system.fuses.set_ckdiv8(ckdiv8);
+				}
+			});
+
+			ui.menu_button("Debug", |ui| {
+				// recording lets the toolbar step back and scrub the timeline
+				let mut history = cpu.history.is_some();
+				if ui.checkbox(&mut history, "Record History").changed() {
+					cpu.history = history.then(History::default);
+				}
+
+				let mut profiling = cpu.profiler.is_some();
+				if ui.checkbox(&mut profiling, "Profile").changed() {
+					cpu.profiler = profiling.then(Profiler::default);
+				}
+			});
+
+			ui.menu_button("State", |ui| {
+				for slot in 1..=STATE_SLOTS {
+					let path = slot_path(slot);
+					ui.horizontal(|ui| {
+						ui.label(format!("Slot {}", slot));
+						if ui.button("Save State").clicked() {
+							if let Err(error) = snapshot::save_file(cpu, &path) {
+								println!("Unable to save {}: {}", path.display(), error);
+							}
+							ui.close_menu();
+						}
+						if ui
+							.add_enabled(path.exists(), egui::Button::new("Load State"))
+							.clicked()
+						{
+							if let Err(error) = snapshot::restore_file(cpu, &path) {
+								println!("Unable to load {}: {}", path.display(), error);
+							}
+							ui.close_menu();
+						}
+					});
+				}
 			});
 
 			if ui.button("Quit").clicked() {
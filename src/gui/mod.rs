@@ -2,14 +2,21 @@ mod assembly_view;
 mod cpu_state;
 mod memory_view;
 mod menu;
+mod profiler_view;
+mod source_view;
 
 use crate::cpu::Cpu;
+use crate::debugger::{History, SourceStep, StepKind};
 use assembly_view::AssemblyView;
 use cpu_state::CpuState;
 use eframe::egui;
 use egui::Sense;
 use memory_view::MemoryView;
 use menu::MenuBar;
+use source_view::SourceView;
+
+/// Instructions run per frame while stepping through source
+const STEP_BUDGET: usize = 10_000;
 
 #[derive(Default)]
 pub struct App {
@@ -18,7 +25,10 @@ pub struct App {
 	cpu_state: CpuState,
 	memory_view: MemoryView,
 	assembly_view: AssemblyView,
+	source_view: SourceView,
 	running: bool,
+	/// Source-level step still in progress
+	source_step: Option<SourceStep>,
 }
 
 impl App {
@@ -31,45 +41,129 @@ impl App {
 }
 
 impl eframe::App for App {
+	fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+		if let Err(error) = self.cpu.system.save_persisted_eeprom() {
+			println!("Unable to save EEPROM: {}", error);
+		}
+	}
+
 	fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
 		if self.running {
 			self.cpu.step();
+			// the firmware ended its run through the debug port
+			self.running = self.cpu.peripherals.debug.exit_code().is_none();
+			if self.cpu.breakpoint_hit {
+				self.cpu.breakpoint_hit = false;
+				self.running = false;
+			}
+			// the hit stays in place so the toolbar can report it
+			if self.cpu.watch_hit.is_some() {
+				self.running = false;
+			}
+			ctx.request_repaint();
+		}
+
+		if let Some(step) = &mut self.source_step {
+			let done = step.run(&mut self.cpu, STEP_BUDGET);
+			if done || self.cpu.peripherals.debug.exit_code().is_some() {
+				self.source_step = None;
+			}
 			ctx.request_repaint();
 		}
 
 		egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-			self.menu_bar.ui(ui, frame, &mut self.cpu.system);
+			self.menu_bar.ui(ui, frame, &mut self.cpu);
 		});
 
 		egui::TopBottomPanel::top("toolbar_panel").show(ctx, |ui| {
 			ui.horizontal_centered(|ui| {
-				if self.running {
+				let busy = self.running || self.source_step.is_some();
+				if busy {
 					if ui.button("Pause").clicked() {
 						self.running = false;
+						self.source_step = None;
 					}
 				} else if ui.button("Run").clicked() {
-					self.running = true;
+					self.cpu.breakpoint_hit = false;
+					self.cpu.watch_hit = None;
+					self.running = !self.cpu.check_entry_breakpoint();
+					self.cpu.breakpoint_hit = false;
 				}
 
-				let sense_type = if self.running {
-					Sense::hover()
-				} else {
-					Sense::click()
-				};
+				let sense_type = if busy { Sense::hover() } else { Sense::click() };
 
 				if ui
 					.add(egui::Button::new("Step").sense(sense_type))
 					.clicked()
 				{
+					self.cpu.watch_hit = None;
 					self.cpu.step();
 				}
 
+				let can_go_back =
+					!busy && (self.cpu.history.as_ref()).is_some_and(History::can_step_back);
+				let back_sense = if can_go_back {
+					Sense::click()
+				} else {
+					Sense::hover()
+				};
+				if ui
+					.add(egui::Button::new("Step Back").sense(back_sense))
+					.clicked()
+				{
+					self.cpu.watch_hit = None;
+					self.cpu.step_back();
+				}
+				if ui
+					.add(egui::Button::new("Reverse Continue").sense(back_sense))
+					.clicked()
+				{
+					self.cpu.watch_hit = None;
+					self.cpu.reverse_continue();
+				}
+
+				if !self.cpu.system.lines.is_empty() {
+					let commands = [
+						("Step Line", StepKind::Line),
+						("Step Over", StepKind::Over),
+						("Step Out", StepKind::Out),
+					];
+					for (name, kind) in commands {
+						if ui.add(egui::Button::new(name).sense(sense_type)).clicked() {
+							self.cpu.watch_hit = None;
+							self.source_step = Some(SourceStep::new(kind, &self.cpu));
+						}
+					}
+				}
+
 				if ui.button("Reset").clicked() {
 					self.cpu.reset();
 				}
+
+				if let Some(hit) = &self.cpu.watch_hit {
+					ui.colored_label(egui::Color32::RED, hit.to_string());
+				}
 			});
 		});
 
+		let timeline = (self.cpu.history.as_ref())
+			.map(|history| history.start_cycles(&self.cpu)..=history.end_cycles(&self.cpu));
+		if let Some(range) = timeline {
+			let busy = self.running || self.source_step.is_some();
+			egui::TopBottomPanel::top("timeline_panel").show(ctx, |ui| {
+				ui.horizontal(|ui| {
+					ui.label("Timeline");
+					ui.spacing_mut().slider_width = (ui.available_width() - 120.0).max(100.0);
+					let mut cycles = self.cpu.cycles;
+					let slider = egui::Slider::new(&mut cycles, range).suffix(" cycles");
+					if ui.add_enabled(!busy, slider).changed() {
+						self.cpu.watch_hit = None;
+						self.cpu.seek(cycles);
+					}
+				});
+			});
+		}
+
 		egui::SidePanel::right("cpu_state")
 			.resizable(false)
 			.show(ctx, |ui| {
@@ -83,10 +177,25 @@ impl eframe::App for App {
 				self.memory_view.ui(ui, &mut self.cpu);
 			});
 
+		if !self.cpu.system.lines.is_empty() {
+			egui::SidePanel::left("source_view")
+				.resizable(true)
+				.default_width(400.0)
+				.show(ctx, |ui| {
+					self.source_view.ui(ui, &self.cpu.system.lines, self.cpu.pc);
+				});
+		}
+
 		egui::CentralPanel::default().show(ctx, |ui| {
 			egui::warn_if_debug_build(ui);
 			if let Some(assembly) = &self.cpu.system.disassembler.assembly {
-				self.assembly_view.ui(ui, assembly, &self.cpu.pc);
+				self.assembly_view.ui(
+					ui,
+					assembly,
+					&self.cpu.system.symbols,
+					&mut self.cpu.breakpoints,
+					&self.cpu.pc,
+				);
 			}
 		});
 	}
@@ -0,0 +1,143 @@
+use crate::{cpu::Cpu, profiler::FunctionProfile};
+use egui_extras::{Column, TableBuilder};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Folded stacks file written by the export button, in the working directory
+const FOLDED_PATH: &str = "profile.folded";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+	Name,
+	Calls,
+	Inclusive,
+	Exclusive,
+}
+
+/// Table of the cycles spent in each function, sorted by clicking a column header
+pub struct ProfilerView {
+	sort_by: SortBy,
+	ascending: bool,
+	/// Outcome of the last export
+	message: Option<String>,
+}
+
+impl Default for ProfilerView {
+	fn default() -> Self {
+		Self {
+			sort_by: SortBy::Inclusive,
+			ascending: false,
+			message: None,
+		}
+	}
+}
+
+impl ProfilerView {
+	pub fn ui(&mut self, ui: &mut egui::Ui, cpu: &mut Cpu) {
+		let Some(profiler) = &mut cpu.profiler else {
+			ui.label("Profiling is off, turn it on in the Debug menu");
+			return;
+		};
+
+		ui.horizontal(|ui| {
+			if ui.button("Clear").clicked() {
+				profiler.clear();
+				self.message = None;
+			}
+			if ui.button("Export Folded Stacks").clicked() {
+				let result = File::create(FOLDED_PATH).and_then(|file| {
+					let mut output = BufWriter::new(file);
+					profiler.write_folded(&cpu.system.symbols, &mut output)?;
+					output.flush()
+				});
+				self.message = Some(match result {
+					Ok(()) => format!("Saved {}", FOLDED_PATH),
+					Err(error) => format!("Unable to save {}: {}", FOLDED_PATH, error),
+				});
+			}
+			if let Some(message) = &self.message {
+				ui.label(message);
+			}
+		});
+
+		ui.separator();
+
+		let total = profiler.total_cycles().max(1) as f64;
+		let mut functions = profiler.functions(&cpu.system.symbols);
+		self.sort(&mut functions);
+
+		let table = TableBuilder::new(ui)
+			.striped(true)
+			.cell_layout(egui::Layout::left_to_right(egui::Align::LEFT))
+			.column(Column::remainder().at_least(200.0))
+			.column(Column::exact(80.0))
+			.column(Column::exact(160.0))
+			.column(Column::exact(160.0))
+			.resizable(false);
+
+		table
+			.header(20.0, |mut header| {
+				let columns = [
+					(SortBy::Name, "Function"),
+					(SortBy::Calls, "Calls"),
+					(SortBy::Inclusive, "Inclusive"),
+					(SortBy::Exclusive, "Exclusive"),
+				];
+				for (column, title) in columns {
+					header.col(|ui| {
+						let title = match (self.sort_by == column, self.ascending) {
+							(true, true) => format!("{} ⏶", title),
+							(true, false) => format!("{} ⏷", title),
+							(false, _) => title.to_string(),
+						};
+						if ui.selectable_label(self.sort_by == column, title).clicked() {
+							// names start from A, numbers from the largest
+							self.ascending = match self.sort_by == column {
+								true => !self.ascending,
+								false => column == SortBy::Name,
+							};
+							self.sort_by = column;
+						}
+					});
+				}
+			})
+			.body(|mut body| {
+				for function in &functions {
+					body.row(18.0, |mut row| {
+						row.col(|ui| {
+							ui.label(&function.name);
+						});
+						row.col(|ui| {
+							ui.label(function.calls.to_string());
+						});
+						row.col(|ui| {
+							ui.label(format!(
+								"{}  ({:.1}%)",
+								function.inclusive,
+								function.inclusive as f64 / total * 100.0
+							));
+						});
+						row.col(|ui| {
+							ui.label(format!(
+								"{}  ({:.1}%)",
+								function.exclusive,
+								function.exclusive as f64 / total * 100.0
+							));
+						});
+					});
+				}
+			});
+	}
+
+	fn sort(&self, functions: &mut [FunctionProfile]) {
+		match self.sort_by {
+			SortBy::Name => functions.sort_by(|a, b| a.name.cmp(&b.name)),
+			SortBy::Calls => functions.sort_by_key(|function| function.calls),
+			SortBy::Inclusive => functions.sort_by_key(|function| function.inclusive),
+			SortBy::Exclusive => functions.sort_by_key(|function| function.exclusive),
+		}
+		if !self.ascending {
+			functions.reverse();
+		}
+	}
+}
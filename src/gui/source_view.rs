@@ -0,0 +1,68 @@
+use crate::dwarf::{LineTable, Location};
+use egui_extras::{Column, TableBuilder};
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct SourceView {
+	/// Lines of the files read so far, `None` for files that could not be read
+	files: HashMap<String, Option<Vec<String>>>,
+	/// Location last scrolled to, the view only follows the program counter when it moves
+	shown: Option<Location>,
+}
+
+impl SourceView {
+	pub fn ui(&mut self, ui: &mut egui::Ui, lines: &LineTable, program_counter: u16) {
+		let location = match lines.location(program_counter) {
+			Some(location) => location,
+			None => {
+				ui.label("No source line for this address");
+				return;
+			}
+		};
+		let path = lines.file(location.file).unwrap_or_default();
+		ui.strong(format!("{}:{}", path, location.line));
+		ui.separator();
+
+		let text = self.files.entry(path.to_string()).or_insert_with(|| {
+			std::fs::read_to_string(path)
+				.ok()
+				.map(|text| text.lines().map(String::from).collect())
+		});
+		let text = match text {
+			Some(text) => text,
+			None => {
+				ui.label("Unable to read the source file");
+				return;
+			}
+		};
+
+		let mut table = TableBuilder::new(ui)
+			.striped(true)
+			.cell_layout(egui::Layout::left_to_right(egui::Align::LEFT))
+			.column(Column::exact(40.0))
+			.column(Column::remainder())
+			.resizable(false);
+
+		if self.shown != Some(location) {
+			self.shown = Some(location);
+			let row = (location.line as usize).saturating_sub(1);
+			table = table.scroll_to_row(row, Some(egui::Align::Center));
+		}
+
+		table.body(|body| {
+			body.rows(18.0, text.len(), |index, mut row| {
+				let current = index + 1 == location.line as usize;
+				row.col(|ui| {
+					if current {
+						ui.code(format!("{}", index + 1));
+					} else {
+						ui.label(format!("{}", index + 1));
+					}
+				});
+				row.col(|ui| {
+					ui.monospace(&text[index]);
+				});
+			});
+		});
+	}
+}
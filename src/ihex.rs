@@ -0,0 +1,220 @@
+use std::fmt;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Bytes per data record when writing, matching avr-objcopy
+const RECORD_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+	MissingStartCode,
+	/// Shorter than the smallest record or an odd number of digits
+	Truncated,
+	InvalidDigit,
+	/// The byte count disagrees with the data in the record
+	LengthMismatch,
+	Checksum {
+		expected: u8,
+		found: u8,
+	},
+	UnknownRecordType(u8),
+	/// An address or end of file record with the wrong byte count
+	InvalidRecordLength(u8),
+	MissingEndOfFile,
+}
+
+/// Malformed Intel HEX input, `line` counts from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+	pub line: usize,
+	pub kind: ErrorKind,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: ", self.line)?;
+		match &self.kind {
+			ErrorKind::MissingStartCode => write!(f, "missing start code"),
+			ErrorKind::Truncated => write!(f, "truncated record"),
+			ErrorKind::InvalidDigit => write!(f, "invalid hex digit"),
+			ErrorKind::LengthMismatch => write!(f, "length does not match data"),
+			ErrorKind::Checksum { expected, found } => write!(
+				f,
+				"checksum mismatch, expected 0x{:02X} but found 0x{:02X}",
+				expected, found
+			),
+			ErrorKind::UnknownRecordType(record_type) => {
+				write!(f, "unknown record type 0x{:02X}", record_type)
+			}
+			ErrorKind::InvalidRecordLength(record_type) => {
+				write!(f, "wrong length for record type 0x{:02X}", record_type)
+			}
+			ErrorKind::MissingEndOfFile => write!(f, "missing end of file record"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for std::io::Error {
+	fn from(error: Error) -> Self {
+		std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+	}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Contents of an Intel HEX file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+	/// `(address, byte)` pairs in file order
+	pub data: Vec<(u32, u8)>,
+	/// Entry point from a start segment (CS:IP) or start linear address record
+	pub start_address: Option<u32>,
+}
+
+fn parse_record(line: &str, number: usize) -> Result<Vec<u8>> {
+	let error = |kind| Error { line: number, kind };
+
+	let hex = line
+		.strip_prefix(':')
+		.ok_or_else(|| error(ErrorKind::MissingStartCode))?;
+
+	// digits are sliced by byte below
+	if !hex.is_ascii() {
+		return Err(error(ErrorKind::InvalidDigit));
+	}
+	if hex.len() % 2 != 0 || hex.len() < 10 {
+		return Err(error(ErrorKind::Truncated));
+	}
+
+	let record = (0..hex.len())
+		.step_by(2)
+		.map(|index| {
+			u8::from_str_radix(&hex[index..index + 2], 16)
+				.map_err(|_| error(ErrorKind::InvalidDigit))
+		})
+		.collect::<Result<Vec<u8>>>()?;
+
+	if record.len() != record[0] as usize + 5 {
+		return Err(error(ErrorKind::LengthMismatch));
+	}
+
+	let (found, contents) = record.split_last().expect("record is not empty");
+	let expected = checksum(contents);
+	if expected != *found {
+		return Err(error(ErrorKind::Checksum {
+			expected,
+			found: *found,
+		}));
+	}
+
+	Ok(record)
+}
+
+/// Two's complement of the sum of the record bytes
+fn checksum(bytes: &[u8]) -> u8 {
+	bytes
+		.iter()
+		.fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+		.wrapping_neg()
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+	bytes
+		.iter()
+		.fold(0, |value, byte| (value << 8) | *byte as u32)
+}
+
+/// Decodes an Intel HEX image, records after the end of file record are ignored
+pub fn parse(text: &str) -> Result<Image> {
+	let mut image = Image::default();
+	let mut base: u32 = 0;
+	let mut lines = 0;
+
+	for (index, line) in text.lines().enumerate() {
+		let number = index + 1;
+		lines = number;
+
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		let record = parse_record(line, number)?;
+		let length = record[0];
+		let address = big_endian(&record[1..3]);
+		let record_type = record[3];
+		let data = &record[4..4 + length as usize];
+
+		let expected_length = match record_type {
+			DATA => length,
+			END_OF_FILE => 0,
+			EXTENDED_SEGMENT_ADDRESS | EXTENDED_LINEAR_ADDRESS => 2,
+			START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => 4,
+			_ => {
+				return Err(Error {
+					line: number,
+					kind: ErrorKind::UnknownRecordType(record_type),
+				})
+			}
+		};
+		if length != expected_length {
+			return Err(Error {
+				line: number,
+				kind: ErrorKind::InvalidRecordLength(record_type),
+			});
+		}
+
+		match record_type {
+			DATA => {
+				for (offset, byte) in data.iter().enumerate() {
+					image
+						.data
+						.push((base.wrapping_add(address + offset as u32), *byte));
+				}
+			}
+			END_OF_FILE => return Ok(image),
+			EXTENDED_SEGMENT_ADDRESS => base = big_endian(data) << 4,
+			EXTENDED_LINEAR_ADDRESS => base = big_endian(data) << 16,
+			START_SEGMENT_ADDRESS => {
+				let segment = big_endian(&data[0..2]);
+				let offset = big_endian(&data[2..4]);
+				image.start_address = Some((segment << 4) + offset);
+			}
+			START_LINEAR_ADDRESS => image.start_address = Some(big_endian(data)),
+			_ => unreachable!(),
+		}
+	}
+
+	Err(Error {
+		line: lines + 1,
+		kind: ErrorKind::MissingEndOfFile,
+	})
+}
+
+/// Encodes `data` starting at address zero as Intel HEX data records
+pub fn write(data: &[u8]) -> String {
+	let mut text = String::new();
+
+	for (index, chunk) in data.chunks(RECORD_LENGTH).enumerate() {
+		let address = (index * RECORD_LENGTH) as u16;
+		let mut record = vec![chunk.len() as u8, (address >> 8) as u8, address as u8, DATA];
+		record.extend_from_slice(chunk);
+		record.push(checksum(&record));
+
+		text.push(':');
+		for byte in record {
+			text.push_str(&format!("{:02X}", byte));
+		}
+		text.push('\n');
+	}
+
+	text.push_str(":00000001FF\n");
+	text
+}
@@ -0,0 +1,64 @@
+//! Emulator core for the ATmega328P microcontroller.
+//!
+//! [`Cpu`] executes instructions from the program memory held in its [`System`] and
+//! steps the on-chip [`peripherals`] along with it. The egui front-end lives in the
+//! `gui` module behind the `gui` feature, so the core can be used without eframe.
+//! [`runner`] runs firmware headless until it stops by itself or a limit is reached.
+//!
+//! ```
+//! use atmega328p_rs::Cpu;
+//!
+//! let mut cpu = Cpu::init();
+//! // ldi r16, 0x2A
+//! cpu.system.flash_from_vec(vec![0xE20A]);
+//! cpu.step();
+//! assert_eq!(cpu.sram.registers[16], 0x2A);
+//! ```
+
+#![forbid(unsafe_code)]
+
+#[cfg(test)]
+mod tests;
+
+/// Executed instructions and branch outcomes, reported as lcov or annotated disassembly
+pub mod coverage;
+/// Instruction execution, interrupts and data space access
+pub mod cpu;
+/// Breakpoints, watchpoints and source-level stepping
+pub mod debugger;
+/// Program memory disassembly for display
+pub mod disassembler;
+/// Line number information from DWARF debug sections
+pub mod dwarf;
+/// ELF32 decoding for avr-gcc executables
+pub mod elf;
+/// Fuse bytes configuring the clock and memories
+pub mod fuses;
+/// GDB remote serial protocol server
+pub mod gdb;
+/// egui front-end
+#[cfg(feature = "gui")]
+pub mod gui;
+/// Intel HEX encoding and decoding
+pub mod ihex;
+/// Program memory, EEPROM and SRAM
+pub mod memory;
+/// On-chip peripherals mapped into the I/O registers
+pub mod peripherals;
+/// Cycles spent in each function of the firmware
+pub mod profiler;
+/// Headless execution with stop conditions, behind the `run` command
+pub mod runner;
+/// Save states of the whole machine
+pub mod snapshot;
+/// Function and variable names from the firmware's symbol table
+pub mod symbols;
+/// Memories, fuses and program loaders
+pub mod system;
+/// Instruction traces in a compact binary format
+pub mod trace;
+/// Bit and byte helpers shared by the instruction implementations
+pub mod utils;
+
+pub use cpu::Cpu;
+pub use system::System;
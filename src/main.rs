@@ -1,19 +1,156 @@
 #![forbid(unsafe_code)]
 
-#[cfg(test)]
-mod tests;
+use atmega328p_rs::gdb;
+use atmega328p_rs::runner::{self, RunOptions, COVERAGE_EXIT_CODE, USAGE};
+use atmega328p_rs::symbols::SymbolTable;
+use atmega328p_rs::{trace, Cpu, System};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
-mod cpu;
-mod disassembler;
-mod gui;
-mod memory;
-mod system;
-pub mod utils;
+fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
 
-use cpu::Cpu;
-use gui::App;
+	if args.first().is_some_and(|command| command == "run") {
+		std::process::exit(run(&args[1..]));
+	}
+	if args.first().is_some_and(|command| command == "trace") {
+		std::process::exit(print_trace(&args[1..]));
+	}
+
+	#[cfg(feature = "gui")]
+	open_window(&args);
+
+	#[cfg(not(feature = "gui"))]
+	{
+		eprintln!("Built without the gui feature, only the run and trace commands are available\n");
+		eprintln!("{}", USAGE);
+		std::process::exit(2);
+	}
+}
+
+/// Runs firmware without a window, returning the process exit code
+fn run(args: &[String]) -> i32 {
+	let options = match RunOptions::parse(args) {
+		Ok(options) => options,
+		Err(error) => {
+			eprintln!("{}\n\n{}", error, USAGE);
+			return 2;
+		}
+	};
+
+	let mut cpu = Cpu::init();
+	if let Err(error) = options.apply(&mut cpu) {
+		eprintln!("{}", error);
+		return 1;
+	}
+
+	if let Some(port) = options.gdb {
+		eprintln!("Waiting for GDB on port {}", port);
+		if let Err(error) = gdb::serve(&mut cpu, port) {
+			eprintln!("GDB session failed: {}", error);
+			return 1;
+		}
+		save_outputs(&mut cpu, &options);
+		return cpu.peripherals.debug.exit_code().unwrap_or(0) as i32;
+	}
+
+	let reason = runner::run(&mut cpu, &options.limits);
+	save_outputs(&mut cpu, &options);
+
+	eprintln!(
+		"Stopped after {} cycles ({:.6} s): {:?}",
+		cpu.cycles,
+		cpu.peripherals.clock.seconds(),
+		reason
+	);
+
+	if let Some(coverage) = &cpu.coverage {
+		let summary = coverage.summary(&cpu.system);
+		eprintln!("Coverage: {}", summary);
+		let minimum = options.min_coverage.unwrap_or(0.0);
+		if reason.exit_code() == 0 && summary.instruction_percent() < minimum {
+			eprintln!("Instruction coverage is below the required {}%", minimum);
+			return COVERAGE_EXIT_CODE;
+		}
+	}
+	reason.exit_code()
+}
+
+/// Writes the EEPROM file, trace, profile and coverage report the run was asked to keep
+fn save_outputs(cpu: &mut Cpu, options: &RunOptions) {
+	if let Err(error) = cpu.system.save_persisted_eeprom() {
+		eprintln!("Unable to save EEPROM: {}", error);
+	}
+	if let Some(Err(error)) = cpu.trace.as_mut().map(|trace| trace.finish()) {
+		eprintln!("Unable to write trace: {}", error);
+	}
+	if let (Some(path), Some(profiler)) = (&options.profile, &cpu.profiler) {
+		let result = File::create(path).and_then(|file| {
+			let mut output = BufWriter::new(file);
+			profiler.write_folded(&cpu.system.symbols, &mut output)?;
+			output.flush()
+		});
+		if let Err(error) = result {
+			eprintln!("Unable to write profile to {}: {}", path.display(), error);
+		}
+	}
+	if let (Some(path), Some(coverage)) = (&options.coverage, &cpu.coverage) {
+		let result = File::create(path).and_then(|file| {
+			let mut output = BufWriter::new(file);
+			coverage.write_report(&cpu.system, &mut output)?;
+			output.flush()
+		});
+		if let Err(error) = result {
+			eprintln!("Unable to write coverage to {}: {}", path.display(), error);
+		}
+	}
+}
+
+/// Prints a trace file as text, naming functions after the firmware if given
+fn print_trace(args: &[String]) -> i32 {
+	let Some(path) = args.first() else {
+		eprintln!("No trace file given\n\n{}", USAGE);
+		return 2;
+	};
+	let bytes = match std::fs::read(path) {
+		Ok(bytes) => bytes,
+		Err(error) => {
+			eprintln!("Unable to read {}: {}", path, error);
+			return 1;
+		}
+	};
+
+	let symbols = match args.get(1) {
+		Some(firmware) => {
+			let mut system = System::default();
+			if let Err(error) = system.load_program(firmware.as_ref()) {
+				eprintln!("Unable to load {}: {}", firmware, error);
+				return 1;
+			}
+			system.symbols
+		}
+		None => SymbolTable::default(),
+	};
+
+	let mut output = BufWriter::new(io::stdout().lock());
+	match trace::export(&bytes, &symbols, &mut output).and_then(|()| output.flush()) {
+		Ok(()) => 0,
+		// stops quietly when piped into e.g. head
+		Err(error) if error.kind() == io::ErrorKind::BrokenPipe => 0,
+		Err(error) => {
+			eprintln!("Unable to print {}: {}", path, error);
+			1
+		}
+	}
+}
+
+#[cfg(feature = "gui")]
+fn open_window(args: &[String]) {
+	use atmega328p_rs::gui::App;
+	use atmega328p_rs::runner::StdoutSerial;
+	use std::cell::RefCell;
+	use std::rc::Rc;
 
-fn main() {
 	let options = eframe::NativeOptions {
 		initial_window_size: Some(egui::vec2(1400.0, 900.0)),
 		min_window_size: Some(egui::vec2(1400.0, 900.0)),
@@ -21,9 +158,27 @@ fn main() {
 		..Default::default()
 	};
 
+	let mut cpu = Cpu::init();
+	cpu.fast_forward = true;
+	cpu.peripherals
+		.debug
+		.attach(Rc::new(RefCell::new(StdoutSerial)));
+
+	// --eeprom <file> keeps the EEPROM contents in a file between sessions
+	if let Some(index) = args.iter().position(|arg| arg == "--eeprom") {
+		match args.get(index + 1) {
+			Some(path) => {
+				if let Err(error) = cpu.system.persist_eeprom(path.into()) {
+					println!("Unable to load EEPROM from {}: {}", path, error);
+				}
+			}
+			None => println!("--eeprom requires a file path"),
+		}
+	}
+
 	eframe::run_native(
 		"ATmega328p Emulator",
 		options,
-		Box::new(|_cc| Box::new(App::new(Cpu::init()))),
+		Box::new(|_cc| Box::new(App::new(cpu))),
 	);
 }
@@ -10,13 +10,29 @@ const EEPROM_RANGE: Range<u16> = 0x0000..0x0400;
 
 const APP_FLASH_SIZE: u16 = 0x7800;
 const BOOT_FLASH_SIZE: u16 = 0x800;
-const EEPROM_SIZE: u16 = 0x400;
+pub const EEPROM_SIZE: u16 = 0x400;
 
 pub const RAMEND: u16 = SRAM_RANGE.end - 1;
 pub const PROGRAM_START: u16 = PROGRAM_FLASH_RANGE.start;
 pub const PROGRAM_END: u16 = PROGRAM_FLASH_RANGE.end - 1;
 pub const FLASH_START: u16 = BOOT_FLASH_RANGE.start;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+	Read,
+	Write,
+}
+
+/// A byte read or written by the firmware, in data space or the EEPROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+	pub address: u16,
+	pub kind: AccessKind,
+	pub value: u8,
+	/// Contents before a write, the same as `value` for reads
+	pub previous: u8,
+}
+
 //------------------ Programmable Flash Memory --------------------------------
 
 pub trait Memory {
@@ -36,11 +52,11 @@ impl Memory for ApplicationFlash {
 	}
 
 	fn read(&mut self, address: u16) -> u16 {
-		self.data[(address as usize)]
+		self.data[address as usize]
 	}
 
 	fn write(&mut self, address: u16, data: u16) {
-		self.data[(address as usize)] = data;
+		self.data[address as usize] = data;
 	}
 }
 
@@ -70,12 +86,12 @@ impl Memory for BootFlash {
 
 	fn read(&mut self, address: u16) -> u16 {
 		let mapped_address = address - FLASH_START;
-		self.data[(mapped_address as usize)]
+		self.data[mapped_address as usize]
 	}
 
 	fn write(&mut self, address: u16, data: u16) {
 		let mapped_address = address - FLASH_START;
-		self.data[(mapped_address as usize)] = data;
+		self.data[mapped_address as usize] = data;
 	}
 }
 
@@ -155,6 +171,23 @@ impl Memory for EepromMemory {
 	}
 }
 
+impl EepromMemory {
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.data
+	}
+
+	/// Replaces the contents with `bytes`, any remainder is left erased
+	pub fn load_bytes(&mut self, bytes: &[u8]) {
+		self.erase();
+		let length = bytes.len().min(self.data.len());
+		self.data[..length].copy_from_slice(&bytes[..length]);
+	}
+
+	pub fn erase(&mut self) {
+		self.data.fill(0xFF);
+	}
+}
+
 //------------------ SRAM -----------------------------------------------------
 
 #[derive(Debug)]
@@ -200,8 +233,23 @@ impl Memory for Sram {
 		}
 	}
 
-	fn write(&mut self, _address: u16, _data: u16) {
-		todo!();
+	fn write(&mut self, address: u16, data: u16) {
+		match address {
+			0x0000..=0x001F => self.registers[address as usize] = data as u8,
+			0x0020..=0x005F => {
+				let mapped_address = address - 0x0020;
+				self.io_registers[mapped_address as usize] = data as u8;
+			}
+			0x0060..=0x00FF => {
+				let mapped_address = address - 0x0060;
+				self.ext_io_registers[mapped_address as usize] = data as u8;
+			}
+			0x0100..=0x08FF => {
+				let mapped_address = address - 0x0100;
+				self.internal_ram[mapped_address as usize] = data as u8;
+			}
+			_ => panic!("SRAM does not contain address 0x{:x?}", address),
+		}
 	}
 }
 
@@ -265,8 +313,8 @@ lazy_static! {
 		sram.insert(0x36, "TIFR1".to_string());
 		sram.insert(0x37, "TIFR2".to_string());
 		sram.insert(0x38, "Reserved".to_string());
-		sram.insert(0x39, "Reserved".to_string());
-		sram.insert(0x3A, "Reserved".to_string());
+		sram.insert(0x39, "DBGCON".to_string());
+		sram.insert(0x3A, "DBGEXIT".to_string());
 		sram.insert(0x3B, "PCIFR".to_string());
 		sram.insert(0x3C, "EIFR".to_string());
 		sram.insert(0x3D, "EIMSK".to_string());
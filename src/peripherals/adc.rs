@@ -0,0 +1,296 @@
+use super::analog::{AnalogInputs, AnalogPin, BANDGAP_VOLTAGE};
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const ADCL: u16 = 0x78;
+pub const ADCH: u16 = 0x79;
+pub const ADCSRA: u16 = 0x7A;
+pub const ADCSRB: u16 = 0x7B;
+pub const ADMUX: u16 = 0x7C;
+pub const DIDR0: u16 = 0x7E;
+
+// ADCSRA bits
+const ADEN: u8 = 7;
+const ADSC: u8 = 6;
+const ADATE: u8 = 5;
+const ADIF: u8 = 4;
+const ADIE: u8 = 3;
+
+// ADMUX bits
+const ADLAR: u8 = 5;
+
+const FIRST_CONVERSION_CLOCKS: usize = 25;
+const CONVERSION_CLOCKS: usize = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+	Aref,
+	Avcc,
+	Internal,
+}
+
+/// Auto trigger sources selected by ADTS2:0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerSource {
+	FreeRunning,
+	AnalogComparator,
+	Int0,
+	Timer0CompareA,
+	Timer0Overflow,
+	Timer1CompareB,
+	Timer1Overflow,
+	Timer1Capture,
+}
+
+impl TriggerSource {
+	fn from_bits(bits: u8) -> Self {
+		match bits & 0x7 {
+			0 => TriggerSource::FreeRunning,
+			1 => TriggerSource::AnalogComparator,
+			2 => TriggerSource::Int0,
+			3 => TriggerSource::Timer0CompareA,
+			4 => TriggerSource::Timer0Overflow,
+			5 => TriggerSource::Timer1CompareB,
+			6 => TriggerSource::Timer1Overflow,
+			_ => TriggerSource::Timer1Capture,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Conversion {
+	remaining_cycles: usize,
+}
+
+#[derive(Default, Clone)]
+pub struct Adc {
+	conversion: Option<Conversion>,
+	/// No conversion has run since the ADC was enabled
+	first_conversion: bool,
+	/// ADCL was read and the data registers are locked until ADCH is read
+	locked: bool,
+}
+
+impl Adc {
+	pub fn is_enabled(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(ADCSRA) as u8, ADEN) != 0
+	}
+
+	pub fn is_converting(&self) -> bool {
+		self.conversion.is_some()
+	}
+
+	/// ADC clock prescaler selected by ADPS2:0
+	pub fn prescaler(&self, sram: &mut Sram) -> usize {
+		match sram.read(ADCSRA) & 0x7 {
+			0 | 1 => 2,
+			2 => 4,
+			3 => 8,
+			4 => 16,
+			5 => 32,
+			6 => 64,
+			_ => 128,
+		}
+	}
+
+	pub fn reference(&self, sram: &mut Sram) -> Reference {
+		match sram.read(ADMUX) >> 6 {
+			0 => Reference::Aref,
+			3 => Reference::Internal,
+			_ => Reference::Avcc,
+		}
+	}
+
+	pub fn trigger_source(&self, sram: &mut Sram) -> TriggerSource {
+		TriggerSource::from_bits(sram.read(ADCSRB) as u8)
+	}
+
+	/// Signals a rising edge on an auto trigger source, starting a conversion if ADATE is
+	/// set and ADTS2:0 selects it
+	pub fn trigger(&mut self, sram: &mut Sram, source: TriggerSource) {
+		let auto_trigger = bit(sram.read(ADCSRA) as u8, ADATE) != 0;
+		if auto_trigger && self.trigger_source(sram) == source && !self.is_converting() {
+			self.start(sram);
+		}
+	}
+
+	/// Entering ADC noise reduction sleep starts a conversion if the ADC is idle
+	pub fn enter_noise_reduction(&mut self, sram: &mut Sram) {
+		if self.is_enabled(sram) && !self.is_converting() {
+			self.start(sram);
+		}
+	}
+
+	/// Voltage currently selected by MUX3:0
+	pub fn input_voltage(&self, sram: &mut Sram, analog: &AnalogInputs) -> f64 {
+		let channel = sram.read(ADMUX) as u8 & 0xF;
+		match channel {
+			0x0..=0x7 => analog.voltage(AnalogPin::adc(channel).unwrap()),
+			0x8 => analog.temperature_sensor_voltage(),
+			0xE => BANDGAP_VOLTAGE,
+			_ => 0.0,
+		}
+	}
+
+	pub fn reference_voltage(&self, sram: &mut Sram, analog: &AnalogInputs) -> f64 {
+		match self.reference(sram) {
+			Reference::Aref => analog.voltage(AnalogPin::Aref),
+			Reference::Avcc => analog.voltage(AnalogPin::Avcc),
+			Reference::Internal => BANDGAP_VOLTAGE,
+		}
+	}
+
+	fn start(&mut self, sram: &mut Sram) {
+		let clocks = if self.first_conversion {
+			FIRST_CONVERSION_CLOCKS
+		} else {
+			CONVERSION_CLOCKS
+		};
+		self.first_conversion = false;
+		self.conversion = Some(Conversion {
+			remaining_cycles: clocks * self.prescaler(sram),
+		});
+
+		let adcsra = sram.read(ADCSRA) as u8;
+		sram.write(ADCSRA, (adcsra | (1 << ADSC)) as u16);
+	}
+
+	fn complete(&mut self, sram: &mut Sram, analog: &AnalogInputs) {
+		let input = self.input_voltage(sram, analog);
+		let reference = self.reference_voltage(sram, analog);
+		let result = if reference <= 0.0 {
+			0x3FF
+		} else {
+			((input * 1024.0 / reference) as i64).clamp(0, 0x3FF) as u16
+		};
+
+		if !self.locked {
+			let adjusted = if bit(sram.read(ADMUX) as u8, ADLAR) != 0 {
+				result << 6
+			} else {
+				result
+			};
+			sram.write(ADCL, adjusted & 0xFF);
+			sram.write(ADCH, adjusted >> 8);
+		}
+
+		let adcsra = sram.read(ADCSRA) as u8;
+		sram.write(ADCSRA, ((adcsra & !(1 << ADSC)) | (1 << ADIF)) as u16);
+
+		self.trigger(sram, TriggerSource::FreeRunning);
+	}
+
+	/// Cycles until the running conversion completes
+	pub fn next_event(&self) -> Option<usize> {
+		self.conversion
+			.as_ref()
+			.map(|conversion| conversion.remaining_cycles)
+	}
+
+	/// Advances a running conversion, sampling `analog` when it completes
+	pub fn clock(&mut self, sram: &mut Sram, analog: &AnalogInputs, cycles: usize) {
+		let conversion = match self.conversion.as_mut() {
+			Some(conversion) => conversion,
+			None => return,
+		};
+
+		if conversion.remaining_cycles > cycles {
+			conversion.remaining_cycles -= cycles;
+			return;
+		}
+
+		self.conversion = None;
+		self.complete(sram, analog);
+	}
+}
+
+impl Peripheral for Adc {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.conversion = None;
+		self.first_conversion = true;
+		self.locked = false;
+		for address in ADCL..=DIDR0 {
+			sram.write(address, 0x00);
+		}
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		(ADCL..=DIDR0).contains(&address)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		match address {
+			ADCL => self.locked = true,
+			ADCH => self.locked = false,
+			_ => {}
+		}
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			ADCL | ADCH => {}
+			ADCSRA => {
+				let adcsra = sram.read(ADCSRA) as u8;
+				let mut value = data & !((1 << ADSC) | (1 << ADIF));
+				value |= adcsra & (1 << ADSC);
+				if bit(data, ADIF) == 0 {
+					value |= adcsra & (1 << ADIF);
+				}
+
+				if bit(data, ADEN) == 0 {
+					self.conversion = None;
+					self.first_conversion = true;
+					value &= !(1 << ADSC);
+				}
+				sram.write(ADCSRA, value as u16);
+
+				if bit(data, ADEN) != 0 && bit(data, ADSC) != 0 && !self.is_converting() {
+					self.start(sram);
+				}
+			}
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let adcsra = sram.read(ADCSRA) as u8;
+		if bit(adcsra, ADIE) != 0 && bit(adcsra, ADIF) != 0 {
+			Some(Interrupt::Adc)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		if interrupt == Interrupt::Adc {
+			let adcsra = sram.read(ADCSRA) as u8;
+			sram.write(ADCSRA, (adcsra & !(1 << ADIF)) as u16);
+		}
+	}
+}
+
+impl Snapshot for Adc {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.conversion.is_some());
+		if let Some(conversion) = &self.conversion {
+			writer.usize(conversion.remaining_cycles);
+		}
+		writer.bool(self.first_conversion);
+		writer.bool(self.locked);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.conversion = match reader.bool()? {
+			true => Some(Conversion {
+				remaining_cycles: reader.usize()?,
+			}),
+			false => None,
+		};
+		self.first_conversion = reader.bool()?;
+		self.locked = reader.bool()?;
+		Ok(())
+	}
+}
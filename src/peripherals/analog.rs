@@ -0,0 +1,121 @@
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+
+/// Internal bandgap reference voltage
+pub const BANDGAP_VOLTAGE: f64 = 1.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogPin {
+	Adc0,
+	Adc1,
+	Adc2,
+	Adc3,
+	Adc4,
+	Adc5,
+	Adc6,
+	Adc7,
+	Ain0,
+	Ain1,
+	Aref,
+	Avcc,
+}
+
+impl AnalogPin {
+	pub fn adc(channel: u8) -> Option<AnalogPin> {
+		match channel {
+			0 => Some(AnalogPin::Adc0),
+			1 => Some(AnalogPin::Adc1),
+			2 => Some(AnalogPin::Adc2),
+			3 => Some(AnalogPin::Adc3),
+			4 => Some(AnalogPin::Adc4),
+			5 => Some(AnalogPin::Adc5),
+			6 => Some(AnalogPin::Adc6),
+			7 => Some(AnalogPin::Adc7),
+			_ => None,
+		}
+	}
+}
+
+/// Voltages applied to the analog pins by the outside world
+#[derive(Debug, Clone)]
+pub struct AnalogInputs {
+	adc: [f64; 8],
+	ain: [f64; 2],
+	aref: f64,
+	avcc: f64,
+	temperature: f64,
+}
+
+impl Default for AnalogInputs {
+	fn default() -> Self {
+		Self {
+			adc: [0.0; 8],
+			ain: [0.0; 2],
+			aref: 5.0,
+			avcc: 5.0,
+			temperature: 25.0,
+		}
+	}
+}
+
+impl AnalogInputs {
+	pub fn set_voltage(&mut self, pin: AnalogPin, volts: f64) {
+		match pin {
+			AnalogPin::Ain0 => self.ain[0] = volts,
+			AnalogPin::Ain1 => self.ain[1] = volts,
+			AnalogPin::Aref => self.aref = volts,
+			AnalogPin::Avcc => self.avcc = volts,
+			_ => self.adc[pin as usize] = volts,
+		}
+	}
+
+	pub fn voltage(&self, pin: AnalogPin) -> f64 {
+		match pin {
+			AnalogPin::Ain0 => self.ain[0],
+			AnalogPin::Ain1 => self.ain[1],
+			AnalogPin::Aref => self.aref,
+			AnalogPin::Avcc => self.avcc,
+			_ => self.adc[pin as usize],
+		}
+	}
+
+	/// Sets the die temperature seen by the internal temperature sensor
+	pub fn set_temperature(&mut self, celsius: f64) {
+		self.temperature = celsius;
+	}
+
+	pub fn temperature(&self) -> f64 {
+		self.temperature
+	}
+
+	/// Output of the temperature sensor, interpolated from the typical values in the
+	/// datasheet (242 mV at -45°C, 314 mV at 25°C, 380 mV at 85°C)
+	pub fn temperature_sensor_voltage(&self) -> f64 {
+		let t = self.temperature;
+		if t < 25.0 {
+			0.314 + (t - 25.0) * (0.314 - 0.242) / 70.0
+		} else {
+			0.314 + (t - 25.0) * (0.380 - 0.314) / 60.0
+		}
+	}
+}
+
+impl Snapshot for AnalogInputs {
+	fn save(&self, writer: &mut Writer) {
+		for voltage in self.adc.iter().chain(&self.ain) {
+			writer.f64(*voltage);
+		}
+		writer.f64(self.aref);
+		writer.f64(self.avcc);
+		writer.f64(self.temperature);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		for voltage in self.adc.iter_mut().chain(&mut self.ain) {
+			*voltage = reader.f64()?;
+		}
+		self.aref = reader.f64()?;
+		self.avcc = reader.f64()?;
+		self.temperature = reader.f64()?;
+		Ok(())
+	}
+}
@@ -0,0 +1,176 @@
+use super::adc::{ADCSRA, ADCSRB, ADMUX};
+use super::analog::{AnalogInputs, AnalogPin, BANDGAP_VOLTAGE};
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const ACSR: u16 = 0x50;
+pub const DIDR1: u16 = 0x7F;
+
+// ACSR bits
+const ACD: u8 = 7;
+const ACBG: u8 = 6;
+const ACO: u8 = 5;
+const ACI: u8 = 4;
+const ACIE: u8 = 3;
+const ACIC: u8 = 2;
+
+// ADCSRB bits
+const ACME: u8 = 6;
+// ADCSRA bits
+const ADEN: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptMode {
+	Toggle,
+	Falling,
+	Rising,
+}
+
+#[derive(Default, Clone)]
+pub struct AnalogComparator {
+	output: bool,
+	/// Output edge not yet passed on to the Timer/Counter1 input capture, true if rising
+	capture_edge: Option<bool>,
+}
+
+impl AnalogComparator {
+	pub fn is_enabled(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(ACSR) as u8, ACD) == 0
+	}
+
+	pub fn output(&self) -> bool {
+		self.output
+	}
+
+	pub fn interrupt_mode(&self, sram: &mut Sram) -> InterruptMode {
+		match sram.read(ACSR) & 0x3 {
+			2 => InterruptMode::Falling,
+			3 => InterruptMode::Rising,
+			_ => InterruptMode::Toggle,
+		}
+	}
+
+	pub fn positive_input(&self, sram: &mut Sram, analog: &AnalogInputs) -> f64 {
+		if bit(sram.read(ACSR) as u8, ACBG) != 0 {
+			BANDGAP_VOLTAGE
+		} else {
+			analog.voltage(AnalogPin::Ain0)
+		}
+	}
+
+	/// AIN1, or the ADC multiplexer output when ACME is set and the ADC is off
+	pub fn negative_input(&self, sram: &mut Sram, analog: &AnalogInputs) -> f64 {
+		let acme = bit(sram.read(ADCSRB) as u8, ACME) != 0;
+		let aden = bit(sram.read(ADCSRA) as u8, ADEN) != 0;
+
+		if acme && !aden {
+			let channel = sram.read(ADMUX) as u8 & 0x7;
+			analog.voltage(AnalogPin::adc(channel).unwrap())
+		} else {
+			analog.voltage(AnalogPin::Ain1)
+		}
+	}
+
+	/// Updates ACO from the inputs, returns true when ACI was raised
+	pub fn compare(&mut self, sram: &mut Sram, analog: &AnalogInputs) -> bool {
+		if !self.is_enabled(sram) {
+			return false;
+		}
+
+		let output = self.positive_input(sram, analog) > self.negative_input(sram, analog);
+		let previous = self.output;
+		self.output = output;
+
+		let acsr = sram.read(ACSR) as u8;
+		let acsr = if output {
+			acsr | (1 << ACO)
+		} else {
+			acsr & !(1 << ACO)
+		};
+		sram.write(ACSR, acsr as u16);
+
+		if output == previous {
+			return false;
+		}
+
+		if bit(acsr, ACIC) != 0 {
+			self.capture_edge = Some(output);
+		}
+
+		let triggered = match self.interrupt_mode(sram) {
+			InterruptMode::Toggle => true,
+			InterruptMode::Falling => !output,
+			InterruptMode::Rising => output,
+		};
+
+		if triggered {
+			sram.write(ACSR, (acsr | (1 << ACI)) as u16);
+		}
+		triggered
+	}
+
+	/// Takes the output edge to capture when ACIC routes the comparator to Timer/Counter1
+	pub fn take_capture_edge(&mut self) -> Option<bool> {
+		self.capture_edge.take()
+	}
+}
+
+impl Peripheral for AnalogComparator {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.output = false;
+		self.capture_edge = None;
+		sram.write(ACSR, 0x00);
+		sram.write(DIDR1, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		address == ACSR || address == DIDR1
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			ACSR => {
+				let acsr = sram.read(ACSR) as u8;
+				let mut value = (data & !((1 << ACO) | (1 << ACI))) | (acsr & (1 << ACO));
+				if bit(data, ACI) == 0 {
+					value |= acsr & (1 << ACI);
+				}
+				sram.write(ACSR, value as u16);
+			}
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let acsr = sram.read(ACSR) as u8;
+		if bit(acsr, ACIE) != 0 && bit(acsr, ACI) != 0 {
+			Some(Interrupt::AnalogComp)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		if interrupt == Interrupt::AnalogComp {
+			let acsr = sram.read(ACSR) as u8;
+			sram.write(ACSR, (acsr & !(1 << ACI)) as u16);
+		}
+	}
+}
+
+impl Snapshot for AnalogComparator {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.output);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.output = reader.bool()?;
+		Ok(())
+	}
+}
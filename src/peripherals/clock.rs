@@ -0,0 +1,185 @@
+use super::{Interrupt, Peripheral};
+use crate::fuses::Fuses;
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const CLKPR: u16 = 0x61;
+
+// CLKPR bits
+const CLKPCE: u8 = 7;
+
+/// CLKPS3:0 must be written within this many cycles of setting CLKPCE
+const CHANGE_ENABLE_CYCLES: usize = 4;
+
+pub const INTERNAL_RC_FREQUENCY: f64 = 8_000_000.0;
+pub const WATCHDOG_OSCILLATOR_FREQUENCY: f64 = 128_000.0;
+pub const WATCH_CRYSTAL_FREQUENCY: f64 = 32_768.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+	External,
+	InternalRc,
+	Internal128k,
+	LowFrequencyCrystal,
+	Crystal,
+}
+
+impl ClockSource {
+	pub const ALL: [ClockSource; 5] = [
+		ClockSource::External,
+		ClockSource::InternalRc,
+		ClockSource::Internal128k,
+		ClockSource::LowFrequencyCrystal,
+		ClockSource::Crystal,
+	];
+
+	/// Decodes CKSEL3:0, the reserved value 0001 is treated as an external clock
+	pub fn from_cksel(cksel: u8) -> Self {
+		match cksel & 0xF {
+			0x2 => ClockSource::InternalRc,
+			0x3 => ClockSource::Internal128k,
+			0x4 | 0x5 => ClockSource::LowFrequencyCrystal,
+			0x6..=0xF => ClockSource::Crystal,
+			_ => ClockSource::External,
+		}
+	}
+}
+
+/// System clock source and prescaler, tracking the emulated time elapsed since reset
+#[derive(Clone)]
+pub struct Clock {
+	pub source: ClockSource,
+	/// Frequency of the crystal or external clock when one of them is selected
+	pub external_frequency: f64,
+	ckdiv8: bool,
+	division: u32,
+	change_enable_cycles: usize,
+	seconds: f64,
+}
+
+impl Default for Clock {
+	fn default() -> Self {
+		let mut clock = Self {
+			source: ClockSource::External,
+			external_frequency: 16_000_000.0,
+			ckdiv8: false,
+			division: 1,
+			change_enable_cycles: 0,
+			seconds: 0.0,
+		};
+		clock.configure(&Fuses::default());
+		clock
+	}
+}
+
+impl Clock {
+	/// Applies the clock fuses, they take effect on the next reset
+	pub fn configure(&mut self, fuses: &Fuses) {
+		self.source = ClockSource::from_cksel(fuses.clock_select());
+		self.ckdiv8 = fuses.ckdiv8();
+	}
+
+	/// Frequency of the selected source before the prescaler
+	pub fn source_frequency(&self) -> f64 {
+		match self.source {
+			ClockSource::InternalRc => INTERNAL_RC_FREQUENCY,
+			ClockSource::Internal128k => WATCHDOG_OSCILLATOR_FREQUENCY,
+			ClockSource::LowFrequencyCrystal => WATCH_CRYSTAL_FREQUENCY,
+			ClockSource::External | ClockSource::Crystal => self.external_frequency,
+		}
+	}
+
+	pub fn division(&self) -> u32 {
+		self.division
+	}
+
+	/// System clock frequency in Hz
+	pub fn frequency(&self) -> f64 {
+		self.source_frequency() / self.division as f64
+	}
+
+	/// Emulated time taken by `cycles` at the current frequency
+	pub fn duration(&self, cycles: usize) -> f64 {
+		cycles as f64 / self.frequency()
+	}
+
+	/// Emulated seconds since reset
+	pub fn seconds(&self) -> f64 {
+		self.seconds
+	}
+
+	pub fn advance(&mut self, sram: &mut Sram, cycles: usize) {
+		self.seconds += self.duration(cycles);
+
+		if self.change_enable_cycles > 0 {
+			self.change_enable_cycles = self.change_enable_cycles.saturating_sub(cycles);
+			if self.change_enable_cycles == 0 {
+				let clkpr = sram.read(CLKPR) as u8;
+				sram.write(CLKPR, (clkpr & !(1 << CLKPCE)) as u16);
+			}
+		}
+	}
+}
+
+impl Peripheral for Clock {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.division = if self.ckdiv8 { 8 } else { 1 };
+		self.change_enable_cycles = 0;
+		self.seconds = 0.0;
+		sram.write(CLKPR, self.division.trailing_zeros() as u16);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		address == CLKPR
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		let clkpr = sram.read(address) as u8;
+
+		if data == 1 << CLKPCE {
+			sram.write(address, (clkpr | data) as u16);
+			self.change_enable_cycles = CHANGE_ENABLE_CYCLES;
+		} else if bit(data, CLKPCE) == 0 && self.change_enable_cycles > 0 {
+			// reserved CLKPS values are treated as the largest division factor
+			let clkps = data & 0x0F;
+			self.division = 1 << clkps.min(8);
+			self.change_enable_cycles = 0;
+			sram.write(address, clkps as u16);
+		}
+	}
+
+	fn pending_interrupt(&mut self, _sram: &mut Sram) -> Option<Interrupt> {
+		None
+	}
+
+	fn acknowledge(&mut self, _sram: &mut Sram, _interrupt: Interrupt) {}
+}
+
+impl Snapshot for Clock {
+	fn save(&self, writer: &mut Writer) {
+		writer.choice(&self.source, &ClockSource::ALL);
+		writer.f64(self.external_frequency);
+		writer.bool(self.ckdiv8);
+		writer.u32(self.division);
+		writer.usize(self.change_enable_cycles);
+		writer.f64(self.seconds);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.source = reader.choice(&ClockSource::ALL, "clock source")?;
+		self.external_frequency = reader.f64()?;
+		self.ckdiv8 = reader.bool()?;
+		self.division = reader.u32()?;
+		if !self.division.is_power_of_two() || self.division > 256 {
+			return Err(snapshot::Error::Invalid("clock division"));
+		}
+		self.change_enable_cycles = reader.usize()?;
+		self.seconds = reader.f64()?;
+		Ok(())
+	}
+}
@@ -0,0 +1,81 @@
+use super::usart::SharedSerialDevice;
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+
+/// Reserved I/O address, bytes written to it are passed to the host console
+pub const DEBUG_CONSOLE: u16 = 0x39;
+/// Reserved I/O address, writing to it ends the run with the byte as exit code
+pub const DEBUG_EXIT: u16 = 0x3A;
+
+/// Emulator specific channel letting firmware under test talk to the host without
+/// setting up a UART. Both registers sit in reserved slots of the I/O space, so on a
+/// real chip the writes have no effect.
+#[derive(Default, Clone)]
+pub struct DebugPort {
+	devices: Vec<SharedSerialDevice>,
+	exit_code: Option<u8>,
+	/// Keeps the devices from receiving while history replays a step
+	pub muted: bool,
+}
+
+impl DebugPort {
+	/// Receives every byte written to `DEBUG_CONSOLE`
+	pub fn attach(&mut self, device: SharedSerialDevice) {
+		self.devices.push(device);
+	}
+
+	/// Exit code the firmware asked for, `None` while it keeps running
+	pub fn exit_code(&self) -> Option<u8> {
+		self.exit_code
+	}
+}
+
+impl Peripheral for DebugPort {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.exit_code = None;
+		sram.write(DEBUG_CONSOLE, 0x00);
+		sram.write(DEBUG_EXIT, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		matches!(address, DEBUG_CONSOLE | DEBUG_EXIT)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			DEBUG_CONSOLE => {
+				for device in self.devices.iter().filter(|_| !self.muted) {
+					device.borrow_mut().receive(data);
+				}
+			}
+			DEBUG_EXIT => self.exit_code = Some(data),
+			_ => {}
+		}
+		sram.write(address, data as u16);
+	}
+
+	fn pending_interrupt(&mut self, _sram: &mut Sram) -> Option<Interrupt> {
+		None
+	}
+
+	fn acknowledge(&mut self, _sram: &mut Sram, _interrupt: Interrupt) {}
+}
+
+impl Snapshot for DebugPort {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.exit_code.is_some());
+		writer.u8(self.exit_code.unwrap_or_default());
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		let exited = reader.bool()?;
+		let code = reader.u8()?;
+		self.exit_code = exited.then_some(code);
+		Ok(())
+	}
+}
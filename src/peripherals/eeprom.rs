@@ -0,0 +1,268 @@
+use super::Interrupt;
+use crate::memory::{Access, AccessKind, EepromMemory, Memory, Sram, EEPROM_SIZE};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::{bit, to_u16};
+
+pub const EECR: u16 = 0x3F;
+pub const EEDR: u16 = 0x40;
+pub const EEARL: u16 = 0x41;
+pub const EEARH: u16 = 0x42;
+
+// EECR bits
+const EERIE: u8 = 3;
+const EEMPE: u8 = 2;
+const EEPE: u8 = 1;
+const EERE: u8 = 0;
+
+/// Tolerance for the rounding accumulated while timing writes in seconds
+const TIME_TOLERANCE: f64 = 1e-12;
+
+/// EEPE must be written within this many cycles of setting EEMPE
+const MASTER_WRITE_ENABLE_CYCLES: usize = 4;
+const READ_STALL_CYCLES: usize = 4;
+const WRITE_STALL_CYCLES: usize = 2;
+
+/// Programming times from the datasheet, in seconds. Writes are timed by the
+/// EEPROM's own oscillator so they do not depend on the system clock.
+const ATOMIC_WRITE_TIME: f64 = 3.4e-3;
+const SPLIT_WRITE_TIME: f64 = 1.8e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgrammingMode {
+	/// Erase and write in one operation
+	Atomic,
+	EraseOnly,
+	WriteOnly,
+}
+
+impl ProgrammingMode {
+	const ALL: [ProgrammingMode; 3] = [
+		ProgrammingMode::Atomic,
+		ProgrammingMode::EraseOnly,
+		ProgrammingMode::WriteOnly,
+	];
+
+	fn from_bits(bits: u8) -> Self {
+		match (bits >> 4) & 0x3 {
+			1 => ProgrammingMode::EraseOnly,
+			2 => ProgrammingMode::WriteOnly,
+			_ => ProgrammingMode::Atomic,
+		}
+	}
+
+	fn duration(&self) -> f64 {
+		match self {
+			ProgrammingMode::Atomic => ATOMIC_WRITE_TIME,
+			_ => SPLIT_WRITE_TIME,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Write {
+	address: u16,
+	data: u8,
+	mode: ProgrammingMode,
+	remaining_time: f64,
+}
+
+/// EECR/EEDR/EEAR access protocol in front of `EepromMemory`
+#[derive(Default, Clone)]
+pub struct Eeprom {
+	write: Option<Write>,
+	/// Cycles left before EEMPE is cleared by hardware
+	master_enable_cycles: usize,
+	/// Cycles the CPU is halted by the last EERE or EEPE strobe
+	stall_cycles: usize,
+	/// Collect reads and completed writes of the array in `accesses`
+	pub record_accesses: bool,
+	/// Array accesses since the CPU last cleared them
+	pub accesses: Vec<Access>,
+}
+
+impl Eeprom {
+	pub fn handles(&self, address: u16) -> bool {
+		(EECR..=EEARH).contains(&address)
+	}
+
+	pub fn is_writing(&self) -> bool {
+		self.write.is_some()
+	}
+
+	pub fn address(&self, sram: &mut Sram) -> u16 {
+		to_u16(sram.read(EEARH) as u8, sram.read(EEARL) as u8) & 0x3FF
+	}
+
+	/// Returns and clears the cycles the CPU must be halted for
+	pub fn take_stall_cycles(&mut self) -> usize {
+		std::mem::take(&mut self.stall_cycles)
+	}
+
+	pub fn reset(&mut self, sram: &mut Sram) {
+		self.write = None;
+		self.master_enable_cycles = 0;
+		self.stall_cycles = 0;
+		for address in EECR..=EEARH {
+			sram.write(address, 0x00);
+		}
+	}
+
+	pub fn write(&mut self, sram: &mut Sram, memory: &mut EepromMemory, address: u16, data: u8) {
+		match address {
+			EECR => self.write_control(sram, memory, data),
+			EEDR | EEARL | EEARH if self.is_writing() => {}
+			EEARH => sram.write(EEARH, (data & 0x3) as u16),
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn write_control(&mut self, sram: &mut Sram, memory: &mut EepromMemory, data: u8) {
+		let eecr = sram.read(EECR) as u8;
+
+		// EEPM1:0 can only be changed while no write is in progress
+		let mut value = if self.is_writing() {
+			eecr & 0x30
+		} else {
+			data & 0x30
+		};
+		value |= data & (1 << EERIE);
+		value |= eecr & ((1 << EEMPE) | (1 << EEPE));
+
+		if bit(data, EEMPE) != 0 && bit(data, EEPE) == 0 {
+			value |= 1 << EEMPE;
+			self.master_enable_cycles = MASTER_WRITE_ENABLE_CYCLES;
+		}
+		sram.write(EECR, value as u16);
+
+		let master_enabled = bit(eecr, EEMPE) != 0;
+
+		if bit(data, EEPE) != 0 && master_enabled && !self.is_writing() {
+			let mode = ProgrammingMode::from_bits(value);
+			self.write = Some(Write {
+				address: self.address(sram),
+				data: sram.read(EEDR) as u8,
+				mode,
+				remaining_time: mode.duration(),
+			});
+			sram.write(EECR, ((value & !(1 << EEMPE)) | (1 << EEPE)) as u16);
+			self.master_enable_cycles = 0;
+			self.stall_cycles += WRITE_STALL_CYCLES;
+		} else if bit(data, EERE) != 0 && !self.is_writing() {
+			let address = self.address(sram);
+			let data = memory.read(address);
+			self.record(address, AccessKind::Read, data as u8, data as u8);
+			sram.write(EEDR, data);
+			self.stall_cycles += READ_STALL_CYCLES;
+		}
+	}
+
+	fn complete(&mut self, sram: &mut Sram, memory: &mut EepromMemory, write: Write) {
+		let current = memory.read(write.address) as u8;
+		let value = match write.mode {
+			ProgrammingMode::Atomic => write.data,
+			ProgrammingMode::EraseOnly => 0xFF,
+			ProgrammingMode::WriteOnly => current & write.data,
+		};
+		memory.write(write.address, value as u16);
+		self.record(write.address, AccessKind::Write, value, current);
+
+		let eecr = sram.read(EECR) as u8;
+		sram.write(EECR, (eecr & !(1 << EEPE)) as u16);
+	}
+
+	fn record(&mut self, address: u16, kind: AccessKind, value: u8, previous: u8) {
+		if self.record_accesses {
+			self.accesses.push(Access {
+				address,
+				kind,
+				value,
+				previous,
+			});
+		}
+	}
+
+	/// Cycles at `frequency` until the EEMPE window closes or the write completes
+	pub fn next_event(&self, frequency: f64) -> Option<usize> {
+		let window = Some(self.master_enable_cycles).filter(|cycles| *cycles > 0);
+		let write = self.write.as_ref().map(|write| {
+			((write.remaining_time - TIME_TOLERANCE) * frequency)
+				.ceil()
+				.max(1.0) as usize
+		});
+
+		match (window, write) {
+			(Some(window), Some(write)) => Some(window.min(write)),
+			(window, write) => window.or(write),
+		}
+	}
+
+	/// Advances the write in progress by `seconds`, and the EEMPE window by `cycles`
+	pub fn clock(
+		&mut self,
+		sram: &mut Sram,
+		memory: &mut EepromMemory,
+		cycles: usize,
+		seconds: f64,
+	) {
+		if self.master_enable_cycles > 0 {
+			self.master_enable_cycles = self.master_enable_cycles.saturating_sub(cycles);
+			if self.master_enable_cycles == 0 {
+				let eecr = sram.read(EECR) as u8;
+				sram.write(EECR, (eecr & !(1 << EEMPE)) as u16);
+			}
+		}
+
+		let write = match self.write.as_mut() {
+			Some(write) => write,
+			None => return,
+		};
+
+		if write.remaining_time - seconds > TIME_TOLERANCE {
+			write.remaining_time -= seconds;
+			return;
+		}
+
+		let write = *write;
+		self.write = None;
+		self.complete(sram, memory, write);
+	}
+
+	/// EE_READY is a level interrupt raised while EERIE is set and no write is running
+	pub fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let eecr = sram.read(EECR) as u8;
+		if bit(eecr, EERIE) != 0 && bit(eecr, EEPE) == 0 {
+			Some(Interrupt::EeReady)
+		} else {
+			None
+		}
+	}
+}
+
+impl Snapshot for Eeprom {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.write.is_some());
+		if let Some(write) = &self.write {
+			writer.u16(write.address);
+			writer.u8(write.data);
+			writer.choice(&write.mode, &ProgrammingMode::ALL);
+			writer.f64(write.remaining_time);
+		}
+		writer.usize(self.master_enable_cycles);
+		writer.usize(self.stall_cycles);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.write = match reader.bool()? {
+			true => Some(Write {
+				address: reader.u16()? % EEPROM_SIZE,
+				data: reader.u8()?,
+				mode: reader.choice(&ProgrammingMode::ALL, "EEPROM programming mode")?,
+				remaining_time: reader.f64()?,
+			}),
+			false => None,
+		};
+		self.master_enable_cycles = reader.usize()?;
+		self.stall_cycles = reader.usize()?;
+		Ok(())
+	}
+}
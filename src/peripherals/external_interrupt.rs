@@ -0,0 +1,185 @@
+use super::gpio::{PortLevels, INT0, INT1};
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const PCIFR: u16 = 0x3B;
+pub const EIFR: u16 = 0x3C;
+pub const EIMSK: u16 = 0x3D;
+pub const PCICR: u16 = 0x68;
+pub const EICRA: u16 = 0x69;
+pub const PCMSK0: u16 = 0x6B;
+pub const PCMSK1: u16 = 0x6C;
+pub const PCMSK2: u16 = 0x6D;
+
+/// PCINT14:8 are PC6:0, PC7 does not exist
+const PCMSK1_MASK: u8 = 0x7F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sense {
+	LowLevel,
+	AnyEdge,
+	FallingEdge,
+	RisingEdge,
+}
+
+/// INT0/INT1 and the three pin change interrupt groups
+#[derive(Default, Clone)]
+pub struct ExternalInterrupts {
+	previous: Option<PortLevels>,
+}
+
+impl ExternalInterrupts {
+	/// ISCn1:0 for INT0 (`n = 0`) or INT1 (`n = 1`)
+	pub fn sense(&self, sram: &mut Sram, n: u8) -> Sense {
+		match (sram.read(EICRA) as u8 >> (n * 2)) & 0x3 {
+			0 => Sense::LowLevel,
+			1 => Sense::AnyEdge,
+			2 => Sense::FallingEdge,
+			_ => Sense::RisingEdge,
+		}
+	}
+
+	fn set_flag(sram: &mut Sram, address: u16, flag: u8) {
+		let flags = sram.read(address) as u8;
+		sram.write(address, (flags | (1 << flag)) as u16);
+	}
+
+	fn clear_flag(sram: &mut Sram, address: u16, flag: u8) {
+		let flags = sram.read(address) as u8;
+		sram.write(address, (flags & !(1 << flag)) as u16);
+	}
+
+	/// Samples the pins, raising flags on detected edges. Edge sensing on INT0/INT1
+	/// needs clk_I/O, pin changes are detected asynchronously. Returns true when INTF0
+	/// was raised, which is an ADC auto trigger source.
+	pub fn sample(&mut self, sram: &mut Sram, levels: PortLevels, io_clock: bool) -> bool {
+		let previous = self.previous.replace(levels).unwrap_or(levels);
+		let mut int0_raised = false;
+
+		for (n, pin) in [INT0, INT1].iter().enumerate() {
+			let port = pin.port as usize;
+			let was_high = bit(previous[port], pin.bit) != 0;
+			let is_high = bit(levels[port], pin.bit) != 0;
+
+			let triggered = match self.sense(sram, n as u8) {
+				Sense::LowLevel => false,
+				Sense::AnyEdge => was_high != is_high,
+				Sense::FallingEdge => was_high && !is_high,
+				Sense::RisingEdge => !was_high && is_high,
+			};
+
+			if triggered && io_clock {
+				Self::set_flag(sram, EIFR, n as u8);
+				int0_raised |= n == 0;
+			}
+		}
+
+		for (group, mask_address) in [PCMSK0, PCMSK1, PCMSK2].iter().enumerate() {
+			let mut mask = sram.read(*mask_address) as u8;
+			if group == 1 {
+				mask &= PCMSK1_MASK;
+			}
+			if (previous[group] ^ levels[group]) & mask != 0 {
+				Self::set_flag(sram, PCIFR, group as u8);
+			}
+		}
+
+		int0_raised
+	}
+
+	/// INTn is requested while its pin is held low in level mode, regardless of INTFn
+	fn requested(&self, sram: &mut Sram, n: u8) -> bool {
+		let level_low = match self.previous {
+			Some(levels) => {
+				let pin = if n == 0 { INT0 } else { INT1 };
+				bit(levels[pin.port as usize], pin.bit) == 0
+			}
+			None => false,
+		};
+
+		match self.sense(sram, n) {
+			Sense::LowLevel => level_low,
+			_ => bit(sram.read(EIFR) as u8, n) != 0,
+		}
+	}
+}
+
+impl Peripheral for ExternalInterrupts {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.previous = None;
+		for address in [PCIFR, EIFR, EIMSK, PCICR, EICRA, PCMSK0, PCMSK1, PCMSK2] {
+			sram.write(address, 0x00);
+		}
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		matches!(
+			address,
+			PCIFR | EIFR | EIMSK | PCICR | EICRA | PCMSK0..=PCMSK2
+		)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			// flags are cleared by writing a logical one
+			EIFR | PCIFR => {
+				let flags = sram.read(address) as u8;
+				sram.write(address, (flags & !data) as u16);
+			}
+			EIMSK => sram.write(address, (data & 0x03) as u16),
+			PCICR => sram.write(address, (data & 0x07) as u16),
+			EICRA => sram.write(address, (data & 0x0F) as u16),
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let eimsk = sram.read(EIMSK) as u8;
+		if bit(eimsk, 0) != 0 && self.requested(sram, 0) {
+			return Some(Interrupt::Int0);
+		}
+		if bit(eimsk, 1) != 0 && self.requested(sram, 1) {
+			return Some(Interrupt::Int1);
+		}
+
+		let pending = sram.read(PCICR) as u8 & sram.read(PCIFR) as u8;
+		match pending.trailing_zeros() {
+			0 => Some(Interrupt::PcInt0),
+			1 => Some(Interrupt::PcInt1),
+			2 => Some(Interrupt::PcInt2),
+			_ => None,
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		match interrupt {
+			Interrupt::Int0 => Self::clear_flag(sram, EIFR, 0),
+			Interrupt::Int1 => Self::clear_flag(sram, EIFR, 1),
+			Interrupt::PcInt0 => Self::clear_flag(sram, PCIFR, 0),
+			Interrupt::PcInt1 => Self::clear_flag(sram, PCIFR, 1),
+			Interrupt::PcInt2 => Self::clear_flag(sram, PCIFR, 2),
+			_ => {}
+		}
+	}
+}
+
+impl Snapshot for ExternalInterrupts {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.previous.is_some());
+		writer.bytes(&self.previous.unwrap_or_default());
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		let sampled = reader.bool()?;
+		let mut levels = PortLevels::default();
+		reader.bytes_into(&mut levels, "pin levels")?;
+		self.previous = sampled.then_some(levels);
+		Ok(())
+	}
+}
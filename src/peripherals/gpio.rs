@@ -0,0 +1,187 @@
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const MCUCR: u16 = 0x55;
+
+// MCUCR bits
+const PUD: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+	B,
+	C,
+	D,
+}
+
+impl Port {
+	pub const ALL: [Port; 3] = [Port::B, Port::C, Port::D];
+
+	pub fn pin_address(&self) -> u16 {
+		match self {
+			Port::B => 0x23,
+			Port::C => 0x26,
+			Port::D => 0x29,
+		}
+	}
+
+	pub fn ddr_address(&self) -> u16 {
+		self.pin_address() + 1
+	}
+
+	pub fn port_address(&self) -> u16 {
+		self.pin_address() + 2
+	}
+
+	/// Returns the port owning a PINx, DDRx or PORTx address
+	pub fn from_address(address: u16) -> Option<Port> {
+		match address {
+			0x23..=0x25 => Some(Port::B),
+			0x26..=0x28 => Some(Port::C),
+			0x29..=0x2B => Some(Port::D),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+	pub port: Port,
+	pub bit: u8,
+}
+
+impl Pin {
+	pub const fn new(port: Port, bit: u8) -> Self {
+		Self { port, bit }
+	}
+
+	pub fn is_output(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(self.port.ddr_address()) as u8, self.bit) != 0
+	}
+
+	/// Level driven by the PORTx register, only meaningful for output pins
+	pub fn output_level(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(self.port.port_address()) as u8, self.bit) != 0
+	}
+}
+
+pub const SS: Pin = Pin::new(Port::B, 2);
+pub const INT0: Pin = Pin::new(Port::D, 2);
+pub const INT1: Pin = Pin::new(Port::D, 3);
+
+/// Levels on all pins of each port, indexed by `Port as usize`
+pub type PortLevels = [u8; 3];
+
+/// Pin levels seen by PINx, combining the port registers with signals driven from outside
+#[derive(Default, Clone)]
+pub struct Gpio {
+	/// Pins driven by the outside world
+	driven: PortLevels,
+	/// Levels of the driven pins
+	external: PortLevels,
+}
+
+impl Gpio {
+	/// Drives `pin` from outside to `level`, or releases it with `None`
+	pub fn drive(&mut self, sram: &mut Sram, pin: Pin, level: Option<bool>) {
+		let port = pin.port as usize;
+		let mask = 1 << pin.bit;
+		match level {
+			Some(level) => {
+				self.driven[port] |= mask;
+				if level {
+					self.external[port] |= mask;
+				} else {
+					self.external[port] &= !mask;
+				}
+			}
+			None => {
+				self.driven[port] &= !mask;
+				self.external[port] &= !mask;
+			}
+		}
+		self.refresh(sram);
+	}
+
+	/// Current level on every pin of `port`
+	pub fn port_levels(&self, sram: &mut Sram, port: Port) -> u8 {
+		let ddr = sram.read(port.ddr_address()) as u8;
+		let output = sram.read(port.port_address()) as u8;
+		let pull_up = if bit(sram.read(MCUCR) as u8, PUD) == 0 {
+			output & !ddr
+		} else {
+			0
+		};
+
+		let index = port as usize;
+		let inputs = (self.external[index] & self.driven[index]) | (pull_up & !self.driven[index]);
+		(output & ddr) | (inputs & !ddr)
+	}
+
+	pub fn levels(&self, sram: &mut Sram) -> PortLevels {
+		Port::ALL.map(|port| self.port_levels(sram, port))
+	}
+
+	pub fn level(&self, sram: &mut Sram, pin: Pin) -> bool {
+		bit(self.port_levels(sram, pin.port), pin.bit) != 0
+	}
+
+	/// Mirrors the pin levels into the PINx registers
+	pub fn refresh(&self, sram: &mut Sram) {
+		for port in Port::ALL {
+			let levels = self.port_levels(sram, port);
+			sram.write(port.pin_address(), levels as u16);
+		}
+	}
+}
+
+impl Peripheral for Gpio {
+	fn reset(&mut self, sram: &mut Sram) {
+		for port in Port::ALL {
+			sram.write(port.ddr_address(), 0x00);
+			sram.write(port.port_address(), 0x00);
+		}
+		self.refresh(sram);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		Port::from_address(address).is_some()
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		self.refresh(sram);
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		let port = Port::from_address(address).unwrap();
+		if address == port.pin_address() {
+			// writing a one to PINx toggles the PORTx bit
+			let output = sram.read(port.port_address()) as u8;
+			sram.write(port.port_address(), (output ^ data) as u16);
+		} else {
+			sram.write(address, data as u16);
+		}
+		self.refresh(sram);
+	}
+
+	fn pending_interrupt(&mut self, _sram: &mut Sram) -> Option<Interrupt> {
+		None
+	}
+
+	fn acknowledge(&mut self, _sram: &mut Sram, _interrupt: Interrupt) {}
+}
+
+impl Snapshot for Gpio {
+	fn save(&self, writer: &mut Writer) {
+		writer.bytes(&self.driven);
+		writer.bytes(&self.external);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		reader.bytes_into(&mut self.driven, "pin levels")?;
+		reader.bytes_into(&mut self.external, "pin levels")?;
+		Ok(())
+	}
+}
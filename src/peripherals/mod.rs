@@ -0,0 +1,555 @@
+pub mod adc;
+pub mod analog;
+pub mod analog_comparator;
+pub mod clock;
+pub mod debug;
+pub mod eeprom;
+pub mod external_interrupt;
+pub mod gpio;
+pub mod power;
+pub mod scheduler;
+pub mod sleep;
+pub mod spi;
+pub mod timer;
+pub mod twi;
+pub mod usart;
+pub mod watchdog;
+
+use crate::memory::{EepromMemory, Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use adc::{Adc, TriggerSource};
+use analog::AnalogInputs;
+use analog_comparator::AnalogComparator;
+use clock::Clock;
+use debug::DebugPort;
+use eeprom::Eeprom;
+use external_interrupt::ExternalInterrupts;
+use gpio::{Gpio, Port};
+use power::{Module, PowerReduction, PRR};
+use scheduler::{EventSource, Scheduler};
+use sleep::SleepMode;
+use spi::Spi;
+use std::collections::VecDeque;
+use timer::{Timer0, Timer1, Timer2};
+use twi::Twi;
+use usart::Usart;
+use watchdog::Watchdog;
+
+/// Interrupt vectors in priority order (lower vector = higher priority)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Interrupt {
+	Int0 = 1,
+	Int1 = 2,
+	PcInt0 = 3,
+	PcInt1 = 4,
+	PcInt2 = 5,
+	Wdt = 6,
+	Timer2CompA = 7,
+	Timer2CompB = 8,
+	Timer2Ovf = 9,
+	Timer1Capt = 10,
+	Timer1CompA = 11,
+	Timer1CompB = 12,
+	Timer1Ovf = 13,
+	Timer0CompA = 14,
+	Timer0CompB = 15,
+	Timer0Ovf = 16,
+	SpiStc = 17,
+	UsartRx = 18,
+	UsartUdre = 19,
+	UsartTx = 20,
+	Adc = 21,
+	EeReady = 22,
+	AnalogComp = 23,
+	Twi = 24,
+	SpmReady = 25,
+}
+
+impl Interrupt {
+	/// Word address of the interrupt vector in program memory
+	pub fn vector_address(&self) -> u16 {
+		(*self as u16) * 2
+	}
+}
+
+pub trait Peripheral {
+	/// Restores internal state and owned registers to their power-on values
+	fn reset(&mut self, sram: &mut Sram);
+	fn handles(&self, address: u16) -> bool;
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8;
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8);
+	/// Cycles until `step` next needs to be called, `None` while idle
+	fn next_event(&self) -> Option<usize> {
+		None
+	}
+	/// Advances the peripheral by the cycles elapsed since it was last stepped
+	fn step(&mut self, _sram: &mut Sram, _cycles: usize) {}
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt>;
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt);
+}
+
+/// How a peripheral reaches the devices attached outside the chip. History records their
+/// answers and replays them later without the devices, which already saw the traffic.
+#[derive(Debug, Default, Clone)]
+pub enum DeviceLog {
+	#[default]
+	Live,
+	Recording(Vec<u8>),
+	Replaying(VecDeque<u8>),
+}
+
+impl DeviceLog {
+	/// True while replaying, when the devices must not be told about the traffic
+	pub fn is_replaying(&self) -> bool {
+		matches!(self, Self::Replaying(_))
+	}
+
+	/// The recorded answer while replaying, otherwise the one `device` gives
+	pub fn answer(&mut self, device: impl FnOnce() -> u8) -> u8 {
+		match self {
+			Self::Live => device(),
+			Self::Recording(answers) => {
+				let answer = device();
+				answers.push(answer);
+				answer
+			}
+			// a replay that went another way reads as an empty bus
+			Self::Replaying(answers) => answers.pop_front().unwrap_or(0xFF),
+		}
+	}
+
+	/// Answers recorded so far, going back to live devices
+	pub fn take(&mut self) -> Vec<u8> {
+		match std::mem::take(self) {
+			Self::Recording(answers) => answers,
+			_ => Vec::new(),
+		}
+	}
+}
+
+#[derive(Default, Clone)]
+pub struct Peripherals {
+	pub analog: AnalogInputs,
+	/// Mode the CPU is sleeping in, `None` while it is running
+	pub sleep_mode: Option<SleepMode>,
+	pub clock: Clock,
+	pub power: PowerReduction,
+	pub gpio: Gpio,
+	pub external_interrupts: ExternalInterrupts,
+	pub spi: Spi,
+	pub twi: Twi,
+	pub usart: Usart,
+	pub adc: Adc,
+	pub analog_comparator: AnalogComparator,
+	pub timer0: Timer0,
+	pub timer1: Timer1,
+	pub timer2: Timer2,
+	pub watchdog: Watchdog,
+	pub debug: DebugPort,
+	/// Needs the EEPROM array, which the CPU passes in for register writes and steps
+	pub eeprom: Eeprom,
+	pub scheduler: Scheduler,
+}
+
+impl Peripherals {
+	fn all(&mut self) -> [&mut dyn Peripheral; 14] {
+		[
+			&mut self.clock,
+			&mut self.power,
+			&mut self.gpio,
+			&mut self.external_interrupts,
+			&mut self.spi,
+			&mut self.twi,
+			&mut self.usart,
+			&mut self.adc,
+			&mut self.analog_comparator,
+			&mut self.timer0,
+			&mut self.timer1,
+			&mut self.timer2,
+			&mut self.watchdog,
+			&mut self.debug,
+		]
+	}
+
+	/// Records what the SPI and TWI devices answer, or with `replay` feeds back the
+	/// answers recorded for the same step and keeps every attached device out of it
+	pub fn log_devices(&mut self, replay: Option<(Vec<u8>, Vec<u8>)>) {
+		let replaying = replay.is_some();
+		(self.spi.log, self.twi.bus.log) = match replay {
+			Some((spi, twi)) => (
+				DeviceLog::Replaying(spi.into()),
+				DeviceLog::Replaying(twi.into()),
+			),
+			None => (
+				DeviceLog::Recording(Vec::new()),
+				DeviceLog::Recording(Vec::new()),
+			),
+		};
+		self.usart.muted = replaying;
+		self.debug.muted = replaying;
+	}
+
+	/// SPI and TWI answers recorded since `log_devices`, going back to live devices
+	pub fn take_device_answers(&mut self) -> (Vec<u8>, Vec<u8>) {
+		self.usart.muted = false;
+		self.debug.muted = false;
+		(self.spi.log.take(), self.twi.bus.log.take())
+	}
+
+	pub fn reset(&mut self, sram: &mut Sram) {
+		self.sleep_mode = None;
+		self.scheduler.reset();
+		for peripheral in self.all() {
+			peripheral.reset(sram);
+		}
+		self.eeprom.reset(sram);
+		self.reschedule_all(sram);
+	}
+
+	/// Resets the peripherals after a watchdog time-out in system reset mode
+	pub fn watchdog_reset(&mut self, sram: &mut Sram) {
+		let mcusr = sram.read(watchdog::MCUSR) as u8;
+		self.reset(sram);
+		self.watchdog.system_reset(sram, mcusr);
+		self.reschedule(sram, EventSource::Wdt);
+	}
+
+	/// Executed by `wdr`
+	pub fn restart_watchdog(&mut self, sram: &mut Sram) {
+		self.sync(sram, None, EventSource::Wdt);
+		self.watchdog.restart();
+		self.reschedule(sram, EventSource::Wdt);
+	}
+
+	/// Registers of a module shut down by PRR read as zero and ignore writes
+	fn is_accessible(&self, sram: &mut Sram, address: u16) -> bool {
+		Module::from_address(address).is_none_or(|module| self.power.is_powered(sram, module))
+	}
+
+	/// Registers where `sbi` and `cbi` only write the addressed bit instead of
+	/// read-modify-writing the whole register, the flags cleared by writing a one and
+	/// PINx which toggles PORTx
+	pub fn writes_single_bits(address: u16) -> bool {
+		matches!(
+			address,
+			timer::TIFR0
+				| timer::TIFR1
+				| timer::TIFR2
+				| external_interrupt::PCIFR
+				| external_interrupt::EIFR
+		) || Port::ALL.iter().any(|port| port.pin_address() == address)
+	}
+
+	pub fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		if !self.is_accessible(sram, address) {
+			return 0x00;
+		}
+
+		// counters and flags are brought up to date before they are read
+		if let Some(source) = Self::event_source(address) {
+			self.sync(sram, None, source);
+		}
+
+		match self
+			.all()
+			.into_iter()
+			.find(|peripheral| peripheral.handles(address))
+		{
+			Some(peripheral) => peripheral.read(sram, address),
+			None => sram.read(address) as u8,
+		}
+	}
+
+	pub fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		if !self.is_accessible(sram, address) {
+			return;
+		}
+
+		let source = Self::event_source(address);
+		if address == PRR {
+			self.sync_all(sram, None);
+		} else if let Some(source) = source {
+			self.sync(sram, None, source);
+		}
+
+		match self
+			.all()
+			.into_iter()
+			.find(|peripheral| peripheral.handles(address))
+		{
+			Some(peripheral) => peripheral.write(sram, address, data),
+			None => sram.write(address, data as u16),
+		}
+
+		if Port::from_address(address).is_some() {
+			self.spi.update_chip_selects(sram);
+		}
+
+		if address == PRR {
+			self.reschedule_all(sram);
+		} else if let Some(source) = source {
+			self.reschedule(sram, source);
+		}
+	}
+
+	pub fn write_eeprom(
+		&mut self,
+		sram: &mut Sram,
+		memory: &mut EepromMemory,
+		address: u16,
+		data: u8,
+	) {
+		self.sync(sram, Some(memory), EventSource::Eeprom);
+		self.eeprom.write(sram, memory, address, data);
+		self.reschedule(sram, EventSource::Eeprom);
+	}
+
+	fn event_source(address: u16) -> Option<EventSource> {
+		match address {
+			timer::TIFR0 | timer::TIMSK0 => return Some(EventSource::Timer0),
+			timer::TIFR1 | timer::TIMSK1 => return Some(EventSource::Timer1),
+			timer::TIFR2 | timer::TIMSK2 => return Some(EventSource::Timer2),
+			watchdog::MCUSR | watchdog::WDTCSR => return Some(EventSource::Wdt),
+			_ => {}
+		}
+
+		match Module::from_address(address) {
+			Some(Module::Spi) => Some(EventSource::Spi),
+			Some(Module::Twi) => Some(EventSource::Twi),
+			Some(Module::Usart0) => Some(EventSource::Usart),
+			Some(Module::Adc) => Some(EventSource::Adc),
+			Some(Module::Timer0) => Some(EventSource::Timer0),
+			Some(Module::Timer1) => Some(EventSource::Timer1),
+			Some(Module::Timer2) => Some(EventSource::Timer2),
+			_ => None,
+		}
+	}
+
+	/// Whether the clock driving `source` runs in the current sleep and power state
+	fn is_clocked(&self, sram: &mut Sram, source: EventSource) -> bool {
+		let io_clock = !self.sleep_mode.is_some_and(|mode| mode.stops_io_clock());
+		match source {
+			EventSource::Spi => io_clock && self.power.is_powered(sram, Module::Spi),
+			EventSource::Twi => io_clock && self.power.is_powered(sram, Module::Twi),
+			EventSource::Usart => io_clock && self.power.is_powered(sram, Module::Usart0),
+			EventSource::Adc => {
+				let adc_clock = io_clock || self.sleep_mode == Some(SleepMode::AdcNoiseReduction);
+				adc_clock && self.power.is_powered(sram, Module::Adc)
+			}
+			EventSource::Timer0 => io_clock && self.power.is_powered(sram, Module::Timer0),
+			EventSource::Timer1 => io_clock && self.power.is_powered(sram, Module::Timer1),
+			EventSource::Timer2 => io_clock && self.power.is_powered(sram, Module::Timer2),
+			// both run from their own oscillators
+			EventSource::Eeprom | EventSource::Wdt => true,
+		}
+	}
+
+	/// Brings `source` up to the current cycle. Time spent with its clock stopped is
+	/// dropped, so callers resync whenever the sleep or power state changes.
+	fn sync(&mut self, sram: &mut Sram, memory: Option<&mut EepromMemory>, source: EventSource) {
+		if source == EventSource::Eeprom && memory.is_none() {
+			// the EEPROM is clocked in every state, it catches up on its next event
+			return;
+		}
+
+		let elapsed = self.scheduler.take_elapsed(source);
+		if elapsed == 0 || !self.is_clocked(sram, source) {
+			return;
+		}
+
+		match source {
+			EventSource::Spi => self.spi.step(sram, elapsed),
+			EventSource::Twi => self.twi.step(sram, elapsed),
+			EventSource::Usart => self.usart.step(sram, elapsed),
+			EventSource::Adc => self.adc.clock(sram, &self.analog, elapsed),
+			EventSource::Eeprom => {
+				if let Some(memory) = memory {
+					let seconds = self.clock.duration(elapsed);
+					self.eeprom.clock(sram, memory, elapsed, seconds);
+				}
+			}
+			EventSource::Timer0 => {
+				let raised = self.timer0.clock(sram, elapsed);
+				self.trigger_adc_on(sram, Timer0::TRIGGERS, raised);
+			}
+			EventSource::Timer1 => {
+				let raised = self.timer1.clock(sram, elapsed);
+				self.trigger_adc_on(sram, Timer1::TRIGGERS, raised);
+			}
+			EventSource::Timer2 => {
+				self.timer2.clock(sram, elapsed);
+			}
+			EventSource::Wdt => {
+				let seconds = self.clock.duration(elapsed);
+				self.watchdog.clock(sram, elapsed, seconds);
+			}
+		}
+	}
+
+	fn sync_all(&mut self, sram: &mut Sram, mut memory: Option<&mut EepromMemory>) {
+		for source in EventSource::ALL {
+			self.sync(sram, memory.as_deref_mut(), source);
+		}
+	}
+
+	fn reschedule(&mut self, sram: &mut Sram, source: EventSource) {
+		let next_event = if self.is_clocked(sram, source) {
+			match source {
+				EventSource::Spi => self.spi.next_event(),
+				EventSource::Twi => self.twi.next_event(),
+				EventSource::Usart => self.usart.next_event(),
+				EventSource::Adc => self.adc.next_event(),
+				EventSource::Eeprom => self.eeprom.next_event(self.clock.frequency()),
+				EventSource::Timer0 => self.timer0.next_event(sram),
+				EventSource::Timer1 => self.timer1.next_event(sram),
+				EventSource::Timer2 => self.timer2.next_event(sram),
+				EventSource::Wdt => self.watchdog.next_event(sram, self.clock.frequency()),
+			}
+		} else {
+			None
+		};
+
+		match next_event {
+			Some(delay) => self.scheduler.schedule(source, delay),
+			None => self.scheduler.cancel(source),
+		}
+	}
+
+	fn reschedule_all(&mut self, sram: &mut Sram) {
+		for source in EventSource::ALL {
+			self.reschedule(sram, source);
+		}
+	}
+
+	fn trigger_adc(&mut self, sram: &mut Sram, source: TriggerSource) {
+		if self.power.is_powered(sram, Module::Adc) {
+			self.sync(sram, None, EventSource::Adc);
+			self.adc.trigger(sram, source);
+			self.reschedule(sram, EventSource::Adc);
+		}
+	}
+
+	/// Triggers the ADC for each timer flag in `raised` that is an auto trigger source
+	fn trigger_adc_on(&mut self, sram: &mut Sram, triggers: &[(u8, TriggerSource)], raised: u8) {
+		for (flag, source) in triggers {
+			if raised & (1 << flag) != 0 {
+				self.trigger_adc(sram, *source);
+			}
+		}
+	}
+
+	pub fn sleep(&mut self, sram: &mut Sram, memory: &mut EepromMemory, mode: SleepMode) {
+		self.sync_all(sram, Some(memory));
+		self.sleep_mode = Some(mode);
+		if mode == SleepMode::AdcNoiseReduction {
+			self.adc.enter_noise_reduction(sram);
+		}
+		self.reschedule_all(sram);
+	}
+
+	pub fn wake(&mut self, sram: &mut Sram, memory: &mut EepromMemory) {
+		self.sync_all(sram, Some(memory));
+		self.sleep_mode = None;
+		self.reschedule_all(sram);
+	}
+
+	/// Advances time by `cycles`, stepping only the peripherals whose events are due
+	pub fn step(&mut self, sram: &mut Sram, memory: &mut EepromMemory, cycles: usize) {
+		self.scheduler.advance(cycles);
+		self.clock.advance(sram, cycles);
+
+		let io_clock = !self.sleep_mode.is_some_and(|mode| mode.stops_io_clock());
+
+		let levels = self.gpio.levels(sram);
+		self.gpio.refresh(sram);
+		if self.external_interrupts.sample(sram, levels, io_clock) {
+			self.trigger_adc(sram, TriggerSource::Int0);
+		}
+
+		if self.analog_comparator.compare(sram, &self.analog) {
+			self.trigger_adc(sram, TriggerSource::AnalogComparator);
+		}
+
+		if let Some(rising) = self.analog_comparator.take_capture_edge() {
+			if self.power.is_powered(sram, Module::Timer1) {
+				self.sync(sram, None, EventSource::Timer1);
+				if self.timer1.input_capture(sram, rising) {
+					self.trigger_adc(sram, TriggerSource::Timer1Capture);
+				}
+			}
+		}
+
+		while let Some(source) = self.scheduler.pop_due() {
+			self.sync(sram, Some(memory), source);
+			self.reschedule(sram, source);
+		}
+	}
+
+	/// Returns the highest priority interrupt that is flagged and enabled. While sleeping
+	/// only interrupts able to wake the CPU from the current mode are considered.
+	pub fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let sleep_mode = self.sleep_mode;
+		let eeprom = self.eeprom.pending_interrupt(sram);
+		self.all()
+			.into_iter()
+			.filter_map(|peripheral| peripheral.pending_interrupt(sram))
+			.chain(eeprom)
+			.filter(|interrupt| sleep_mode.is_none_or(|mode| mode.wakes_on(*interrupt)))
+			.min()
+	}
+
+	pub fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		for peripheral in self.all() {
+			peripheral.acknowledge(sram, interrupt);
+		}
+	}
+}
+
+impl Snapshot for Peripherals {
+	/// The power reduction bits live in PRR, which is saved with the I/O registers
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.sleep_mode.is_some());
+		if let Some(mode) = &self.sleep_mode {
+			writer.choice(mode, &SleepMode::ALL);
+		}
+		self.analog.save(writer);
+		self.clock.save(writer);
+		self.gpio.save(writer);
+		self.external_interrupts.save(writer);
+		self.spi.save(writer);
+		self.twi.save(writer);
+		self.usart.save(writer);
+		self.adc.save(writer);
+		self.analog_comparator.save(writer);
+		self.timer0.save(writer);
+		self.timer1.save(writer);
+		self.timer2.save(writer);
+		self.watchdog.save(writer);
+		self.debug.save(writer);
+		self.eeprom.save(writer);
+		self.scheduler.save(writer);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.sleep_mode = match reader.bool()? {
+			true => Some(reader.choice(&SleepMode::ALL, "sleep mode")?),
+			false => None,
+		};
+		self.analog.restore(reader)?;
+		self.clock.restore(reader)?;
+		self.gpio.restore(reader)?;
+		self.external_interrupts.restore(reader)?;
+		self.spi.restore(reader)?;
+		self.twi.restore(reader)?;
+		self.usart.restore(reader)?;
+		self.adc.restore(reader)?;
+		self.analog_comparator.restore(reader)?;
+		self.timer0.restore(reader)?;
+		self.timer1.restore(reader)?;
+		self.timer2.restore(reader)?;
+		self.watchdog.restore(reader)?;
+		self.debug.restore(reader)?;
+		self.eeprom.restore(reader)?;
+		self.scheduler.restore(reader)
+	}
+}
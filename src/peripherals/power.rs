@@ -0,0 +1,111 @@
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::utils::bit;
+use std::ops::RangeInclusive;
+
+pub const PRR: u16 = 0x64;
+
+/// Bit 4 is reserved
+const PRR_MASK: u8 = 0xEF;
+
+/// Blocks whose clock can be stopped through PRR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+	Adc,
+	Usart0,
+	Spi,
+	Timer1,
+	Timer0,
+	Timer2,
+	Twi,
+}
+
+impl Module {
+	pub const ALL: [Module; 7] = [
+		Module::Twi,
+		Module::Timer2,
+		Module::Timer0,
+		Module::Timer1,
+		Module::Spi,
+		Module::Usart0,
+		Module::Adc,
+	];
+
+	/// Bit of the module in PRR
+	pub fn bit(&self) -> u8 {
+		match self {
+			Module::Adc => 0,
+			Module::Usart0 => 1,
+			Module::Spi => 2,
+			Module::Timer1 => 3,
+			Module::Timer0 => 5,
+			Module::Timer2 => 6,
+			Module::Twi => 7,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Module::Adc => "ADC",
+			Module::Usart0 => "USART0",
+			Module::Spi => "SPI",
+			Module::Timer1 => "TIM1",
+			Module::Timer0 => "TIM0",
+			Module::Timer2 => "TIM2",
+			Module::Twi => "TWI",
+		}
+	}
+
+	/// Registers that can not be accessed while the module is shut down
+	fn registers(&self) -> RangeInclusive<u16> {
+		match self {
+			Module::Adc => 0x78..=0x7C,
+			Module::Usart0 => 0xC0..=0xC6,
+			Module::Spi => 0x4C..=0x4E,
+			Module::Timer1 => 0x80..=0x8B,
+			Module::Timer0 => 0x44..=0x48,
+			Module::Timer2 => 0xB0..=0xB4,
+			Module::Twi => 0xB8..=0xBD,
+		}
+	}
+
+	pub fn from_address(address: u16) -> Option<Module> {
+		Module::ALL
+			.into_iter()
+			.find(|module| module.registers().contains(&address))
+	}
+}
+
+/// Power reduction register, shutting down the clock of individual modules
+#[derive(Default, Clone)]
+pub struct PowerReduction {}
+
+impl PowerReduction {
+	pub fn is_powered(&self, sram: &mut Sram, module: Module) -> bool {
+		bit(sram.read(PRR) as u8, module.bit()) == 0
+	}
+}
+
+impl Peripheral for PowerReduction {
+	fn reset(&mut self, sram: &mut Sram) {
+		sram.write(PRR, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		address == PRR
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		sram.write(address, (data & PRR_MASK) as u16);
+	}
+
+	fn pending_interrupt(&mut self, _sram: &mut Sram) -> Option<Interrupt> {
+		None
+	}
+
+	fn acknowledge(&mut self, _sram: &mut Sram, _interrupt: Interrupt) {}
+}
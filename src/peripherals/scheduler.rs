@@ -0,0 +1,145 @@
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Peripherals that advance on their own time base rather than with every instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventSource {
+	Spi,
+	Twi,
+	Usart,
+	Adc,
+	Eeprom,
+	Timer0,
+	Timer1,
+	Timer2,
+	Wdt,
+}
+
+impl EventSource {
+	pub const ALL: [EventSource; 9] = [
+		EventSource::Spi,
+		EventSource::Twi,
+		EventSource::Usart,
+		EventSource::Adc,
+		EventSource::Eeprom,
+		EventSource::Timer0,
+		EventSource::Timer1,
+		EventSource::Timer2,
+		EventSource::Wdt,
+	];
+}
+
+const SOURCES: usize = EventSource::ALL.len();
+
+/// Future events keyed on the cycle count, at most one per source
+#[derive(Default, Clone)]
+pub struct Scheduler {
+	now: usize,
+	queue: BinaryHeap<Reverse<(usize, EventSource)>>,
+	/// Cycle each source is scheduled for, entries in `queue` that disagree are stale
+	due: [Option<usize>; SOURCES],
+	/// Cycle each source was last brought up to date
+	synced: [usize; SOURCES],
+}
+
+impl Scheduler {
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+
+	/// Cycles elapsed since reset
+	pub fn now(&self) -> usize {
+		self.now
+	}
+
+	pub fn advance(&mut self, cycles: usize) {
+		self.now += cycles;
+	}
+
+	/// Schedules `source` `delay` cycles from now, replacing any earlier event
+	pub fn schedule(&mut self, source: EventSource, delay: usize) {
+		let at = self.now + delay;
+		self.due[source as usize] = Some(at);
+		self.queue.push(Reverse((at, source)));
+	}
+
+	pub fn cancel(&mut self, source: EventSource) {
+		self.due[source as usize] = None;
+	}
+
+	pub fn scheduled(&self, source: EventSource) -> Option<usize> {
+		self.due[source as usize]
+	}
+
+	fn discard_stale(&mut self) {
+		while let Some(Reverse((at, source))) = self.queue.peek() {
+			if self.due[*source as usize] == Some(*at) {
+				break;
+			}
+			self.queue.pop();
+		}
+	}
+
+	/// Cycles until the next event, zero if one is already due
+	pub fn next_event(&mut self) -> Option<usize> {
+		self.discard_stale();
+		self.queue
+			.peek()
+			.map(|Reverse((at, _))| at.saturating_sub(self.now))
+	}
+
+	/// Removes and returns the earliest event that is due
+	pub fn pop_due(&mut self) -> Option<EventSource> {
+		self.discard_stale();
+		match self.queue.peek() {
+			Some(Reverse((at, source))) if *at <= self.now => {
+				let source = *source;
+				self.queue.pop();
+				self.due[source as usize] = None;
+				Some(source)
+			}
+			_ => None,
+		}
+	}
+
+	/// Cycles since `source` was last brought up to date, marking it current
+	pub fn take_elapsed(&mut self, source: EventSource) -> usize {
+		let elapsed = self.now - self.synced[source as usize];
+		self.synced[source as usize] = self.now;
+		elapsed
+	}
+}
+
+impl Snapshot for Scheduler {
+	fn save(&self, writer: &mut Writer) {
+		writer.usize(self.now);
+		writer.u32(self.queue.len() as u32);
+		for Reverse((cycle, source)) in &self.queue {
+			writer.usize(*cycle);
+			writer.choice(source, &EventSource::ALL);
+		}
+		for (due, synced) in self.due.iter().zip(&self.synced) {
+			writer.bool(due.is_some());
+			writer.usize(due.unwrap_or_default());
+			writer.usize(*synced);
+		}
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.now = reader.usize()?;
+		self.queue.clear();
+		for _ in 0..reader.u32()? {
+			let cycle = reader.usize()?;
+			let source = reader.choice(&EventSource::ALL, "event source")?;
+			self.queue.push(Reverse((cycle, source)));
+		}
+		for (due, synced) in self.due.iter_mut().zip(&mut self.synced) {
+			let scheduled = reader.bool()?;
+			let cycle = reader.usize()?;
+			*due = scheduled.then_some(cycle);
+			*synced = reader.usize()?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,70 @@
+use super::Interrupt;
+use crate::memory::{Memory, Sram};
+use crate::utils::bit;
+
+pub const SMCR: u16 = 0x53;
+
+// SMCR bits
+const SE: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepMode {
+	Idle,
+	AdcNoiseReduction,
+	PowerDown,
+	PowerSave,
+	Standby,
+	ExtendedStandby,
+}
+
+impl SleepMode {
+	pub const ALL: [SleepMode; 6] = [
+		SleepMode::Idle,
+		SleepMode::AdcNoiseReduction,
+		SleepMode::PowerDown,
+		SleepMode::PowerSave,
+		SleepMode::Standby,
+		SleepMode::ExtendedStandby,
+	];
+
+	/// Mode entered by the `sleep` instruction, `None` unless SE is set
+	pub fn from_smcr(sram: &mut Sram) -> Option<Self> {
+		let smcr = sram.read(SMCR) as u8;
+		if bit(smcr, SE) == 0 {
+			return None;
+		}
+
+		match (smcr >> 1) & 0x7 {
+			1 => Some(SleepMode::AdcNoiseReduction),
+			2 => Some(SleepMode::PowerDown),
+			3 => Some(SleepMode::PowerSave),
+			6 => Some(SleepMode::Standby),
+			7 => Some(SleepMode::ExtendedStandby),
+			_ => Some(SleepMode::Idle),
+		}
+	}
+
+	/// clk_I/O only runs in idle, so INT0/INT1 can only sense a low level in deeper modes
+	pub fn stops_io_clock(&self) -> bool {
+		*self != SleepMode::Idle
+	}
+
+	pub fn wakes_on(&self, interrupt: Interrupt) -> bool {
+		use Interrupt::*;
+
+		let asynchronous = matches!(
+			interrupt,
+			Int0 | Int1 | PcInt0 | PcInt1 | PcInt2 | Wdt | Twi
+		);
+		let timer2 = matches!(interrupt, Timer2CompA | Timer2CompB | Timer2Ovf);
+
+		match self {
+			SleepMode::Idle => true,
+			SleepMode::AdcNoiseReduction => {
+				asynchronous || timer2 || matches!(interrupt, Adc | EeReady | SpmReady)
+			}
+			SleepMode::PowerDown | SleepMode::Standby => asynchronous,
+			SleepMode::PowerSave | SleepMode::ExtendedStandby => asynchronous || timer2,
+		}
+	}
+}
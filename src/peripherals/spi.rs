@@ -0,0 +1,381 @@
+use super::gpio::{Pin, SS};
+use super::{DeviceLog, Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const SPCR: u16 = 0x4C;
+pub const SPSR: u16 = 0x4D;
+pub const SPDR: u16 = 0x4E;
+
+// SPCR bits
+const SPIE: u8 = 7;
+const SPE: u8 = 6;
+const DORD: u8 = 5;
+const MSTR: u8 = 4;
+const CPOL: u8 = 3;
+const CPHA: u8 = 2;
+
+// SPSR bits
+const SPIF: u8 = 7;
+const WCOL: u8 = 6;
+const SPI2X: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiMode {
+	/// CPOL = 0, CPHA = 0
+	Mode0,
+	/// CPOL = 0, CPHA = 1
+	Mode1,
+	/// CPOL = 1, CPHA = 0
+	Mode2,
+	/// CPOL = 1, CPHA = 1
+	Mode3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataOrder {
+	MsbFirst,
+	LsbFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiSettings {
+	pub mode: SpiMode,
+	pub data_order: DataOrder,
+}
+
+/// A virtual chip attached to the SPI bus (flash, SD card, shift register...)
+pub trait SpiDevice {
+	/// Called when the device's chip select is driven low
+	fn select(&mut self) {}
+
+	/// Called when the device's chip select is driven high
+	fn deselect(&mut self) {}
+
+	/// Shifts `data` into the device and returns the byte it shifts out on MISO
+	fn transfer(&mut self, settings: SpiSettings, data: u8) -> u8;
+}
+
+pub type SharedSpiDevice = Rc<RefCell<dyn SpiDevice>>;
+
+#[derive(Clone)]
+struct AttachedDevice {
+	device: SharedSpiDevice,
+	chip_select: Pin,
+	selected: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+	data: u8,
+	remaining_cycles: usize,
+}
+
+#[derive(Clone)]
+pub struct Spi {
+	devices: Vec<AttachedDevice>,
+	transfer: Option<Transfer>,
+	receive_buffer: u8,
+	/// SPSR was read while SPIF was set, the next SPDR access clears the flags
+	flags_read: bool,
+	/// Level of the SS pin as driven by an external master
+	slave_select: bool,
+	pub log: DeviceLog,
+}
+
+impl Default for Spi {
+	fn default() -> Self {
+		Self {
+			devices: Vec::new(),
+			transfer: None,
+			receive_buffer: 0x00,
+			flags_read: false,
+			slave_select: true,
+			log: DeviceLog::Live,
+		}
+	}
+}
+
+impl Spi {
+	/// Attaches a device that is selected while `chip_select` is an output driven low
+	pub fn attach(&mut self, device: SharedSpiDevice, chip_select: Pin) {
+		self.devices.push(AttachedDevice {
+			device,
+			chip_select,
+			selected: false,
+		});
+	}
+
+	pub fn is_enabled(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(SPCR) as u8, SPE) != 0
+	}
+
+	pub fn is_master(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(SPCR) as u8, MSTR) != 0
+	}
+
+	pub fn is_busy(&self) -> bool {
+		self.transfer.is_some()
+	}
+
+	pub fn settings(&self, sram: &mut Sram) -> SpiSettings {
+		let spcr = sram.read(SPCR) as u8;
+		let mode = match (bit(spcr, CPOL) != 0, bit(spcr, CPHA) != 0) {
+			(false, false) => SpiMode::Mode0,
+			(false, true) => SpiMode::Mode1,
+			(true, false) => SpiMode::Mode2,
+			(true, true) => SpiMode::Mode3,
+		};
+		let data_order = if bit(spcr, DORD) != 0 {
+			DataOrder::LsbFirst
+		} else {
+			DataOrder::MsbFirst
+		};
+		SpiSettings { mode, data_order }
+	}
+
+	/// SCK period in CPU cycles as selected by SPR1:0 and SPI2X
+	pub fn clock_divider(&self, sram: &mut Sram) -> usize {
+		let divider = match sram.read(SPCR) & 0x3 {
+			0 => 4,
+			1 => 16,
+			2 => 64,
+			_ => 128,
+		};
+		if bit(sram.read(SPSR) as u8, SPI2X) != 0 {
+			divider / 2
+		} else {
+			divider
+		}
+	}
+
+	/// Drives the SS pin from an external master. Pulling SS low while configured as a
+	/// master with SS as an input drops the SPI into slave mode and sets SPIF.
+	pub fn set_slave_select(&mut self, sram: &mut Sram, level: bool) {
+		self.slave_select = level;
+
+		if !level && self.is_enabled(sram) && self.is_master(sram) && !SS.is_output(sram) {
+			let spcr = sram.read(SPCR) as u8;
+			sram.write(SPCR, (spcr & !(1 << MSTR)) as u16);
+			self.transfer = None;
+			self.set_flag(sram, SPIF);
+		}
+	}
+
+	/// Clocks a byte in from an external master while in slave mode. Returns the byte the
+	/// firmware loaded into SPDR, or `None` if the slave is disabled or not selected.
+	pub fn slave_transfer(&mut self, sram: &mut Sram, data: u8) -> Option<u8> {
+		if !self.is_enabled(sram) || self.is_master(sram) || self.slave_select {
+			return None;
+		}
+
+		let output = sram.read(SPDR) as u8;
+		self.complete(sram, data);
+		Some(output)
+	}
+
+	/// Selects or deselects attached devices whose chip select line changed level
+	pub fn update_chip_selects(&mut self, sram: &mut Sram) {
+		let replaying = self.log.is_replaying();
+		for attached in self.devices.iter_mut() {
+			let selected =
+				attached.chip_select.is_output(sram) && !attached.chip_select.output_level(sram);
+
+			if selected != attached.selected {
+				attached.selected = selected;
+				if replaying {
+					continue;
+				}
+				if selected {
+					attached.device.borrow_mut().select();
+				} else {
+					attached.device.borrow_mut().deselect();
+				}
+			}
+		}
+	}
+
+	fn set_flag(&mut self, sram: &mut Sram, flag: u8) {
+		let spsr = sram.read(SPSR) as u8;
+		sram.write(SPSR, (spsr | (1 << flag)) as u16);
+	}
+
+	fn clear_flags(&mut self, sram: &mut Sram) {
+		let spsr = sram.read(SPSR) as u8;
+		sram.write(SPSR, (spsr & !((1 << SPIF) | (1 << WCOL))) as u16);
+	}
+
+	fn start(&mut self, sram: &mut Sram, data: u8) {
+		self.transfer = Some(Transfer {
+			data,
+			remaining_cycles: 8 * self.clock_divider(sram),
+		});
+	}
+
+	fn exchange(&mut self, sram: &mut Sram, data: u8) -> u8 {
+		let settings = self.settings(sram);
+		let devices = &self.devices;
+		self.log.answer(|| {
+			devices
+				.iter()
+				.filter(|attached| attached.selected)
+				.fold(0xFF, |miso, attached| {
+					miso & attached.device.borrow_mut().transfer(settings, data)
+				})
+		})
+	}
+
+	fn complete(&mut self, sram: &mut Sram, received: u8) {
+		self.receive_buffer = received;
+		sram.write(SPDR, received as u16);
+		self.set_flag(sram, SPIF);
+	}
+}
+
+impl Peripheral for Spi {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.transfer = None;
+		self.receive_buffer = 0x00;
+		self.flags_read = false;
+		for address in SPCR..=SPDR {
+			sram.write(address, 0x00);
+		}
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		(SPCR..=SPDR).contains(&address)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		match address {
+			SPSR => {
+				let spsr = sram.read(SPSR) as u8;
+				self.flags_read = bit(spsr, SPIF) != 0 || bit(spsr, WCOL) != 0;
+				spsr
+			}
+			SPDR => {
+				if self.flags_read {
+					self.flags_read = false;
+					self.clear_flags(sram);
+				}
+				self.receive_buffer
+			}
+			_ => sram.read(address) as u8,
+		}
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			SPCR => {
+				sram.write(SPCR, data as u16);
+				if bit(data, SPE) == 0 {
+					self.transfer = None;
+				}
+			}
+			SPSR => {
+				let spsr = sram.read(SPSR) as u8;
+				let value = (spsr & !(1 << SPI2X)) | (data & (1 << SPI2X));
+				sram.write(SPSR, value as u16);
+			}
+			SPDR => {
+				if self.flags_read {
+					self.flags_read = false;
+					self.clear_flags(sram);
+				}
+
+				if self.is_busy() {
+					self.set_flag(sram, WCOL);
+					return;
+				}
+
+				sram.write(SPDR, data as u16);
+				if self.is_enabled(sram) && self.is_master(sram) {
+					self.start(sram, data);
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn next_event(&self) -> Option<usize> {
+		self.transfer
+			.as_ref()
+			.map(|transfer| transfer.remaining_cycles)
+	}
+
+	fn step(&mut self, sram: &mut Sram, cycles: usize) {
+		let transfer = match self.transfer.as_mut() {
+			Some(transfer) => transfer,
+			None => return,
+		};
+
+		if transfer.remaining_cycles > cycles {
+			transfer.remaining_cycles -= cycles;
+			return;
+		}
+
+		let data = transfer.data;
+		self.transfer = None;
+		let received = self.exchange(sram, data);
+		self.complete(sram, received);
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let enabled = bit(sram.read(SPCR) as u8, SPIE) != 0;
+		let flagged = bit(sram.read(SPSR) as u8, SPIF) != 0;
+		if enabled && flagged {
+			Some(Interrupt::SpiStc)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		if interrupt == Interrupt::SpiStc {
+			let spsr = sram.read(SPSR) as u8;
+			sram.write(SPSR, (spsr & !(1 << SPIF)) as u16);
+		}
+	}
+}
+
+impl Snapshot for Spi {
+	/// Chip selects are kept per attached device, in the order they were attached
+	fn save(&self, writer: &mut Writer) {
+		writer.u32(self.devices.len() as u32);
+		for device in &self.devices {
+			writer.bool(device.selected);
+		}
+		writer.bool(self.transfer.is_some());
+		if let Some(transfer) = &self.transfer {
+			writer.u8(transfer.data);
+			writer.usize(transfer.remaining_cycles);
+		}
+		writer.u8(self.receive_buffer);
+		writer.bool(self.flags_read);
+		writer.bool(self.slave_select);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		let count = reader.u32()? as usize;
+		for index in 0..count {
+			let selected = reader.bool()?;
+			if let Some(device) = self.devices.get_mut(index) {
+				device.selected = selected;
+			}
+		}
+		self.transfer = match reader.bool()? {
+			true => Some(Transfer {
+				data: reader.u8()?,
+				remaining_cycles: reader.usize()?,
+			}),
+			false => None,
+		};
+		self.receive_buffer = reader.u8()?;
+		self.flags_read = reader.bool()?;
+		self.slave_select = reader.bool()?;
+		Ok(())
+	}
+}
@@ -0,0 +1,553 @@
+use super::adc::TriggerSource;
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::{bit, high_byte, low_byte, to_u16};
+
+pub const TIFR0: u16 = 0x35;
+pub const TIFR1: u16 = 0x36;
+pub const TIFR2: u16 = 0x37;
+pub const TCCR0A: u16 = 0x44;
+pub const TCCR0B: u16 = 0x45;
+pub const TCNT0: u16 = 0x46;
+pub const OCR0A: u16 = 0x47;
+pub const OCR0B: u16 = 0x48;
+pub const TIMSK0: u16 = 0x6E;
+pub const TIMSK1: u16 = 0x6F;
+pub const TIMSK2: u16 = 0x70;
+pub const TCCR1A: u16 = 0x80;
+pub const TCCR1B: u16 = 0x81;
+pub const TCCR1C: u16 = 0x82;
+pub const TCNT1L: u16 = 0x84;
+pub const TCNT1H: u16 = 0x85;
+pub const ICR1L: u16 = 0x86;
+pub const ICR1H: u16 = 0x87;
+pub const OCR1AL: u16 = 0x88;
+pub const OCR1AH: u16 = 0x89;
+pub const OCR1BL: u16 = 0x8A;
+pub const OCR1BH: u16 = 0x8B;
+pub const TCCR2A: u16 = 0xB0;
+pub const TCCR2B: u16 = 0xB1;
+pub const TCNT2: u16 = 0xB2;
+pub const OCR2A: u16 = 0xB3;
+pub const OCR2B: u16 = 0xB4;
+
+// TIFRn and TIMSKn bits
+const ICF: u8 = 5;
+const OCFB: u8 = 2;
+const OCFA: u8 = 1;
+const TOV: u8 = 0;
+
+// TCCRnB bits
+const ICES1: u8 = 6;
+/// FOCnA and FOCnB of Timer0 and Timer2, strobes that always read as zero
+const FORCE_OUTPUT_COMPARE: u8 = 0xC0;
+
+pub type Timer0 = Timer<0>;
+pub type Timer1 = Timer<1>;
+pub type Timer2 = Timer<2>;
+
+struct Registers {
+	tccra: u16,
+	tccrb: u16,
+	/// TCNTn, the low byte for Timer1
+	tcnt: u16,
+	ocra: u16,
+	ocrb: u16,
+	tifr: u16,
+	timsk: u16,
+}
+
+const REGISTERS: [Registers; 3] = [
+	Registers {
+		tccra: TCCR0A,
+		tccrb: TCCR0B,
+		tcnt: TCNT0,
+		ocra: OCR0A,
+		ocrb: OCR0B,
+		tifr: TIFR0,
+		timsk: TIMSK0,
+	},
+	Registers {
+		tccra: TCCR1A,
+		tccrb: TCCR1B,
+		tcnt: TCNT1L,
+		ocra: OCR1AL,
+		ocrb: OCR1BL,
+		tifr: TIFR1,
+		timsk: TIMSK1,
+	},
+	Registers {
+		tccra: TCCR2A,
+		tccrb: TCCR2B,
+		tcnt: TCNT2,
+		ocra: OCR2A,
+		ocrb: OCR2B,
+		tifr: TIFR2,
+		timsk: TIMSK2,
+	},
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveformMode {
+	Normal,
+	Ctc,
+	FastPwm,
+	PhaseCorrectPwm,
+	PhaseFrequencyCorrectPwm,
+}
+
+impl WaveformMode {
+	fn is_pwm(&self) -> bool {
+		!matches!(self, WaveformMode::Normal | WaveformMode::Ctc)
+	}
+}
+
+/// Value the counter turns around at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Top {
+	Fixed(u16),
+	OcrA,
+	Icr,
+}
+
+/// Timer/Counter `N`, counting the prescaled I/O clock. Flags are raised on the timer
+/// clock leaving the matching count, as in the datasheet timing diagrams. External
+/// clock sources, asynchronous operation of Timer2 and the output compare pins are not
+/// emulated, a timer clocked from the T0/T1 pins is stopped.
+#[derive(Default, Clone)]
+pub struct Timer<const N: usize> {
+	/// Cycles into the current timer clock period
+	prescaler_cycles: usize,
+	/// Phase correct modes count down from TOP back to BOTTOM
+	counting_down: bool,
+	/// OCRnA and OCRnB as used for compare matches, double buffered in the PWM modes
+	compare: [u16; 2],
+	/// High byte of 16-bit accesses to Timer1
+	temp: u8,
+	/// Writing TCNTn blocks compare matches on the next timer clock
+	compare_blocked: bool,
+}
+
+impl<const N: usize> Timer<N> {
+	const MAX: u16 = if N == 1 { 0xFFFF } else { 0xFF };
+
+	fn registers(&self) -> &'static Registers {
+		&REGISTERS[N]
+	}
+
+	/// ADC auto trigger sources following the flags of this timer
+	pub const TRIGGERS: &'static [(u8, TriggerSource)] = match N {
+		0 => &[
+			(OCFA, TriggerSource::Timer0CompareA),
+			(TOV, TriggerSource::Timer0Overflow),
+		],
+		1 => &[
+			(OCFB, TriggerSource::Timer1CompareB),
+			(TOV, TriggerSource::Timer1Overflow),
+			(ICF, TriggerSource::Timer1Capture),
+		],
+		_ => &[],
+	};
+
+	/// Interrupt flags in priority order
+	const INTERRUPTS: &'static [(u8, Interrupt)] = match N {
+		0 => &[
+			(OCFA, Interrupt::Timer0CompA),
+			(OCFB, Interrupt::Timer0CompB),
+			(TOV, Interrupt::Timer0Ovf),
+		],
+		1 => &[
+			(ICF, Interrupt::Timer1Capt),
+			(OCFA, Interrupt::Timer1CompA),
+			(OCFB, Interrupt::Timer1CompB),
+			(TOV, Interrupt::Timer1Ovf),
+		],
+		_ => &[
+			(OCFA, Interrupt::Timer2CompA),
+			(OCFB, Interrupt::Timer2CompB),
+			(TOV, Interrupt::Timer2Ovf),
+		],
+	};
+
+	/// Timer clock division selected by CSn2:0, `None` while stopped
+	pub fn prescaler(&self, sram: &mut Sram) -> Option<usize> {
+		let clock_select = sram.read(self.registers().tccrb) & 0x7;
+		if N == 2 {
+			match clock_select {
+				0 => None,
+				1 => Some(1),
+				2 => Some(8),
+				3 => Some(32),
+				4 => Some(64),
+				5 => Some(128),
+				6 => Some(256),
+				_ => Some(1024),
+			}
+		} else {
+			match clock_select {
+				1 => Some(1),
+				2 => Some(8),
+				3 => Some(64),
+				4 => Some(256),
+				5 => Some(1024),
+				_ => None,
+			}
+		}
+	}
+
+	/// Mode and TOP selected by WGMn, reserved values count in normal mode
+	pub fn waveform(&self, sram: &mut Sram) -> (WaveformMode, Top) {
+		use WaveformMode::*;
+
+		let tccra = sram.read(self.registers().tccra) as u8;
+		let tccrb = sram.read(self.registers().tccrb) as u8;
+		let wgm = (tccra & 0x3) | ((tccrb >> 1) & 0xC);
+
+		if N == 1 {
+			match wgm {
+				1 => (PhaseCorrectPwm, Top::Fixed(0xFF)),
+				2 => (PhaseCorrectPwm, Top::Fixed(0x1FF)),
+				3 => (PhaseCorrectPwm, Top::Fixed(0x3FF)),
+				4 => (Ctc, Top::OcrA),
+				5 => (FastPwm, Top::Fixed(0xFF)),
+				6 => (FastPwm, Top::Fixed(0x1FF)),
+				7 => (FastPwm, Top::Fixed(0x3FF)),
+				8 => (PhaseFrequencyCorrectPwm, Top::Icr),
+				9 => (PhaseFrequencyCorrectPwm, Top::OcrA),
+				10 => (PhaseCorrectPwm, Top::Icr),
+				11 => (PhaseCorrectPwm, Top::OcrA),
+				12 => (Ctc, Top::Icr),
+				14 => (FastPwm, Top::Icr),
+				15 => (FastPwm, Top::OcrA),
+				_ => (Normal, Top::Fixed(Self::MAX)),
+			}
+		} else {
+			match wgm & 0x7 {
+				1 => (PhaseCorrectPwm, Top::Fixed(0xFF)),
+				2 => (Ctc, Top::OcrA),
+				3 => (FastPwm, Top::Fixed(0xFF)),
+				5 => (PhaseCorrectPwm, Top::OcrA),
+				7 => (FastPwm, Top::OcrA),
+				_ => (Normal, Top::Fixed(Self::MAX)),
+			}
+		}
+	}
+
+	fn top(&self, sram: &mut Sram, top: Top) -> u16 {
+		match top {
+			Top::Fixed(value) => value,
+			Top::OcrA => self.compare[0],
+			Top::Icr => to_u16(sram.read(ICR1H) as u8, sram.read(ICR1L) as u8),
+		}
+	}
+
+	pub fn count(&self, sram: &mut Sram) -> u16 {
+		let tcnt = self.registers().tcnt;
+		if N == 1 {
+			to_u16(sram.read(tcnt + 1) as u8, sram.read(tcnt) as u8)
+		} else {
+			sram.read(tcnt)
+		}
+	}
+
+	fn set_count(&self, sram: &mut Sram, count: u16) {
+		let tcnt = self.registers().tcnt;
+		sram.write(tcnt, low_byte(count));
+		if N == 1 {
+			sram.write(tcnt + 1, high_byte(count));
+		}
+	}
+
+	/// Value written to OCRnA or OCRnB, which only takes effect at the update point in
+	/// the PWM modes
+	fn compare_buffer(&self, sram: &mut Sram, index: usize) -> u16 {
+		let address = [self.registers().ocra, self.registers().ocrb][index];
+		if N == 1 {
+			to_u16(sram.read(address + 1) as u8, sram.read(address) as u8)
+		} else {
+			sram.read(address)
+		}
+	}
+
+	fn update_compare(&mut self, sram: &mut Sram) {
+		self.compare = [self.compare_buffer(sram, 0), self.compare_buffer(sram, 1)];
+	}
+
+	/// Timer clocks that only move the counter before one that matches, turns around or
+	/// raises a flag
+	fn plain_ticks(&self, sram: &mut Sram) -> usize {
+		if self.compare_blocked {
+			return 0;
+		}
+
+		let (mode, top) = self.waveform(sram);
+		let top = self.top(sram, top);
+		let count = self.count(sram);
+		let phase_correct = matches!(
+			mode,
+			WaveformMode::PhaseCorrectPwm | WaveformMode::PhaseFrequencyCorrectPwm
+		);
+		if phase_correct && !self.counting_down && count >= top {
+			return 0;
+		}
+
+		let stops = [self.compare[0], self.compare[1], top, Self::MAX];
+
+		if self.counting_down {
+			stops
+				.into_iter()
+				.filter(|stop| *stop <= count)
+				.map(|stop| count - stop)
+				.min()
+				.unwrap_or(count) as usize
+		} else {
+			stops
+				.into_iter()
+				.filter(|stop| *stop >= count)
+				.map(|stop| stop - count)
+				.min()
+				.unwrap_or(0) as usize
+		}
+	}
+
+	/// Advances the counter by one timer clock, returns the flags that were raised
+	fn tick(&mut self, sram: &mut Sram) -> u8 {
+		use WaveformMode::*;
+
+		let (mode, top_source) = self.waveform(sram);
+		let top = self.top(sram, top_source);
+		let count = self.count(sram);
+		let mut flags = 0;
+
+		if !std::mem::take(&mut self.compare_blocked) {
+			if count == self.compare[0] {
+				flags |= 1 << OCFA;
+			}
+			if count == self.compare[1] {
+				flags |= 1 << OCFB;
+			}
+		}
+		if top_source == Top::Icr && count == top && !self.counting_down {
+			flags |= 1 << ICF;
+		}
+
+		let next = match mode {
+			Normal | Ctc | FastPwm if count == top || count == Self::MAX => {
+				if count == Self::MAX || mode == FastPwm {
+					flags |= 1 << TOV;
+				}
+				if mode == FastPwm {
+					self.update_compare(sram);
+				}
+				0
+			}
+			Normal | Ctc | FastPwm => count + 1,
+			PhaseCorrectPwm | PhaseFrequencyCorrectPwm if self.counting_down => {
+				if count == 0 {
+					flags |= 1 << TOV;
+					self.counting_down = false;
+					if mode == PhaseFrequencyCorrectPwm {
+						self.update_compare(sram);
+					}
+					1.min(top)
+				} else {
+					count - 1
+				}
+			}
+			PhaseCorrectPwm | PhaseFrequencyCorrectPwm => {
+				if count >= top {
+					self.counting_down = true;
+					if mode == PhaseCorrectPwm {
+						self.update_compare(sram);
+					}
+					count.saturating_sub(1)
+				} else {
+					count + 1
+				}
+			}
+		};
+		self.set_count(sram, next);
+
+		let tifr = sram.read(self.registers().tifr) as u8;
+		sram.write(self.registers().tifr, (tifr | flags) as u16);
+		flags & !tifr
+	}
+
+	/// Cycles until the next timer clock that matches, turns around or raises a flag
+	pub fn next_event(&self, sram: &mut Sram) -> Option<usize> {
+		let prescaler = self.prescaler(sram)?;
+		let ticks = self.plain_ticks(sram) + 1;
+		Some(ticks * prescaler - self.prescaler_cycles)
+	}
+
+	/// Counts the timer clocks in `cycles`, returns the flags that went from clear to set
+	pub fn clock(&mut self, sram: &mut Sram, cycles: usize) -> u8 {
+		let prescaler = match self.prescaler(sram) {
+			Some(prescaler) => prescaler,
+			None => return 0,
+		};
+
+		let cycles = self.prescaler_cycles + cycles;
+		let mut ticks = cycles / prescaler;
+		self.prescaler_cycles = cycles % prescaler;
+
+		let mut raised = 0;
+		while ticks > 0 {
+			let plain = self.plain_ticks(sram).min(ticks);
+			if plain == 0 {
+				raised |= self.tick(sram);
+				ticks -= 1;
+				continue;
+			}
+
+			let count = self.count(sram);
+			let count = if self.counting_down {
+				count - plain as u16
+			} else {
+				count + plain as u16
+			};
+			self.set_count(sram, count);
+			ticks -= plain;
+		}
+		raised
+	}
+
+	/// Writes a 16-bit register through TEMP, the high byte is latched until the low
+	/// byte is written
+	fn write_wide(&mut self, sram: &mut Sram, address: u16, data: u8) -> Option<u16> {
+		if address & 1 == 1 {
+			self.temp = data;
+			return None;
+		}
+		sram.write(address, data as u16);
+		sram.write(address + 1, self.temp as u16);
+		Some(to_u16(self.temp, data))
+	}
+}
+
+impl Timer1 {
+	/// Signals an edge on the input capture source, latching TCNT1 into ICR1 when ICES1
+	/// selects it. Returns true when ICF1 was raised.
+	pub fn input_capture(&mut self, sram: &mut Sram, rising: bool) -> bool {
+		let tccrb = sram.read(TCCR1B) as u8;
+		if (bit(tccrb, ICES1) != 0) != rising {
+			return false;
+		}
+
+		// ICR1 is not updated while it holds TOP
+		if self.waveform(sram).1 != Top::Icr {
+			let count = self.count(sram);
+			sram.write(ICR1L, low_byte(count));
+			sram.write(ICR1H, high_byte(count));
+		}
+
+		let tifr = sram.read(TIFR1) as u8;
+		sram.write(TIFR1, (tifr | (1 << ICF)) as u16);
+		bit(tifr, ICF) == 0
+	}
+}
+
+impl<const N: usize> Peripheral for Timer<N> {
+	fn reset(&mut self, sram: &mut Sram) {
+		*self = Self::default();
+		let registers = self.registers();
+		let last = if N == 1 { OCR1BH } else { registers.ocrb };
+		for address in registers.tccra..=last {
+			sram.write(address, 0x00);
+		}
+		sram.write(registers.tifr, 0x00);
+		sram.write(registers.timsk, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		let registers = self.registers();
+		let last = if N == 1 { OCR1BH } else { registers.ocrb };
+		(registers.tccra..=last).contains(&address)
+			|| address == registers.tifr
+			|| address == registers.timsk
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		match address {
+			TCNT1L | ICR1L if N == 1 => self.temp = sram.read(address + 1) as u8,
+			TCNT1H | ICR1H if N == 1 => return self.temp,
+			_ => {}
+		}
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		let registers = self.registers();
+
+		if address == registers.tifr {
+			// interrupt flags are cleared by writing a logical one
+			let flags = sram.read(address) as u8;
+			sram.write(address, (flags & !data) as u16);
+			return;
+		}
+
+		match address {
+			TCCR1C if N == 1 => {}
+			TCNT1L | TCNT1H | ICR1L | ICR1H | OCR1AL | OCR1AH | OCR1BL | OCR1BH if N == 1 => {
+				let written = self.write_wide(sram, address, data);
+				if written.is_some() && address & !1 == TCNT1L {
+					self.compare_blocked = true;
+				}
+			}
+			_ if address == registers.tcnt => {
+				sram.write(address, data as u16);
+				self.compare_blocked = true;
+			}
+			_ if address == registers.tccrb && N != 1 => {
+				sram.write(address, (data & !FORCE_OUTPUT_COMPARE) as u16);
+			}
+			_ => sram.write(address, data as u16),
+		}
+
+		if !self.waveform(sram).0.is_pwm() {
+			self.update_compare(sram);
+			self.counting_down = false;
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let registers = self.registers();
+		let pending = sram.read(registers.tifr) as u8 & sram.read(registers.timsk) as u8;
+		Self::INTERRUPTS
+			.iter()
+			.find(|(flag, _)| bit(pending, *flag) != 0)
+			.map(|(_, interrupt)| *interrupt)
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		let tifr = self.registers().tifr;
+		if let Some((flag, _)) = Self::INTERRUPTS
+			.iter()
+			.find(|(_, vector)| *vector == interrupt)
+		{
+			let flags = sram.read(tifr) as u8;
+			sram.write(tifr, (flags & !(1 << flag)) as u16);
+		}
+	}
+}
+
+impl<const N: usize> Snapshot for Timer<N> {
+	fn save(&self, writer: &mut Writer) {
+		writer.usize(self.prescaler_cycles);
+		writer.bool(self.counting_down);
+		writer.u16(self.compare[0]);
+		writer.u16(self.compare[1]);
+		writer.u8(self.temp);
+		writer.bool(self.compare_blocked);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.prescaler_cycles = reader.usize()?;
+		self.counting_down = reader.bool()?;
+		self.compare = [reader.u16()?, reader.u16()?];
+		self.temp = reader.u8()?;
+		self.compare_blocked = reader.bool()?;
+		Ok(())
+	}
+}
@@ -0,0 +1,628 @@
+use super::{DeviceLog, Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const TWBR: u16 = 0xB8;
+pub const TWSR: u16 = 0xB9;
+pub const TWAR: u16 = 0xBA;
+pub const TWDR: u16 = 0xBB;
+pub const TWCR: u16 = 0xBC;
+pub const TWAMR: u16 = 0xBD;
+
+// TWCR bits
+const TWINT: u8 = 7;
+const TWEA: u8 = 6;
+const TWSTA: u8 = 5;
+const TWSTO: u8 = 4;
+const TWWC: u8 = 3;
+const TWEN: u8 = 2;
+const TWIE: u8 = 0;
+
+// TWAR bits
+const TWGCE: u8 = 0;
+
+pub mod status {
+	pub const START: u8 = 0x08;
+	pub const REPEATED_START: u8 = 0x10;
+	pub const MT_SLA_ACK: u8 = 0x18;
+	pub const MT_SLA_NACK: u8 = 0x20;
+	pub const MT_DATA_ACK: u8 = 0x28;
+	pub const MT_DATA_NACK: u8 = 0x30;
+	pub const ARBITRATION_LOST: u8 = 0x38;
+	pub const MR_SLA_ACK: u8 = 0x40;
+	pub const MR_SLA_NACK: u8 = 0x48;
+	pub const MR_DATA_ACK: u8 = 0x50;
+	pub const MR_DATA_NACK: u8 = 0x58;
+	pub const SR_SLA_ACK: u8 = 0x60;
+	pub const SR_ARBITRATION_LOST_SLA_ACK: u8 = 0x68;
+	pub const SR_GCALL_ACK: u8 = 0x70;
+	pub const SR_ARBITRATION_LOST_GCALL_ACK: u8 = 0x78;
+	pub const SR_DATA_ACK: u8 = 0x80;
+	pub const SR_DATA_NACK: u8 = 0x88;
+	pub const SR_GCALL_DATA_ACK: u8 = 0x90;
+	pub const SR_GCALL_DATA_NACK: u8 = 0x98;
+	pub const SR_STOP: u8 = 0xA0;
+	pub const ST_SLA_ACK: u8 = 0xA8;
+	pub const ST_ARBITRATION_LOST_SLA_ACK: u8 = 0xB0;
+	pub const ST_DATA_ACK: u8 = 0xB8;
+	pub const ST_DATA_NACK: u8 = 0xC0;
+	pub const ST_LAST_DATA: u8 = 0xC8;
+	pub const NO_INFO: u8 = 0xF8;
+	pub const BUS_ERROR: u8 = 0x00;
+}
+
+/// A virtual chip attached to the I2C bus (EEPROM, RTC, sensor...)
+pub trait I2cDevice {
+	/// Called when the device's address is sent, returns true to acknowledge
+	fn start(&mut self, _read: bool) -> bool {
+		true
+	}
+
+	/// Receives a byte from the master, returns true to acknowledge
+	fn write(&mut self, data: u8) -> bool;
+
+	/// Returns the next byte requested by the master
+	fn read(&mut self) -> u8;
+
+	/// Called on a STOP condition or when another device is addressed
+	fn stop(&mut self) {}
+}
+
+pub type SharedI2cDevice = Rc<RefCell<dyn I2cDevice>>;
+
+/// Host side of the two-wire bus that virtual devices join by 7-bit address
+#[derive(Default, Clone)]
+pub struct I2cBus {
+	devices: Vec<(u8, SharedI2cDevice)>,
+	active: Option<usize>,
+	pub log: DeviceLog,
+}
+
+impl I2cBus {
+	pub fn attach(&mut self, address: u8, device: SharedI2cDevice) {
+		self.devices.push((address & 0x7F, device));
+	}
+
+	fn start(&mut self, address: u8, read: bool) -> bool {
+		self.stop();
+		self.active = self
+			.devices
+			.iter()
+			.position(|(device_address, _)| *device_address == address);
+
+		let (devices, active) = (&self.devices, self.active);
+		self.log.answer(|| match active {
+			Some(index) => devices[index].1.borrow_mut().start(read) as u8,
+			None => 0,
+		}) != 0
+	}
+
+	fn write(&mut self, data: u8) -> bool {
+		let (devices, active) = (&self.devices, self.active);
+		self.log.answer(|| match active {
+			Some(index) => devices[index].1.borrow_mut().write(data) as u8,
+			None => 0,
+		}) != 0
+	}
+
+	fn read(&mut self) -> u8 {
+		let (devices, active) = (&self.devices, self.active);
+		self.log.answer(|| match active {
+			Some(index) => devices[index].1.borrow_mut().read(),
+			None => 0xFF,
+		})
+	}
+
+	fn stop(&mut self) {
+		if let Some(index) = self.active.take().filter(|_| !self.log.is_replaying()) {
+			self.devices[index].1.borrow_mut().stop();
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+	Idle,
+	MasterStarted,
+	MasterTransmit,
+	MasterReceive,
+	SlaveReceive { general_call: bool },
+	SlaveTransmit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+	Start,
+	Address(u8),
+	Write(u8),
+	Read { ack: bool },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingOperation {
+	operation: Operation,
+	remaining_cycles: usize,
+}
+
+#[derive(Clone)]
+pub struct Twi {
+	pub bus: I2cBus,
+	state: State,
+	pending: Option<PendingOperation>,
+	/// TWEA at the time the last slave byte was acknowledged
+	slave_ack: bool,
+}
+
+impl Default for Twi {
+	fn default() -> Self {
+		Self {
+			bus: I2cBus::default(),
+			state: State::Idle,
+			pending: None,
+			slave_ack: false,
+		}
+	}
+}
+
+impl Twi {
+	pub fn is_enabled(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(TWCR) as u8, TWEN) != 0
+	}
+
+	/// SCL period in CPU cycles: 16 + 2 * TWBR * 4^TWPS
+	pub fn bit_period(&self, sram: &mut Sram) -> usize {
+		let twbr = sram.read(TWBR) as usize;
+		let prescaler = 4_usize.pow((sram.read(TWSR) & 0x3) as u32);
+		16 + 2 * twbr * prescaler
+	}
+
+	pub fn status(&self, sram: &mut Sram) -> u8 {
+		sram.read(TWSR) as u8 & 0xF8
+	}
+
+	fn set_status(&mut self, sram: &mut Sram, status: u8) {
+		let prescaler = sram.read(TWSR) as u8 & 0x3;
+		sram.write(TWSR, (status | prescaler) as u16);
+	}
+
+	fn interrupt_flag(&self, sram: &mut Sram) -> bool {
+		bit(sram.read(TWCR) as u8, TWINT) != 0
+	}
+
+	fn set_interrupt_flag(&mut self, sram: &mut Sram) {
+		let twcr = sram.read(TWCR) as u8;
+		sram.write(TWCR, (twcr | (1 << TWINT)) as u16);
+	}
+
+	fn control_bit(&self, sram: &mut Sram, control_bit: u8) -> bool {
+		bit(sram.read(TWCR) as u8, control_bit) != 0
+	}
+
+	fn finish(&mut self, sram: &mut Sram, status: u8) {
+		self.set_status(sram, status);
+		self.set_interrupt_flag(sram);
+	}
+
+	fn schedule(&mut self, sram: &mut Sram, operation: Operation) {
+		let bits = match operation {
+			Operation::Start => 1,
+			_ => 9,
+		};
+		self.pending = Some(PendingOperation {
+			operation,
+			remaining_cycles: bits * self.bit_period(sram),
+		});
+	}
+
+	fn is_master(&self) -> bool {
+		matches!(
+			self.state,
+			State::MasterStarted | State::MasterTransmit | State::MasterReceive
+		)
+	}
+
+	fn execute(&mut self, sram: &mut Sram, operation: Operation) {
+		match operation {
+			Operation::Start => {
+				let status = if self.is_master() {
+					status::REPEATED_START
+				} else {
+					status::START
+				};
+				self.state = State::MasterStarted;
+				self.finish(sram, status);
+			}
+			Operation::Address(sla) => {
+				let read = sla & 1 != 0;
+				let ack = self.bus.start(sla >> 1, read);
+				let status = match (read, ack) {
+					(false, true) => status::MT_SLA_ACK,
+					(false, false) => status::MT_SLA_NACK,
+					(true, true) => status::MR_SLA_ACK,
+					(true, false) => status::MR_SLA_NACK,
+				};
+				self.state = if read {
+					State::MasterReceive
+				} else {
+					State::MasterTransmit
+				};
+				self.finish(sram, status);
+			}
+			Operation::Write(data) => {
+				let status = if self.bus.write(data) {
+					status::MT_DATA_ACK
+				} else {
+					status::MT_DATA_NACK
+				};
+				self.finish(sram, status);
+			}
+			Operation::Read { ack } => {
+				let data = self.bus.read();
+				sram.write(TWDR, data as u16);
+				let status = if ack {
+					status::MR_DATA_ACK
+				} else {
+					status::MR_DATA_NACK
+				};
+				self.finish(sram, status);
+			}
+		}
+	}
+
+	fn stop(&mut self, sram: &mut Sram) {
+		if self.is_master() {
+			self.bus.stop();
+		}
+		self.state = State::Idle;
+		self.pending = None;
+		self.set_status(sram, status::NO_INFO);
+	}
+
+	/// Matches an address sent by an external master against TWAR and TWAMR, returning
+	/// whether it was a general call
+	fn matches_address(&self, sram: &mut Sram, address: u8) -> Option<bool> {
+		let twar = sram.read(TWAR) as u8;
+		let mask = sram.read(TWAMR) as u8 >> 1;
+
+		if address == 0 && bit(twar, TWGCE) != 0 {
+			return Some(true);
+		}
+
+		if (address ^ (twar >> 1)) & !mask & 0x7F == 0 {
+			Some(false)
+		} else {
+			None
+		}
+	}
+
+	/// Addresses the AVR from an external master. Returns whether the slave acknowledged,
+	/// or `None` while the slave is still holding SCL low.
+	pub fn host_start(&mut self, sram: &mut Sram, address: u8, read: bool) -> Option<bool> {
+		if !self.is_enabled(sram) || !self.control_bit(sram, TWEA) {
+			return Some(false);
+		}
+
+		let general_call = match self.matches_address(sram, address) {
+			Some(general_call) => general_call,
+			None => return Some(false),
+		};
+
+		if self.interrupt_flag(sram) && !self.is_master() {
+			return None;
+		}
+
+		let arbitration_lost = self.is_master() || self.pending.is_some();
+		self.pending = None;
+
+		let status = match (read, general_call, arbitration_lost) {
+			(true, _, false) => status::ST_SLA_ACK,
+			(true, _, true) => status::ST_ARBITRATION_LOST_SLA_ACK,
+			(false, false, false) => status::SR_SLA_ACK,
+			(false, false, true) => status::SR_ARBITRATION_LOST_SLA_ACK,
+			(false, true, false) => status::SR_GCALL_ACK,
+			(false, true, true) => status::SR_ARBITRATION_LOST_GCALL_ACK,
+		};
+
+		self.state = if read {
+			State::SlaveTransmit
+		} else {
+			State::SlaveReceive { general_call }
+		};
+		self.slave_ack = true;
+		self.finish(sram, status);
+		Some(true)
+	}
+
+	/// Makes an external master win arbitration against the AVR's own master transfer
+	pub fn host_arbitrate(&mut self, sram: &mut Sram) {
+		if self.is_master() || self.pending.is_some() {
+			self.pending = None;
+			self.state = State::Idle;
+			self.finish(sram, status::ARBITRATION_LOST);
+		}
+	}
+
+	/// Sends a byte to the addressed AVR. Returns whether it was acknowledged, or `None`
+	/// while the slave is still holding SCL low.
+	pub fn host_write(&mut self, sram: &mut Sram, data: u8) -> Option<bool> {
+		let general_call = match self.state {
+			State::SlaveReceive { general_call } => general_call,
+			_ => return Some(false),
+		};
+
+		if self.interrupt_flag(sram) {
+			return None;
+		}
+
+		if !self.slave_ack {
+			return Some(false);
+		}
+
+		let ack = self.control_bit(sram, TWEA);
+		sram.write(TWDR, data as u16);
+		let status = match (general_call, ack) {
+			(false, true) => status::SR_DATA_ACK,
+			(false, false) => status::SR_DATA_NACK,
+			(true, true) => status::SR_GCALL_DATA_ACK,
+			(true, false) => status::SR_GCALL_DATA_NACK,
+		};
+		self.slave_ack = ack;
+		self.finish(sram, status);
+		Some(ack)
+	}
+
+	/// Reads a byte from the addressed AVR, acknowledging it with `ack`. Returns `None`
+	/// while the slave is still holding SCL low.
+	pub fn host_read(&mut self, sram: &mut Sram, ack: bool) -> Option<u8> {
+		if self.state != State::SlaveTransmit {
+			return Some(0xFF);
+		}
+
+		if self.interrupt_flag(sram) {
+			return None;
+		}
+
+		if !self.slave_ack {
+			return Some(0xFF);
+		}
+
+		let data = sram.read(TWDR) as u8;
+		let last = !self.control_bit(sram, TWEA);
+		let status = match (ack, last) {
+			(false, _) => status::ST_DATA_NACK,
+			(true, false) => status::ST_DATA_ACK,
+			(true, true) => status::ST_LAST_DATA,
+		};
+		self.slave_ack = ack && !last;
+		self.finish(sram, status);
+		Some(data)
+	}
+
+	/// Ends the transfer started by the external master
+	pub fn host_stop(&mut self, sram: &mut Sram) {
+		let addressed = matches!(
+			self.state,
+			State::SlaveReceive { .. } | State::SlaveTransmit
+		);
+		if !addressed {
+			return;
+		}
+
+		self.state = State::Idle;
+		if self.slave_ack && !self.interrupt_flag(sram) {
+			self.finish(sram, status::SR_STOP);
+		}
+	}
+}
+
+impl Peripheral for Twi {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.bus.stop();
+		self.state = State::Idle;
+		self.pending = None;
+		self.slave_ack = false;
+		sram.write(TWBR, 0x00);
+		sram.write(TWSR, status::NO_INFO as u16);
+		sram.write(TWAR, 0xFE);
+		sram.write(TWDR, 0xFF);
+		sram.write(TWCR, 0x00);
+		sram.write(TWAMR, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		(TWBR..=TWAMR).contains(&address)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			TWSR => {
+				let twsr = sram.read(TWSR) as u8;
+				sram.write(TWSR, ((twsr & 0xF8) | (data & 0x3)) as u16);
+			}
+			TWDR => {
+				if self.interrupt_flag(sram) {
+					sram.write(TWDR, data as u16);
+					let twcr = sram.read(TWCR) as u8;
+					sram.write(TWCR, (twcr & !(1 << TWWC)) as u16);
+				} else {
+					let twcr = sram.read(TWCR) as u8;
+					sram.write(TWCR, (twcr | (1 << TWWC)) as u16);
+				}
+			}
+			TWCR => {
+				let twcr = sram.read(TWCR) as u8;
+				let clear_flag = bit(data, TWINT) != 0;
+				let flag = if clear_flag { 0 } else { twcr & (1 << TWINT) };
+				let value = flag | (twcr & (1 << TWWC)) | (data & !((1 << TWINT) | (1 << TWWC)));
+				sram.write(TWCR, value as u16);
+
+				if bit(data, TWEN) == 0 {
+					self.bus.stop();
+					self.state = State::Idle;
+					self.pending = None;
+					return;
+				}
+
+				if !clear_flag {
+					return;
+				}
+
+				if bit(data, TWSTO) != 0 {
+					self.stop(sram);
+					let twcr = sram.read(TWCR) as u8;
+					sram.write(TWCR, (twcr & !(1 << TWSTO)) as u16);
+				}
+
+				let operation = if bit(data, TWSTA) != 0 {
+					Some(Operation::Start)
+				} else {
+					match self.state {
+						State::MasterStarted => Some(Operation::Address(sram.read(TWDR) as u8)),
+						State::MasterTransmit => Some(Operation::Write(sram.read(TWDR) as u8)),
+						State::MasterReceive => Some(Operation::Read {
+							ack: bit(data, TWEA) != 0,
+						}),
+						_ => None,
+					}
+				};
+
+				if let Some(operation) = operation {
+					self.schedule(sram, operation);
+				}
+			}
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn next_event(&self) -> Option<usize> {
+		self.pending
+			.as_ref()
+			.map(|pending| pending.remaining_cycles)
+	}
+
+	fn step(&mut self, sram: &mut Sram, cycles: usize) {
+		let pending = match self.pending.as_mut() {
+			Some(pending) => pending,
+			None => return,
+		};
+
+		if pending.remaining_cycles > cycles {
+			pending.remaining_cycles -= cycles;
+			return;
+		}
+
+		let operation = pending.operation;
+		self.pending = None;
+		self.execute(sram, operation);
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		if self.control_bit(sram, TWIE) && self.interrupt_flag(sram) {
+			Some(Interrupt::Twi)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, _sram: &mut Sram, _interrupt: Interrupt) {}
+}
+
+impl State {
+	fn save(&self, writer: &mut Writer) {
+		match self {
+			State::Idle => writer.u8(0),
+			State::MasterStarted => writer.u8(1),
+			State::MasterTransmit => writer.u8(2),
+			State::MasterReceive => writer.u8(3),
+			State::SlaveReceive { general_call } => {
+				writer.u8(4);
+				writer.bool(*general_call);
+			}
+			State::SlaveTransmit => writer.u8(5),
+		}
+	}
+
+	fn restore(reader: &mut Reader) -> snapshot::Result<Self> {
+		Ok(match reader.u8()? {
+			0 => State::Idle,
+			1 => State::MasterStarted,
+			2 => State::MasterTransmit,
+			3 => State::MasterReceive,
+			4 => State::SlaveReceive {
+				general_call: reader.bool()?,
+			},
+			5 => State::SlaveTransmit,
+			_ => return Err(snapshot::Error::Invalid("TWI state")),
+		})
+	}
+}
+
+impl Operation {
+	fn save(&self, writer: &mut Writer) {
+		match self {
+			Operation::Start => writer.u8(0),
+			Operation::Address(byte) => {
+				writer.u8(1);
+				writer.u8(*byte);
+			}
+			Operation::Write(byte) => {
+				writer.u8(2);
+				writer.u8(*byte);
+			}
+			Operation::Read { ack } => {
+				writer.u8(3);
+				writer.bool(*ack);
+			}
+		}
+	}
+
+	fn restore(reader: &mut Reader) -> snapshot::Result<Self> {
+		Ok(match reader.u8()? {
+			0 => Operation::Start,
+			1 => Operation::Address(reader.u8()?),
+			2 => Operation::Write(reader.u8()?),
+			3 => Operation::Read {
+				ack: reader.bool()?,
+			},
+			_ => return Err(snapshot::Error::Invalid("TWI operation")),
+		})
+	}
+}
+
+impl Snapshot for Twi {
+	/// The addressed device is kept by its position on the bus
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.bus.active.is_some());
+		writer.u32(self.bus.active.unwrap_or_default() as u32);
+		self.state.save(writer);
+		writer.bool(self.pending.is_some());
+		if let Some(pending) = &self.pending {
+			pending.operation.save(writer);
+			writer.usize(pending.remaining_cycles);
+		}
+		writer.bool(self.slave_ack);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		let addressed = reader.bool()?;
+		let index = reader.u32()? as usize;
+		self.bus.active = (addressed && index < self.bus.devices.len()).then_some(index);
+		self.state = State::restore(reader)?;
+		self.pending = match reader.bool()? {
+			true => Some(PendingOperation {
+				operation: Operation::restore(reader)?,
+				remaining_cycles: reader.usize()?,
+			}),
+			false => None,
+		};
+		self.slave_ack = reader.bool()?;
+		Ok(())
+	}
+}
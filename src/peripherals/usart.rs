@@ -0,0 +1,262 @@
+use super::{Interrupt, Peripheral};
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const UCSR0A: u16 = 0xC0;
+pub const UCSR0B: u16 = 0xC1;
+pub const UCSR0C: u16 = 0xC2;
+pub const UBRR0L: u16 = 0xC4;
+pub const UBRR0H: u16 = 0xC5;
+pub const UDR0: u16 = 0xC6;
+
+// UCSR0A bits
+const RXC: u8 = 7;
+const TXC: u8 = 6;
+const UDRE: u8 = 5;
+const DOR: u8 = 3;
+const U2X: u8 = 1;
+const MPCM: u8 = 0;
+
+// UCSR0B bits
+const RXCIE: u8 = 7;
+const TXCIE: u8 = 6;
+const UDRIE: u8 = 5;
+const RXEN: u8 = 4;
+const TXEN: u8 = 3;
+const UCSZ2: u8 = 2;
+
+// UCSR0C bits
+const USBS: u8 = 3;
+
+/// Receives the bytes transmitted on TXD
+pub trait SerialDevice {
+	fn receive(&mut self, data: u8);
+}
+
+pub type SharedSerialDevice = Rc<RefCell<dyn SerialDevice>>;
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+	data: u8,
+	remaining_cycles: usize,
+}
+
+/// Asynchronous mode of USART0, framing is honoured for timing only
+#[derive(Default, Clone)]
+pub struct Usart {
+	devices: Vec<SharedSerialDevice>,
+	/// Byte written to UDR0 while the shift register was busy
+	transmit_buffer: Option<u8>,
+	shifting: Option<Frame>,
+	receive_buffer: u8,
+	/// Keeps the devices from receiving while history replays a step
+	pub muted: bool,
+}
+
+impl Usart {
+	pub fn attach(&mut self, device: SharedSerialDevice) {
+		self.devices.push(device);
+	}
+
+	pub fn is_transmitting(&self) -> bool {
+		self.shifting.is_some()
+	}
+
+	/// System clock cycles per bit
+	pub fn bit_cycles(&self, sram: &mut Sram) -> usize {
+		let ubrr = ((sram.read(UBRR0H) as usize & 0x0F) << 8) | sram.read(UBRR0L) as usize;
+		let samples = if bit(sram.read(UCSR0A) as u8, U2X) != 0 {
+			8
+		} else {
+			16
+		};
+		samples * (ubrr + 1)
+	}
+
+	/// Bits in a frame including start, parity and stop bits
+	pub fn frame_bits(&self, sram: &mut Sram) -> usize {
+		let ucsr0b = sram.read(UCSR0B) as u8;
+		let ucsr0c = sram.read(UCSR0C) as u8;
+
+		let data_bits = match bit(ucsr0b, UCSZ2) | ((ucsr0c >> 1) & 0x3) {
+			0 => 5,
+			1 => 6,
+			2 => 7,
+			3 => 8,
+			_ => 9,
+		};
+		let parity_bits = usize::from((ucsr0c >> 4) & 0x3 != 0);
+		let stop_bits = if bit(ucsr0c, USBS) != 0 { 2 } else { 1 };
+
+		1 + data_bits + parity_bits + stop_bits
+	}
+
+	/// Delivers a byte arriving on RXD, returns false if the receiver is disabled
+	pub fn receive(&mut self, sram: &mut Sram, data: u8) -> bool {
+		if bit(sram.read(UCSR0B) as u8, RXEN) == 0 {
+			return false;
+		}
+
+		let ucsr0a = sram.read(UCSR0A) as u8;
+		if bit(ucsr0a, RXC) != 0 {
+			sram.write(UCSR0A, (ucsr0a | (1 << DOR)) as u16);
+			return true;
+		}
+
+		self.receive_buffer = data;
+		sram.write(UCSR0A, (ucsr0a | (1 << RXC)) as u16);
+		true
+	}
+
+	fn set_flags(sram: &mut Sram, set: u8, clear: u8) {
+		let ucsr0a = sram.read(UCSR0A) as u8;
+		sram.write(UCSR0A, ((ucsr0a | set) & !clear) as u16);
+	}
+
+	fn start(&mut self, sram: &mut Sram, data: u8) {
+		self.shifting = Some(Frame {
+			data,
+			remaining_cycles: self.frame_bits(sram) * self.bit_cycles(sram),
+		});
+	}
+}
+
+impl Peripheral for Usart {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.transmit_buffer = None;
+		self.shifting = None;
+		self.receive_buffer = 0;
+		sram.write(UCSR0A, 1 << UDRE);
+		sram.write(UCSR0B, 0x00);
+		sram.write(UCSR0C, 0x06);
+		sram.write(UBRR0L, 0x00);
+		sram.write(UBRR0H, 0x00);
+		sram.write(UDR0, 0x00);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		matches!(address, UCSR0A..=UCSR0C | UBRR0L..=UDR0)
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		match address {
+			UDR0 => {
+				Self::set_flags(sram, 0, (1 << RXC) | (1 << DOR));
+				self.receive_buffer
+			}
+			_ => sram.read(address) as u8,
+		}
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			UCSR0A => {
+				let writable = (1 << U2X) | (1 << MPCM);
+				let ucsr0a = sram.read(UCSR0A) as u8;
+				let mut value = (ucsr0a & !writable) | (data & writable);
+				if bit(data, TXC) != 0 {
+					value &= !(1 << TXC);
+				}
+				sram.write(UCSR0A, value as u16);
+			}
+			UDR0 => {
+				let ucsr0a = sram.read(UCSR0A) as u8;
+				let enabled = bit(sram.read(UCSR0B) as u8, TXEN) != 0;
+				if !enabled || bit(ucsr0a, UDRE) == 0 {
+					return;
+				}
+
+				if self.shifting.is_none() {
+					self.start(sram, data);
+				} else {
+					self.transmit_buffer = Some(data);
+					Self::set_flags(sram, 0, 1 << UDRE);
+				}
+			}
+			UBRR0H => sram.write(address, (data & 0x0F) as u16),
+			_ => sram.write(address, data as u16),
+		}
+	}
+
+	fn next_event(&self) -> Option<usize> {
+		self.shifting.map(|frame| frame.remaining_cycles)
+	}
+
+	fn step(&mut self, sram: &mut Sram, cycles: usize) {
+		let frame = match self.shifting.as_mut() {
+			Some(frame) => frame,
+			None => return,
+		};
+
+		if frame.remaining_cycles > cycles {
+			frame.remaining_cycles -= cycles;
+			return;
+		}
+
+		let data = frame.data;
+		self.shifting = None;
+		for device in self.devices.iter().filter(|_| !self.muted) {
+			device.borrow_mut().receive(data);
+		}
+
+		match self.transmit_buffer.take() {
+			Some(next) => {
+				self.start(sram, next);
+				Self::set_flags(sram, 1 << UDRE, 0);
+			}
+			None => Self::set_flags(sram, 1 << TXC, 0),
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let ucsr0a = sram.read(UCSR0A) as u8;
+		let ucsr0b = sram.read(UCSR0B) as u8;
+
+		if bit(ucsr0b, RXCIE) != 0 && bit(ucsr0a, RXC) != 0 {
+			Some(Interrupt::UsartRx)
+		} else if bit(ucsr0b, UDRIE) != 0 && bit(ucsr0a, UDRE) != 0 {
+			Some(Interrupt::UsartUdre)
+		} else if bit(ucsr0b, TXCIE) != 0 && bit(ucsr0a, TXC) != 0 {
+			Some(Interrupt::UsartTx)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		if interrupt == Interrupt::UsartTx {
+			Self::set_flags(sram, 0, 1 << TXC);
+		}
+	}
+}
+
+impl Snapshot for Usart {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.transmit_buffer.is_some());
+		writer.u8(self.transmit_buffer.unwrap_or_default());
+		writer.bool(self.shifting.is_some());
+		if let Some(frame) = &self.shifting {
+			writer.u8(frame.data);
+			writer.usize(frame.remaining_cycles);
+		}
+		writer.u8(self.receive_buffer);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		let buffered = reader.bool()?;
+		let data = reader.u8()?;
+		self.transmit_buffer = buffered.then_some(data);
+		self.shifting = match reader.bool()? {
+			true => Some(Frame {
+				data: reader.u8()?,
+				remaining_cycles: reader.usize()?,
+			}),
+			false => None,
+		};
+		self.receive_buffer = reader.u8()?;
+		Ok(())
+	}
+}
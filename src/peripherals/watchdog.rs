@@ -0,0 +1,228 @@
+use super::clock::WATCHDOG_OSCILLATOR_FREQUENCY;
+use super::{Interrupt, Peripheral};
+use crate::fuses::Fuses;
+use crate::memory::{Memory, Sram};
+use crate::snapshot::{self, Reader, Snapshot, Writer};
+use crate::utils::bit;
+
+pub const MCUSR: u16 = 0x54;
+pub const WDTCSR: u16 = 0x60;
+
+// MCUSR bits
+const WDRF: u8 = 3;
+const PORF: u8 = 0;
+
+// WDTCSR bits
+const WDIF: u8 = 7;
+const WDIE: u8 = 6;
+const WDP3: u8 = 5;
+const WDCE: u8 = 4;
+const WDE: u8 = 3;
+/// WDP3:0 and WDE, which can only be changed through the timed sequence
+const PROTECTED: u8 = 0x2F;
+
+/// WDE and WDP3:0 must be written within this many cycles of setting WDCE
+const CHANGE_ENABLE_CYCLES: usize = 4;
+
+/// Tolerance for the rounding accumulated while timing the watchdog in seconds
+const TIME_TOLERANCE: f64 = 1e-12;
+
+/// Watchdog timer, clocked by its own 128 kHz oscillator so it keeps running in every
+/// sleep mode
+#[derive(Default, Clone)]
+pub struct Watchdog {
+	/// WDTON is programmed, the watchdog always runs in system reset mode
+	always_on: bool,
+	change_enable_cycles: usize,
+	/// Oscillator time since the watchdog was last restarted
+	elapsed_time: f64,
+	reset_requested: bool,
+}
+
+impl Watchdog {
+	/// Applies the WDTON fuse, it takes effect on the next reset
+	pub fn configure(&mut self, fuses: &Fuses) {
+		self.always_on = fuses.wdton();
+	}
+
+	pub fn is_running(&self, sram: &mut Sram) -> bool {
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		bit(wdtcsr, WDE) != 0 || bit(wdtcsr, WDIE) != 0
+	}
+
+	/// Time-out period selected by WDP3:0 in seconds, reserved values select the longest
+	pub fn timeout(&self, sram: &mut Sram) -> f64 {
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		let prescaler = (bit(wdtcsr, WDP3) >> 2) | (wdtcsr & 0x7);
+		(2048 << prescaler.min(9)) as f64 / WATCHDOG_OSCILLATOR_FREQUENCY
+	}
+
+	/// Executed by `wdr`
+	pub fn restart(&mut self) {
+		self.elapsed_time = 0.0;
+	}
+
+	/// A time-out in system reset mode requested a reset of the MCU
+	pub fn take_reset_request(&mut self) -> bool {
+		std::mem::take(&mut self.reset_requested)
+	}
+
+	/// Flags the watchdog as the reset source, which keeps it enabled in reset mode
+	pub fn system_reset(&mut self, sram: &mut Sram, mcusr: u8) {
+		sram.write(MCUSR, (mcusr | (1 << WDRF)) as u16);
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		sram.write(WDTCSR, (wdtcsr | (1 << WDE)) as u16);
+	}
+
+	fn is_reset_enabled(&self, sram: &mut Sram) -> bool {
+		self.always_on || bit(sram.read(WDTCSR) as u8, WDE) != 0
+	}
+
+	pub fn next_event(&self, sram: &mut Sram, frequency: f64) -> Option<usize> {
+		let window = Some(self.change_enable_cycles).filter(|cycles| *cycles > 0);
+		let timeout = self.is_running(sram).then(|| {
+			((self.timeout(sram) - self.elapsed_time - TIME_TOLERANCE) * frequency)
+				.ceil()
+				.max(1.0) as usize
+		});
+
+		match (window, timeout) {
+			(Some(window), Some(timeout)) => Some(window.min(timeout)),
+			(window, timeout) => window.or(timeout),
+		}
+	}
+
+	/// Advances the watchdog by `cycles` of the system clock lasting `seconds`
+	pub fn clock(&mut self, sram: &mut Sram, cycles: usize, seconds: f64) {
+		if self.change_enable_cycles > 0 {
+			self.change_enable_cycles = self.change_enable_cycles.saturating_sub(cycles);
+			if self.change_enable_cycles == 0 {
+				let wdtcsr = sram.read(WDTCSR) as u8;
+				sram.write(WDTCSR, (wdtcsr & !(1 << WDCE)) as u16);
+			}
+		}
+
+		if !self.is_running(sram) {
+			return;
+		}
+
+		self.elapsed_time += seconds;
+		if self.elapsed_time + TIME_TOLERANCE < self.timeout(sram) {
+			return;
+		}
+		self.elapsed_time = 0.0;
+
+		// in interrupt and system reset mode a time-out with the interrupt still
+		// pending resets the MCU
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		let interrupt = bit(wdtcsr, WDIE) != 0;
+		let reset = self.is_reset_enabled(sram);
+		if interrupt && !(reset && bit(wdtcsr, WDIF) != 0) {
+			sram.write(WDTCSR, (wdtcsr | (1 << WDIF)) as u16);
+		} else if reset {
+			self.reset_requested = true;
+		}
+	}
+
+	fn write_control(&mut self, sram: &mut Sram, data: u8) {
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		let was_running = self.is_running(sram);
+
+		let mut value = data & (1 << WDIE);
+		if bit(data, WDIF) == 0 {
+			value |= wdtcsr & (1 << WDIF);
+		}
+
+		if self.change_enable_cycles > 0 && bit(data, WDCE) == 0 {
+			value |= data & PROTECTED;
+			self.change_enable_cycles = 0;
+		} else {
+			// WDE can be set at any time, clearing it needs the timed sequence
+			value |= (wdtcsr & PROTECTED) | (data & (1 << WDE));
+			if bit(data, WDCE) != 0 && bit(data, WDE) != 0 {
+				value |= 1 << WDCE;
+				self.change_enable_cycles = CHANGE_ENABLE_CYCLES;
+			}
+		}
+
+		// WDRF and WDTON keep the watchdog enabled
+		if self.always_on || bit(sram.read(MCUSR) as u8, WDRF) != 0 {
+			value |= 1 << WDE;
+		}
+		if self.always_on {
+			value &= !(1 << WDIE);
+		}
+		sram.write(WDTCSR, value as u16);
+
+		if !was_running {
+			self.restart();
+		}
+	}
+}
+
+impl Peripheral for Watchdog {
+	fn reset(&mut self, sram: &mut Sram) {
+		self.change_enable_cycles = 0;
+		self.elapsed_time = 0.0;
+		self.reset_requested = false;
+		sram.write(MCUSR, 1 << PORF);
+		let wdtcsr = if self.always_on { 1 << WDE } else { 0x00 };
+		sram.write(WDTCSR, wdtcsr);
+	}
+
+	fn handles(&self, address: u16) -> bool {
+		address == MCUSR || address == WDTCSR
+	}
+
+	fn read(&mut self, sram: &mut Sram, address: u16) -> u8 {
+		sram.read(address) as u8
+	}
+
+	fn write(&mut self, sram: &mut Sram, address: u16, data: u8) {
+		match address {
+			// reset flags are cleared by writing a logical zero
+			MCUSR => {
+				let mcusr = sram.read(MCUSR) as u8;
+				sram.write(MCUSR, (mcusr & data & 0x0F) as u16);
+			}
+			_ => self.write_control(sram, data),
+		}
+	}
+
+	fn pending_interrupt(&mut self, sram: &mut Sram) -> Option<Interrupt> {
+		let wdtcsr = sram.read(WDTCSR) as u8;
+		if bit(wdtcsr, WDIE) != 0 && bit(wdtcsr, WDIF) != 0 {
+			Some(Interrupt::Wdt)
+		} else {
+			None
+		}
+	}
+
+	fn acknowledge(&mut self, sram: &mut Sram, interrupt: Interrupt) {
+		if interrupt == Interrupt::Wdt {
+			// in interrupt and system reset mode the next time-out resets the MCU
+			let mut wdtcsr = sram.read(WDTCSR) as u8 & !(1 << WDIF);
+			if self.is_reset_enabled(sram) {
+				wdtcsr &= !(1 << WDIE);
+			}
+			sram.write(WDTCSR, wdtcsr as u16);
+		}
+	}
+}
+
+impl Snapshot for Watchdog {
+	fn save(&self, writer: &mut Writer) {
+		writer.bool(self.always_on);
+		writer.usize(self.change_enable_cycles);
+		writer.f64(self.elapsed_time);
+		writer.bool(self.reset_requested);
+	}
+
+	fn restore(&mut self, reader: &mut Reader) -> snapshot::Result<()> {
+		self.always_on = reader.bool()?;
+		self.change_enable_cycles = reader.usize()?;
+		self.elapsed_time = reader.f64()?;
+		self.reset_requested = reader.bool()?;
+		Ok(())
+	}
+}
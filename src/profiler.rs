@@ -0,0 +1,204 @@
+use crate::cpu::Cpu;
+use crate::disassembler::is_call;
+use crate::memory::Memory;
+use crate::symbols::SymbolTable;
+use crate::trace::Event;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A function reached through a particular call path
+#[derive(Debug, Clone)]
+struct Node {
+	/// Entry address of the function
+	function: u16,
+	parent: Option<usize>,
+	children: BTreeMap<u16, usize>,
+	calls: u64,
+	/// Cycles spent in the function itself on this path
+	cycles: usize,
+}
+
+/// A function being executed
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+	node: usize,
+	/// Stack pointer once the function returned
+	return_sp: u16,
+}
+
+/// Cycles and calls of a function over all the paths it was called through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+	pub name: String,
+	/// Word address the function starts at
+	pub address: u16,
+	pub calls: u64,
+	/// Cycles spent in the function and everything it called
+	pub inclusive: usize,
+	/// Cycles spent in the function itself
+	pub exclusive: usize,
+}
+
+/// Attributes the cycles of each step to the function executing it, following calls,
+/// interrupts and returns. A function returns once the stack pointer is back above its
+/// return address, which also covers stacks unwound without `ret`.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+	nodes: Vec<Node>,
+	roots: BTreeMap<u16, usize>,
+	stack: Vec<Frame>,
+}
+
+impl Profiler {
+	pub fn clear(&mut self) {
+		*self = Self::default();
+	}
+
+	/// Cycles attributed so far
+	pub fn total_cycles(&self) -> usize {
+		self.nodes.iter().map(|node| node.cycles).sum()
+	}
+
+	/// Accounts the step that started at `pc` and `cycles`, called by [`Cpu::step`]
+	pub(crate) fn step(&mut self, cpu: &mut Cpu, event: Event, cycles: usize, pc: u16) {
+		if self.stack.is_empty() {
+			let function = function_entry(&cpu.system.symbols, pc);
+			let node = self.node(None, function);
+			self.nodes[node].calls += 1;
+			self.stack.push(Frame {
+				node,
+				return_sp: u16::MAX,
+			});
+		}
+		let top = self.stack[self.stack.len() - 1];
+		self.nodes[top.node].cycles += cpu.cycles - cycles;
+
+		while self.stack.len() > 1 && self.stack[self.stack.len() - 1].return_sp <= cpu.sp {
+			self.stack.pop();
+		}
+
+		let target = match event {
+			Event::Instruction if is_call(cpu.system.program_memory.read(pc)) => cpu.pc,
+			Event::Interrupt => vector_target(cpu, cpu.pc),
+			// woke up for an interrupt
+			Event::Sleep if !cpu.is_sleeping() => vector_target(cpu, cpu.pc),
+			_ => return,
+		};
+		let function = function_entry(&cpu.system.symbols, target);
+		let parent = self.stack[self.stack.len() - 1].node;
+		let node = self.node(Some(parent), function);
+		self.nodes[node].calls += 1;
+		self.stack.push(Frame {
+			node,
+			return_sp: cpu.sp.wrapping_add(2),
+		});
+	}
+
+	/// Child of `parent` for `function`, created on first use
+	fn node(&mut self, parent: Option<usize>, function: u16) -> usize {
+		let next = self.nodes.len();
+		let children = match parent {
+			Some(parent) => &mut self.nodes[parent].children,
+			None => &mut self.roots,
+		};
+		let node = *children.entry(function).or_insert(next);
+		if node == next {
+			self.nodes.push(Node {
+				function,
+				parent,
+				children: BTreeMap::new(),
+				calls: 0,
+				cycles: 0,
+			});
+		}
+		node
+	}
+
+	/// Functions on the path to `node`, starting with the outermost
+	fn path(&self, node: usize) -> Vec<u16> {
+		let mut path = Vec::new();
+		let mut current = Some(node);
+		while let Some(node) = current {
+			path.push(self.nodes[node].function);
+			current = self.nodes[node].parent;
+		}
+		path.reverse();
+		path
+	}
+
+	/// Every function seen, most inclusive cycles first. Recursive calls count once
+	/// towards the inclusive cycles.
+	pub fn functions(&self, symbols: &SymbolTable) -> Vec<FunctionProfile> {
+		let mut functions: BTreeMap<u16, FunctionProfile> = BTreeMap::new();
+		for (index, node) in self.nodes.iter().enumerate() {
+			let mut path = self.path(index);
+			path.sort_unstable();
+			path.dedup();
+			for function in path {
+				let profile = functions
+					.entry(function)
+					.or_insert_with(|| FunctionProfile {
+						name: function_name(symbols, function),
+						address: function,
+						calls: 0,
+						inclusive: 0,
+						exclusive: 0,
+					});
+				profile.inclusive += node.cycles;
+			}
+
+			let profile = functions.get_mut(&node.function).unwrap();
+			profile.calls += node.calls;
+			profile.exclusive += node.cycles;
+		}
+
+		let mut functions: Vec<FunctionProfile> = functions.into_values().collect();
+		functions.sort_by_key(|function| std::cmp::Reverse(function.inclusive));
+		functions
+	}
+
+	/// Writes one line per call path with the cycles spent at its end, the folded stack
+	/// format flame graph tools read
+	pub fn write_folded(&self, symbols: &SymbolTable, output: &mut impl Write) -> io::Result<()> {
+		for (index, node) in self.nodes.iter().enumerate() {
+			if node.cycles == 0 {
+				continue;
+			}
+			let names: Vec<String> = (self.path(index).into_iter())
+				.map(|function| function_name(symbols, function))
+				.collect();
+			writeln!(output, "{} {}", names.join(";"), node.cycles)?;
+		}
+		Ok(())
+	}
+}
+
+/// Where the jump in an interrupt vector leads, the vector itself if it holds
+/// something else
+fn vector_target(cpu: &mut Cpu, vector: u16) -> u16 {
+	let memory = &mut cpu.system.program_memory;
+	let opcode = memory.read(vector);
+	if opcode & 0xFE0E == 0x940C {
+		// flash is small enough for the low word of the target
+		memory.read(vector + 1) & 0x3FFF
+	} else if opcode & 0xF000 == 0xC000 {
+		let offset = ((opcode << 4) as i16 >> 4) as u16;
+		vector.wrapping_add(1).wrapping_add(offset) & 0x3FFF
+	} else {
+		vector
+	}
+}
+
+/// Start of the function containing `address`, the address itself without symbols
+fn function_entry(symbols: &SymbolTable, address: u16) -> u16 {
+	symbols
+		.function_containing(address)
+		.map_or(address, |label| label.address)
+}
+
+fn function_name(symbols: &SymbolTable, address: u16) -> String {
+	match symbols.function_at(address) {
+		Some(label) => label.name.clone(),
+		None => format!("0x{:04X}", address),
+	}
+}
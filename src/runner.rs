@@ -0,0 +1,358 @@
+use crate::coverage::Coverage;
+use crate::cpu::Cpu;
+use crate::debugger::WatchHit;
+use crate::peripherals::usart::SerialDevice;
+use crate::profiler::Profiler;
+use crate::trace::Tracer;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// CKSEL3:0 of a full swing crystal oscillator
+const CRYSTAL_CKSEL: u8 = 0xF;
+
+pub const USAGE: &str = "\
+Usage: atmega328p-rs run <firmware.hex|firmware.elf> [options]
+       atmega328p-rs trace <file> [firmware.elf]   print a trace as text
+
+Options:
+  --cycles <n>        stop after n CPU cycles
+  --time <duration>   stop after an emulated duration, e.g. 1.5, 20ms, 100us
+  --freq <frequency>  clock the CPU from a crystal, e.g. 16M, 8M, 32768
+  --uart <sink>       where USART0 transmits to, stdout or none (default)
+  --eeprom <file>     keep the EEPROM contents in a file between runs
+  --gdb <port>        wait for avr-gdb on a local TCP port instead of running
+  --trace <file>      log the executed instructions to a binary trace file
+  --trace-last <n>    only keep the last n instructions, written when the run ends
+  --trace-range <a-b> only trace word addresses a to b, e.g. 0x100-0x13F
+  --trace-func <name> only trace a function, both can be given several times
+  --profile <file>    write the cycles spent in each call stack for flame graphs
+  --coverage <file>   write the executed lines and branches as lcov, or an
+                      annotated disassembly for firmware without debug lines
+  --min-coverage <p>  exit with 125 if under p percent of the instructions ran";
+
+/// Exit code of a run that passed but executed less of the program than
+/// `--min-coverage` asks for
+pub const COVERAGE_EXIT_CODE: i32 = 125;
+
+/// Why a run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+	CycleLimit,
+	TimeLimit,
+	/// A `break` instruction was executed
+	Break,
+	/// Stopped before the instruction at a breakpoint, holds its word address
+	Breakpoint(u16),
+	/// An instruction made an access a watchpoint stops on
+	Watchpoint(WatchHit),
+	/// Asleep with interrupts disabled, nothing can wake the CPU
+	Deadlock,
+	/// The firmware wrote its exit code to `DEBUG_EXIT`
+	Exit(u8),
+}
+
+impl StopReason {
+	/// Process exit code, 124 like `timeout` when a limit ran out
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			StopReason::Exit(code) => *code as i32,
+			StopReason::Break
+			| StopReason::Breakpoint(_)
+			| StopReason::Watchpoint(_)
+			| StopReason::Deadlock => 0,
+			StopReason::CycleLimit | StopReason::TimeLimit => 124,
+		}
+	}
+}
+
+/// When to give up on a run that does not stop by itself
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+	pub cycles: Option<usize>,
+	/// Emulated seconds
+	pub seconds: Option<f64>,
+}
+
+/// Steps `cpu` until the firmware stops or a limit is reached
+pub fn run(cpu: &mut Cpu, limits: &Limits) -> StopReason {
+	cpu.check_entry_breakpoint();
+	loop {
+		if let Some(code) = cpu.peripherals.debug.exit_code() {
+			return StopReason::Exit(code);
+		}
+		if cpu.break_hit {
+			cpu.break_hit = false;
+			return StopReason::Break;
+		}
+		if let Some(hit) = cpu.watch_hit.take() {
+			return StopReason::Watchpoint(hit);
+		}
+		if cpu.breakpoint_hit {
+			cpu.breakpoint_hit = false;
+			return StopReason::Breakpoint(cpu.pc);
+		}
+		if cpu.is_sleeping() && !cpu.status.I {
+			return StopReason::Deadlock;
+		}
+		if limits.cycles.is_some_and(|cycles| cpu.cycles >= cycles) {
+			return StopReason::CycleLimit;
+		}
+		if limits
+			.seconds
+			.is_some_and(|seconds| cpu.peripherals.clock.seconds() >= seconds)
+		{
+			return StopReason::TimeLimit;
+		}
+
+		cpu.step();
+	}
+}
+
+/// Where the bytes transmitted by USART0 end up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UartSink {
+	#[default]
+	None,
+	Stdout,
+}
+
+/// Writes bytes to standard output as they arrive
+pub struct StdoutSerial;
+
+impl SerialDevice for StdoutSerial {
+	fn receive(&mut self, data: u8) {
+		let mut stdout = std::io::stdout();
+		let _ = stdout.write_all(&[data]);
+		let _ = stdout.flush();
+	}
+}
+
+/// Arguments of the `run` command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+	pub program: PathBuf,
+	pub limits: Limits,
+	/// Crystal frequency in Hz, the fuses are left alone when `None`
+	pub frequency: Option<f64>,
+	pub uart: UartSink,
+	pub eeprom: Option<PathBuf>,
+	/// Local TCP port to serve GDB on, the limits don't apply to debugging sessions
+	pub gdb: Option<u16>,
+	/// File to write an instruction trace to
+	pub trace: Option<PathBuf>,
+	/// Only keep the last this many instructions of the trace
+	pub trace_last: Option<usize>,
+	/// Word address ranges to trace, everything unless these or functions are given
+	pub trace_ranges: Vec<Range<u16>>,
+	/// Functions to trace by name
+	pub trace_functions: Vec<String>,
+	/// File to write the profile to as folded stacks
+	pub profile: Option<PathBuf>,
+	/// File to write the coverage report to
+	pub coverage: Option<PathBuf>,
+	/// Instruction coverage in percent the run has to reach
+	pub min_coverage: Option<f64>,
+}
+
+impl RunOptions {
+	/// Parses the arguments following `run`
+	pub fn parse(args: &[String]) -> Result<Self, String> {
+		let mut options = RunOptions::default();
+		let mut program = None;
+		let mut args = args.iter();
+
+		while let Some(arg) = args.next() {
+			let mut value = |name: &str| {
+				args.next()
+					.ok_or_else(|| format!("{} requires a value", name))
+			};
+
+			match arg.as_str() {
+				"--cycles" => {
+					let cycles = value(arg)?;
+					options.limits.cycles = Some(
+						cycles
+							.parse()
+							.map_err(|_| format!("Invalid cycle count: {}", cycles))?,
+					);
+				}
+				"--time" => {
+					let time = value(arg)?;
+					options.limits.seconds = Some(
+						parse_duration(time).ok_or_else(|| format!("Invalid time: {}", time))?,
+					);
+				}
+				"--freq" => {
+					let frequency = value(arg)?;
+					options.frequency = Some(
+						parse_frequency(frequency)
+							.ok_or_else(|| format!("Invalid frequency: {}", frequency))?,
+					);
+				}
+				"--uart" => {
+					options.uart = match value(arg)?.as_str() {
+						"stdout" => UartSink::Stdout,
+						"none" => UartSink::None,
+						sink => return Err(format!("Unknown UART sink: {}", sink)),
+					};
+				}
+				"--eeprom" => options.eeprom = Some(value(arg)?.into()),
+				"--gdb" => {
+					let port = value(arg)?;
+					options.gdb = Some(
+						port.parse()
+							.map_err(|_| format!("Invalid port: {}", port))?,
+					);
+				}
+				"--trace" => options.trace = Some(value(arg)?.into()),
+				"--trace-last" => {
+					let count = value(arg)?;
+					options.trace_last = Some(
+						count
+							.parse()
+							.ok()
+							.filter(|count| *count > 0)
+							.ok_or_else(|| format!("Invalid instruction count: {}", count))?,
+					);
+				}
+				"--trace-range" => {
+					let range = value(arg)?;
+					options.trace_ranges.push(
+						parse_range(range).ok_or_else(|| format!("Invalid range: {}", range))?,
+					);
+				}
+				"--trace-func" => options.trace_functions.push(value(arg)?.clone()),
+				"--profile" => options.profile = Some(value(arg)?.into()),
+				"--coverage" => options.coverage = Some(value(arg)?.into()),
+				"--min-coverage" => {
+					let percent = value(arg)?;
+					options.min_coverage = Some(
+						(percent.strip_suffix('%').unwrap_or(percent).parse())
+							.ok()
+							.filter(|percent| (0.0..=100.0).contains(percent))
+							.ok_or_else(|| format!("Invalid coverage: {}", percent))?,
+					);
+				}
+				_ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+				_ if program.is_none() => program = Some(PathBuf::from(arg)),
+				_ => return Err(format!("Unexpected argument: {}", arg)),
+			}
+		}
+
+		options.program = program.ok_or("No firmware given")?;
+		if options.trace.is_none()
+			&& (options.trace_last.is_some()
+				|| !options.trace_ranges.is_empty()
+				|| !options.trace_functions.is_empty())
+		{
+			return Err("The trace options require --trace".to_string());
+		}
+		Ok(options)
+	}
+
+	/// Sets up `cpu` to run the firmware, the CPU is reset afterwards
+	pub fn apply(&self, cpu: &mut Cpu) -> Result<(), String> {
+		cpu.system
+			.load_program(&self.program)
+			.map_err(|error| format!("Unable to load {}: {}", self.program.display(), error))?;
+
+		if let Some(path) = &self.eeprom {
+			cpu.system.persist_eeprom(path.clone()).map_err(|error| {
+				format!("Unable to load EEPROM from {}: {}", path.display(), error)
+			})?;
+		}
+
+		if let Some(frequency) = self.frequency {
+			cpu.system.fuses.set_clock_select(CRYSTAL_CKSEL);
+			cpu.system.fuses.set_ckdiv8(false);
+			cpu.peripherals.clock.external_frequency = frequency;
+		}
+
+		cpu.peripherals
+			.debug
+			.attach(Rc::new(RefCell::new(StdoutSerial)));
+		if self.uart == UartSink::Stdout {
+			cpu.peripherals
+				.usart
+				.attach(Rc::new(RefCell::new(StdoutSerial)));
+		}
+
+		if let Some(path) = &self.trace {
+			let file = File::create(path)
+				.map_err(|error| format!("Unable to create {}: {}", path.display(), error))?;
+			let mut tracer = match self.trace_last {
+				Some(count) => Tracer::last(count, file),
+				None => Tracer::new(file),
+			};
+			tracer.filter = self.trace_ranges.clone();
+			for name in &self.trace_functions {
+				let label = (cpu.system.symbols.functions())
+					.find(|label| label.name == *name)
+					.ok_or_else(|| format!("Unknown function: {}", name))?;
+				tracer
+					.filter
+					.push(label.address..label.address + label.size.max(1));
+			}
+			cpu.trace = Some(tracer);
+		}
+		if self.profile.is_some() {
+			cpu.profiler = Some(Profiler::default());
+		}
+		if self.coverage.is_some() || self.min_coverage.is_some() {
+			cpu.coverage = Some(Coverage::default());
+		}
+
+		cpu.fast_forward = true;
+		cpu.reset();
+		Ok(())
+	}
+}
+
+/// Inclusive range of word addresses from e.g. "0x100-0x13F"
+fn parse_range(text: &str) -> Option<Range<u16>> {
+	let address = |text: &str| {
+		let text = text.trim();
+		let digits = text.strip_prefix("0x").unwrap_or(text);
+		u16::from_str_radix(digits, 16).ok()
+	};
+	let (start, end) = text.split_once('-')?;
+	let (start, end) = (address(start)?, address(end)?);
+	(start <= end).then(|| start..end + 1)
+}
+
+/// Parses a value with an optional metric prefix given as a power of ten, e.g. "16M"
+fn parse_scaled(text: &str, prefixes: &[(&str, i32)]) -> Option<f64> {
+	let (number, exponent) = prefixes
+		.iter()
+		.find_map(|(suffix, exponent)| text.strip_suffix(suffix).map(|number| (number, *exponent)))
+		.unwrap_or((text, 0));
+
+	// dividing keeps e.g. 100us exactly 1e-4
+	let scale = 10f64.powi(exponent.abs());
+	number
+		.parse::<f64>()
+		.ok()
+		.filter(|value| value.is_finite() && *value > 0.0)
+		.map(|value| {
+			if exponent < 0 {
+				value / scale
+			} else {
+				value * scale
+			}
+		})
+}
+
+/// Frequency in Hz from e.g. "16M", "8MHz", "128k" or "32768"
+pub fn parse_frequency(text: &str) -> Option<f64> {
+	let text = text.strip_suffix("Hz").unwrap_or(text);
+	parse_scaled(text, &[("M", 6), ("k", 3)])
+}
+
+/// Duration in seconds from e.g. "1.5", "2s", "20ms" or "100us"
+pub fn parse_duration(text: &str) -> Option<f64> {
+	parse_scaled(text, &[("ms", -3), ("us", -6), ("s", 0)])
+}
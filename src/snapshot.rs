@@ -0,0 +1,300 @@
+use crate::cpu::Cpu;
+use crate::memory::{Memory, ProgramMemory, Sram, PROGRAM_END, PROGRAM_START, RAMEND};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Start of every save state file
+pub const MAGIC: &[u8; 8] = b"M328SNAP";
+
+/// Layout version, bumped whenever the encoding changes
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	/// Does not start with [`MAGIC`]
+	NotSnapshot,
+	/// Written by a newer emulator
+	UnsupportedVersion(u16),
+	/// Ends before all the state was read
+	Truncated,
+	/// A value outside its range, holds what was being read
+	Invalid(&'static str),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::NotSnapshot => write!(f, "not a save state"),
+			Error::UnsupportedVersion(version) => {
+				write!(f, "save state version {} is not supported", version)
+			}
+			Error::Truncated => write!(f, "save state is truncated"),
+			Error::Invalid(what) => write!(f, "invalid {} in save state", what),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Little endian encoder for save states
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+	bytes: Vec<u8>,
+}
+
+impl Writer {
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+
+	pub fn u8(&mut self, value: u8) {
+		self.bytes.push(value);
+	}
+
+	pub fn bool(&mut self, value: bool) {
+		self.u8(value as u8);
+	}
+
+	pub fn u16(&mut self, value: u16) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	pub fn u32(&mut self, value: u32) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	/// Cycle counts and other sizes, as 64 bits on every host
+	pub fn usize(&mut self, value: usize) {
+		self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+	}
+
+	pub fn f64(&mut self, value: f64) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	/// Index of `value` in `values`, read back by [`Reader::choice`]
+	pub fn choice<T: PartialEq>(&mut self, value: &T, values: &[T]) {
+		let index = values.iter().position(|other| other == value);
+		self.u8(index.expect("value missing from its table") as u8);
+	}
+
+	/// Length prefixed bytes
+	pub fn bytes(&mut self, bytes: &[u8]) {
+		self.u32(bytes.len() as u32);
+		self.bytes.extend_from_slice(bytes);
+	}
+}
+
+/// Decoder for what [`Writer`] encodes
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Reader<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		Self { bytes, position: 0 }
+	}
+
+	fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+		let bytes = self
+			.bytes
+			.get(self.position..self.position + N)
+			.ok_or(Error::Truncated)?;
+		self.position += N;
+		Ok(bytes.try_into().unwrap())
+	}
+
+	pub fn u8(&mut self) -> Result<u8> {
+		Ok(self.take::<1>()?[0])
+	}
+
+	pub fn bool(&mut self) -> Result<bool> {
+		match self.u8()? {
+			0 => Ok(false),
+			1 => Ok(true),
+			_ => Err(Error::Invalid("flag")),
+		}
+	}
+
+	pub fn u16(&mut self) -> Result<u16> {
+		Ok(u16::from_le_bytes(self.take()?))
+	}
+
+	pub fn u32(&mut self) -> Result<u32> {
+		Ok(u32::from_le_bytes(self.take()?))
+	}
+
+	pub fn usize(&mut self) -> Result<usize> {
+		usize::try_from(u64::from_le_bytes(self.take()?)).map_err(|_| Error::Invalid("size"))
+	}
+
+	pub fn f64(&mut self) -> Result<f64> {
+		Ok(f64::from_le_bytes(self.take()?))
+	}
+
+	pub fn bytes(&mut self) -> Result<&'a [u8]> {
+		let length = self.u32()? as usize;
+		let bytes = self
+			.bytes
+			.get(self.position..self.position + length)
+			.ok_or(Error::Truncated)?;
+		self.position += length;
+		Ok(bytes)
+	}
+
+	/// Length prefixed bytes that have to fill `destination` exactly
+	pub fn bytes_into(&mut self, destination: &mut [u8], what: &'static str) -> Result<()> {
+		let bytes = self.bytes()?;
+		if bytes.len() != destination.len() {
+			return Err(Error::Invalid(what));
+		}
+		destination.copy_from_slice(bytes);
+		Ok(())
+	}
+
+	/// One of `values` by its index
+	pub fn choice<T: Copy>(&mut self, values: &[T], what: &'static str) -> Result<T> {
+		values
+			.get(self.u8()? as usize)
+			.copied()
+			.ok_or(Error::Invalid(what))
+	}
+}
+
+/// State that goes into a save state. Devices attached from outside the chip are not
+/// part of it and stay as they are on restore.
+pub trait Snapshot {
+	fn save(&self, writer: &mut Writer);
+	/// Reads back what `save` wrote, leaving `self` in an unspecified state on error
+	fn restore(&mut self, reader: &mut Reader) -> Result<()>;
+}
+
+/// Encodes the whole machine: CPU registers, SRAM, flash, EEPROM, fuses and the
+/// peripherals. Breakpoints, watchpoints and symbols belong to the debugger and are not
+/// included.
+pub fn save(cpu: &Cpu) -> Vec<u8> {
+	let mut writer = Writer::default();
+	writer.bytes.extend_from_slice(MAGIC);
+	writer.u16(VERSION);
+
+	writer.u16(cpu.pc);
+	writer.u16(cpu.sp);
+	writer.u8(cpu.status.byte());
+	writer.usize(cpu.cycles);
+	writer.u16(cpu.opcode);
+
+	writer.bytes(&cpu.sram.registers);
+	writer.bytes(&cpu.sram.io_registers);
+	writer.bytes(&cpu.sram.ext_io_registers);
+	writer.bytes(&cpu.sram.internal_ram);
+
+	let system = &cpu.system;
+	let flash = &system.program_memory;
+	let app_end = flash.app_flash.address_range().end as usize;
+	let words: Vec<u8> = (flash.app_flash.data[..app_end].iter())
+		.chain(&flash.boot_flash.data)
+		.flat_map(|word| word.to_le_bytes())
+		.collect();
+	writer.bytes(&words);
+	writer.bytes(system.eeprom_memory.as_bytes());
+	for fuse in [
+		system.fuses.low,
+		system.fuses.high,
+		system.fuses.extended,
+		system.fuses.lock,
+	] {
+		writer.u8(fuse);
+	}
+	writer.u16(system.entry_point);
+
+	cpu.peripherals.save(&mut writer);
+	writer.into_bytes()
+}
+
+/// Puts `cpu` in the state `bytes` were saved from. Nothing changes unless the whole
+/// save state is valid.
+pub fn restore(cpu: &mut Cpu, bytes: &[u8]) -> Result<()> {
+	if !bytes.starts_with(MAGIC) {
+		return Err(Error::NotSnapshot);
+	}
+	let mut reader = Reader::new(&bytes[MAGIC.len()..]);
+	let version = reader.u16()?;
+	if version == 0 || version > VERSION {
+		return Err(Error::UnsupportedVersion(version));
+	}
+
+	let (pc, sp, sreg) = (reader.u16()?, reader.u16()?, reader.u8()?);
+	let (cycles, opcode) = (reader.usize()?, reader.u16()?);
+
+	let mut sram = Sram::default();
+	reader.bytes_into(&mut sram.registers, "registers")?;
+	reader.bytes_into(&mut sram.io_registers, "I/O registers")?;
+	reader.bytes_into(&mut sram.ext_io_registers, "I/O registers")?;
+	reader.bytes_into(&mut sram.internal_ram, "SRAM")?;
+
+	let mut words = vec![0; (PROGRAM_END - PROGRAM_START + 1) as usize * 2];
+	reader.bytes_into(&mut words, "flash")?;
+	let mut eeprom = vec![0; cpu.system.eeprom_memory.as_bytes().len()];
+	reader.bytes_into(&mut eeprom, "EEPROM")?;
+	let fuses = [reader.u8()?, reader.u8()?, reader.u8()?, reader.u8()?];
+	let entry_point = reader.u16()?;
+	if entry_point > PROGRAM_END || pc > PROGRAM_END {
+		return Err(Error::Invalid("program counter"));
+	}
+	if sp > RAMEND {
+		return Err(Error::Invalid("stack pointer"));
+	}
+
+	let mut peripherals = cpu.peripherals.clone();
+	peripherals.restore(&mut reader)?;
+
+	cpu.pc = pc;
+	cpu.sp = sp;
+	cpu.status.set_byte(sreg);
+	cpu.cycles = cycles;
+	cpu.opcode = opcode;
+	cpu.sram = sram;
+	cpu.peripherals = peripherals;
+	cpu.break_hit = false;
+	cpu.breakpoint_hit = false;
+	cpu.watch_hit = None;
+	if let Some(history) = &mut cpu.history {
+		history.clear();
+	}
+
+	let system = &mut cpu.system;
+	system.program_memory = ProgramMemory::default();
+	let mut length = 0;
+	for (address, word) in (PROGRAM_START..).zip(words.chunks(2)) {
+		let word = u16::from_le_bytes([word[0], word[1]]);
+		system.program_memory.write(address, word);
+		if word != 0 {
+			length = address + 1;
+		}
+	}
+	system.disassemble(length);
+	system.eeprom_memory.load_bytes(&eeprom);
+	[
+		system.fuses.low,
+		system.fuses.high,
+		system.fuses.extended,
+		system.fuses.lock,
+	] = fuses;
+	system.entry_point = entry_point;
+	Ok(())
+}
+
+pub fn save_file(cpu: &Cpu, path: &Path) -> io::Result<()> {
+	fs::write(path, save(cpu))
+}
+
+pub fn restore_file(cpu: &mut Cpu, path: &Path) -> io::Result<()> {
+	let bytes = fs::read(path)?;
+	restore(cpu, &bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
@@ -0,0 +1,130 @@
+use crate::elf::{Elf, SymbolKind};
+use std::collections::BTreeMap;
+
+/// Data space starts here in the avr-gcc address space
+pub const DATA_OFFSET: u32 = 0x80_0000;
+
+/// Program memory words, the largest address a function symbol can have
+const FLASH_WORDS: u32 = 0x4000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+	pub name: String,
+	/// Word address of a function, data space address of a variable
+	pub address: u16,
+	/// Length in the same unit as the address, zero when unknown
+	pub size: u16,
+}
+
+impl Label {
+	fn contains(&self, address: u16) -> bool {
+		address == self.address || address - self.address < self.size
+	}
+}
+
+/// Function and variable names of the loaded firmware. Offsets count words for code
+/// and bytes for data, like the addresses shown alongside them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+	functions: BTreeMap<u16, Label>,
+	variables: BTreeMap<u16, Label>,
+}
+
+impl SymbolTable {
+	pub fn from_elf(elf: &Elf) -> Self {
+		let mut table = SymbolTable::default();
+
+		for symbol in &elf.symbols {
+			match symbol.kind {
+				SymbolKind::Function if symbol.value / 2 < FLASH_WORDS => {
+					let label = Label {
+						name: symbol.name.clone(),
+						address: (symbol.value / 2) as u16,
+						size: symbol.size.div_ceil(2) as u16,
+					};
+					table.functions.entry(label.address).or_insert(label);
+				}
+				SymbolKind::Object if symbol.value >= DATA_OFFSET => {
+					let address = symbol.value - DATA_OFFSET;
+					if address <= u16::MAX as u32 {
+						let label = Label {
+							name: symbol.name.clone(),
+							address: address as u16,
+							size: symbol.size.min(u16::MAX as u32) as u16,
+						};
+						table.variables.entry(label.address).or_insert(label);
+					}
+				}
+				_ => {}
+			}
+		}
+
+		table
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.functions.is_empty() && self.variables.is_empty()
+	}
+
+	pub fn functions(&self) -> impl Iterator<Item = &Label> {
+		self.functions.values()
+	}
+
+	/// Function starting exactly at the word address
+	pub fn function_at(&self, address: u16) -> Option<&Label> {
+		self.functions.get(&address)
+	}
+
+	/// Function containing the word address
+	pub fn function_containing(&self, address: u16) -> Option<&Label> {
+		Self::containing(&self.functions, address)
+	}
+
+	/// Variable containing the data space address
+	pub fn variable_containing(&self, address: u16) -> Option<&Label> {
+		Self::containing(&self.variables, address)
+	}
+
+	fn containing(labels: &BTreeMap<u16, Label>, address: u16) -> Option<&Label> {
+		labels
+			.range(..=address)
+			.next_back()
+			.map(|(_, label)| label)
+			.filter(|label| label.contains(address))
+	}
+
+	/// `name` or `name+0x12` for a word address in flash
+	pub fn code_location(&self, address: u16) -> Option<String> {
+		self.function_containing(address)
+			.map(|label| Self::location(label, address))
+	}
+
+	/// `name` or `name+0x3` for an address in data space
+	pub fn data_location(&self, address: u16) -> Option<String> {
+		self.variable_containing(address)
+			.map(|label| Self::location(label, address))
+	}
+
+	fn location(label: &Label, address: u16) -> String {
+		match address - label.address {
+			0 => label.name.clone(),
+			offset => format!("{}+0x{:X}", label.name, offset),
+		}
+	}
+
+	/// Function or variable with exactly this name
+	pub fn find(&self, name: &str) -> Option<&Label> {
+		self.functions
+			.values()
+			.chain(self.variables.values())
+			.find(|label| label.name == name)
+	}
+
+	/// Functions whose name contains `query`, ignoring case
+	pub fn search<'a>(&'a self, query: &str) -> impl Iterator<Item = &'a Label> {
+		let query = query.to_lowercase();
+		self.functions
+			.values()
+			.filter(move |label| label.name.to_lowercase().contains(&query))
+	}
+}
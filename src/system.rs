@@ -1,24 +1,142 @@
-use regex::Regex;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::{
 	disassembler::Disassembler,
-	memory::{EepromMemory, Memory, ProgramMemory, PROGRAM_START},
+	dwarf::LineTable,
+	elf,
+	fuses::{Fuses, SIGNATURE},
+	ihex,
+	memory::{EepromMemory, Memory, ProgramMemory, EEPROM_SIZE, PROGRAM_END, PROGRAM_START},
+	symbols::SymbolTable,
 };
 
+/// Address avr-gcc places the `.eeprom` section at in its images
+pub const EEPROM_IMAGE_OFFSET: u32 = 0x81_0000;
+const FUSE_IMAGE_OFFSET: u32 = 0x82_0000;
+const LOCK_IMAGE_OFFSET: u32 = 0x83_0000;
+const SIGNATURE_IMAGE_OFFSET: u32 = 0x84_0000;
+
+const PROGRAM_FLASH_BYTES: usize = (PROGRAM_END as usize + 1) * 2;
+
+/// Where a byte of a firmware image ends up
+enum Region {
+	Flash(u32),
+	Eeprom(u16),
+	/// Low, high and extended fuse byte
+	Fuse(usize),
+	Lock,
+	Signature(usize),
+}
+
+impl Region {
+	fn from_address(address: u32) -> Result<Self, LoadError> {
+		let offset =
+			|start: u32, length: u32| address.checked_sub(start).filter(|offset| *offset < length);
+
+		if let Some(offset) = offset(0, PROGRAM_FLASH_BYTES as u32) {
+			Ok(Region::Flash(offset))
+		} else if let Some(offset) = offset(EEPROM_IMAGE_OFFSET, EEPROM_SIZE as u32) {
+			Ok(Region::Eeprom(offset as u16))
+		} else if let Some(offset) = offset(FUSE_IMAGE_OFFSET, 3) {
+			Ok(Region::Fuse(offset as usize))
+		} else if offset(LOCK_IMAGE_OFFSET, 1).is_some() {
+			Ok(Region::Lock)
+		} else if let Some(offset) = offset(SIGNATURE_IMAGE_OFFSET, 3) {
+			Ok(Region::Signature(offset as usize))
+		} else {
+			Err(LoadError::OutOfRange(address))
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+	Io(std::io::Error),
+	Hex(ihex::Error),
+	Elf(elf::Error),
+	/// Data at a byte address outside of every memory
+	OutOfRange(u32),
+	/// Signature byte at an index that does not match this device
+	Signature(u8, u8),
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LoadError::Io(error) => write!(f, "{}", error),
+			LoadError::Hex(error) => write!(f, "{}", error),
+			LoadError::Elf(error) => write!(f, "{}", error),
+			LoadError::OutOfRange(address) => {
+				write!(f, "address 0x{:06X} is outside of every memory", address)
+			}
+			LoadError::Signature(index, byte) => write!(
+				f,
+				"signature byte {} is 0x{:02X}, the firmware was built for another device",
+				index, byte
+			),
+		}
+	}
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+	fn from(error: std::io::Error) -> Self {
+		LoadError::Io(error)
+	}
+}
+
+impl From<elf::Error> for LoadError {
+	fn from(error: elf::Error) -> Self {
+		LoadError::Elf(error)
+	}
+}
+
+impl From<ihex::Error> for LoadError {
+	fn from(error: ihex::Error) -> Self {
+		LoadError::Hex(error)
+	}
+}
+
+/// On-disk formats for EEPROM images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EepromFormat {
+	/// Intel HEX, as written to `.eep` files by avr-objcopy
+	IntelHex,
+	/// Raw bytes starting at address zero
+	Binary,
+}
+
+impl EepromFormat {
+	pub fn from_path(path: &Path) -> Self {
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some("eep" | "hex" | "ihex") => EepromFormat::IntelHex,
+			_ => EepromFormat::Binary,
+		}
+	}
+}
+
 #[derive(Default)]
 pub struct System {
 	pub program_memory: ProgramMemory,
 	pub eeprom_memory: EepromMemory,
 	pub disassembler: Disassembler,
+	pub fuses: Fuses,
+	/// File the EEPROM is loaded from and saved to on exit
+	pub eeprom_file: Option<PathBuf>,
+	/// Word address execution starts from after reset
+	pub entry_point: u16,
+	/// Names from the ELF symbol table, empty for other formats
+	pub symbols: SymbolTable,
+	/// Source lines from the ELF debug information, empty for other formats
+	pub lines: LineTable,
 	pub last_instuction_address: u16,
 }
 
 impl System {
-	#[cfg(test)]
+	/// Writes `program` to flash from address zero and disassembles it
 	pub fn flash_from_vec(&mut self, program: Vec<u16>) {
 		let program_length = program.len() as u16;
 		for (index, word) in program.into_iter().enumerate() {
@@ -29,60 +147,192 @@ impl System {
 			&mut self.program_memory.app_flash,
 			PROGRAM_START,
 			program_length,
+			&self.symbols,
 		);
 	}
 
-	pub fn flash_from_hex_file(&mut self, program_file: &PathBuf) {
-		let re = match Regex::new(
-			r":(?P<data_size>[A-z0-9]{2})(?P<start_address>[A-z0-9]{4})(?P<record_type>[A-z0-9]{2})(?P<data>[A-z0-9]+)(?P<checksum>[A-z0-9]{2})$",
-		) {
-			Err(_) => {
-				println!("Invalid regex string for hex file parsing");
-				return;
-			}
-			Ok(rgx) => rgx,
-		};
+	/// Loads an Intel HEX file, flash and EEPROM images are both accepted
+	pub fn flash_from_hex_file(&mut self, path: &Path) -> Result<(), LoadError> {
+		let text = fs::read_to_string(path)?;
+		self.flash_from_hex(&text)
+	}
 
-		let file = match File::open(program_file) {
-			Err(_) => {
-				println!("Unable to open .hex file: {}", program_file.display());
-				return;
-			}
-			Ok(f) => f,
-		};
+	/// Loads Intel HEX text, laid out like avr-objcopy output. Nothing is written unless
+	/// the whole image is valid.
+	pub fn flash_from_hex(&mut self, text: &str) -> Result<(), LoadError> {
+		let image = ihex::parse(text)?;
+		let entry = image.start_address.unwrap_or(0);
+		self.load_image(
+			&image.data,
+			entry,
+			SymbolTable::default(),
+			LineTable::default(),
+		)
+	}
 
-		let reader = BufReader::new(file);
-		let lines: Vec<_> = reader.lines().map(|line| line.unwrap()).collect();
+	/// Loads an ELF file as built by avr-gcc
+	pub fn flash_from_elf_file(&mut self, path: &Path) -> Result<(), LoadError> {
+		self.flash_from_elf(&fs::read(path)?)
+	}
 
-		self.program_memory.app_flash.clear();
+	/// Loads ELF32 AVR contents: `.text` and `.data` to flash, `.eeprom` to the EEPROM,
+	/// `.fuse` and `.lock` to the fuses, while `.signature` has to match this device
+	pub fn flash_from_elf(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+		let elf = elf::parse(bytes)?;
+		// the firmware runs just as well without line information it can't decode
+		let lines = LineTable::from_elf(&elf).unwrap_or_default();
+		self.load_image(&elf.image(), elf.entry, SymbolTable::from_elf(&elf), lines)
+	}
+
+	/// Loads an ELF or Intel HEX file, told apart by their contents
+	pub fn load_program(&mut self, path: &Path) -> Result<(), LoadError> {
+		let bytes = fs::read(path)?;
+		if bytes.starts_with(b"\x7FELF") {
+			self.flash_from_elf(&bytes)
+		} else {
+			let text = String::from_utf8(bytes)
+				.map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+			self.flash_from_hex(&text)
+		}
+	}
+
+	/// Places `(address, byte)` pairs in the unified address space of avr-gcc and sets the
+	/// entry point from a byte address
+	fn load_image(
+		&mut self,
+		data: &[(u32, u8)],
+		entry: u32,
+		symbols: SymbolTable,
+		lines: LineTable,
+	) -> Result<(), LoadError> {
+		let regions = data
+			.iter()
+			.map(|(address, byte)| Ok((Region::from_address(*address)?, *byte)))
+			.collect::<Result<Vec<(Region, u8)>, LoadError>>()?;
+
+		for (region, byte) in &regions {
+			if let Region::Signature(index) = region {
+				// avr-libc stores the signature last byte first
+				if SIGNATURE[SIGNATURE.len() - 1 - *index] != *byte {
+					return Err(LoadError::Signature(*index as u8, *byte));
+				}
+			}
+		}
+
+		self.program_memory = ProgramMemory::default();
 		let mut program_length: u16 = 0;
 
-		for line in lines.iter() {
-			match re.captures(line) {
-				None => continue,
-				Some(capture) => {
-					let chars: Vec<char> = capture["data"].chars().to_owned().collect();
-
-					for x in 0..(chars.len() / 4) {
-						let index = x * 4;
-						let a = chars[index + 2].to_digit(16).unwrap() as u16;
-						let b = chars[index + 3].to_digit(16).unwrap() as u16;
-						let c = chars[index].to_digit(16).unwrap() as u16;
-						let d = chars[index + 1].to_digit(16).unwrap() as u16;
-
-						let word = ((a << 12) | (b << 8)) | ((c << 4) | d);
-						program_length += 1;
-						self.program_memory
-							.write(PROGRAM_START + program_length, word);
-					}
+		for (region, byte) in regions {
+			match region {
+				Region::Flash(address) => {
+					// flash is little endian, each byte replaces one half of a word
+					let word_address = (address / 2) as u16;
+					let word = self.program_memory.read(word_address);
+					let word = if address % 2 == 0 {
+						(word & 0xFF00) | byte as u16
+					} else {
+						(word & 0x00FF) | ((byte as u16) << 8)
+					};
+					self.program_memory.write(word_address, word);
+					program_length = program_length.max(word_address + 1);
 				}
+				Region::Eeprom(address) => self.eeprom_memory.write(address, byte as u16),
+				Region::Fuse(0) => self.fuses.low = byte,
+				Region::Fuse(1) => self.fuses.high = byte,
+				Region::Fuse(_) => self.fuses.extended = byte,
+				Region::Lock => self.fuses.lock = byte,
+				Region::Signature(_) => {}
 			}
 		}
 
+		self.entry_point = (entry / 2) as u16;
+		self.symbols = symbols;
+		self.lines = lines;
+
+		self.disassemble(program_length);
+		Ok(())
+	}
+
+	/// Replaces the disassembly with the first `program_length` words of flash
+	pub(crate) fn disassemble(&mut self, program_length: u16) {
+		let app_end = self.program_memory.app_flash.address_range().end;
+		self.disassembler = Disassembler::default();
 		self.disassembler.disassemble(
 			&mut self.program_memory.app_flash,
 			PROGRAM_START,
-			program_length,
+			program_length.min(app_end),
+			&self.symbols,
 		);
 	}
+
+	/// Replaces the EEPROM contents with an image, which must fit the EEPROM
+	pub fn load_eeprom(&mut self, path: &Path) -> Result<(), LoadError> {
+		match EepromFormat::from_path(path) {
+			EepromFormat::IntelHex => {
+				let text = fs::read_to_string(path)?;
+				let data = ihex::parse(&text)?
+					.data
+					.into_iter()
+					.map(|(address, byte)| {
+						// .eep files start at zero unless the section address was kept
+						let offset = address.checked_sub(EEPROM_IMAGE_OFFSET).unwrap_or(address);
+						match offset < EEPROM_SIZE as u32 {
+							true => Ok((offset as u16, byte)),
+							false => Err(LoadError::OutOfRange(address)),
+						}
+					})
+					.collect::<Result<Vec<(u16, u8)>, LoadError>>()?;
+
+				self.eeprom_memory.erase();
+				for (address, byte) in data {
+					self.eeprom_memory.write(address, byte as u16);
+				}
+			}
+			EepromFormat::Binary => {
+				let bytes = fs::read(path)?;
+				if bytes.len() > EEPROM_SIZE as usize {
+					return Err(LoadError::OutOfRange(EEPROM_SIZE as u32));
+				}
+				self.eeprom_memory.load_bytes(&bytes);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn save_eeprom(&self, path: &Path) -> std::io::Result<()> {
+		let bytes = self.eeprom_memory.as_bytes();
+		match EepromFormat::from_path(path) {
+			EepromFormat::IntelHex => fs::write(path, ihex::write(bytes)),
+			EepromFormat::Binary => fs::write(path, bytes),
+		}
+	}
+
+	/// Persists the EEPROM to `path`, loading its previous contents if the file exists
+	pub fn persist_eeprom(&mut self, path: PathBuf) -> Result<(), LoadError> {
+		if path.exists() {
+			self.load_eeprom(&path)?;
+		}
+		self.eeprom_file = Some(path);
+		Ok(())
+	}
+
+	/// Writes the EEPROM back to the persistence file, if one is set
+	pub fn save_persisted_eeprom(&self) -> std::io::Result<()> {
+		match &self.eeprom_file {
+			Some(path) => self.save_eeprom(path),
+			None => Ok(()),
+		}
+	}
+
+	/// Erases flash, and the EEPROM unless the EESAVE fuse is programmed
+	pub fn chip_erase(&mut self) {
+		self.program_memory.app_flash.clear();
+		self.program_memory.boot_flash = Default::default();
+		self.disassembler = Disassembler::default();
+		self.symbols = SymbolTable::default();
+		self.lines = LineTable::default();
+		if !self.fuses.eesave() {
+			self.eeprom_memory.erase();
+		}
+	}
 }
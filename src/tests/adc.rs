@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod conversion {
+	use crate::cpu::Cpu;
+	use crate::peripherals::adc::{TriggerSource, ADCH, ADCL, ADCSRA, ADCSRB, ADMUX};
+	use crate::peripherals::analog::AnalogPin;
+	use crate::tests::{run, setup};
+
+	const ADEN: u8 = 0x80;
+	const ADSC: u8 = 0x40;
+	const ADATE: u8 = 0x20;
+	const ADIF: u8 = 0x10;
+	const ADIE: u8 = 0x08;
+
+	fn result(cpu: &mut Cpu) -> u16 {
+		let low = cpu.read_data(ADCL) as u16;
+		let high = cpu.read_data(ADCH) as u16;
+		(high << 8) | low
+	}
+
+	#[test]
+	fn single_conversion() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc3, 2.5);
+
+		// AVCC reference, ADC3
+		cpu.write_data(ADMUX, 0x43);
+		// prescaler 2
+		cpu.write_data(ADCSRA, ADEN | ADSC | 0x01);
+
+		run(&mut cpu, 49);
+		assert_eq!(cpu.read_data(ADCSRA) & ADSC, ADSC);
+		run(&mut cpu, 1);
+		assert_eq!(cpu.read_data(ADCSRA) & (ADSC | ADIF), ADIF);
+		assert_eq!(result(&mut cpu), 512);
+
+		// subsequent conversions take 13 ADC clocks
+		cpu.write_data(ADCSRA, ADEN | ADSC | ADIF | 0x07);
+		assert_eq!(cpu.read_data(ADCSRA) & ADIF, 0);
+		run(&mut cpu, 13 * 128 - 1);
+		assert_eq!(cpu.read_data(ADCSRA) & ADSC, ADSC);
+		run(&mut cpu, 1);
+		assert_eq!(cpu.read_data(ADCSRA) & ADSC, 0);
+	}
+
+	#[test]
+	fn left_adjust_and_references() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc0, 0.55);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Aref, 2.2);
+
+		// AREF reference, ADLAR, ADC0
+		cpu.write_data(ADMUX, 0x20);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 50);
+		assert_eq!(cpu.read_data(ADCL), 0x00);
+		assert_eq!(cpu.read_data(ADCH), 0x40);
+
+		// internal 1.1V reference, ADC0
+		cpu.write_data(ADMUX, 0xC0);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 26);
+		assert_eq!(result(&mut cpu), 512);
+	}
+
+	#[test]
+	fn internal_channels() {
+		let mut cpu = setup(&[]);
+
+		// bandgap measured against AVCC
+		cpu.write_data(ADMUX, 0x4E);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 50);
+		assert_eq!(result(&mut cpu), 225);
+
+		// temperature sensor against the internal reference
+		cpu.peripherals.analog.set_temperature(85.0);
+		cpu.write_data(ADMUX, 0xC8);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 26);
+		assert_eq!(result(&mut cpu), 353);
+	}
+
+	#[test]
+	fn data_registers_lock() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc1, 5.0);
+		cpu.write_data(ADMUX, 0x41);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 50);
+
+		cpu.read_data(ADCL);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc1, 0.0);
+		cpu.write_data(ADCSRA, ADEN | ADSC);
+		run(&mut cpu, 26);
+
+		assert_eq!(cpu.read_data(ADCH), 0x03);
+	}
+
+	#[test]
+	fn free_running() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc2, 1.0);
+		cpu.write_data(ADMUX, 0x42);
+		cpu.write_data(ADCSRA, ADEN | ADSC | ADATE);
+		run(&mut cpu, 50);
+		assert_eq!(result(&mut cpu), 204);
+		assert!(cpu.peripherals.adc.is_converting());
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc2, 2.0);
+		run(&mut cpu, 26);
+		assert_eq!(result(&mut cpu), 409);
+	}
+
+	#[test]
+	fn auto_trigger() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ADCSRB, 0x02);
+		cpu.write_data(ADCSRA, ADEN | ADATE);
+
+		cpu.peripherals
+			.adc
+			.trigger(&mut cpu.sram, TriggerSource::AnalogComparator);
+		assert!(!cpu.peripherals.adc.is_converting());
+
+		cpu.peripherals
+			.adc
+			.trigger(&mut cpu.sram, TriggerSource::Int0);
+		assert!(cpu.peripherals.adc.is_converting());
+	}
+
+	#[test]
+	fn interrupt() {
+		let mut cpu = setup(&[]);
+		cpu.status.I = true;
+		cpu.write_data(ADCSRA, ADEN | ADSC | ADIE);
+		run(&mut cpu, 51);
+
+		assert_eq!(cpu.pc, 0x2A);
+		assert_eq!(cpu.read_data(ADCSRA) & ADIF, 0);
+	}
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod comparator {
+	use crate::peripherals::adc::{ADCSRA, ADCSRB, ADMUX};
+	use crate::peripherals::analog::AnalogPin;
+	use crate::peripherals::analog_comparator::ACSR;
+	use crate::peripherals::timer::{ICR1L, TCCR1B, TCNT1L, TIFR1, TIMSK1};
+	use crate::tests::setup;
+
+	const ACBG: u8 = 0x40;
+	const ACO: u8 = 0x20;
+	const ACI: u8 = 0x10;
+	const ACIE: u8 = 0x08;
+	const ACIC: u8 = 0x04;
+
+	#[test]
+	fn output_and_toggle_flag() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 2.0);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain1, 1.0);
+
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & (ACO | ACI), ACO | ACI);
+
+		cpu.write_data(ACSR, ACI);
+		assert_eq!(cpu.read_data(ACSR) & (ACO | ACI), ACO);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain1, 3.0);
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & (ACO | ACI), ACI);
+	}
+
+	#[test]
+	fn rising_edge_only() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ACSR, 0x03);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 2.0);
+		cpu.step();
+		cpu.write_data(ACSR, ACI | 0x03);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 0.0);
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & ACI, 0);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 2.0);
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & ACI, ACI);
+	}
+
+	#[test]
+	fn bandgap_and_multiplexed_inputs() {
+		let mut cpu = setup(&[]);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain1, 0.5);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc5, 1.5);
+		cpu.write_data(ACSR, ACBG);
+
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & ACO, ACO);
+
+		// ACME with the ADC disabled routes ADC5 to the negative input
+		cpu.write_data(ADCSRB, 0x40);
+		cpu.write_data(ADMUX, 0x05);
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & ACO, 0);
+
+		// enabling the ADC returns the negative input to AIN1
+		cpu.write_data(ADCSRA, 0x80);
+		cpu.step();
+		assert_eq!(cpu.read_data(ACSR) & ACO, ACO);
+	}
+
+	#[test]
+	fn interrupt() {
+		let mut cpu = setup(&[]);
+		cpu.status.I = true;
+		cpu.write_data(ACSR, ACIE);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 1.0);
+
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.pc, 0x2E);
+		assert_eq!(cpu.read_data(ACSR) & ACI, 0);
+	}
+
+	#[test]
+	fn input_capture() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ACSR, ACIC);
+		cpu.write_data(TCCR1B, 0x40);
+		cpu.write_data(TCNT1L, 0x34);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 1.0);
+		cpu.step();
+		assert_eq!(cpu.read_data(ICR1L), 0x34);
+		assert_eq!(cpu.read_data(TIFR1) & 0x20, 0x20);
+
+		cpu.write_data(TIFR1, 0x20);
+		assert_eq!(cpu.read_data(TIFR1) & 0x20, 0);
+
+		cpu.status.I = true;
+		cpu.write_data(TIMSK1, 0x20);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 0.0);
+		cpu.step();
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 1.0);
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.pc, 0x14);
+	}
+
+	#[test]
+	fn triggers_adc() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ADCSRB, 0x01);
+		cpu.write_data(ADCSRA, 0xA0);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 1.0);
+		cpu.step();
+		assert!(cpu.peripherals.adc.is_converting());
+	}
+}
@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod breakpoint {
+	use crate::cpu::Cpu;
+	use crate::debugger::{Breakpoint, Condition};
+	use crate::peripherals::sleep::SMCR;
+	use crate::runner::{self, Limits, StopReason};
+	use crate::tests::setup;
+
+	// inc r16; rjmp .-4
+	const COUNTER: [u16; 2] = [0x9503, 0xCFFE];
+
+	fn limits() -> Limits {
+		Limits {
+			cycles: Some(10_000),
+			seconds: None,
+		}
+	}
+
+	fn evaluate(cpu: &Cpu, text: &str) -> bool {
+		Condition::parse(text).unwrap().evaluate(cpu)
+	}
+
+	#[test]
+	fn conditions() {
+		let mut cpu = setup(&[0x0000]);
+		cpu.sram.registers[24] = 0xE8;
+		cpu.sram.registers[25] = 0x03;
+		cpu.status.Z = true;
+
+		assert!(evaluate(&cpu, "r24 == 0xE8 && Z"));
+		assert!(evaluate(&cpu, "r25:r24 >= 1000"));
+		assert!(evaluate(&cpu, "!I && (C || z)"));
+		assert!(evaluate(&cpu, "SREG == 0b10 && SP > 0x800 && PC == 0"));
+		assert!(!evaluate(&cpu, "r24 < 10 || r25 != 3"));
+		assert_eq!(
+			Condition::parse(" r1 <= 2 ").unwrap().to_string(),
+			"r1 <= 2"
+		);
+
+		assert!(Condition::parse("r24 = 1").is_err());
+		assert!(Condition::parse("r32 == 1").is_err());
+		assert!(Condition::parse("r24 ==").is_err());
+		assert!(Condition::parse("(r24").is_err());
+		assert!(Condition::parse("counter > 1").is_err());
+		assert!(Condition::parse("0x1G").is_err());
+	}
+
+	#[test]
+	fn stops_before_instruction() {
+		// ldi r16, 1; ldi r17, 2; rjmp .-2
+		let mut cpu = setup(&[0xE001, 0xE012, 0xCFFF]);
+		cpu.breakpoints.insert(Breakpoint::new(0x0001));
+
+		let reason = runner::run(&mut cpu, &limits());
+		assert_eq!(reason, StopReason::Breakpoint(0x0001));
+		assert_eq!(cpu.sram.registers[16], 1);
+		assert_eq!(cpu.sram.registers[17], 0);
+
+		// resuming executes the instruction under the breakpoint
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::CycleLimit);
+		assert_eq!(cpu.sram.registers[17], 2);
+	}
+
+	#[test]
+	fn conditional() {
+		let mut cpu = setup(&COUNTER);
+		cpu.breakpoints.insert(Breakpoint {
+			condition: Some(Condition::parse("r16 == 5").unwrap()),
+			..Breakpoint::new(0x0000)
+		});
+
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::Breakpoint(0));
+		assert_eq!(cpu.sram.registers[16], 5);
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, 1);
+	}
+
+	#[test]
+	fn hit_counts() {
+		let mut cpu = setup(&COUNTER);
+		cpu.breakpoints.insert(Breakpoint {
+			ignore_count: 3,
+			..Breakpoint::new(0x0000)
+		});
+
+		// the first hit is at the entry point, before any step
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::Breakpoint(0));
+		assert_eq!(cpu.sram.registers[16], 3);
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, 4);
+
+		runner::run(&mut cpu, &limits());
+		assert_eq!(cpu.sram.registers[16], 4);
+
+		cpu.breakpoints.get_mut(0x0000).unwrap().enabled = false;
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::CycleLimit);
+
+		cpu.breakpoints.toggle(0x0000);
+		assert!(cpu.breakpoints.is_empty());
+		cpu.breakpoints.toggle(0x0001);
+		assert!(cpu.breakpoints.get(0x0001).unwrap().enabled);
+	}
+
+	#[test]
+	fn entry_point() {
+		// ldi r16, 1; rjmp .-2
+		let mut cpu = setup(&[0xE001, 0xCFFF]);
+		cpu.breakpoints.insert(Breakpoint::new(0x0000));
+
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::Breakpoint(0));
+		assert_eq!(cpu.cycles, 0);
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, 1);
+
+		// resuming does not stop at the entry point again
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::CycleLimit);
+		assert_eq!(cpu.sram.registers[16], 1);
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, 1);
+
+		cpu.reset();
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::Breakpoint(0));
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, 2);
+	}
+
+	#[test]
+	fn sleeping_does_not_hit() {
+		// sleep; rjmp .-2
+		let mut cpu = setup(&[0x9588, 0xCFFF]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x01);
+		cpu.breakpoints.insert(Breakpoint::new(0x0001));
+
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::Breakpoint(1));
+		assert!(cpu.is_sleeping());
+
+		// nothing wakes the CPU, so the PC stays at the breakpoint without landing on it
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::CycleLimit);
+		assert_eq!(cpu.breakpoints.get(0x0001).unwrap().hits, 1);
+
+		// neither does fast forwarding through the idle loop
+		let mut cpu = setup(&[0x0000, 0xCFFF]);
+		cpu.fast_forward = true;
+		cpu.breakpoints.insert(Breakpoint {
+			ignore_count: 1,
+			..Breakpoint::new(0x0001)
+		});
+		assert_eq!(runner::run(&mut cpu, &limits()), StopReason::CycleLimit);
+		assert_eq!(cpu.breakpoints.get(0x0001).unwrap().hits, 1);
+	}
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod system_clock {
+	use crate::peripherals::clock::{ClockSource, CLKPR};
+	use crate::tests::{setup, LOOP};
+
+	#[test]
+	fn factory_fuses() {
+		let cpu = setup(&[LOOP]);
+		assert_eq!(cpu.peripherals.clock.source, ClockSource::InternalRc);
+		assert_eq!(cpu.peripherals.clock.division(), 8);
+		assert_eq!(cpu.peripherals.clock.frequency(), 1_000_000.0);
+	}
+
+	#[test]
+	fn fuses_apply_on_reset() {
+		let mut cpu = setup(&[LOOP]);
+		cpu.system.fuses.set_clock_select(0xF);
+		cpu.system.fuses.set_ckdiv8(false);
+		assert_eq!(cpu.peripherals.clock.frequency(), 1_000_000.0);
+
+		cpu.reset();
+		assert_eq!(cpu.peripherals.clock.source, ClockSource::Crystal);
+		assert_eq!(cpu.peripherals.clock.frequency(), 16_000_000.0);
+		assert_eq!(cpu.read_data(CLKPR), 0x00);
+
+		cpu.system.fuses.set_clock_select(0x3);
+		cpu.reset();
+		assert_eq!(cpu.peripherals.clock.frequency(), 128_000.0);
+	}
+
+	#[test]
+	fn prescaler_change_sequence() {
+		let mut cpu = setup(&[LOOP]);
+		assert_eq!(cpu.read_data(CLKPR), 0x03);
+
+		// CLKPS written without CLKPCE is ignored
+		cpu.write_data(CLKPR, 0x00);
+		assert_eq!(cpu.peripherals.clock.division(), 8);
+
+		cpu.write_data(CLKPR, 0x80);
+		assert_eq!(cpu.read_data(CLKPR), 0x83);
+		cpu.write_data(CLKPR, 0x01);
+		assert_eq!(cpu.read_data(CLKPR), 0x01);
+		assert_eq!(cpu.peripherals.clock.frequency(), 4_000_000.0);
+	}
+
+	#[test]
+	fn change_enable_times_out() {
+		let mut cpu = setup(&[LOOP]);
+		cpu.write_data(CLKPR, 0x80);
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.read_data(CLKPR), 0x03);
+
+		cpu.write_data(CLKPR, 0x00);
+		assert_eq!(cpu.peripherals.clock.division(), 8);
+	}
+
+	#[test]
+	fn emulated_time() {
+		let mut cpu = setup(&[LOOP]);
+		for _ in 0..5 {
+			cpu.step();
+		}
+		assert!((cpu.peripherals.clock.seconds() - 10e-6).abs() < 1e-12);
+
+		// halving the division doubles the rate of later cycles
+		cpu.write_data(CLKPR, 0x80);
+		cpu.write_data(CLKPR, 0x02);
+		for _ in 0..5 {
+			cpu.step();
+		}
+		assert!((cpu.peripherals.clock.seconds() - 15e-6).abs() < 1e-12);
+
+		cpu.reset();
+		assert_eq!(cpu.peripherals.clock.seconds(), 0.0);
+	}
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod code_coverage {
+	use crate::coverage::{BranchCount, Coverage};
+	use crate::cpu::Cpu;
+	use crate::tests;
+	use crate::tests::dwarf::line_table;
+
+	fn report(cpu: &Cpu, lcov: bool) -> String {
+		let coverage = cpu.coverage.as_ref().unwrap();
+		let mut output = Vec::new();
+		if lcov {
+			coverage.write_lcov(&cpu.system, &mut output).unwrap();
+		} else {
+			coverage.write_annotated(&cpu.system, &mut output).unwrap();
+		}
+		String::from_utf8(output).unwrap()
+	}
+
+	#[test]
+	fn executed_words() {
+		// ldi r16, 1; inc r16; rjmp .-2; ldi r17, 2
+		let mut cpu = tests::setup(&[0xE001, 0x9503, 0xCFFF, 0xE012]);
+		cpu.coverage = Some(Coverage::default());
+		for _ in 0..5 {
+			cpu.step();
+		}
+
+		let coverage = cpu.coverage.as_ref().unwrap();
+		let hits: Vec<u64> = (0..4).map(|address| coverage.hits(address)).collect();
+		assert_eq!(hits, vec![1, 1, 3, 0]);
+		let summary = coverage.summary(&cpu.system);
+		assert_eq!((summary.executed, summary.instructions), (3, 4));
+		assert_eq!(summary.instruction_percent(), 75.0);
+
+		let text = report(&cpu, false);
+		assert!(text.contains("     #####  0003  ldi"));
+		assert!(text.contains("         3  0002  rjmp"));
+		assert!(text.ends_with(&format!("{}\n", summary)));
+	}
+
+	#[test]
+	fn branches() {
+		let mut coverage = Coverage::default();
+		// breq .+4, taken and then not
+		coverage.record(0x0010, 0xF011, 0x0013);
+		coverage.record(0x0010, 0xF011, 0x0011);
+		coverage.record(0x0010, 0xF011, 0x0011);
+		assert_eq!(
+			coverage.branch(0x0010),
+			Some(BranchCount {
+				taken: 1,
+				not_taken: 2
+			})
+		);
+		// sbrs r16, 0 skipping a two word instruction
+		coverage.record(0x0020, 0xFF00, 0x0023);
+		assert_eq!(coverage.branch(0x0020).map(|branch| branch.taken), Some(1));
+
+		// call 0x0100 executes both of its words
+		coverage.record(0x0030, 0x940E, 0x0100);
+		assert_eq!((coverage.hits(0x0030), coverage.hits(0x0031)), (1, 1));
+		assert_eq!(coverage.branch(0x0030), None);
+
+		coverage.clear();
+		assert_eq!(coverage.hits(0x0010), 0);
+		assert_eq!(coverage.branch(0x0010), None);
+	}
+
+	#[test]
+	fn executed_branches() {
+		// ldi r16, 3; subi r16, 1; cpi r16, 0; brne .-6; rjmp .-2
+		let mut cpu = tests::setup(&[0xE003, 0x5001, 0x3000, 0xF7E9, 0xCFFF]);
+		cpu.coverage = Some(Coverage::default());
+		for _ in 0..11 {
+			cpu.step();
+		}
+
+		let coverage = cpu.coverage.as_ref().unwrap();
+		assert_eq!(
+			coverage.branch(0x0003),
+			Some(BranchCount {
+				taken: 2,
+				not_taken: 1
+			})
+		);
+		let summary = coverage.summary(&cpu.system);
+		assert_eq!((summary.branches_seen, summary.branches), (2, 2));
+	}
+
+	#[test]
+	fn lcov() {
+		let mut cpu = line_table::setup();
+		cpu.coverage = Some(Coverage::default());
+		// main up to the call and all of func, not the lines after the call
+		for _ in 0..6 {
+			cpu.step();
+		}
+
+		let text = report(&cpu, true);
+		let lines: Vec<&str> = text.lines().collect();
+		assert_eq!(lines[..2], ["TN:", "SF:src/main.c"]);
+		assert!(lines.contains(&"DA:10,1"));
+		assert!(lines.contains(&"DA:12,0"));
+		assert!(lines.contains(&"DA:22,1"));
+		assert!(lines.contains(&"BRF:0"));
+		assert!(lines.contains(&"LF:7"));
+		assert!(lines.contains(&"LH:5"));
+		assert_eq!(lines.last(), Some(&"end_of_record"));
+	}
+}
@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod instructions {
 	mod arithmetic {
 		use crate::cpu::Cpu;
@@ -339,10 +340,203 @@ mod instructions {
 		}
 	}
 
-	mod branch {}
+	mod branch {
+		use crate::cpu::Cpu;
+		use crate::memory::{Memory, RAMEND};
+
+		#[test]
+		fn jmp() {
+			let mut cpu = Cpu::init();
+			// jmp 0x0003; nop; nop; jmp 0x0000
+			cpu.system
+				.flash_from_vec([0x940C, 0x0003, 0x0000, 0x940C, 0x0000].to_vec());
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0003);
+			assert_eq!(cpu.cycles, 3);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0000);
+			assert_eq!(cpu.cycles, 6);
+		}
+
+		#[test]
+		fn wraps_at_end_of_flash() {
+			let mut cpu = Cpu::init();
+			let mut program = vec![0x0000; 0x4000];
+			// jmp 0x3FFF; nop at the last word
+			program[0] = 0x940C;
+			program[1] = 0x3FFF;
+			cpu.system.flash_from_vec(program);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x3FFF);
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0000);
+		}
+
+		#[test]
+		fn ijmp() {
+			let mut cpu = Cpu::init();
+			// ijmp; nop; nop
+			cpu.system.flash_from_vec([0x9409, 0x0000, 0x0000].to_vec());
+			cpu.sram.registers[30] = 0x02;
+			cpu.sram.registers[31] = 0x00;
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0002);
+			assert_eq!(cpu.cycles, 2);
+		}
+
+		#[test]
+		fn rcall() {
+			let mut cpu = Cpu::init();
+			// rcall .+4; nop; nop; ret
+			cpu.system
+				.flash_from_vec([0xD002, 0x0000, 0x0000, 0x9508].to_vec());
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0003);
+			assert_eq!(cpu.sp, RAMEND - 2);
+			assert_eq!(cpu.sram.read(RAMEND), 0x01);
+			assert_eq!(cpu.cycles, 3);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0001);
+			assert_eq!(cpu.sp, RAMEND);
+		}
+
+		#[test]
+		fn call() {
+			let mut cpu = Cpu::init();
+			// call 0x0004; nop; ret
+			cpu.system
+				.flash_from_vec([0x940E, 0x0004, 0x0000, 0x0000, 0x9508].to_vec());
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0004);
+			assert_eq!(cpu.cycles, 4);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0002);
+		}
+
+		#[test]
+		fn icall() {
+			let mut cpu = Cpu::init();
+			// icall; nop; ret
+			cpu.system.flash_from_vec([0x9509, 0x0000, 0x9508].to_vec());
+			cpu.sram.registers[30] = 0x02;
+			cpu.sram.registers[31] = 0x00;
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0002);
+			assert_eq!(cpu.cycles, 3);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0001);
+		}
+
+		#[test]
+		fn compare() {
+			let mut cpu = Cpu::init();
+			// cp r24, r22; cpc r25, r23, comparing r25:r24 with r23:r22 twice
+			cpu.system
+				.flash_from_vec([0x1786, 0x0797, 0x1786, 0x0797].to_vec());
+			cpu.sram.registers[25] = 0x01;
+			cpu.sram.registers[23] = 0x00;
+			cpu.sram.registers[22] = 0xFF;
+
+			cpu.step();
+			assert!(cpu.status.C);
+			cpu.step();
+			// 0x0100 is above 0x00FF
+			assert!(!cpu.status.C);
+			assert!(!cpu.status.Z);
+
+			cpu.sram.registers[23] = 0x01;
+			cpu.sram.registers[22] = 0x00;
+			cpu.step();
+			assert!(cpu.status.Z);
+			cpu.step();
+			// Z carries over from the low byte
+			assert!(cpu.status.Z);
+			assert!(!cpu.status.C);
+			assert_eq!(cpu.cycles, 4);
+		}
+
+		#[test]
+		fn loop_() {
+			let mut cpu = Cpu::init();
+			// ldi r16, 3; subi r16, 1; cpi r16, 0; brne .-6; rjmp .-2
+			cpu.system
+				.flash_from_vec([0xE003, 0x5001, 0x3000, 0xF7E9, 0xCFFF].to_vec());
+
+			for _ in 0..10 {
+				cpu.step();
+			}
+			assert_eq!(cpu.sram.registers[16], 0);
+			assert_eq!(cpu.pc, 0x0004);
+			// brne takes 2 cycles when taken and 1 when not
+			assert_eq!(cpu.cycles, 12);
+		}
+
+		#[test]
+		fn signed_and_unsigned() {
+			let mut cpu = Cpu::init();
+			// ldi r16, -2; cpi r16, 1; brcs .+2; brlt .+2
+			cpu.system
+				.flash_from_vec([0xEF0E, 0x3001, 0xF010, 0xF014].to_vec());
+
+			for _ in 0..3 {
+				cpu.step();
+			}
+			// 0xFE is not below 1 unsigned
+			assert_eq!(cpu.pc, 0x0003);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0006);
+			assert_eq!(cpu.cycles, 5);
+		}
+
+		#[test]
+		fn skips() {
+			let mut cpu = Cpu::init();
+			// cpse r0, r1; sts 0x0100, r0; sbrs r16, 0; ldi r17, 1; sbrc r16, 0; ldi r18, 1
+			cpu.system
+				.flash_from_vec([0x1001, 0x9200, 0x0100, 0xFF00, 0xE011, 0xFD00, 0xE021].to_vec());
+			cpu.sram.registers[16] = 0x01;
+
+			// skipping the two words of sts
+			cpu.step();
+			assert_eq!((cpu.pc, cpu.cycles), (0x0003, 3));
+			cpu.step();
+			assert_eq!((cpu.pc, cpu.cycles), (0x0005, 5));
+			cpu.step();
+			assert_eq!((cpu.pc, cpu.cycles), (0x0006, 6));
+			cpu.step();
+			assert_eq!(cpu.sram.registers[17], 0x00);
+			assert_eq!(cpu.sram.registers[18], 0x01);
+		}
+
+		#[test]
+		fn io_skips() {
+			let mut cpu = Cpu::init();
+			// sbic GPIOR0, 2; sbis GPIOR0, 2; ldi r17, 1; ldi r18, 1
+			cpu.system
+				.flash_from_vec([0x99F2, 0x9BF2, 0xE011, 0xE021].to_vec());
+			cpu.sram.write(0x3E, 0x04);
+
+			cpu.step();
+			assert_eq!(cpu.pc, 0x0001);
+			cpu.step();
+			assert_eq!((cpu.pc, cpu.cycles), (0x0003, 3));
+		}
+	}
 
 	mod bit {
 		use crate::cpu::Cpu;
+		use crate::memory::Memory;
 		#[test]
 		fn sec() {
 			let mut cpu = Cpu::init();
@@ -470,7 +664,147 @@ mod instructions {
 			cpu.step();
 			assert_eq!(cpu.status.H, false);
 		}
+
+		#[test]
+		fn sbi_cbi() {
+			let mut cpu = Cpu::init();
+			// sbi GPIOR0, 3; cbi GPIOR0, 0
+			cpu.system.flash_from_vec([0x9AF3, 0x98F0].to_vec());
+			cpu.sram.write(0x3E, 0x01);
+
+			cpu.step();
+			assert_eq!(cpu.sram.read(0x3E), 0x09);
+			cpu.step();
+			assert_eq!(cpu.sram.read(0x3E), 0x08);
+			assert_eq!(cpu.cycles, 4);
+		}
+
+		#[test]
+		fn sbi_clears_only_addressed_flag() {
+			let mut cpu = Cpu::init();
+			// sbi EIFR, 0
+			cpu.system.flash_from_vec([0x9AE0].to_vec());
+			cpu.sram.write(0x3C, 0x03);
+
+			cpu.step();
+			assert_eq!(cpu.sram.read(0x3C), 0x02);
+		}
 	}
 
-	mod data_transfer {}
+	mod data_transfer {
+		use crate::cpu::Cpu;
+		use crate::memory::{Memory, RAMEND};
+
+		#[test]
+		fn mov() {
+			let mut cpu = Cpu::init();
+			// mov r1, r31; movw r25:r24, r31:r30
+			cpu.system.flash_from_vec([0x2E1F, 0x01CF].to_vec());
+			cpu.sram.registers[30] = 0x34;
+			cpu.sram.registers[31] = 0x12;
+
+			cpu.step();
+			assert_eq!(cpu.sram.registers[1], 0x12);
+
+			cpu.step();
+			assert_eq!(cpu.sram.registers[24], 0x34);
+			assert_eq!(cpu.sram.registers[25], 0x12);
+			assert_eq!(cpu.cycles, 2);
+		}
+
+		#[test]
+		fn push_pop() {
+			let mut cpu = Cpu::init();
+			// push r24; pop r25
+			cpu.system.flash_from_vec([0x938F, 0x919F].to_vec());
+			cpu.sram.registers[24] = 0x5A;
+
+			cpu.step();
+			assert_eq!(cpu.sp, RAMEND - 1);
+			assert_eq!(cpu.sram.read(RAMEND), 0x5A);
+			assert_eq!(cpu.cycles, 2);
+
+			cpu.step();
+			assert_eq!(cpu.sp, RAMEND);
+			assert_eq!(cpu.sram.registers[25], 0x5A);
+			assert_eq!(cpu.cycles, 4);
+		}
+
+		#[test]
+		fn ld_st_pointer_modes() {
+			let mut cpu = Cpu::init();
+			// st X+, r16; st X+, r17; ld r18, -X; st -Y, r16; ld r19, Y+; st Z, r17; ld r20, Z
+			cpu.system
+				.flash_from_vec([0x930D, 0x931D, 0x912E, 0x930A, 0x9139, 0x8310, 0x8140].to_vec());
+			cpu.sram.registers[16] = 0x11;
+			cpu.sram.registers[17] = 0x22;
+			// X = 0x0100, Y = 0x0200, Z = 0x0300
+			cpu.sram.registers[27] = 0x01;
+			cpu.sram.registers[29] = 0x02;
+			cpu.sram.registers[31] = 0x03;
+
+			for _ in 0..7 {
+				cpu.step();
+			}
+			assert_eq!(cpu.sram.read(0x0100), 0x11);
+			assert_eq!(cpu.sram.read(0x0101), 0x22);
+			assert_eq!(cpu.sram.registers[18], 0x22);
+			assert_eq!(
+				(cpu.sram.registers[26], cpu.sram.registers[27]),
+				(0x01, 0x01)
+			);
+			assert_eq!(cpu.sram.read(0x01FF), 0x11);
+			assert_eq!(cpu.sram.registers[19], 0x11);
+			assert_eq!(
+				(cpu.sram.registers[28], cpu.sram.registers[29]),
+				(0x00, 0x02)
+			);
+			assert_eq!(cpu.sram.read(0x0300), 0x22);
+			assert_eq!(cpu.sram.registers[20], 0x22);
+			assert_eq!(
+				(cpu.sram.registers[30], cpu.sram.registers[31]),
+				(0x00, 0x03)
+			);
+			assert_eq!(cpu.cycles, 14);
+		}
+
+		#[test]
+		fn ldd_std() {
+			let mut cpu = Cpu::init();
+			// std Y+63, r16; ldd r17, Y+63; std Z+5, r16; ldd r18, Z+5
+			cpu.system
+				.flash_from_vec([0xAF0F, 0xAD1F, 0x8305, 0x8125].to_vec());
+			cpu.sram.registers[16] = 0x5A;
+			cpu.sram.registers[29] = 0x01;
+			cpu.sram.registers[31] = 0x02;
+
+			for _ in 0..4 {
+				cpu.step();
+			}
+			assert_eq!(cpu.sram.read(0x013F), 0x5A);
+			assert_eq!(cpu.sram.registers[17], 0x5A);
+			assert_eq!(cpu.sram.read(0x0205), 0x5A);
+			assert_eq!(cpu.sram.registers[18], 0x5A);
+			assert_eq!(cpu.cycles, 8);
+		}
+
+		#[test]
+		fn lpm() {
+			let mut cpu = Cpu::init();
+			// lpm; lpm r16, Z+; lpm r17, Z; data 0xBBAA
+			cpu.system
+				.flash_from_vec([0x95C8, 0x9105, 0x9114, 0xBBAA].to_vec());
+			// Z = byte address of word 3
+			cpu.sram.registers[30] = 0x06;
+
+			cpu.step();
+			assert_eq!(cpu.sram.registers[0], 0xAA);
+			cpu.step();
+			assert_eq!(cpu.sram.registers[16], 0xAA);
+			assert_eq!(cpu.sram.registers[30], 0x07);
+			cpu.step();
+			assert_eq!(cpu.sram.registers[17], 0xBB);
+			assert_eq!(cpu.cycles, 9);
+		}
+	}
 }
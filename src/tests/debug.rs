@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod debug_port {
+	use crate::cpu::Cpu;
+	use crate::peripherals::debug::{DEBUG_CONSOLE, DEBUG_EXIT};
+	use crate::peripherals::usart::SerialDevice;
+	use crate::runner::{self, Limits, StopReason};
+	use crate::tests::setup_idling;
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	// ldi r16, 'H'
+	const LDI_H: u16 = 0xE408;
+	// ldi r16, 'i'
+	const LDI_I: u16 = 0xE609;
+	// ldi r16, 0x00
+	const LDI_0: u16 = 0xE000;
+	// out 0x19, r16
+	const OUT_CONSOLE: u16 = 0xBB09;
+	// out 0x1A, r16
+	const OUT_EXIT: u16 = 0xBB0A;
+
+	#[derive(Default)]
+	struct Console(Vec<u8>);
+
+	impl SerialDevice for Console {
+		fn receive(&mut self, data: u8) {
+			self.0.push(data);
+		}
+	}
+
+	fn setup(program: &[u16]) -> (Cpu, Rc<RefCell<Console>>) {
+		let mut cpu = setup_idling(program);
+		let console = Rc::new(RefCell::new(Console::default()));
+		cpu.peripherals.debug.attach(console.clone());
+		(cpu, console)
+	}
+
+	#[test]
+	fn console_and_exit() {
+		let (mut cpu, console) = setup(&[LDI_H, OUT_CONSOLE, LDI_I, OUT_CONSOLE, LDI_0, OUT_EXIT]);
+
+		let reason = runner::run(&mut cpu, &Limits::default());
+		assert_eq!(reason, StopReason::Exit(0));
+		assert_eq!(reason.exit_code(), 0);
+		assert_eq!(console.borrow().0, b"Hi");
+		assert_eq!(cpu.cycles, 6);
+	}
+
+	#[test]
+	fn library_access() {
+		let (mut cpu, console) = setup(&[]);
+		cpu.write_data(DEBUG_CONSOLE, b'!');
+		assert_eq!(console.borrow().0, b"!");
+		assert_eq!(cpu.peripherals.debug.exit_code(), None);
+
+		cpu.write_data(DEBUG_EXIT, 42);
+		assert_eq!(cpu.peripherals.debug.exit_code(), Some(42));
+		assert_eq!(cpu.read_data(DEBUG_EXIT), 42);
+
+		cpu.reset();
+		assert_eq!(cpu.peripherals.debug.exit_code(), None);
+	}
+}
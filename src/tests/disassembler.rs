@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod operands {
+	use crate::elf::{Elf, Symbol, SymbolKind};
+	use crate::symbols::SymbolTable;
+	use crate::system::System;
+
+	fn symbol(name: &str, value: u32, size: u32, kind: SymbolKind) -> Symbol {
+		Symbol {
+			name: name.to_string(),
+			value,
+			size,
+			kind,
+		}
+	}
+
+	fn symbols() -> SymbolTable {
+		SymbolTable::from_elf(&Elf {
+			symbols: vec![
+				symbol("main", 0x0000, 0x10, SymbolKind::Function),
+				symbol("delay", 0x0040, 0x08, SymbolKind::Function),
+				symbol("counter", 0x80_0100, 2, SymbolKind::Object),
+				// absolute symbols such as register aliases are not labels
+				symbol("__SP_L__", 0x003D, 0, SymbolKind::Other),
+				symbol("__data_load_end", 0x1000, 0, SymbolKind::Other),
+			],
+			..Default::default()
+		})
+	}
+
+	fn disassemble(program: &[u16], symbols: SymbolTable) -> System {
+		let mut system = System {
+			symbols,
+			..Default::default()
+		};
+		system.flash_from_vec(program.to_vec());
+		system
+	}
+
+	#[test]
+	fn lookups() {
+		let symbols = symbols();
+		assert_eq!(
+			symbols.function_at(0x0020).map(|l| l.name.as_str()),
+			Some("delay")
+		);
+		assert_eq!(symbols.code_location(0x0003).as_deref(), Some("main+0x3"));
+		assert_eq!(symbols.code_location(0x0010), None);
+		assert_eq!(symbols.data_location(0x0100).as_deref(), Some("counter"));
+		assert_eq!(symbols.data_location(0x0102), None);
+		assert_eq!(symbols.find("counter").map(|l| l.address), Some(0x0100));
+		assert!(symbols.find("__SP_L__").is_none());
+
+		let matches: Vec<&str> = symbols.search("EL").map(|l| l.name.as_str()).collect();
+		assert_eq!(matches, vec!["delay"]);
+	}
+
+	#[test]
+	fn jump_targets() {
+		let system = disassemble(
+			&[
+				0xD002, // rcall .+4
+				0x940C, 0x0020, // jmp 0x0020
+				0x940E, 0x0004, // call 0x0004
+				0xF3F1, // breq .-4
+			],
+			symbols(),
+		);
+		let assembly = system.disassembler.assembly.as_ref().unwrap();
+
+		assert_eq!(assembly[&0x0000].label.as_deref(), Some("main"));
+		assert_eq!(assembly[&0x0000].operands, "0x0003 <main+0x3>");
+		assert_eq!(assembly[&0x0001].operands, "0x0020 <delay>");
+		// the second word of a two word instruction is not disassembled
+		assert!(!assembly.contains_key(&0x0002));
+		assert_eq!(assembly[&0x0003].operands, "0x0004 <main+0x4>");
+		assert_eq!(assembly[&0x0005].operands, "0x0004 <main+0x4>");
+	}
+
+	#[test]
+	fn data_addresses() {
+		let system = disassemble(
+			&[
+				0x9180, 0x0101, // lds r24, 0x0101
+				0x9380, 0x0200, // sts 0x0200, r24
+			],
+			symbols(),
+		);
+		let assembly = system.disassembler.assembly.as_ref().unwrap();
+
+		assert_eq!(assembly[&0x0000].operands, "r24, 0x0101 <counter+0x1>");
+		assert_eq!(assembly[&0x0002].operands, "0x0200, r24");
+	}
+
+	#[test]
+	fn without_symbols() {
+		let system = disassemble(&[0xCFFF], SymbolTable::default());
+		let assembly = system.disassembler.assembly.as_ref().unwrap();
+		assert_eq!(assembly[&0x0000].operands, "0x0000");
+		assert_eq!(assembly[&0x0000].label, None);
+	}
+}
@@ -0,0 +1,182 @@
+#[cfg(test)]
+pub(crate) mod line_table {
+	use crate::cpu::Cpu;
+	use crate::debugger::{SourceStep, StepKind};
+	use crate::dwarf::{Error, LineTable};
+
+	const LINE_BASE: i8 = -5;
+	const LINE_RANGE: u8 = 14;
+	const OPCODE_BASE: u8 = 13;
+	const OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+	const DW_FORM_UDATA: u8 = 0x0F;
+	const DW_FORM_LINE_STRP: u8 = 0x1F;
+
+	/// A line program unit, `tables` holds the directories and files
+	fn unit(version: u16, tables: &[u8], program: &[u8]) -> Vec<u8> {
+		let mut header = vec![1];
+		if version >= 4 {
+			header.push(1);
+		}
+		header.extend_from_slice(&[1, LINE_BASE as u8, LINE_RANGE, OPCODE_BASE]);
+		header.extend_from_slice(&OPCODE_LENGTHS);
+		header.extend_from_slice(tables);
+
+		let mut body = version.to_le_bytes().to_vec();
+		if version >= 5 {
+			body.extend_from_slice(&[4, 0]);
+		}
+		body.extend_from_slice(&(header.len() as u32).to_le_bytes());
+		body.extend_from_slice(&header);
+		body.extend_from_slice(program);
+
+		let mut bytes = (body.len() as u32).to_le_bytes().to_vec();
+		bytes.extend_from_slice(&body);
+		bytes
+	}
+
+	fn set_address(address: u32) -> Vec<u8> {
+		let mut bytes = vec![0, 5, 2];
+		bytes.extend_from_slice(&address.to_le_bytes());
+		bytes
+	}
+
+	/// Advances both registers and appends a row
+	fn special(address: u8, line: i8) -> u8 {
+		(line - LINE_BASE) as u8 + LINE_RANGE * address + OPCODE_BASE
+	}
+
+	const END_SEQUENCE: [u8; 3] = [0, 1, 1];
+
+	/// main at word 0 calls func at word 4, one instruction per line
+	fn program() -> Vec<u8> {
+		let mut program = set_address(0);
+		// line 10
+		program.extend_from_slice(&[3, 9, 1]);
+		program.extend_from_slice(&[special(2, 1), special(2, 1), special(2, 1)]);
+		program.extend_from_slice(&[special(2, 7), special(2, 1), special(2, 1)]);
+		program.extend_from_slice(&[2, 4]);
+		program.extend_from_slice(&END_SEQUENCE);
+		program
+	}
+
+	fn version_4() -> Vec<u8> {
+		let tables = b"src\0\0main.c\0\x01\0\0\0";
+		unit(4, tables, &program())
+	}
+
+	#[test]
+	fn version_4_locations() {
+		let table = LineTable::parse(&version_4(), &[], &[]).unwrap();
+
+		let location = table.location(0x0000).unwrap();
+		assert_eq!(table.file(location.file), Some("src/main.c"));
+		assert_eq!(location.line, 10);
+		assert_eq!(table.location(0x0004).map(|l| l.line), Some(20));
+		assert_eq!(table.location(0x0007).map(|l| l.line), Some(22));
+		// past the end of the sequence
+		assert_eq!(table.location(0x0008), None);
+		assert!(table.is_statement(0x0001));
+	}
+
+	#[test]
+	fn version_5_locations() {
+		let line_strings = b"/build\0include\0main.c\0io.h\0";
+		let mut tables = vec![1, 1, DW_FORM_LINE_STRP, 2, 0, 0, 0, 0, 7, 0, 0, 0];
+		tables.extend_from_slice(&[2, 1, DW_FORM_LINE_STRP, 2, DW_FORM_UDATA, 2]);
+		tables.extend_from_slice(&[15, 0, 0, 0, 0, 22, 0, 0, 0, 1]);
+
+		// file registers count from zero
+		let mut program = vec![4, 1];
+		program.extend_from_slice(&set_address(0x0010));
+		program.extend_from_slice(&[3, 41, 1, 2, 2]);
+		program.extend_from_slice(&END_SEQUENCE);
+
+		let mut bytes = version_4();
+		bytes.extend_from_slice(&unit(5, &tables, &program));
+		let table = LineTable::parse(&bytes, line_strings, &[]).unwrap();
+
+		let location = table.location(0x0008).unwrap();
+		assert_eq!(table.file(location.file), Some("include/io.h"));
+		assert_eq!(location.line, 42);
+		assert_eq!(table.location(0x0009), None);
+		assert_eq!(table.location(0x0000).map(|l| l.line), Some(10));
+	}
+
+	#[test]
+	fn invalid_programs() {
+		let bytes = unit(6, &[], &[]);
+		assert_eq!(
+			LineTable::parse(&bytes, &[], &[]),
+			Err(Error::UnsupportedVersion(6))
+		);
+
+		let bytes = version_4();
+		assert_eq!(
+			LineTable::parse(&bytes[..bytes.len() - 1], &[], &[]),
+			Err(Error::Truncated)
+		);
+	}
+
+	pub(crate) fn setup() -> Cpu {
+		let mut cpu = Cpu::init();
+		// main: ldi r16, 1; rcall func; ldi r17, 2; rjmp .-2
+		// func: nop; ldi r18, 3; nop; ret
+		cpu.system.flash_from_vec(vec![
+			0xE001, 0xD002, 0xE012, 0xCFFF, 0x0000, 0xE023, 0x0000, 0x9508,
+		]);
+		cpu.system.lines = LineTable::parse(&version_4(), &[], &[]).unwrap();
+		cpu
+	}
+
+	fn step(cpu: &mut Cpu, kind: StepKind) -> u32 {
+		assert!(SourceStep::new(kind, cpu).run(cpu, 100));
+		cpu.system.lines.location(cpu.pc).unwrap().line
+	}
+
+	#[test]
+	fn step_line() {
+		let mut cpu = setup();
+		assert_eq!(step(&mut cpu, StepKind::Line), 11);
+		// into the called function
+		assert_eq!(step(&mut cpu, StepKind::Line), 20);
+		assert_eq!(step(&mut cpu, StepKind::Line), 21);
+	}
+
+	#[test]
+	fn step_over() {
+		let mut cpu = setup();
+		assert_eq!(step(&mut cpu, StepKind::Over), 11);
+		assert_eq!(step(&mut cpu, StepKind::Over), 12);
+		assert_eq!(cpu.sram.registers[18], 3);
+	}
+
+	#[test]
+	fn step_over_stack_reservation() {
+		let mut cpu = setup();
+		// func: rcall .+0; ldi r18, 3; pop r0; pop r0; ret
+		cpu.system.flash_from_vec(vec![
+			0xE001, 0xD002, 0xE012, 0xCFFF, 0xD000, 0xE023, 0x900F, 0x900F, 0x9508,
+		]);
+		cpu.system.lines = LineTable::parse(&version_4(), &[], &[]).unwrap();
+
+		assert_eq!(step(&mut cpu, StepKind::Over), 11);
+		// the rcall .+0 in the prologue is not a call to step over
+		assert_eq!(step(&mut cpu, StepKind::Over), 12);
+		assert_eq!(cpu.sram.registers[18], 3);
+		assert_eq!(cpu.pc, 0x0002);
+	}
+
+	#[test]
+	fn step_out() {
+		let mut cpu = setup();
+		step(&mut cpu, StepKind::Line);
+		step(&mut cpu, StepKind::Line);
+		assert_eq!(step(&mut cpu, StepKind::Out), 12);
+		assert_eq!(cpu.pc, 0x0002);
+
+		// the loop at line 13 never reaches another line
+		assert_eq!(step(&mut cpu, StepKind::Line), 13);
+		assert!(!SourceStep::new(StepKind::Line, &cpu).run(&mut cpu, 100));
+	}
+}
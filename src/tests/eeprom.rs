@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod controller {
+	use crate::cpu::Cpu;
+	use crate::memory::Memory;
+	use crate::peripherals::eeprom::{EEARH, EEARL, EECR, EEDR};
+	use crate::tests::{run, setup, LOOP};
+
+	const EEPM1: u8 = 0x20;
+	const EEPM0: u8 = 0x10;
+	const EERIE: u8 = 0x08;
+	const EEMPE: u8 = 0x04;
+	const EEPE: u8 = 0x02;
+	const EERE: u8 = 0x01;
+
+	// at the factory default 1 MHz system clock
+	const ATOMIC_WRITE_CYCLES: usize = 3_400;
+	const SPLIT_WRITE_CYCLES: usize = 1_800;
+
+	fn start_write(cpu: &mut Cpu, address: u16, data: u8, mode: u8) {
+		cpu.write_data(EEARH, (address >> 8) as u8);
+		cpu.write_data(EEARL, address as u8);
+		cpu.write_data(EEDR, data);
+		cpu.write_data(EECR, mode | EEMPE);
+		cpu.write_data(EECR, mode | EEPE);
+	}
+
+	#[test]
+	fn read_stalls_cpu() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		cpu.system.eeprom_memory.write(0x123, 0x5A);
+
+		cpu.write_data(EEARH, 0x01);
+		cpu.write_data(EEARL, 0x23);
+		cpu.write_data(EECR, EERE);
+
+		assert_eq!(cpu.read_data(EEDR), 0x5A);
+		assert_eq!(cpu.cycles, 4);
+		assert_eq!(cpu.read_data(EECR) & EERE, 0);
+	}
+
+	#[test]
+	fn atomic_write() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		start_write(&mut cpu, 0x010, 0xA5, 0);
+		assert_eq!(cpu.read_data(EECR) & (EEPE | EEMPE), EEPE);
+
+		run(&mut cpu, ATOMIC_WRITE_CYCLES - 2);
+		assert_eq!(cpu.system.eeprom_memory.read(0x010), 0x00);
+		run(&mut cpu, 2);
+		assert_eq!(cpu.system.eeprom_memory.read(0x010), 0xA5);
+		assert_eq!(cpu.read_data(EECR) & EEPE, 0);
+	}
+
+	#[test]
+	fn write_requires_master_enable() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		cpu.write_data(EEDR, 0x11);
+		cpu.write_data(EECR, EEPE);
+		assert_eq!(cpu.read_data(EECR) & EEPE, 0);
+
+		// EEMPE is cleared by hardware four cycles after it was set
+		cpu.write_data(EECR, EEMPE);
+		run(&mut cpu, 4);
+		assert_eq!(cpu.read_data(EECR) & EEMPE, 0);
+		cpu.write_data(EECR, EEPE);
+		assert_eq!(cpu.read_data(EECR) & EEPE, 0);
+	}
+
+	#[test]
+	fn split_erase_and_write() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		cpu.system.eeprom_memory.write(0x020, 0x0F);
+
+		start_write(&mut cpu, 0x020, 0xF3, EEPM1);
+		run(&mut cpu, SPLIT_WRITE_CYCLES);
+		assert_eq!(cpu.system.eeprom_memory.read(0x020), 0x03);
+
+		start_write(&mut cpu, 0x020, 0x00, EEPM0);
+		run(&mut cpu, SPLIT_WRITE_CYCLES);
+		assert_eq!(cpu.system.eeprom_memory.read(0x020), 0xFF);
+	}
+
+	#[test]
+	fn write_time_is_independent_of_clock() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		cpu.system.fuses.set_clock_select(0xF);
+		cpu.system.fuses.set_ckdiv8(false);
+		cpu.reset();
+
+		start_write(&mut cpu, 0x040, 0x99, 0);
+		run(&mut cpu, ATOMIC_WRITE_CYCLES * 16 - 2);
+		assert!(cpu.peripherals.eeprom.is_writing());
+		run(&mut cpu, 2);
+		assert!(!cpu.peripherals.eeprom.is_writing());
+	}
+
+	#[test]
+	fn registers_locked_during_write() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		start_write(&mut cpu, 0x030, 0x42, 0);
+
+		cpu.write_data(EEARL, 0x31);
+		cpu.write_data(EEDR, 0x00);
+		cpu.write_data(EECR, EERE);
+		assert_eq!(cpu.read_data(EEARL), 0x30);
+		assert_eq!(cpu.read_data(EEDR), 0x42);
+	}
+
+	#[test]
+	fn ready_interrupt() {
+		let mut cpu = setup(&[LOOP; 0x100]);
+		cpu.status.I = true;
+		start_write(&mut cpu, 0x000, 0x01, 0);
+		cpu.write_data(EECR, EERIE);
+
+		run(&mut cpu, 10);
+		assert_eq!(cpu.pc, 0x00);
+
+		run(&mut cpu, ATOMIC_WRITE_CYCLES);
+		assert_eq!(cpu.pc, 0x2C);
+		assert!(!cpu.status.I);
+	}
+}
+
+#[cfg(test)]
+mod persistence {
+	use crate::ihex;
+	use crate::memory::Memory;
+	use crate::system::{LoadError, System};
+	use std::path::PathBuf;
+
+	fn temp_file(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("atmega328p-rs-{}-{}", std::process::id(), name))
+	}
+
+	#[test]
+	fn intel_hex_round_trip() {
+		let path = temp_file("round_trip.eep");
+		let mut system = System::default();
+		system.eeprom_memory.write(0x000, 0x12);
+		system.eeprom_memory.write(0x3FF, 0x34);
+		system.save_eeprom(&path).unwrap();
+
+		let mut restored = System::default();
+		restored.load_eeprom(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(restored.eeprom_memory.read(0x000), 0x12);
+		assert_eq!(restored.eeprom_memory.read(0x3FF), 0x34);
+		assert_eq!(
+			restored.eeprom_memory.as_bytes(),
+			system.eeprom_memory.as_bytes()
+		);
+	}
+
+	#[test]
+	fn binary_image_is_padded_with_erased_bytes() {
+		let path = temp_file("short.bin");
+		std::fs::write(&path, [0x01, 0x02]).unwrap();
+
+		let mut system = System::default();
+		system.load_eeprom(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(system.eeprom_memory.read(0x001), 0x02);
+		assert_eq!(system.eeprom_memory.read(0x002), 0xFF);
+	}
+
+	#[test]
+	fn rejects_oversized_images() {
+		let mut system = System::default();
+		system.eeprom_memory.write(0x000, 0x12);
+
+		let path = temp_file("oversized.eep");
+		std::fs::write(&path, ":01040000AA51\n:00000001FF\n").unwrap();
+		let error = system.load_eeprom(&path).unwrap_err();
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(error, LoadError::OutOfRange(0x0400)));
+
+		let path = temp_file("oversized.bin");
+		std::fs::write(&path, [0xAA; 0x401]).unwrap();
+		let error = system.load_eeprom(&path).unwrap_err();
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(error, LoadError::OutOfRange(0x0400)));
+
+		// nothing was loaded
+		assert_eq!(system.eeprom_memory.read(0x000), 0x12);
+	}
+
+	#[test]
+	fn persist_loads_existing_file() {
+		let path = temp_file("persist.bin");
+		let mut system = System::default();
+		system.persist_eeprom(path.clone()).unwrap();
+		system.eeprom_memory.write(0x010, 0xAB);
+		system.save_persisted_eeprom().unwrap();
+
+		let mut restarted = System::default();
+		restarted.persist_eeprom(path.clone()).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(restarted.eeprom_memory.read(0x010), 0xAB);
+	}
+
+	#[test]
+	fn parses_objcopy_records() {
+		let text = ":02000004008179\n:03001000010203E7\n:00000001FF\n";
+		let bytes = ihex::parse(text).unwrap().data;
+		assert_eq!(bytes[0], (0x0081_0010, 0x01));
+		assert_eq!(bytes[2], (0x0081_0012, 0x03));
+
+		assert!(ihex::parse(":0300100001\n").is_err());
+	}
+
+	#[test]
+	fn chip_erase_honours_eesave() {
+		let mut system = System::default();
+		system.eeprom_memory.write(0x000, 0x55);
+
+		system.fuses.set_eesave(true);
+		system.chip_erase();
+		assert_eq!(system.eeprom_memory.read(0x000), 0x55);
+
+		system.fuses.set_eesave(false);
+		system.chip_erase();
+		assert_eq!(system.eeprom_memory.read(0x000), 0xFF);
+	}
+}
@@ -0,0 +1,408 @@
+#[cfg(test)]
+mod loader {
+	use crate::cpu::Cpu;
+	use crate::elf::{self, Error, SymbolKind};
+	use crate::memory::Memory;
+	use crate::peripherals::usart::SerialDevice;
+	use crate::runner::{self, Limits, StopReason};
+	use crate::system::{LoadError, System};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	const SHT_PROGBITS: u32 = 1;
+	const SHT_SYMTAB: u32 = 2;
+	const SHT_STRTAB: u32 = 3;
+	const SHT_NOBITS: u32 = 8;
+
+	const STT_OBJECT: u8 = 1;
+	const STT_FUNC: u8 = 2;
+
+	struct TestSection {
+		name: &'static str,
+		kind: u32,
+		address: u32,
+		load_address: u32,
+		data: Vec<u8>,
+		/// Section header index of the string table of a symbol table
+		link: u32,
+	}
+
+	impl TestSection {
+		fn is_loaded(&self) -> bool {
+			self.kind == SHT_PROGBITS
+		}
+
+		fn is_allocated(&self) -> bool {
+			matches!(self.kind, SHT_PROGBITS | SHT_NOBITS)
+		}
+	}
+
+	fn section(name: &'static str, address: u32, data: &[u8]) -> TestSection {
+		TestSection {
+			name,
+			kind: SHT_PROGBITS,
+			address,
+			load_address: address,
+			data: data.to_vec(),
+			link: 0,
+		}
+	}
+
+	/// `.symtab` and its `.strtab`, to be appended after `count` other sections
+	fn symbol_sections(count: usize, symbols: &[(&str, u32, u32, u8)]) -> [TestSection; 2] {
+		let mut strings = vec![0u8];
+		let mut entries = vec![0u8; 16];
+		for (name, value, size, kind) in symbols {
+			put_u32(&mut entries, strings.len() as u32);
+			put_u32(&mut entries, *value);
+			put_u32(&mut entries, *size);
+			entries.extend_from_slice(&[0x10 | kind, 0]);
+			put_u16(&mut entries, 1);
+			strings.extend_from_slice(name.as_bytes());
+			strings.push(0);
+		}
+
+		[
+			TestSection {
+				name: ".symtab",
+				kind: SHT_SYMTAB,
+				address: 0,
+				load_address: 0,
+				data: entries,
+				link: count as u32 + 2,
+			},
+			TestSection {
+				name: ".strtab",
+				kind: SHT_STRTAB,
+				address: 0,
+				load_address: 0,
+				data: strings,
+				link: 0,
+			},
+		]
+	}
+
+	fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+		bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+		bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	/// Lays out an executable like avr-ld, with a load segment for every section
+	fn build(sections: &[TestSection], entry: u32) -> Vec<u8> {
+		let mut strings = vec![0u8];
+		let mut names = Vec::new();
+		for section in sections
+			.iter()
+			.map(|section| section.name)
+			.chain([".shstrtab"])
+		{
+			names.push(strings.len() as u32);
+			strings.extend_from_slice(section.as_bytes());
+			strings.push(0);
+		}
+
+		let mut offsets = Vec::new();
+		let mut contents = Vec::new();
+		for section in sections {
+			offsets.push(52 + contents.len() as u32);
+			if section.kind != SHT_NOBITS {
+				contents.extend_from_slice(&section.data);
+			}
+		}
+		let strings_offset = 52 + contents.len() as u32;
+		contents.extend_from_slice(&strings);
+
+		let loaded: Vec<usize> = (0..sections.len())
+			.filter(|index| sections[*index].is_loaded())
+			.collect();
+		let program_headers = 52 + contents.len() as u32;
+		let section_headers = program_headers + 32 * loaded.len() as u32;
+
+		let mut bytes = b"\x7FELF\x01\x01\x01".to_vec();
+		bytes.resize(16, 0);
+		put_u16(&mut bytes, 2);
+		put_u16(&mut bytes, 83);
+		put_u32(&mut bytes, 1);
+		put_u32(&mut bytes, entry);
+		put_u32(&mut bytes, program_headers);
+		put_u32(&mut bytes, section_headers);
+		put_u32(&mut bytes, 0);
+		put_u16(&mut bytes, 52);
+		put_u16(&mut bytes, 32);
+		put_u16(&mut bytes, loaded.len() as u16);
+		put_u16(&mut bytes, 40);
+		put_u16(&mut bytes, sections.len() as u16 + 2);
+		put_u16(&mut bytes, sections.len() as u16 + 1);
+		bytes.extend_from_slice(&contents);
+
+		for index in loaded {
+			let section = &sections[index];
+			for value in [
+				1,
+				offsets[index],
+				section.address,
+				section.load_address,
+				section.data.len() as u32,
+				section.data.len() as u32,
+				0x7,
+				1,
+			] {
+				put_u32(&mut bytes, value);
+			}
+		}
+
+		bytes.extend_from_slice(&[0; 40]);
+		for (index, section) in sections.iter().enumerate() {
+			for value in [
+				names[index],
+				section.kind,
+				if section.is_allocated() { 0x2 } else { 0 },
+				section.address,
+				offsets[index],
+				section.data.len() as u32,
+				section.link,
+				0,
+				1,
+				0,
+			] {
+				put_u32(&mut bytes, value);
+			}
+		}
+		for value in [
+			names[sections.len()],
+			SHT_STRTAB,
+			0,
+			0,
+			strings_offset,
+			strings.len() as u32,
+			0,
+			0,
+			1,
+			0,
+		] {
+			put_u32(&mut bytes, value);
+		}
+
+		bytes
+	}
+
+	fn firmware() -> Vec<TestSection> {
+		vec![
+			// ldi r16, 0x2A; rjmp .-2
+			section(".text", 0x0000, &[0x0A, 0xE2, 0xFF, 0xCF]),
+			TestSection {
+				load_address: 0x0004,
+				..section(".data", 0x80_0100, &[0x11, 0x22])
+			},
+			TestSection {
+				kind: SHT_NOBITS,
+				..section(".bss", 0x80_0102, &[0; 8])
+			},
+			section(".eeprom", 0x81_0000, &[0xAB, 0xCD]),
+			section(".fuse", 0x82_0000, &[0xFF, 0xDE, 0xFD]),
+			section(".lock", 0x83_0000, &[0xFC]),
+			section(".signature", 0x84_0000, &[0x0F, 0x95, 0x1E]),
+		]
+	}
+
+	#[test]
+	fn parses_sections() {
+		let parsed = elf::parse(&build(&firmware(), 0)).unwrap();
+		assert_eq!(parsed.entry, 0);
+
+		let data = parsed.section(".data").unwrap();
+		assert_eq!(data.address, 0x80_0100);
+		assert_eq!(data.load_address, 0x0004);
+		assert!(parsed.section(".bss").unwrap().data.is_empty());
+		assert_eq!(
+			parsed.section(".shstrtab").map(|s| s.allocated),
+			Some(false)
+		);
+	}
+
+	#[test]
+	fn places_sections() {
+		let mut system = System::default();
+		system.flash_from_elf(&build(&firmware(), 0)).unwrap();
+
+		assert_eq!(system.program_memory.read(0x0000), 0xE20A);
+		assert_eq!(system.program_memory.read(0x0001), 0xCFFF);
+		// .data is stored in flash after .text
+		assert_eq!(system.program_memory.read(0x0002), 0x2211);
+		assert_eq!(system.eeprom_memory.read(0x0000), 0xAB);
+		assert_eq!(system.eeprom_memory.read(0x0001), 0xCD);
+		assert_eq!(
+			(system.fuses.low, system.fuses.high, system.fuses.extended),
+			(0xFF, 0xDE, 0xFD)
+		);
+		assert_eq!(system.fuses.lock, 0xFC);
+	}
+
+	#[test]
+	fn entry_point() {
+		let mut system = System::default();
+		system.flash_from_elf(&build(&firmware(), 0x0002)).unwrap();
+		assert_eq!(system.entry_point, 0x0001);
+	}
+
+	#[test]
+	fn rejects_other_devices() {
+		let mut sections = firmware();
+		// ATmega2560
+		sections[6] = section(".signature", 0x84_0000, &[0x01, 0x98, 0x1E]);
+		let mut system = System::default();
+		let error = system.flash_from_elf(&build(&sections, 0)).unwrap_err();
+		assert!(matches!(error, LoadError::Signature(0, 0x01)));
+
+		let mut bytes = build(&firmware(), 0);
+		bytes[18] = 40;
+		assert_eq!(elf::parse(&bytes), Err(Error::WrongMachine(40)));
+		assert_eq!(elf::parse(b"not an elf"), Err(Error::NotElf));
+
+		let mut bytes = build(&firmware(), 0);
+		bytes[4] = 2;
+		assert_eq!(elf::parse(&bytes), Err(Error::UnsupportedFormat));
+
+		let bytes = build(&firmware(), 0);
+		assert_eq!(elf::parse(&bytes[..100]), Err(Error::Truncated));
+	}
+
+	#[test]
+	fn detects_format() {
+		let directory = std::env::temp_dir();
+		let elf_path = directory.join("atmega328p-rs-load.elf");
+		let hex_path = directory.join("atmega328p-rs-load.hex");
+		std::fs::write(&elf_path, build(&firmware(), 0)).unwrap();
+		std::fs::write(&hex_path, ":020000000AE212\n:00000001FF\n").unwrap();
+
+		let mut system = System::default();
+		system.load_program(&elf_path).unwrap();
+		assert_eq!(system.program_memory.read(0x0001), 0xCFFF);
+
+		system.load_program(&hex_path).unwrap();
+		assert_eq!(system.program_memory.read(0x0000), 0xE20A);
+		assert_eq!(system.program_memory.read(0x0001), 0x0000);
+
+		std::fs::remove_file(elf_path).unwrap();
+		std::fs::remove_file(hex_path).unwrap();
+	}
+
+	#[derive(Default)]
+	struct Console(Vec<u8>);
+
+	impl SerialDevice for Console {
+		fn receive(&mut self, data: u8) {
+			self.0.push(data);
+		}
+	}
+
+	/// Words of the start up code avr-gcc links in front of `main`, with `main` printing
+	/// the `.data` string to the debug console and exiting with the first `.bss` byte
+	#[rustfmt::skip]
+	fn crt_program() -> Vec<u16> {
+		// __vectors: jmp __ctors_end, then jmp __bad_interrupt for the other 25
+		let mut program = vec![0x940C, 0x0034];
+		for _ in 1..26 {
+			program.extend_from_slice(&[0x940C, 0x0051]);
+		}
+		program.extend_from_slice(&[
+			// __ctors_end: eor r1, r1; out SREG, r1; SP = RAMEND
+			0x2411, 0xBE1F, 0xEFCF, 0xE0D8, 0xBFDE, 0xBFCD,
+			// __do_copy_data: ldi r17, hi(__data_end); X = 0x0100; Z = __data_load_start
+			0xE011, 0xE0A0, 0xE0B1, 0xECE0, 0xE0F0,
+			// rjmp .+4; lpm r0, Z+; st X+, r0; cpi r26, lo(__data_end); cpc r27, r17; brne .-10
+			0xC002, 0x9005, 0x920D, 0x30A4, 0x07B1, 0xF7D9,
+			// __do_clear_bss: ldi r18, hi(__bss_end); X = __bss_start
+			0xE021, 0xE0A4, 0xE0B1,
+			// rjmp .+2; st X+, r1; cpi r26, lo(__bss_end); cpc r27, r18; brne .-8
+			0xC001, 0x921D, 0x30A6, 0x07B2, 0xF7E1,
+			// call main; jmp _exit
+			0x940E, 0x0053, 0x940C, 0x005E,
+			// __bad_interrupt: jmp __vectors
+			0x940C, 0x0000,
+			// main: X = 0x0100; ld r24, X+; tst r24; breq .+4; out 0x19, r24; rjmp .-10
+			0xE0A0, 0xE0B1, 0x918D, 0x2388, 0xF011, 0xBB89, 0xCFFB,
+			// lds r24, __bss_start; out 0x1A, r24; ret
+			0x9180, 0x0104, 0xBB8A, 0x9508,
+			// _exit: cli; rjmp .-2
+			0x94F8, 0xCFFF,
+		]);
+		program
+	}
+
+	#[test]
+	fn runs_to_exit() {
+		let text: Vec<u8> = crt_program()
+			.iter()
+			.flat_map(|word| word.to_le_bytes())
+			.collect();
+		let sections = [
+			section(".text", 0x0000, &text),
+			TestSection {
+				load_address: text.len() as u32,
+				..section(".data", 0x80_0100, b"Hi!\0")
+			},
+			TestSection {
+				kind: SHT_NOBITS,
+				..section(".bss", 0x80_0104, &[0; 2])
+			},
+		];
+
+		let mut cpu = Cpu::init();
+		cpu.system.flash_from_elf(&build(&sections, 0)).unwrap();
+		cpu.reset();
+		let console = Rc::new(RefCell::new(Console::default()));
+		cpu.peripherals.debug.attach(console.clone());
+		// left over from before a reset, the start up code clears it
+		cpu.sram.write(0x0104, 0x55);
+		cpu.sram.write(0x0105, 0x55);
+
+		let limits = Limits {
+			cycles: Some(10_000),
+			seconds: None,
+		};
+		assert_eq!(runner::run(&mut cpu, &limits), StopReason::Exit(0));
+		assert_eq!(console.borrow().0, b"Hi!");
+		assert_eq!(cpu.sram.read(0x0105), 0x00);
+	}
+
+	#[test]
+	fn symbols() {
+		let mut sections = firmware();
+		let count = sections.len();
+		sections.extend(symbol_sections(
+			count,
+			&[
+				("main", 0x0000, 4, STT_FUNC),
+				("counter", 0x80_0100, 2, STT_OBJECT),
+				("__tmp_reg__", 0x0000, 0, 0),
+			],
+		));
+
+		let parsed = elf::parse(&build(&sections, 0)).unwrap();
+		assert_eq!(parsed.symbols.len(), 3);
+		assert_eq!(parsed.symbols[0].name, "main");
+		assert_eq!(parsed.symbols[0].kind, SymbolKind::Function);
+		assert_eq!(parsed.symbols[1].value, 0x80_0100);
+		assert_eq!(parsed.symbols[1].kind, SymbolKind::Object);
+		assert_eq!(parsed.symbols[2].kind, SymbolKind::Other);
+
+		let mut system = System::default();
+		system.flash_from_elf(&build(&sections, 0)).unwrap();
+		assert_eq!(
+			system.symbols.find("main").map(|label| label.address),
+			Some(0)
+		);
+		assert_eq!(
+			system.symbols.data_location(0x0101).as_deref(),
+			Some("counter+0x1")
+		);
+
+		let assembly = system.disassembler.assembly.as_ref().unwrap();
+		assert_eq!(assembly[&0x0000].label.as_deref(), Some("main"));
+		assert_eq!(assembly[&0x0001].operands, "0x0001 <main+0x1>");
+	}
+}
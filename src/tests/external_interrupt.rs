@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod pins {
+	use crate::cpu::Cpu;
+	use crate::peripherals::external_interrupt::{EICRA, EIFR, EIMSK, PCICR, PCIFR, PCMSK0};
+	use crate::peripherals::gpio::{Pin, Port, INT0, MCUCR};
+	use crate::peripherals::sleep::SMCR;
+	use crate::tests::setup_idling;
+
+	const SLEEP: u16 = 0x9588;
+
+	const PB0: Pin = Pin::new(Port::B, 0);
+	const PB1: Pin = Pin::new(Port::B, 1);
+
+	fn drive(cpu: &mut Cpu, pin: Pin, level: bool) {
+		cpu.peripherals.gpio.drive(&mut cpu.sram, pin, Some(level));
+		cpu.step();
+	}
+
+	#[test]
+	fn pin_levels() {
+		let mut cpu = setup_idling(&[]);
+		let pind = Port::D.pin_address();
+
+		// input with the pull-up enabled
+		cpu.write_data(Port::D.port_address(), 0x04);
+		assert_eq!(cpu.read_data(pind), 0x04);
+
+		cpu.write_data(MCUCR, 0x10);
+		assert_eq!(cpu.read_data(pind), 0x00);
+
+		cpu.peripherals.gpio.drive(&mut cpu.sram, INT0, Some(true));
+		assert_eq!(cpu.read_data(pind), 0x04);
+
+		// writing PINx toggles an output
+		cpu.write_data(Port::D.ddr_address(), 0x80);
+		cpu.write_data(pind, 0x80);
+		assert_eq!(cpu.read_data(Port::D.port_address()), 0x84);
+		assert_eq!(cpu.read_data(pind), 0x84);
+	}
+
+	#[test]
+	fn edge_sensing() {
+		let mut cpu = setup_idling(&[]);
+		cpu.write_data(EICRA, 0x02);
+		drive(&mut cpu, INT0, true);
+		assert_eq!(cpu.read_data(EIFR), 0x00);
+
+		drive(&mut cpu, INT0, false);
+		assert_eq!(cpu.read_data(EIFR), 0x01);
+		cpu.write_data(EIFR, 0x01);
+		assert_eq!(cpu.read_data(EIFR), 0x00);
+
+		// rising edge only
+		cpu.write_data(EICRA, 0x03);
+		drive(&mut cpu, INT0, false);
+		assert_eq!(cpu.read_data(EIFR), 0x00);
+		drive(&mut cpu, INT0, true);
+		assert_eq!(cpu.read_data(EIFR), 0x01);
+	}
+
+	#[test]
+	fn low_level_interrupt() {
+		let mut cpu = setup_idling(&[]);
+		cpu.status.I = true;
+		cpu.write_data(EIMSK, 0x01);
+
+		drive(&mut cpu, INT0, false);
+		cpu.step();
+		assert_eq!(cpu.pc, 0x02);
+		assert_eq!(cpu.read_data(EIFR), 0x00);
+	}
+
+	#[test]
+	fn pin_change() {
+		let mut cpu = setup_idling(&[]);
+		cpu.write_data(PCMSK0, 0x01);
+
+		drive(&mut cpu, PB1, true);
+		assert_eq!(cpu.read_data(PCIFR), 0x00);
+		drive(&mut cpu, PB0, true);
+		assert_eq!(cpu.read_data(PCIFR), 0x01);
+		cpu.write_data(PCIFR, 0x01);
+
+		// outputs toggled by the firmware are detected too
+		cpu.peripherals.gpio.drive(&mut cpu.sram, PB0, None);
+		cpu.step();
+		cpu.write_data(PCIFR, 0x01);
+		cpu.write_data(Port::B.ddr_address(), 0x01);
+		cpu.write_data(Port::B.pin_address(), 0x01);
+		cpu.step();
+		assert_eq!(cpu.read_data(PCIFR), 0x01);
+
+		cpu.status.I = true;
+		cpu.write_data(PCICR, 0x01);
+		cpu.step();
+		assert_eq!(cpu.pc, 0x06);
+		assert_eq!(cpu.read_data(PCIFR), 0x00);
+	}
+
+	#[test]
+	fn power_down_wakes_on_level_only() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x05);
+		cpu.write_data(EICRA, 0x03);
+		cpu.write_data(EIMSK, 0x01);
+		cpu.step();
+		assert!(cpu.is_sleeping());
+
+		drive(&mut cpu, INT0, true);
+		drive(&mut cpu, INT0, false);
+		drive(&mut cpu, INT0, true);
+		assert!(cpu.is_sleeping());
+		assert_eq!(cpu.read_data(EIFR), 0x00);
+
+		cpu.write_data(EICRA, 0x00);
+		drive(&mut cpu, INT0, false);
+		cpu.step();
+		assert!(!cpu.is_sleeping());
+		assert_eq!(cpu.pc, 0x02);
+	}
+
+	#[test]
+	fn power_down_wakes_on_pin_change() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x05);
+		cpu.write_data(PCICR, 0x01);
+		cpu.write_data(PCMSK0, 0x01);
+		cpu.step();
+
+		drive(&mut cpu, PB0, true);
+		let cycles = cpu.cycles;
+		cpu.step();
+		assert_eq!(cpu.pc, 0x06);
+		assert_eq!(cpu.cycles - cycles, 8);
+	}
+
+	#[test]
+	fn idle_wakes_on_edge() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x01);
+		cpu.write_data(EICRA, 0x03);
+		cpu.write_data(EIMSK, 0x01);
+		cpu.step();
+
+		drive(&mut cpu, INT0, true);
+		cpu.step();
+		assert_eq!(cpu.pc, 0x02);
+	}
+
+	#[test]
+	fn sleep_without_interrupts_never_wakes() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.write_data(SMCR, 0x01);
+		cpu.write_data(EIMSK, 0x01);
+		cpu.step();
+
+		drive(&mut cpu, INT0, false);
+		cpu.step();
+		assert!(cpu.is_sleeping());
+		assert_eq!(cpu.pc, 0x01);
+	}
+}
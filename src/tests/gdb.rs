@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod remote_protocol {
+	use crate::cpu::Cpu;
+	use crate::debugger::{Breakpoint, Condition, WatchKind, WatchSpace, Watchpoint};
+	use crate::gdb::{self, Action, GdbServer};
+	use crate::memory::Memory;
+	use crate::tests;
+	use std::io::{Read, Write};
+	use std::net::{TcpListener, TcpStream};
+
+	fn setup(program: &[u16]) -> (Cpu, GdbServer) {
+		(tests::setup(program), GdbServer::default())
+	}
+
+	fn reply(server: &mut GdbServer, cpu: &mut Cpu, packet: &str) -> String {
+		match server.handle(cpu, packet.as_bytes()) {
+			Action::Reply(reply) => reply,
+			Action::Resume { step } => server.resume(cpu, step, || false),
+			Action::Close(reply) => reply,
+		}
+	}
+
+	#[test]
+	fn registers() {
+		let (mut cpu, mut server) = setup(&[0x0000]);
+		cpu.sram.registers[0] = 0x12;
+		cpu.sram.registers[31] = 0xAB;
+		cpu.status.Z = true;
+		cpu.sp = 0x08FF;
+		cpu.pc = 0x0123;
+
+		let registers = reply(&mut server, &mut cpu, "g");
+		assert_eq!(registers.len(), 78);
+		assert!(registers.starts_with("12"));
+		// r31, SREG, SP and the PC as a byte address
+		assert!(registers.ends_with("ab02ff0846020000"));
+
+		assert_eq!(reply(&mut server, &mut cpu, "p21"), "ff08");
+		assert_eq!(reply(&mut server, &mut cpu, "P22=10000000"), "OK");
+		assert_eq!(cpu.pc, 0x0008);
+		assert_eq!(reply(&mut server, &mut cpu, "P10=7f"), "OK");
+		assert_eq!(cpu.sram.registers[16], 0x7F);
+		assert_eq!(reply(&mut server, &mut cpu, "p23"), "E01");
+
+		assert_eq!(
+			reply(&mut server, &mut cpu, &format!("G{}", registers)),
+			"OK"
+		);
+		assert_eq!(cpu.pc, 0x0123);
+		assert_eq!(cpu.sram.registers[16], 0x00);
+	}
+
+	#[test]
+	fn memory() {
+		// ldi r16, 0x2A
+		let (mut cpu, mut server) = setup(&[0xE20A]);
+
+		assert_eq!(reply(&mut server, &mut cpu, "m0,2"), "0ae2");
+		assert_eq!(reply(&mut server, &mut cpu, "M800100,2:abcd"), "OK");
+		assert_eq!(cpu.sram.read(0x0100), 0xAB);
+		assert_eq!(reply(&mut server, &mut cpu, "m800101,1"), "cd");
+		// SPH
+		assert_eq!(reply(&mut server, &mut cpu, "m80005e,1"), "08");
+
+		assert_eq!(reply(&mut server, &mut cpu, "M810010,1:5a"), "OK");
+		assert_eq!(cpu.system.eeprom_memory.read(0x0010), 0x5A);
+		assert_eq!(reply(&mut server, &mut cpu, "X810011,1:}]"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "m810010,2"), "5a7d");
+
+		// reads stop at the end of data space
+		assert_eq!(reply(&mut server, &mut cpu, "m8008fe,4").len(), 4);
+		assert_eq!(reply(&mut server, &mut cpu, "m900000,1"), "E01");
+	}
+
+	#[test]
+	fn breakpoints() {
+		// ldi r16, 1; ldi r17, 2; ldi r18, 3; rjmp .-2
+		let (mut cpu, mut server) = setup(&[0xE001, 0xE012, 0xE023, 0xCFFF]);
+
+		assert_eq!(reply(&mut server, &mut cpu, "s"), "S05");
+		assert_eq!(cpu.pc, 0x0001);
+
+		assert_eq!(reply(&mut server, &mut cpu, "Z0,6,2"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "S05");
+		assert_eq!(cpu.pc, 0x0003);
+		assert_eq!(cpu.sram.registers[18], 3);
+
+		// resuming from a breakpoint executes it first
+		assert_eq!(reply(&mut server, &mut cpu, "Z1,0,2"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "c0"), "S05");
+		assert_eq!(cpu.pc, 0x0003);
+		assert_eq!(reply(&mut server, &mut cpu, "z0,6,2"), "OK");
+
+		// the idle loop only ends through an interrupt from GDB
+		assert_eq!(server.resume(&mut cpu, false, || true), "S02");
+	}
+
+	#[test]
+	fn keeps_cpu_breakpoints() {
+		// ldi r16, 1; ldi r17, 2; rjmp .-2
+		let (mut cpu, mut server) = setup(&[0xE001, 0xE012, 0xCFFF]);
+		cpu.breakpoints.insert(Breakpoint {
+			condition: Some(Condition::parse("r16 == 1").unwrap()),
+			..Breakpoint::new(0x0001)
+		});
+
+		// GDB setting and clearing its own breakpoint at the same address leaves the
+		// conditional one alone
+		assert_eq!(reply(&mut server, &mut cpu, "Z0,2,2"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "z0,2,2"), "OK");
+		let breakpoint = cpu.breakpoints.get(0x0001).unwrap();
+		assert!(breakpoint.condition.is_some());
+
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "S05");
+		assert_eq!(cpu.pc, 0x0001);
+		assert_eq!(cpu.breakpoints.get(0x0001).unwrap().hits, 1);
+	}
+
+	#[test]
+	fn watchpoints() {
+		// ldi r24, 0x55; sts 0x0100, r24; lds r25, 0x0100; rjmp .-2
+		let (mut cpu, mut server) = setup(&[0xE585, 0x9380, 0x0100, 0x9190, 0x0100, 0xCFFF]);
+
+		assert_eq!(reply(&mut server, &mut cpu, "Z2,800100,1"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "Z3,800100,1"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "T05watch:800100;");
+		assert_eq!(cpu.pc, 0x0003);
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "T05rwatch:800100;");
+		assert_eq!(cpu.sram.registers[25], 0x55);
+		assert_eq!(cpu.watchpoints.iter().count(), 2);
+		assert_eq!(reply(&mut server, &mut cpu, "z3,800100,1"), "OK");
+		assert_eq!(cpu.watchpoints.iter().count(), 1);
+
+		assert_eq!(reply(&mut server, &mut cpu, "Z2,810000,1"), "OK");
+		assert_eq!(reply(&mut server, &mut cpu, "Z2,810400,1"), "");
+	}
+
+	#[test]
+	fn change_watchpoint_is_a_plain_trap() {
+		// ldi r24, 0x55; sts 0x0100, r24; rjmp .-2
+		let (mut cpu, mut server) = setup(&[0xE585, 0x9380, 0x0100, 0xCFFF]);
+		cpu.watchpoints.insert(Watchpoint::new(
+			WatchSpace::Data,
+			0x0100,
+			1,
+			WatchKind::Change,
+		));
+
+		// GDB never set a watchpoint of this kind, so it is not reported as one
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "S05");
+		assert_eq!(cpu.pc, 0x0003);
+	}
+
+	#[test]
+	fn exit() {
+		// ldi r16, 3; out DEBUG_EXIT, r16
+		let (mut cpu, mut server) = setup(&[0xE003, 0xBB0A]);
+		assert_eq!(reply(&mut server, &mut cpu, "c"), "W03");
+	}
+
+	#[test]
+	fn session() {
+		let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+		let port = listener.local_addr().unwrap().port();
+
+		let client = std::thread::spawn(move || {
+			let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+			let mut exchange = |packet: &str, expected: &str| {
+				stream.write_all(packet.as_bytes()).unwrap();
+				let mut received = vec![0; expected.len()];
+				stream.read_exact(&mut received).unwrap();
+				assert_eq!(String::from_utf8(received).unwrap(), expected);
+			};
+
+			exchange("$?#3f", "+$S05#b8");
+			// a corrupted packet is asked for again
+			exchange("+$?#00", "-");
+			exchange("$QStartNoAckMode#b0", "+$OK#9a");
+			exchange("$D#44", "$OK#9a");
+		});
+
+		let (stream, _) = listener.accept().unwrap();
+		let (mut cpu, _) = setup(&[0x0000]);
+		gdb::serve_connection(&mut cpu, stream).unwrap();
+		client.join().unwrap();
+	}
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+mod time_travel {
+	use crate::cpu::Cpu;
+	use crate::debugger::history::CHECKPOINT_INTERVAL;
+	use crate::debugger::{Breakpoint, Condition, History};
+	use crate::memory::Memory;
+	use crate::peripherals::adc::{ADCH, ADCSRA};
+	use crate::peripherals::analog::AnalogPin;
+	use crate::peripherals::gpio::{Pin, Port};
+	use crate::peripherals::spi::{SpiDevice, SpiSettings, SPCR, SPDR, SPSR};
+	use crate::peripherals::usart::SerialDevice;
+	use crate::tests::{self, LOOP};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	const ADIF: u16 = 0x10;
+
+	// inc r16; rjmp .-4
+	const COUNTER: [u16; 2] = [0x9503, 0xCFFE];
+
+	fn setup(program: &[u16], history: History) -> Cpu {
+		let mut cpu = tests::setup(program);
+		cpu.history = Some(history);
+		cpu
+	}
+
+	/// Answers each transfer with how many it has seen
+	#[derive(Default)]
+	struct Counter(u8);
+
+	impl SpiDevice for Counter {
+		fn select(&mut self) {}
+
+		fn deselect(&mut self) {}
+
+		fn transfer(&mut self, _settings: SpiSettings, _data: u8) -> u8 {
+			self.0 += 1;
+			self.0
+		}
+	}
+
+	#[derive(Default)]
+	struct Console(Vec<u8>);
+
+	impl SerialDevice for Console {
+		fn receive(&mut self, data: u8) {
+			self.0.push(data);
+		}
+	}
+
+	fn state(cpu: &mut Cpu) -> (u16, u16, u8, usize, Vec<u8>) {
+		let ram = (0x0100..0x0104).map(|a| cpu.sram.read(a) as u8).collect();
+		(cpu.pc, cpu.sp, cpu.status.byte(), cpu.cycles, ram)
+	}
+
+	#[test]
+	fn step_back() {
+		// ldi r24, 0x55; sts 0x0100, r24; rcall .+0; inc r24; sts 0x0101, r24; rjmp .-2
+		let program = [
+			0xE585, 0x9380, 0x0100, 0xD000, 0x9583, 0x9380, 0x0101, 0xCFFF,
+		];
+		let mut cpu = setup(&program, History::default());
+
+		let mut states = Vec::new();
+		for _ in 0..6 {
+			states.push((state(&mut cpu), cpu.sram.registers.clone()));
+			cpu.step();
+		}
+		assert_eq!(cpu.sram.read(0x0101), 0x56);
+
+		while let Some((expected, registers)) = states.pop() {
+			assert!(cpu.step_back());
+			assert_eq!(state(&mut cpu), expected);
+			assert_eq!(cpu.sram.registers, registers);
+		}
+		assert!(!cpu.step_back());
+		assert_eq!(cpu.sram.read(0x0100), 0x00);
+	}
+
+	#[test]
+	fn peripheral_state() {
+		// ldi r16, 3; out DEBUG_EXIT, r16; rjmp .-2
+		let mut cpu = setup(&[0xE003, 0xBB0A, 0xCFFF], History::default());
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.peripherals.debug.exit_code(), Some(3));
+
+		assert!(cpu.step_back());
+		assert_eq!(cpu.peripherals.debug.exit_code(), None);
+		assert_eq!(cpu.pc, 0x0001);
+	}
+
+	#[test]
+	fn io_registers() {
+		// ldi r16, ADEN | ADSC; sts ADCSRA, r16; rjmp .-2
+		let mut cpu = setup(&[0xEC00, 0x9300, 0x007A, 0xCFFF], History::default());
+		cpu.peripherals.analog.set_voltage(AnalogPin::Aref, 5.0);
+		cpu.peripherals.analog.set_voltage(AnalogPin::Adc0, 2.5);
+		// the result is written by the ADC, not by an instruction
+		while cpu.sram.read(ADCSRA) & ADIF == 0 {
+			cpu.step();
+		}
+		assert_eq!(cpu.sram.read(ADCH), 0x02);
+
+		assert!(cpu.step_back());
+		assert_eq!(cpu.sram.read(ADCH), 0x00);
+		assert_eq!(cpu.sram.read(ADCSRA) & ADIF, 0);
+	}
+
+	#[test]
+	fn reverse_continue() {
+		let mut cpu = setup(&COUNTER, History::default());
+		cpu.breakpoints.insert(Breakpoint {
+			condition: Some(Condition::parse("r16 == 3").unwrap()),
+			..Breakpoint::new(0x0000)
+		});
+		for _ in 0..100 {
+			cpu.step();
+		}
+		let hits = cpu.breakpoints.get(0x0000).unwrap().hits;
+
+		assert!(cpu.reverse_continue());
+		assert_eq!((cpu.pc, cpu.sram.registers[16]), (0x0000, 3));
+		assert_eq!(cpu.breakpoints.get(0x0000).unwrap().hits, hits);
+		assert!(!cpu.breakpoint_hit);
+
+		// nothing further back stops it
+		assert!(!cpu.reverse_continue());
+		assert_eq!((cpu.cycles, cpu.sram.registers[16]), (0, 0));
+	}
+
+	#[test]
+	fn timeline() {
+		let mut cpu = setup(&COUNTER, History::new(CHECKPOINT_INTERVAL));
+		for _ in 0..5 * CHECKPOINT_INTERVAL {
+			cpu.step();
+		}
+
+		let history = cpu.history.as_ref().unwrap();
+		let (start, end) = (history.start_cycles(&cpu), history.end_cycles(&cpu));
+		// old steps were dropped a checkpoint interval at a time
+		assert_eq!(start, 3 * (4 * CHECKPOINT_INTERVAL / 2));
+		assert_eq!(end, cpu.cycles);
+
+		// each loop takes 3 cycles and counts once
+		cpu.seek(start + 300);
+		assert_eq!(cpu.cycles, start + 300);
+		assert_eq!(cpu.pc, 0x0000);
+		assert_eq!(cpu.sram.registers[16], ((start + 300) / 3) as u8);
+
+		cpu.seek(end);
+		assert_eq!(cpu.cycles, end);
+		// seeking forward stops at the furthest step reached
+		cpu.seek(end + 100);
+		assert_eq!(cpu.cycles, end);
+
+		cpu.seek(0);
+		assert_eq!(cpu.cycles, start);
+		cpu.reset();
+		assert!(!cpu.history.as_ref().unwrap().can_step_back());
+	}
+
+	#[test]
+	fn devices_see_traffic_once() {
+		// out SPDR, r16; out DEBUG_CONSOLE, r16; rjmp .-2
+		let mut cpu = setup(&[0xBD0E, 0xBB09, LOOP], History::default());
+		let counter = Rc::new(RefCell::new(Counter::default()));
+		let chip_select = Pin::new(Port::B, 1);
+		cpu.peripherals.spi.attach(counter.clone(), chip_select);
+		let console = Rc::new(RefCell::new(Console::default()));
+		cpu.peripherals.debug.attach(console.clone());
+		cpu.write_data(Port::B.ddr_address(), 1 << chip_select.bit);
+		// SPE | MSTR, fosc/4
+		cpu.write_data(SPCR, 0x50);
+
+		while cpu.sram.read(SPSR) & 0x80 == 0 {
+			cpu.step();
+		}
+		cpu.step();
+		assert_eq!(cpu.sram.read(SPDR), 1);
+
+		// the replay from the checkpoint goes through the transfer and the write again
+		assert!(cpu.step_back());
+		assert_eq!(cpu.sram.read(SPDR), 1);
+		assert_eq!(counter.borrow().0, 1);
+		assert_eq!(console.borrow().0, vec![0x00]);
+
+		// stepping again replays the recorded step as well
+		cpu.step();
+		assert_eq!(counter.borrow().0, 1);
+		assert_eq!(console.borrow().0, vec![0x00]);
+	}
+}
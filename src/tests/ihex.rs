@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod intel_hex {
+	use crate::ihex::{self, ErrorKind};
+	use crate::memory::Memory;
+	use crate::system::{LoadError, System};
+
+	const END: &str = ":00000001FF";
+
+	fn record(address: u16, record_type: u8, data: &[u8]) -> String {
+		let mut bytes = vec![
+			data.len() as u8,
+			(address >> 8) as u8,
+			address as u8,
+			record_type,
+		];
+		bytes.extend_from_slice(data);
+		let checksum = bytes
+			.iter()
+			.fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+			.wrapping_neg();
+		bytes.push(checksum);
+
+		let digits: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+		format!(":{}", digits)
+	}
+
+	fn image(records: &[String]) -> String {
+		let mut text = records.join("\n");
+		text.push('\n');
+		text.push_str(END);
+		text
+	}
+
+	#[test]
+	fn round_trip() {
+		let data: Vec<u8> = (0..40).collect();
+		let parsed = ihex::parse(&ihex::write(&data)).unwrap();
+		let expected: Vec<(u32, u8)> = (0..40).map(|byte| (byte as u32, byte)).collect();
+		assert_eq!(parsed.data, expected);
+		assert_eq!(parsed.start_address, None);
+	}
+
+	#[test]
+	fn line_numbered_errors() {
+		let checksum = ihex::parse(":0100000001FF\n:00000001FF").unwrap_err();
+		assert_eq!(checksum.line, 1);
+		assert_eq!(
+			checksum.kind,
+			ErrorKind::Checksum {
+				expected: 0xFE,
+				found: 0xFF
+			}
+		);
+
+		let text = format!(
+			"{}\n\n{}\n{}",
+			record(0, 0x00, &[1]),
+			record(0, 0x06, &[]),
+			END
+		);
+		let unknown = ihex::parse(&text).unwrap_err();
+		assert_eq!(unknown.line, 3);
+		assert_eq!(unknown.kind, ErrorKind::UnknownRecordType(0x06));
+		assert_eq!(unknown.to_string(), "line 3: unknown record type 0x06");
+
+		let error = ihex::parse(&record(0, 0x00, &[1])).unwrap_err();
+		assert_eq!(error.kind, ErrorKind::MissingEndOfFile);
+		assert_eq!(error.line, 2);
+
+		let error = ihex::parse(&image(&[record(0, 0x04, &[0x00])])).unwrap_err();
+		assert_eq!(error.kind, ErrorKind::InvalidRecordLength(0x04));
+
+		assert_eq!(
+			ihex::parse("0000000001FF").unwrap_err().kind,
+			ErrorKind::MissingStartCode
+		);
+		assert_eq!(
+			ihex::parse(":0000000G01FF").unwrap_err().kind,
+			ErrorKind::InvalidDigit
+		);
+		assert_eq!(
+			ihex::parse(":000000FF").unwrap_err().kind,
+			ErrorKind::Truncated
+		);
+		assert_eq!(
+			ihex::parse(":02000000FF").unwrap_err().kind,
+			ErrorKind::LengthMismatch
+		);
+	}
+
+	#[test]
+	fn rejects_non_ascii() {
+		let error = ihex::parse(":0\u{e9}00000000000\n:00000001FF").unwrap_err();
+		assert_eq!(error.line, 1);
+		assert_eq!(error.kind, ErrorKind::InvalidDigit);
+	}
+
+	#[test]
+	fn address_records() {
+		let text = image(&[
+			// segment 0x0010, byte address 0x100
+			record(0, 0x02, &[0x00, 0x10]),
+			record(0x0004, 0x00, &[0xAA]),
+			record(0, 0x04, &[0x00, 0x01]),
+			record(0x0002, 0x00, &[0xBB]),
+			record(0, 0x05, &[0x00, 0x00, 0x01, 0x00]),
+		]);
+		let parsed = ihex::parse(&text).unwrap();
+		assert_eq!(parsed.data, vec![(0x0104, 0xAA), (0x0001_0002, 0xBB)]);
+		assert_eq!(parsed.start_address, Some(0x100));
+
+		let text = image(&[record(0, 0x03, &[0x00, 0x10, 0x00, 0x02])]);
+		assert_eq!(ihex::parse(&text).unwrap().start_address, Some(0x102));
+	}
+
+	#[test]
+	fn loads_flash_at_record_address() {
+		let mut system = System::default();
+		let text = image(&[
+			// ldi r16, 0x2A; nop
+			record(0x0000, 0x00, &[0x0A, 0xE2, 0x00, 0x00]),
+			record(0x0010, 0x00, &[0xFF, 0xCF]),
+		]);
+		system.flash_from_hex(&text).unwrap();
+
+		assert_eq!(system.program_memory.read(0x0000), 0xE20A);
+		assert_eq!(system.program_memory.read(0x0001), 0x0000);
+		assert_eq!(system.program_memory.read(0x0008), 0xCFFF);
+		assert_eq!(system.entry_point, 0x0000);
+
+		let assembly = system.disassembler.assembly.as_ref().unwrap();
+		assert_eq!(assembly.keys().last(), Some(&0x0008));
+	}
+
+	#[test]
+	fn loads_eeprom_section() {
+		let mut system = System::default();
+		let text = image(&[
+			record(0x0000, 0x00, &[0x0A, 0xE2]),
+			record(0, 0x04, &[0x00, 0x81]),
+			record(0x0010, 0x00, &[0x12, 0x34]),
+		]);
+		system.flash_from_hex(&text).unwrap();
+
+		assert_eq!(system.program_memory.read(0x0000), 0xE20A);
+		assert_eq!(system.eeprom_memory.read(0x0010), 0x12);
+		assert_eq!(system.eeprom_memory.read(0x0011), 0x34);
+	}
+
+	#[test]
+	fn rejects_out_of_range() {
+		let mut system = System::default();
+		system.flash_from_vec(vec![0xE20A]);
+
+		let text = image(&[
+			record(0x0000, 0x00, &[0x00, 0x00]),
+			record(0, 0x04, &[0x00, 0x02]),
+			record(0x0000, 0x00, &[0x01]),
+		]);
+		let error = system.flash_from_hex(&text).unwrap_err();
+		assert!(matches!(error, LoadError::OutOfRange(0x0002_0000)));
+
+		// nothing was written
+		assert_eq!(system.program_memory.read(0x0000), 0xE20A);
+	}
+
+	#[test]
+	fn missing_file() {
+		let mut system = System::default();
+		let error = system
+			.flash_from_hex_file(std::path::Path::new("does/not/exist.hex"))
+			.unwrap_err();
+		assert!(matches!(error, LoadError::Io(_)));
+	}
+}
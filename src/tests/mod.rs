@@ -1 +1,62 @@
+pub mod adc;
+pub mod analog_comparator;
+pub mod breakpoints;
+pub mod clock;
+pub mod coverage;
 pub mod cpu;
+pub mod debug;
+pub mod disassembler;
+pub mod dwarf;
+pub mod eeprom;
+pub mod elf;
+pub mod external_interrupt;
+pub mod gdb;
+pub mod history;
+pub mod ihex;
+pub mod power;
+pub mod profiler;
+pub mod runner;
+pub mod scheduler;
+pub mod snapshot;
+pub mod spi;
+pub mod timer;
+pub mod trace;
+pub mod twi;
+pub mod usart;
+pub mod watchdog;
+pub mod watchpoints;
+
+#[cfg(test)]
+use crate::cpu::Cpu;
+
+/// `rjmp .-2`, the loop test programs idle in
+#[cfg(test)]
+pub const LOOP: u16 = 0xCFFF;
+
+/// A CPU with `program` at the start of flash, the rest of flash reads as `nop`
+#[cfg(test)]
+pub fn setup(program: &[u16]) -> Cpu {
+	let mut cpu = Cpu::init();
+	cpu.system.flash_from_vec(program.to_vec());
+	cpu
+}
+
+/// Like `setup`, with idle loops up to word 0x100 so the program also idles in the
+/// interrupt vectors, fast forwarding through them
+#[cfg(test)]
+pub fn setup_idling(program: &[u16]) -> Cpu {
+	let mut flash = program.to_vec();
+	flash.resize(0x100, LOOP);
+	let mut cpu = setup(&flash);
+	cpu.fast_forward = true;
+	cpu
+}
+
+/// Steps the CPU until at least `cycles` more cycles have passed
+#[cfg(test)]
+pub fn run(cpu: &mut Cpu, cycles: usize) {
+	let end = cpu.cycles + cycles;
+	while cpu.cycles < end {
+		cpu.step();
+	}
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod power_reduction {
+	use crate::peripherals::adc::{ADCSRA, ADMUX};
+	use crate::peripherals::power::{Module, PRR};
+	use crate::peripherals::spi::SPCR;
+	use crate::peripherals::twi::TWSR;
+	use crate::tests::{run, setup};
+
+	const PRADC: u8 = 0x01;
+	const PRSPI: u8 = 0x04;
+	const PRTWI: u8 = 0x80;
+
+	#[test]
+	fn reserved_bit() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(PRR, 0xFF);
+		assert_eq!(cpu.read_data(PRR), 0xEF);
+	}
+
+	#[test]
+	fn registers_inaccessible_while_shut_down() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(SPCR, 0x50);
+		assert_eq!(cpu.read_data(TWSR), 0xF8);
+
+		cpu.write_data(PRR, PRSPI | PRTWI);
+		assert!(!cpu.peripherals.power.is_powered(&mut cpu.sram, Module::Spi));
+		assert_eq!(cpu.read_data(SPCR), 0x00);
+		assert_eq!(cpu.read_data(TWSR), 0x00);
+
+		cpu.write_data(SPCR, 0x00);
+		cpu.write_data(PRR, 0x00);
+		assert_eq!(cpu.read_data(SPCR), 0x50);
+		assert_eq!(cpu.read_data(TWSR), 0xF8);
+	}
+
+	#[test]
+	fn clock_stopped_while_shut_down() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ADMUX, 0x40);
+		cpu.write_data(ADCSRA, 0xC0);
+		run(&mut cpu, 10);
+
+		cpu.write_data(PRR, PRADC);
+		run(&mut cpu, 100);
+		assert!(cpu.peripherals.adc.is_converting());
+
+		cpu.write_data(PRR, 0x00);
+		run(&mut cpu, 40);
+		assert!(!cpu.peripherals.adc.is_converting());
+	}
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod cycle_profile {
+	use crate::cpu::Cpu;
+	use crate::profiler::{FunctionProfile, Profiler};
+	use crate::symbols::SymbolTable;
+	use crate::tests::setup;
+
+	// 0: ldi r16, 1; rcall 4; rcall 4; rjmp .-2
+	// 4: inc r16; rcall 7; ret
+	// 7: rcall .+0; pop r0; pop r0; ret
+	const PROGRAM: [u16; 11] = [
+		0xE001, 0xD002, 0xD001, 0xCFFF, 0x9503, 0xD001, 0x9508, 0xD000, 0x900F, 0x900F, 0x9508,
+	];
+
+	fn profile() -> (Cpu, Vec<FunctionProfile>) {
+		let mut cpu = setup(&PROGRAM);
+		cpu.profiler = Some(Profiler::default());
+		for _ in 0..40 {
+			cpu.step();
+		}
+		let functions = (cpu.profiler.as_ref().unwrap()).functions(&SymbolTable::default());
+		(cpu, functions)
+	}
+
+	fn function(functions: &[FunctionProfile], address: u16) -> &FunctionProfile {
+		functions
+			.iter()
+			.find(|function| function.address == address)
+			.unwrap()
+	}
+
+	#[test]
+	fn calls_and_cycles() {
+		let (cpu, functions) = profile();
+		assert_eq!(cpu.sram.registers[16], 3);
+		// the rcall .+0 reserving stack space is not a call
+		assert_eq!(functions.len(), 3);
+
+		let root = function(&functions, 0x0000);
+		let outer = function(&functions, 0x0004);
+		let inner = function(&functions, 0x0007);
+		assert_eq!((root.calls, outer.calls, inner.calls), (1, 2, 2));
+		assert_eq!(root.name, "0x0000");
+
+		// ldi 1, rcall 3, rcall 3, then 23 turns of rjmp 2
+		assert_eq!((root.inclusive, root.exclusive), (91, 53));
+		// inc 1, rcall 3, ret 4 on each call
+		assert_eq!((outer.inclusive, outer.exclusive), (38, 16));
+		// rcall 3, pop 2, pop 2, ret 4 on each call
+		assert_eq!((inner.inclusive, inner.exclusive), (22, 22));
+
+		let total = cpu.profiler.as_ref().unwrap().total_cycles();
+		assert_eq!(total, cpu.cycles);
+		assert_eq!(total, 91);
+		assert_eq!(functions[0].address, 0x0000);
+	}
+
+	#[test]
+	fn folded_stacks() {
+		let (cpu, functions) = profile();
+		let mut output = Vec::new();
+		(cpu.profiler.as_ref().unwrap())
+			.write_folded(&SymbolTable::default(), &mut output)
+			.unwrap();
+		let text = String::from_utf8(output).unwrap();
+		let lines: Vec<&str> = text.lines().collect();
+
+		let inner = function(&functions, 0x0007);
+		assert_eq!(lines.len(), 3);
+		assert!(lines[0].starts_with("0x0000 "));
+		assert_eq!(
+			lines[2],
+			format!("0x0000;0x0004;0x0007 {}", inner.exclusive)
+		);
+	}
+
+	#[test]
+	fn clear() {
+		let (mut cpu, _) = profile();
+		let profiler = cpu.profiler.as_mut().unwrap();
+		profiler.clear();
+		assert_eq!(profiler.total_cycles(), 0);
+		assert!(profiler.functions(&SymbolTable::default()).is_empty());
+	}
+}
@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod headless {
+	use crate::runner::UartSink;
+	use crate::runner::{self, parse_duration, parse_frequency, Limits, RunOptions, StopReason};
+	use crate::tests::{setup, setup_idling};
+
+	const BREAK: u16 = 0x9598;
+	const SLEEP: u16 = 0x9588;
+	const CLI: u16 = 0x94F8;
+	// ldi r16, 0x01
+	const LDI_R16_1: u16 = 0xE001;
+	// ldi r16, 0x03
+	const LDI_R16_3: u16 = 0xE003;
+	// out SMCR, r16
+	const OUT_SMCR: u16 = 0xBF03;
+	// out 0x1A, r16
+	const OUT_EXIT: u16 = 0xBB0A;
+
+	fn args(text: &str) -> Vec<String> {
+		text.split_whitespace().map(String::from).collect()
+	}
+
+	#[test]
+	fn exit_register() {
+		let mut cpu = setup_idling(&[LDI_R16_3, OUT_EXIT]);
+		let reason = runner::run(&mut cpu, &Limits::default());
+		assert_eq!(reason, StopReason::Exit(3));
+		assert_eq!(reason.exit_code(), 3);
+		assert_eq!(cpu.cycles, 2);
+	}
+
+	#[test]
+	fn break_instruction() {
+		let mut cpu = setup_idling(&[BREAK]);
+		assert_eq!(runner::run(&mut cpu, &Limits::default()), StopReason::Break);
+		assert!(!cpu.break_hit);
+		assert_eq!(cpu.pc, 1);
+	}
+
+	#[test]
+	fn sleep_deadlock() {
+		let mut cpu = setup_idling(&[CLI, LDI_R16_1, OUT_SMCR, SLEEP]);
+		let reason = runner::run(&mut cpu, &Limits::default());
+		assert_eq!(reason, StopReason::Deadlock);
+		assert_eq!(reason.exit_code(), 0);
+	}
+
+	#[test]
+	fn limits() {
+		let mut cpu = setup_idling(&[]);
+		let limits = Limits {
+			cycles: Some(1000),
+			seconds: None,
+		};
+		let reason = runner::run(&mut cpu, &limits);
+		assert_eq!(reason, StopReason::CycleLimit);
+		assert_eq!(reason.exit_code(), 124);
+		assert!(cpu.cycles >= 1000);
+
+		// 1 MHz from the factory fuses
+		let mut cpu = setup_idling(&[]);
+		let limits = Limits {
+			cycles: None,
+			seconds: Some(1e-3),
+		};
+		assert_eq!(runner::run(&mut cpu, &limits), StopReason::TimeLimit);
+		assert!((1000..1002).contains(&cpu.cycles));
+	}
+
+	#[test]
+	fn wraps_around_flash() {
+		// jmp 0x0000
+		let mut cpu = setup(&[0x940C, 0x0000]);
+		let limits = Limits {
+			cycles: Some(30),
+			seconds: None,
+		};
+		assert_eq!(runner::run(&mut cpu, &limits), StopReason::CycleLimit);
+		assert_eq!(cpu.pc, 0x0000);
+
+		// running off the end of flash through nops starts over at the reset vector
+		let mut cpu = setup(&[]);
+		let limits = Limits {
+			cycles: Some(0x4001),
+			seconds: None,
+		};
+		assert_eq!(runner::run(&mut cpu, &limits), StopReason::CycleLimit);
+		assert_eq!(cpu.pc, 0x0001);
+	}
+
+	#[test]
+	fn arguments() {
+		let options = RunOptions::parse(&args(
+			"blink.hex --cycles 500 --freq 8M --uart stdout --time 20ms",
+		))
+		.unwrap();
+		assert_eq!(options.program.to_str(), Some("blink.hex"));
+		assert_eq!(options.limits.cycles, Some(500));
+		assert_eq!(options.limits.seconds, Some(20e-3));
+		assert_eq!(options.frequency, Some(8e6));
+		assert_eq!(options.uart, UartSink::Stdout);
+
+		assert!(RunOptions::parse(&args("--cycles 10")).is_err());
+		assert!(RunOptions::parse(&args("a.hex --cycles")).is_err());
+		assert!(RunOptions::parse(&args("a.hex --uart serial")).is_err());
+		assert!(RunOptions::parse(&args("a.hex b.hex")).is_err());
+
+		let options = RunOptions::parse(&args("a.elf --gdb 1234")).unwrap();
+		assert_eq!(options.gdb, Some(1234));
+		assert!(RunOptions::parse(&args("a.elf --gdb 99999")).is_err());
+
+		let options = RunOptions::parse(&args(
+			"a.elf --trace a.trace --trace-last 100 --trace-range 0x100-0x13F --trace-func main",
+		))
+		.unwrap();
+		assert_eq!(options.trace_last, Some(100));
+		assert_eq!(options.trace_ranges, vec![0x100..0x140]);
+		assert_eq!(options.trace_functions, vec!["main".to_string()]);
+		assert!(RunOptions::parse(&args("a.elf --trace-last 100")).is_err());
+
+		let options = RunOptions::parse(&args("a.elf --profile a.folded")).unwrap();
+		assert_eq!(options.profile.unwrap().to_str(), Some("a.folded"));
+
+		let options =
+			RunOptions::parse(&args("a.elf --coverage a.info --min-coverage 80%")).unwrap();
+		assert_eq!(options.coverage.unwrap().to_str(), Some("a.info"));
+		assert_eq!(options.min_coverage, Some(80.0));
+		assert!(RunOptions::parse(&args("a.elf --min-coverage 101")).is_err());
+		assert!(RunOptions::parse(&args("a.elf --trace a.trace --trace-range 0x20-0x10")).is_err());
+	}
+
+	#[test]
+	fn units() {
+		assert_eq!(parse_frequency("16M"), Some(16e6));
+		assert_eq!(parse_frequency("128kHz"), Some(128e3));
+		assert_eq!(parse_frequency("32768"), Some(32768.0));
+		assert_eq!(parse_frequency("fast"), None);
+		assert_eq!(parse_duration("1.5"), Some(1.5));
+		assert_eq!(parse_duration("2s"), Some(2.0));
+		assert_eq!(parse_duration("100us"), Some(100e-6));
+		assert_eq!(parse_duration("-1"), None);
+	}
+}
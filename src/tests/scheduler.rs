@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod events {
+	use crate::peripherals::adc::ADCSRA;
+	use crate::peripherals::scheduler::{EventSource, Scheduler};
+	use crate::peripherals::sleep::SMCR;
+	use crate::tests::setup_idling;
+
+	const ADEN: u8 = 0x80;
+	const ADSC: u8 = 0x40;
+	const ADIF: u8 = 0x10;
+	const ADIE: u8 = 0x08;
+
+	const SLEEP: u16 = 0x9588;
+
+	#[test]
+	fn queue_order() {
+		let mut scheduler = Scheduler::default();
+		scheduler.schedule(EventSource::Adc, 10);
+		scheduler.schedule(EventSource::Spi, 4);
+		scheduler.schedule(EventSource::Twi, 6);
+		assert_eq!(scheduler.next_event(), Some(4));
+
+		// rescheduling replaces the earlier event
+		scheduler.schedule(EventSource::Spi, 20);
+		scheduler.cancel(EventSource::Twi);
+		assert_eq!(scheduler.next_event(), Some(10));
+
+		scheduler.advance(9);
+		assert_eq!(scheduler.pop_due(), None);
+		scheduler.advance(12);
+		assert_eq!(scheduler.pop_due(), Some(EventSource::Adc));
+		assert_eq!(scheduler.pop_due(), Some(EventSource::Spi));
+		assert_eq!(scheduler.pop_due(), None);
+		assert_eq!(scheduler.next_event(), None);
+	}
+
+	#[test]
+	fn peripherals_register_events() {
+		let mut cpu = setup_idling(&[]);
+		cpu.write_data(ADCSRA, ADEN | ADSC | 0x07);
+		assert_eq!(
+			cpu.peripherals.scheduler.scheduled(EventSource::Adc),
+			Some(25 * 128)
+		);
+
+		cpu.write_data(ADCSRA, 0x00);
+		assert_eq!(cpu.peripherals.scheduler.scheduled(EventSource::Adc), None);
+	}
+
+	#[test]
+	fn idle_loop_fast_forwards() {
+		let mut cpu = setup_idling(&[]);
+		cpu.step();
+		assert_eq!(cpu.cycles, 2);
+
+		cpu.write_data(ADCSRA, ADEN | ADSC | 0x07);
+		cpu.step();
+		assert_eq!(cpu.cycles, 2 + 25 * 128);
+		assert_eq!(cpu.read_data(ADCSRA) & (ADSC | ADIF), ADIF);
+	}
+
+	#[test]
+	fn sleep_fast_forwards() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x01);
+		cpu.write_data(ADCSRA, ADEN | ADSC | ADIE | 0x07);
+
+		cpu.step();
+		assert!(cpu.is_sleeping());
+		cpu.step();
+		assert_eq!(cpu.cycles, 25 * 128);
+
+		cpu.step();
+		assert!(!cpu.is_sleeping());
+		assert_eq!(cpu.pc, 0x2A);
+	}
+
+	#[test]
+	fn stopped_clock_drops_events() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.write_data(ADCSRA, ADEN | ADSC | 0x07);
+
+		// power-down stops the ADC clock
+		cpu.write_data(SMCR, 0x05);
+		cpu.step();
+		assert_eq!(cpu.peripherals.scheduler.scheduled(EventSource::Adc), None);
+
+		let cycles = cpu.cycles;
+		cpu.step();
+		assert_eq!(cpu.cycles - cycles, 1);
+		assert!(cpu.peripherals.adc.is_converting());
+	}
+}
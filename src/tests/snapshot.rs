@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod save_state {
+	use crate::cpu::Cpu;
+	use crate::memory::Memory;
+	use crate::peripherals::eeprom::{EEARL, EECR, EEDR};
+	use crate::peripherals::timer::{TCCR0B, TCNT0};
+	use crate::snapshot::{self, Error, MAGIC};
+	use crate::tests::{self, run};
+	use std::path::PathBuf;
+
+	const EEMPE: u8 = 0x04;
+	const EEPE: u8 = 0x02;
+
+	// ldi r24, 0x55; inc r24; sts 0x0100, r24; rcall .+0; rjmp .-10
+	const PROGRAM: [u16; 6] = [0xE585, 0x9583, 0x9380, 0x0100, 0xD000, 0xCFFA];
+
+	fn temp_file(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("atmega328p-rs-{}-{}", std::process::id(), name))
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut cpu = tests::setup(&PROGRAM);
+		run(&mut cpu, 20);
+		// an EEPROM write in progress is peripheral state
+		cpu.write_data(EEARL, 0x10);
+		cpu.write_data(EEDR, 0xA5);
+		cpu.write_data(EECR, EEMPE);
+		cpu.write_data(EECR, EEPE);
+		cpu.system.fuses.high = 0xDE;
+		// saved partway into a timer clock period, clk/64
+		cpu.write_data(TCCR0B, 0x03);
+		run(&mut cpu, 5);
+
+		let state = snapshot::save(&cpu);
+		assert!(state.starts_with(MAGIC));
+
+		let mut restored = Cpu::init();
+		snapshot::restore(&mut restored, &state).unwrap();
+		assert_eq!(snapshot::save(&restored), state);
+		assert_eq!(restored.system.program_memory.read(0x0005), 0xCFFA);
+		assert_eq!(restored.system.fuses.high, 0xDE);
+		assert!(restored.system.disassembler.assembly.is_some());
+
+		// both machines carry on identically
+		run(&mut cpu, 5_000);
+		run(&mut restored, 5_000);
+		assert_eq!(restored.system.eeprom_memory.read(0x0010), 0xA5);
+		assert_eq!(restored.sram.read(TCNT0), cpu.sram.read(TCNT0));
+		assert_eq!(snapshot::save(&restored), snapshot::save(&cpu));
+	}
+
+	#[test]
+	fn invalid() {
+		let mut cpu = tests::setup(&PROGRAM);
+		run(&mut cpu, 3);
+		let state = snapshot::save(&cpu);
+
+		let mut target = Cpu::init();
+		assert_eq!(
+			snapshot::restore(&mut target, b"not a save state"),
+			Err(Error::NotSnapshot)
+		);
+
+		let mut newer = state.clone();
+		newer[MAGIC.len()] = 0x63;
+		assert_eq!(
+			snapshot::restore(&mut target, &newer),
+			Err(Error::UnsupportedVersion(0x63))
+		);
+
+		assert_eq!(
+			snapshot::restore(&mut target, &state[..state.len() - 1]),
+			Err(Error::Truncated)
+		);
+
+		// SP follows the magic, the version and PC
+		let mut stack = state.clone();
+		stack[MAGIC.len() + 4..MAGIC.len() + 6].copy_from_slice(&0x0900u16.to_le_bytes());
+		assert_eq!(
+			snapshot::restore(&mut target, &stack),
+			Err(Error::Invalid("stack pointer"))
+		);
+
+		// nothing is restored from a bad save state
+		assert_eq!(target.pc, 0x0000);
+		assert_eq!(target.cycles, 0);
+		assert_eq!(target.system.program_memory.read(0x0000), 0x0000);
+	}
+
+	#[test]
+	fn files() {
+		let mut cpu = tests::setup(&PROGRAM);
+		run(&mut cpu, 7);
+		let path = temp_file("slot.state");
+		snapshot::save_file(&cpu, &path).unwrap();
+
+		let mut restored = Cpu::init();
+		snapshot::restore_file(&mut restored, &path).unwrap();
+		assert_eq!((restored.pc, restored.sp), (cpu.pc, cpu.sp));
+		assert_eq!(restored.sram.registers, cpu.sram.registers);
+
+		std::fs::write(&path, b"M328SNAP").unwrap();
+		let error = snapshot::restore_file(&mut restored, &path).unwrap_err();
+		assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+		std::fs::remove_file(&path).unwrap();
+	}
+}
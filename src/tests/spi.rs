@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod bus {
+	use crate::cpu::Cpu;
+	use crate::memory::Memory;
+	use crate::peripherals::gpio::{Pin, Port};
+	use crate::peripherals::spi::{SpiDevice, SpiMode, SpiSettings, SPCR, SPDR, SPSR};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	const CHIP_SELECT: Pin = Pin::new(Port::B, 1);
+
+	#[derive(Default)]
+	struct Echo {
+		selected: bool,
+		received: Vec<u8>,
+		modes: Vec<SpiMode>,
+	}
+
+	impl SpiDevice for Echo {
+		fn select(&mut self) {
+			self.selected = true;
+		}
+
+		fn deselect(&mut self) {
+			self.selected = false;
+		}
+
+		fn transfer(&mut self, settings: SpiSettings, data: u8) -> u8 {
+			self.received.push(data);
+			self.modes.push(settings.mode);
+			data.wrapping_add(1)
+		}
+	}
+
+	fn setup() -> (Cpu, Rc<RefCell<Echo>>) {
+		let mut cpu = Cpu::init();
+		cpu.system.flash_from_vec(vec![0x0000; 0x200]);
+
+		let echo = Rc::new(RefCell::new(Echo::default()));
+		cpu.peripherals.spi.attach(echo.clone(), CHIP_SELECT);
+
+		cpu.write_data(Port::B.ddr_address(), 1 << CHIP_SELECT.bit);
+		(cpu, echo)
+	}
+
+	#[test]
+	fn master_transfer() {
+		let (mut cpu, echo) = setup();
+		assert!(echo.borrow().selected);
+
+		// SPE | MSTR | CPHA, fosc/16
+		cpu.write_data(SPCR, 0x55);
+		cpu.write_data(SPDR, 0x42);
+
+		for _ in 0..127 {
+			cpu.step();
+		}
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0);
+
+		cpu.step();
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0x80);
+		assert_eq!(echo.borrow().received, vec![0x42]);
+		assert_eq!(echo.borrow().modes, vec![SpiMode::Mode1]);
+
+		assert_eq!(cpu.read_data(SPSR) & 0x80, 0x80);
+		assert_eq!(cpu.read_data(SPDR), 0x43);
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0);
+
+		cpu.write_data(Port::B.port_address(), 1 << CHIP_SELECT.bit);
+		assert!(!echo.borrow().selected);
+	}
+
+	#[test]
+	fn double_speed() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(SPCR, 0x50);
+		assert_eq!(cpu.peripherals.spi.clock_divider(&mut cpu.sram), 4);
+		cpu.write_data(SPSR, 0x01);
+		assert_eq!(cpu.peripherals.spi.clock_divider(&mut cpu.sram), 2);
+		cpu.write_data(SPCR, 0x53);
+		assert_eq!(cpu.peripherals.spi.clock_divider(&mut cpu.sram), 64);
+	}
+
+	#[test]
+	fn write_collision() {
+		let (mut cpu, echo) = setup();
+		cpu.write_data(SPCR, 0x50);
+		cpu.write_data(SPDR, 0x01);
+		cpu.write_data(SPDR, 0x02);
+
+		assert_eq!(cpu.sram.read(SPSR) & 0x40, 0x40);
+
+		for _ in 0..32 {
+			cpu.step();
+		}
+		assert_eq!(echo.borrow().received, vec![0x01]);
+	}
+
+	#[test]
+	fn interrupt() {
+		let (mut cpu, _) = setup();
+		cpu.status.I = true;
+
+		// SPIE | SPE | MSTR, fosc/4
+		cpu.write_data(SPCR, 0xD0);
+		cpu.write_data(SPDR, 0x10);
+
+		for _ in 0..32 {
+			cpu.step();
+		}
+		assert_eq!(cpu.pc, 32);
+
+		cpu.step();
+		assert_eq!(cpu.pc, 0x22);
+		assert!(!cpu.status.I);
+		assert_eq!(cpu.sp, 0x08FD);
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0);
+	}
+
+	#[test]
+	fn slave_mode() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(SPCR, 0x40);
+		cpu.write_data(SPDR, 0xA5);
+
+		assert_eq!(
+			cpu.peripherals.spi.slave_transfer(&mut cpu.sram, 0x3C),
+			None
+		);
+
+		cpu.peripherals.spi.set_slave_select(&mut cpu.sram, false);
+		assert_eq!(
+			cpu.peripherals.spi.slave_transfer(&mut cpu.sram, 0x3C),
+			Some(0xA5)
+		);
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0x80);
+		assert_eq!(cpu.read_data(SPDR), 0x3C);
+	}
+
+	#[test]
+	fn master_demoted_by_slave_select() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(SPCR, 0x50);
+
+		cpu.peripherals.spi.set_slave_select(&mut cpu.sram, false);
+
+		assert!(!cpu.peripherals.spi.is_master(&mut cpu.sram));
+		assert_eq!(cpu.sram.read(SPSR) & 0x80, 0x80);
+	}
+}
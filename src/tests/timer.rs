@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod timer_counter {
+	use crate::peripherals::adc::{ADCSRA, ADCSRB};
+	use crate::peripherals::analog::AnalogPin;
+	use crate::peripherals::analog_comparator::ACSR;
+	use crate::peripherals::power::PRR;
+	use crate::peripherals::timer::{
+		ICR1H, ICR1L, OCR0A, OCR1AH, OCR1AL, TCCR0A, TCCR0B, TCCR1B, TCCR2A, TCCR2B, TCNT0, TCNT1H,
+		TCNT1L, TCNT2, TIFR0, TIFR2, TIMSK1,
+	};
+	use crate::tests::{run, setup, setup_idling};
+
+	const OCF0A: u8 = 0x02;
+	const TOV: u8 = 0x01;
+
+	#[test]
+	fn normal_mode_overflow() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCCR0B, 0x01);
+
+		run(&mut cpu, 255);
+		assert_eq!(cpu.read_data(TCNT0), 0xFF);
+		assert_eq!(cpu.read_data(TIFR0) & TOV, 0);
+
+		run(&mut cpu, 1);
+		assert_eq!(cpu.read_data(TCNT0), 0x00);
+		assert_eq!(cpu.read_data(TIFR0) & TOV, TOV);
+
+		cpu.write_data(TIFR0, TOV);
+		assert_eq!(cpu.read_data(TIFR0) & TOV, 0);
+	}
+
+	#[test]
+	fn prescaler() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCCR0B, 0x03);
+		run(&mut cpu, 64 * 10 + 63);
+		assert_eq!(cpu.read_data(TCNT0), 10);
+	}
+
+	#[test]
+	fn clear_timer_on_compare_match() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCCR0A, 0x02);
+		cpu.write_data(OCR0A, 9);
+		cpu.write_data(TCCR0B, 0x01);
+
+		run(&mut cpu, 9);
+		assert_eq!(cpu.read_data(TCNT0), 9);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), 0);
+
+		run(&mut cpu, 1);
+		assert_eq!(cpu.read_data(TCNT0), 0);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), OCF0A);
+	}
+
+	#[test]
+	fn fast_pwm_buffers_compare() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(OCR0A, 0x10);
+		cpu.write_data(TCCR0A, 0x03);
+		cpu.write_data(TCCR0B, 0x01);
+
+		// the new value takes effect at BOTTOM
+		cpu.write_data(OCR0A, 0x20);
+		run(&mut cpu, 0x11);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), OCF0A);
+
+		cpu.write_data(TIFR0, OCF0A);
+		run(&mut cpu, 0x100 - 0x11);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), TOV);
+
+		run(&mut cpu, 0x11);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), TOV);
+		run(&mut cpu, 0x10);
+		assert_eq!(cpu.read_data(TIFR0) & (OCF0A | TOV), TOV | OCF0A);
+	}
+
+	#[test]
+	fn phase_correct_counts_down() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCCR2A, 0x01);
+		cpu.write_data(TCCR2B, 0x01);
+
+		run(&mut cpu, 256);
+		assert_eq!(cpu.read_data(TCNT2), 0xFE);
+
+		run(&mut cpu, 254);
+		assert_eq!(cpu.read_data(TCNT2), 0x00);
+		assert_eq!(cpu.read_data(TIFR2) & TOV, 0);
+
+		run(&mut cpu, 1);
+		assert_eq!(cpu.read_data(TCNT2), 0x01);
+		assert_eq!(cpu.read_data(TIFR2) & TOV, TOV);
+	}
+
+	#[test]
+	fn sixteen_bit_access() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCNT1H, 0x12);
+		cpu.write_data(TCNT1L, 0x34);
+
+		// reading the low byte latches the high byte
+		assert_eq!(cpu.read_data(TCNT1L), 0x34);
+		cpu.write_data(TCNT1H, 0x56);
+		assert_eq!(cpu.read_data(TCNT1H), 0x56);
+		assert_eq!(cpu.read_data(TCNT1L), 0x34);
+		assert_eq!(cpu.read_data(TCNT1H), 0x12);
+	}
+
+	#[test]
+	fn fast_forwards_to_compare_match() {
+		let mut cpu = setup_idling(&[]);
+		cpu.status.I = true;
+		cpu.write_data(OCR1AH, 0x01);
+		cpu.write_data(OCR1AL, 0x00);
+		cpu.write_data(TIMSK1, 0x02);
+		// CTC with TOP = OCR1A, clk/1024
+		cpu.write_data(TCCR1B, 0x0D);
+
+		// OCR1B = 0 also matches on the way, the idle loop skips everything in between
+		let mut steps = 0;
+		while cpu.pc != 0x16 {
+			cpu.step();
+			steps += 1;
+		}
+		assert!(steps < 10);
+		assert_eq!(cpu.cycles, 0x101 * 1024 + 4);
+		assert_eq!(cpu.read_data(TCNT1L), 0x00);
+	}
+
+	#[test]
+	fn input_capture_latches_count() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(ACSR, 0x04);
+		cpu.write_data(TCCR1B, 0x41);
+		run(&mut cpu, 100);
+
+		cpu.peripherals.analog.set_voltage(AnalogPin::Ain0, 1.0);
+		cpu.step();
+		assert_eq!(cpu.read_data(ICR1L), 101);
+		assert_eq!(cpu.read_data(ICR1H), 0);
+	}
+
+	#[test]
+	fn triggers_adc() {
+		let mut cpu = setup(&[]);
+		// auto trigger on Timer0 overflow
+		cpu.write_data(ADCSRB, 0x04);
+		cpu.write_data(ADCSRA, 0xA7);
+		cpu.write_data(TCCR0B, 0x01);
+
+		run(&mut cpu, 255);
+		assert!(!cpu.peripherals.adc.is_converting());
+		run(&mut cpu, 1);
+		assert!(cpu.peripherals.adc.is_converting());
+	}
+
+	#[test]
+	fn stopped_by_power_reduction() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(TCCR0B, 0x01);
+		run(&mut cpu, 10);
+
+		cpu.write_data(PRR, 0x20);
+		run(&mut cpu, 10);
+		cpu.write_data(PRR, 0x00);
+		assert_eq!(cpu.read_data(TCNT0), 10);
+	}
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod execution_trace {
+	use crate::debugger::History;
+	use crate::symbols::SymbolTable;
+	use crate::tests::setup;
+	use crate::trace::{self, Error, Event, Record, Records, Tracer};
+	use std::cell::RefCell;
+	use std::io::{self, Write};
+	use std::rc::Rc;
+
+	/// Output that stays readable after the tracer took it
+	#[derive(Clone, Default)]
+	struct Shared(Rc<RefCell<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+			self.0.borrow_mut().extend_from_slice(bytes);
+			Ok(bytes.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	fn records(output: &Shared) -> Vec<Record> {
+		let bytes = output.0.borrow();
+		Records::new(&bytes).unwrap().map(Result::unwrap).collect()
+	}
+
+	#[test]
+	fn changes() {
+		// ldi r24, 0x55; sts 0x0100, r24; inc r24; rcall .+0
+		let mut cpu = setup(&[0xE585, 0x9380, 0x0100, 0x9583, 0xD000]);
+		let output = Shared::default();
+		cpu.trace = Some(Tracer::new(output.clone()));
+		for _ in 0..4 {
+			cpu.step();
+		}
+		cpu.trace.as_mut().unwrap().finish().unwrap();
+
+		let records = records(&output);
+		assert_eq!(records.len(), 4);
+		assert_eq!(records[0].event, Event::Instruction);
+		assert_eq!((records[0].cycles, records[0].pc), (0, 0x0000));
+		assert_eq!(records[0].registers, vec![(24, 0x55)]);
+		assert_eq!(records[1].operand, Some(0x0100));
+		assert_eq!(records[1].writes, vec![(0x0100, 0x55)]);
+		assert_eq!(records[1].cycles, 1);
+		assert_eq!(records[2].registers, vec![(24, 0x56)]);
+		assert!(records[2].sreg.is_none());
+		assert_eq!(records[3].sp, Some(cpu.sp));
+		assert_eq!(records[3].writes.len(), 2);
+
+		let mut text = Vec::new();
+		let bytes = output.0.borrow();
+		trace::export(&bytes, &SymbolTable::default(), &mut text).unwrap();
+		let text = String::from_utf8(text).unwrap();
+		let lines: Vec<&str> = text.lines().collect();
+		assert_eq!(lines.len(), 4);
+		assert!(lines[0].contains("ldi") && lines[0].ends_with("r24=55"));
+		assert!(lines[1].contains("sts") && lines[1].ends_with("[0100]=55"));
+	}
+
+	#[test]
+	fn last_instructions() {
+		// inc r16; rjmp .-4
+		let mut cpu = setup(&[0x9503, 0xCFFE]);
+		let output = Shared::default();
+		let mut tracer = Tracer::last(3, output.clone());
+		tracer.filter.push(0x0000..0x0001);
+		cpu.trace = Some(tracer);
+		for _ in 0..20 {
+			cpu.step();
+		}
+
+		let tracer = cpu.trace.as_mut().unwrap();
+		let kept: Vec<Record> = tracer.records().cloned().collect();
+		let counts: Vec<u8> = kept.iter().map(|record| record.registers[0].1).collect();
+		assert_eq!(counts, vec![8, 9, 10]);
+		assert!(kept.iter().all(|record| record.pc == 0x0000));
+		assert!(output.0.borrow().is_empty());
+
+		tracer.finish().unwrap();
+		assert_eq!(records(&output), kept);
+	}
+
+	#[test]
+	fn history_replay() {
+		// inc r16; rjmp .-4
+		let mut cpu = setup(&[0x9503, 0xCFFE]);
+		cpu.history = Some(History::default());
+		let output = Shared::default();
+		cpu.trace = Some(Tracer::new(output.clone()));
+		for _ in 0..3 {
+			cpu.step();
+		}
+		assert!(cpu.step_back());
+		cpu.trace.as_mut().unwrap().finish().unwrap();
+
+		// going back replays from a checkpoint without tracing it again
+		assert_eq!(records(&output).len(), 3);
+	}
+
+	#[test]
+	fn invalid() {
+		assert_eq!(
+			Records::new(b"M328SNAP\x01\x00").err(),
+			Some(Error::NotTrace)
+		);
+		assert_eq!(
+			Records::new(b"M328TRCE\x09\x00").err(),
+			Some(Error::UnsupportedVersion(9))
+		);
+		let mut records = Records::new(b"M328TRCE\x01\x00\x00\x00\x00").unwrap();
+		assert_eq!(records.next(), Some(Err(Error::Truncated)));
+		assert_eq!(records.next(), None);
+	}
+}
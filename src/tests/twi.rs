@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod bus {
+	use crate::cpu::Cpu;
+	use crate::peripherals::twi::{status, I2cDevice, TWAMR, TWAR, TWBR, TWCR, TWDR, TWSR};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	const TWINT: u8 = 0x80;
+	const TWEA: u8 = 0x40;
+	const TWSTA: u8 = 0x20;
+	const TWSTO: u8 = 0x10;
+	const TWEN: u8 = 0x04;
+
+	#[derive(Default)]
+	struct Register {
+		pointer: Option<u8>,
+		data: [u8; 16],
+		stopped: bool,
+	}
+
+	impl I2cDevice for Register {
+		fn write(&mut self, data: u8) -> bool {
+			match self.pointer {
+				None => self.pointer = Some(data),
+				Some(pointer) => {
+					self.data[pointer as usize] = data;
+					self.pointer = Some(pointer + 1);
+				}
+			}
+			true
+		}
+
+		fn read(&mut self) -> u8 {
+			let pointer = self.pointer.unwrap_or(0);
+			self.pointer = Some(pointer + 1);
+			self.data[pointer as usize]
+		}
+
+		fn stop(&mut self) {
+			self.stopped = true;
+		}
+	}
+
+	fn setup() -> (Cpu, Rc<RefCell<Register>>) {
+		let mut cpu = Cpu::init();
+		cpu.system.flash_from_vec(vec![0x0000; 0x1000]);
+
+		let device = Rc::new(RefCell::new(Register::default()));
+		cpu.peripherals.twi.bus.attach(0x50, device.clone());
+
+		cpu.write_data(TWBR, 2);
+		(cpu, device)
+	}
+
+	fn wait(cpu: &mut Cpu) -> u8 {
+		while cpu.read_data(TWCR) & TWINT == 0 {
+			cpu.step();
+		}
+		cpu.read_data(TWSR) & 0xF8
+	}
+
+	fn command(cpu: &mut Cpu, control: u8) -> u8 {
+		cpu.write_data(TWCR, TWINT | TWEN | control);
+		wait(cpu)
+	}
+
+	#[test]
+	fn bit_rate() {
+		let (mut cpu, _) = setup();
+		assert_eq!(cpu.peripherals.twi.bit_period(&mut cpu.sram), 20);
+
+		cpu.write_data(TWBR, 72);
+		cpu.write_data(TWSR, 0x01);
+		assert_eq!(cpu.peripherals.twi.bit_period(&mut cpu.sram), 592);
+		assert_eq!(cpu.read_data(TWSR), status::NO_INFO | 0x01);
+	}
+
+	#[test]
+	fn master_transmit() {
+		let (mut cpu, device) = setup();
+
+		assert_eq!(command(&mut cpu, TWSTA), status::START);
+		assert_eq!(cpu.cycles, 20);
+
+		cpu.write_data(TWDR, 0x50 << 1);
+		assert_eq!(command(&mut cpu, 0), status::MT_SLA_ACK);
+
+		cpu.write_data(TWDR, 0x04);
+		assert_eq!(command(&mut cpu, 0), status::MT_DATA_ACK);
+		cpu.write_data(TWDR, 0xAB);
+		assert_eq!(command(&mut cpu, 0), status::MT_DATA_ACK);
+
+		cpu.write_data(TWCR, TWINT | TWEN | TWSTO);
+		assert_eq!(cpu.read_data(TWCR) & TWSTO, 0);
+		assert_eq!(cpu.read_data(TWSR), status::NO_INFO);
+
+		assert_eq!(device.borrow().data[4], 0xAB);
+		assert!(device.borrow().stopped);
+	}
+
+	#[test]
+	fn master_receive() {
+		let (mut cpu, device) = setup();
+		device.borrow_mut().data[0] = 0x11;
+		device.borrow_mut().data[1] = 0x22;
+
+		assert_eq!(command(&mut cpu, TWSTA), status::START);
+		cpu.write_data(TWDR, 0x50 << 1);
+		assert_eq!(command(&mut cpu, 0), status::MT_SLA_ACK);
+		cpu.write_data(TWDR, 0x00);
+		assert_eq!(command(&mut cpu, 0), status::MT_DATA_ACK);
+
+		assert_eq!(command(&mut cpu, TWSTA), status::REPEATED_START);
+		cpu.write_data(TWDR, (0x50 << 1) | 1);
+		assert_eq!(command(&mut cpu, 0), status::MR_SLA_ACK);
+
+		assert_eq!(command(&mut cpu, TWEA), status::MR_DATA_ACK);
+		assert_eq!(cpu.read_data(TWDR), 0x11);
+		assert_eq!(command(&mut cpu, 0), status::MR_DATA_NACK);
+		assert_eq!(cpu.read_data(TWDR), 0x22);
+	}
+
+	#[test]
+	fn missing_device_nacks() {
+		let (mut cpu, _) = setup();
+
+		assert_eq!(command(&mut cpu, TWSTA), status::START);
+		cpu.write_data(TWDR, 0x20 << 1);
+		assert_eq!(command(&mut cpu, 0), status::MT_SLA_NACK);
+	}
+
+	#[test]
+	fn write_collision() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(TWCR, TWEN);
+		cpu.write_data(TWDR, 0x12);
+		assert_eq!(cpu.read_data(TWCR) & 0x08, 0x08);
+	}
+
+	#[test]
+	fn slave_receive() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(TWAR, 0x30 << 1);
+		cpu.write_data(TWAMR, 0x01 << 1);
+		cpu.write_data(TWCR, TWEN | TWEA);
+
+		let twi = &mut cpu.peripherals.twi;
+		assert_eq!(twi.host_start(&mut cpu.sram, 0x40, false), Some(false));
+		assert_eq!(twi.host_start(&mut cpu.sram, 0x31, false), Some(true));
+		assert_eq!(twi.status(&mut cpu.sram), status::SR_SLA_ACK);
+
+		assert_eq!(twi.host_write(&mut cpu.sram, 0x99), None);
+		cpu.write_data(TWCR, TWINT | TWEN | TWEA);
+
+		let twi = &mut cpu.peripherals.twi;
+		assert_eq!(twi.host_write(&mut cpu.sram, 0x99), Some(true));
+		assert_eq!(twi.status(&mut cpu.sram), status::SR_DATA_ACK);
+		assert_eq!(cpu.read_data(TWDR), 0x99);
+
+		cpu.write_data(TWCR, TWINT | TWEN | TWEA);
+		cpu.peripherals.twi.host_stop(&mut cpu.sram);
+		assert_eq!(cpu.read_data(TWSR), status::SR_STOP);
+	}
+
+	#[test]
+	fn slave_transmit() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(TWAR, 0x30 << 1);
+		cpu.write_data(TWCR, TWEN | TWEA);
+
+		let twi = &mut cpu.peripherals.twi;
+		assert_eq!(twi.host_start(&mut cpu.sram, 0x30, true), Some(true));
+		assert_eq!(cpu.read_data(TWSR), status::ST_SLA_ACK);
+
+		cpu.write_data(TWDR, 0x5A);
+		cpu.write_data(TWCR, TWINT | TWEN);
+
+		let twi = &mut cpu.peripherals.twi;
+		assert_eq!(twi.host_read(&mut cpu.sram, true), Some(0x5A));
+		assert_eq!(twi.status(&mut cpu.sram), status::ST_LAST_DATA);
+	}
+
+	#[test]
+	fn general_call() {
+		let (mut cpu, _) = setup();
+		cpu.write_data(TWAR, (0x30 << 1) | 1);
+		cpu.write_data(TWCR, TWEN | TWEA);
+
+		let twi = &mut cpu.peripherals.twi;
+		assert_eq!(twi.host_start(&mut cpu.sram, 0x00, false), Some(true));
+		assert_eq!(cpu.read_data(TWSR), status::SR_GCALL_ACK);
+	}
+
+	#[test]
+	fn arbitration_lost() {
+		let (mut cpu, _) = setup();
+
+		assert_eq!(command(&mut cpu, TWSTA), status::START);
+		cpu.write_data(TWDR, 0x50 << 1);
+		cpu.write_data(TWCR, TWINT | TWEN);
+
+		cpu.peripherals.twi.host_arbitrate(&mut cpu.sram);
+		assert_eq!(cpu.read_data(TWSR), status::ARBITRATION_LOST);
+	}
+}
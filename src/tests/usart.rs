@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod transmitter {
+	use crate::cpu::Cpu;
+	use crate::memory::Memory;
+	use crate::peripherals::power::PRR;
+	use crate::peripherals::usart::{SerialDevice, UBRR0L, UCSR0A, UCSR0B, UCSR0C, UDR0};
+	use crate::peripherals::Interrupt;
+	use crate::tests::{self, run, LOOP};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	const RXC: u8 = 0x80;
+	const TXC: u8 = 0x40;
+	const UDRE: u8 = 0x20;
+	const DOR: u8 = 0x08;
+	const RXEN: u8 = 0x10;
+	const TXEN: u8 = 0x08;
+	const TXCIE: u8 = 0x40;
+
+	#[derive(Default)]
+	struct Capture(Vec<u8>);
+
+	impl SerialDevice for Capture {
+		fn receive(&mut self, data: u8) {
+			self.0.push(data);
+		}
+	}
+
+	fn setup() -> (Cpu, Rc<RefCell<Capture>>) {
+		let mut cpu = tests::setup(&[LOOP; 0x10]);
+		let capture = Rc::new(RefCell::new(Capture::default()));
+		cpu.peripherals.usart.attach(capture.clone());
+		// 16 cycles per bit, 8N1
+		cpu.write_data(UBRR0L, 0);
+		cpu.write_data(UCSR0B, TXEN);
+		(cpu, capture)
+	}
+
+	#[test]
+	fn frame_timing() {
+		let (mut cpu, capture) = setup();
+		cpu.write_data(UDR0, b'A');
+		assert!(cpu.peripherals.usart.is_transmitting());
+
+		run(&mut cpu, 158);
+		assert!(capture.borrow().0.is_empty());
+		run(&mut cpu, 2);
+		assert_eq!(capture.borrow().0, b"A");
+		assert_eq!(cpu.read_data(UCSR0A) & (TXC | UDRE), TXC | UDRE);
+
+		// two stop bits, even parity
+		cpu.write_data(UCSR0A, TXC);
+		cpu.write_data(UCSR0C, 0x2E);
+		cpu.write_data(UDR0, b'B');
+		run(&mut cpu, 12 * 16);
+		assert_eq!(capture.borrow().0, b"AB");
+	}
+
+	#[test]
+	fn buffered_write() {
+		let (mut cpu, capture) = setup();
+		cpu.write_data(UDR0, b'h');
+		cpu.write_data(UDR0, b'i');
+		assert_eq!(cpu.read_data(UCSR0A) & UDRE, 0);
+
+		// the data register is full, this byte is lost
+		cpu.write_data(UDR0, b'!');
+
+		run(&mut cpu, 160);
+		assert_eq!(cpu.read_data(UCSR0A) & (TXC | UDRE), UDRE);
+		run(&mut cpu, 160);
+		assert_eq!(capture.borrow().0, b"hi");
+		assert_eq!(cpu.read_data(UCSR0A) & TXC, TXC);
+	}
+
+	#[test]
+	fn disabled_transmitter() {
+		let (mut cpu, capture) = setup();
+		cpu.write_data(UCSR0B, 0);
+		cpu.write_data(UDR0, b'A');
+		run(&mut cpu, 200);
+		assert!(capture.borrow().0.is_empty());
+
+		// shut down through PRR
+		cpu.write_data(UCSR0B, TXEN);
+		cpu.write_data(UDR0, b'B');
+		cpu.write_data(PRR, 0x02);
+		run(&mut cpu, 200);
+		assert!(capture.borrow().0.is_empty());
+		cpu.write_data(PRR, 0x00);
+		run(&mut cpu, 200);
+		assert_eq!(capture.borrow().0, b"B");
+	}
+
+	#[test]
+	fn transmit_complete_interrupt() {
+		let (mut cpu, _) = setup();
+		cpu.status.I = true;
+		cpu.write_data(UCSR0B, TXEN | TXCIE);
+		cpu.write_data(UDR0, b'A');
+		run(&mut cpu, 160);
+		assert_eq!(
+			cpu.peripherals.pending_interrupt(&mut cpu.sram),
+			Some(Interrupt::UsartTx)
+		);
+
+		cpu.step();
+		assert_eq!(cpu.pc, Interrupt::UsartTx.vector_address());
+		assert_eq!(cpu.sram.read(UCSR0A) as u8 & TXC, 0);
+	}
+
+	#[test]
+	fn receiver() {
+		let (mut cpu, _) = setup();
+		assert!(!cpu.peripherals.usart.receive(&mut cpu.sram, b'x'));
+
+		cpu.write_data(UCSR0B, RXEN);
+		assert!(cpu.peripherals.usart.receive(&mut cpu.sram, b'x'));
+		assert!(cpu.peripherals.usart.receive(&mut cpu.sram, b'y'));
+		assert_eq!(cpu.read_data(UCSR0A) & (RXC | DOR), RXC | DOR);
+
+		assert_eq!(cpu.read_data(UDR0), b'x');
+		assert_eq!(cpu.read_data(UCSR0A) & (RXC | DOR), 0);
+	}
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod watchdog_timer {
+	use crate::peripherals::sleep::SMCR;
+	use crate::peripherals::watchdog::{MCUSR, WDTCSR};
+	use crate::tests::{run, setup, setup_idling, LOOP};
+
+	const WDIF: u8 = 0x80;
+	const WDIE: u8 = 0x40;
+	const WDCE: u8 = 0x10;
+	const WDE: u8 = 0x08;
+
+	const WDRF: u8 = 0x08;
+	const PORF: u8 = 0x01;
+
+	const SLEEP: u16 = 0x9588;
+	const WDR: u16 = 0x95A8;
+
+	// 16 ms at the factory default 1 MHz system clock
+	const TIMEOUT_CYCLES: usize = 16_000;
+
+	#[test]
+	fn interrupt_mode() {
+		let mut cpu = setup_idling(&[]);
+		cpu.status.I = true;
+		cpu.write_data(WDTCSR, WDIE);
+
+		cpu.step();
+		assert_eq!(cpu.cycles, TIMEOUT_CYCLES);
+		assert_eq!(cpu.read_data(WDTCSR), WDIF | WDIE);
+
+		cpu.step();
+		assert_eq!(cpu.pc, 0x0C);
+		assert_eq!(cpu.read_data(WDTCSR), WDIE);
+	}
+
+	#[test]
+	fn system_reset() {
+		let mut cpu = setup_idling(&[]);
+		assert_eq!(cpu.read_data(MCUSR), PORF);
+		cpu.write_data(WDTCSR, WDE);
+		cpu.pc = 0x80;
+
+		cpu.step();
+		assert_eq!(cpu.pc, 0x00);
+		assert_eq!(cpu.cycles, TIMEOUT_CYCLES);
+		assert_eq!(cpu.read_data(MCUSR), WDRF | PORF);
+
+		// WDRF keeps the watchdog enabled
+		cpu.write_data(WDTCSR, WDCE | WDE);
+		cpu.write_data(WDTCSR, 0x00);
+		assert_eq!(cpu.read_data(WDTCSR), WDE);
+
+		cpu.write_data(MCUSR, 0x00);
+		cpu.write_data(WDTCSR, WDCE | WDE);
+		cpu.write_data(WDTCSR, 0x00);
+		assert_eq!(cpu.read_data(WDTCSR), 0x00);
+	}
+
+	#[test]
+	fn interrupt_then_reset() {
+		let mut cpu = setup_idling(&[]);
+		cpu.status.I = true;
+		cpu.write_data(WDTCSR, WDIE | WDE);
+
+		// taking the interrupt switches to system reset mode
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.pc, 0x0C);
+		assert_eq!(cpu.read_data(WDTCSR), WDE);
+
+		cpu.step();
+		assert_eq!(cpu.read_data(MCUSR) & WDRF, WDRF);
+	}
+
+	#[test]
+	fn wdr_restarts() {
+		let mut program = vec![0x0000; 10_000];
+		program.push(WDR);
+		program.push(LOOP);
+		let mut cpu = setup(&program);
+		cpu.write_data(WDTCSR, WDE);
+
+		run(&mut cpu, TIMEOUT_CYCLES);
+		assert_eq!(cpu.read_data(MCUSR) & WDRF, 0);
+
+		run(&mut cpu, 10_001);
+		assert_eq!(cpu.read_data(MCUSR) & WDRF, WDRF);
+	}
+
+	#[test]
+	fn timed_sequence() {
+		let mut cpu = setup(&[]);
+		cpu.write_data(WDTCSR, WDE);
+
+		// WDE can not be cleared and the prescaler not changed without WDCE
+		cpu.write_data(WDTCSR, 0x07);
+		assert_eq!(cpu.read_data(WDTCSR), WDE);
+
+		cpu.write_data(WDTCSR, WDCE | WDE);
+		assert_eq!(cpu.read_data(WDTCSR), WDCE | WDE);
+		cpu.write_data(WDTCSR, WDE | 0x07);
+		assert_eq!(cpu.read_data(WDTCSR), WDE | 0x07);
+
+		// the change enable window closes after four cycles
+		cpu.write_data(WDTCSR, WDCE | WDE);
+		run(&mut cpu, 4);
+		assert_eq!(cpu.read_data(WDTCSR), WDE | 0x07);
+		cpu.write_data(WDTCSR, 0x00);
+		assert_eq!(cpu.read_data(WDTCSR), WDE | 0x07);
+	}
+
+	#[test]
+	fn wakes_from_power_down() {
+		let mut cpu = setup_idling(&[SLEEP]);
+		cpu.status.I = true;
+		cpu.write_data(SMCR, 0x05);
+		cpu.write_data(WDTCSR, WDIE);
+
+		cpu.step();
+		assert!(cpu.is_sleeping());
+		cpu.step();
+		cpu.step();
+		assert!(!cpu.is_sleeping());
+		assert_eq!(cpu.pc, 0x0C);
+	}
+}
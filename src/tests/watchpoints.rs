@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod watchpoint {
+	use crate::cpu::Cpu;
+	use crate::debugger::{WatchKind, WatchSpace, Watchpoint};
+	use crate::memory::{AccessKind, Memory};
+	use crate::peripherals::eeprom::{EEARL, EECR, EEDR};
+	use crate::runner::{self, Limits, StopReason};
+	use crate::tests::setup;
+
+	const EEMPE: u8 = 0x04;
+	const EEPE: u8 = 0x02;
+
+	// ldi r24, 0x55; sts 0x0100, r24; lds r25, 0x0100; rjmp .-2
+	const STORE_LOAD: [u16; 6] = [0xE585, 0x9380, 0x0100, 0x9190, 0x0100, 0xCFFF];
+
+	fn resume(cpu: &mut Cpu) -> StopReason {
+		let limits = Limits {
+			cycles: Some(cpu.cycles + 10_000),
+			seconds: None,
+		};
+		runner::run(cpu, &limits)
+	}
+
+	#[test]
+	fn data_space() {
+		let mut cpu = setup(&STORE_LOAD);
+		cpu.watchpoints.insert(Watchpoint::new(
+			WatchSpace::Data,
+			0x00FF,
+			2,
+			WatchKind::Write,
+		));
+		cpu.watchpoints.insert(Watchpoint::new(
+			WatchSpace::Data,
+			0x0100,
+			1,
+			WatchKind::Read,
+		));
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.pc, 0x0001);
+		assert_eq!(hit.instruction, "sts 0x0100, r24");
+		assert_eq!(hit.kind, WatchKind::Write);
+		assert_eq!(hit.access.address, 0x0100);
+		assert_eq!((hit.access.previous, hit.access.value), (0x00, 0x55));
+		assert_eq!(
+			hit.to_string(),
+			"write watchpoint on data 0x0100 at PC 0x0001 (sts 0x0100, r24): 0x00 -> 0x55"
+		);
+		// stops after the instruction that made the access
+		assert_eq!(cpu.pc, 0x0003);
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.pc, 0x0003);
+		assert_eq!(hit.access.kind, AccessKind::Read);
+		assert_eq!(cpu.sram.registers[25], 0x55);
+
+		assert_eq!(resume(&mut cpu), StopReason::CycleLimit);
+	}
+
+	#[test]
+	fn value_change() {
+		// ldi r16, 0x05; out PORTB, r16; out PORTB, r16; ldi r16, 0x06; out PORTB, r16
+		let mut cpu = setup(&[0xE005, 0xB905, 0xB905, 0xE006, 0xB905, 0xCFFF]);
+		cpu.watchpoints
+			.insert(Watchpoint::register("portb", WatchKind::Change).unwrap());
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.pc, 0x0001);
+		assert_eq!(hit.access.address, 0x0025);
+
+		// writing the same value again is not a change
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.pc, 0x0004);
+		assert_eq!((hit.access.previous, hit.access.value), (0x05, 0x06));
+
+		assert!(Watchpoint::register("Reserved", WatchKind::Write).is_none());
+		assert!(Watchpoint::register("PORTZ", WatchKind::Write).is_none());
+	}
+
+	#[test]
+	fn registers() {
+		// ldi r24, 0x01; mov r25, r24; inc r24; rjmp .-2
+		let mut cpu = setup(&[0xE081, 0x2F98, 0x9583, 0xCFFF]);
+		cpu.watchpoints
+			.insert(Watchpoint::new(WatchSpace::Data, 24, 1, WatchKind::Write));
+		cpu.watchpoints
+			.insert(Watchpoint::new(WatchSpace::Data, 25, 1, WatchKind::Change));
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!((hit.pc, hit.access.address), (0x0000, 24));
+		assert!(hit.instruction.starts_with("ldi r24"));
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!((hit.pc, hit.access.address), (0x0001, 25));
+		assert_eq!((hit.access.previous, hit.access.value), (0x00, 0x01));
+
+		// the result of an ALU instruction
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!((hit.pc, hit.access.address), (0x0002, 24));
+		assert_eq!(hit.access.value, 0x02);
+		assert_eq!(hit.instruction, "inc r24");
+	}
+
+	#[test]
+	fn enable_and_remove() {
+		let mut cpu = setup(&STORE_LOAD);
+		let watchpoint = Watchpoint::new(WatchSpace::Data, 0x0100, 1, WatchKind::Access);
+		cpu.watchpoints.insert(watchpoint.clone());
+
+		cpu.watchpoints
+			.iter_mut()
+			.for_each(|point| point.enabled = false);
+		assert_eq!(resume(&mut cpu), StopReason::CycleLimit);
+
+		assert!(!cpu.watchpoints.remove(&watchpoint));
+		cpu.watchpoints
+			.iter_mut()
+			.for_each(|point| point.enabled = true);
+		assert!(cpu.watchpoints.remove(&watchpoint));
+		assert!(cpu.watchpoints.is_empty());
+	}
+
+	#[test]
+	fn eeprom() {
+		// ldi r16, 0x10; out EEARL, r16; ldi r16, EERE; out EECR, r16; rjmp .-2
+		let mut cpu = setup(&[0xE100, 0xBD01, 0xE001, 0xBB0F, 0xCFFF]);
+		cpu.watchpoints.insert(Watchpoint::new(
+			WatchSpace::Eeprom,
+			0x0010,
+			1,
+			WatchKind::Access,
+		));
+		cpu.system.eeprom_memory.write(0x0010, 0x3C);
+
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.space, WatchSpace::Eeprom);
+		assert_eq!(hit.pc, 0x0003);
+		assert_eq!(
+			(hit.access.kind, hit.access.value),
+			(AccessKind::Read, 0x3C)
+		);
+
+		// writes are reported when the EEPROM cell is programmed
+		cpu.write_data(EEARL, 0x10);
+		cpu.write_data(EEDR, 0xA5);
+		cpu.write_data(EECR, EEMPE);
+		cpu.write_data(EECR, EEPE);
+		let StopReason::Watchpoint(hit) = resume(&mut cpu) else {
+			panic!("no watchpoint hit");
+		};
+		assert_eq!(hit.access.kind, AccessKind::Write);
+		assert_eq!((hit.access.previous, hit.access.value), (0x3C, 0xA5));
+		assert_eq!(cpu.system.eeprom_memory.read(0x0010), 0xA5);
+	}
+}
@@ -0,0 +1,450 @@
+use crate::cpu::Cpu;
+use crate::disassembler::{is_two_words, Disassembler};
+use crate::memory::{AccessKind, Memory};
+use crate::symbols::SymbolTable;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Start of every trace file
+pub const MAGIC: &[u8; 8] = b"M328TRCE";
+
+/// Layout version, bumped whenever the encoding changes
+pub const VERSION: u16 = 1;
+
+/// Encoded bytes collected before they are handed to the output
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Flags byte at the start of each record, the low two bits hold the event
+const EVENT_MASK: u8 = 0x03;
+const HAS_OPERAND: u8 = 0x04;
+const HAS_SREG: u8 = 0x08;
+const HAS_SP: u8 = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	/// Does not start with [`MAGIC`]
+	NotTrace,
+	/// Written by a newer emulator
+	UnsupportedVersion(u16),
+	/// Ends in the middle of a record
+	Truncated,
+	/// A value outside its range, holds what was being read
+	Invalid(&'static str),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::NotTrace => write!(f, "not an instruction trace"),
+			Error::UnsupportedVersion(version) => {
+				write!(f, "trace version {} is not supported", version)
+			}
+			Error::Truncated => write!(f, "trace is truncated"),
+			Error::Invalid(what) => write!(f, "invalid {} in trace", what),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What a step did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+	/// Executed the instruction at the program counter
+	Instruction,
+	/// Jumped to an interrupt vector instead
+	Interrupt,
+	/// Slept, possibly waking up for an interrupt
+	Sleep,
+}
+
+impl Event {
+	pub const ALL: [Event; 3] = [Event::Instruction, Event::Interrupt, Event::Sleep];
+}
+
+/// One step of a trace with what it changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+	pub event: Event,
+	/// Cycle count before the step
+	pub cycles: usize,
+	/// Word address the step started at
+	pub pc: u16,
+	pub opcode: u16,
+	/// Second word of `jmp`, `call`, `lds` and `sts`
+	pub operand: Option<u16>,
+	/// Registers the step changed, with their new contents
+	pub registers: Vec<(u8, u8)>,
+	/// SREG if the step changed it
+	pub sreg: Option<u8>,
+	/// Stack pointer if the step changed it
+	pub sp: Option<u16>,
+	/// Data space writes past the registers, in the order they were made
+	pub writes: Vec<(u16, u8)>,
+}
+
+/// Records the steps of a [`Cpu`] that start in its address ranges, either streaming
+/// them to the output or keeping only the last few for a post-mortem
+pub struct Tracer {
+	/// Word address ranges to record, everything when empty
+	pub filter: Vec<Range<u16>>,
+	output: Box<dyn Write>,
+	/// Most records kept, `None` to stream every record
+	capacity: Option<usize>,
+	records: VecDeque<Record>,
+	encoder: Encoder,
+	/// First write to the output that failed, reported by [`Tracer::finish`]
+	error: Option<io::Error>,
+	/// Registers, SREG and SP before the step being recorded
+	before: (Vec<u8>, u8, u16),
+}
+
+impl Tracer {
+	/// Streams every record to `output`
+	pub fn new(output: impl Write + 'static) -> Self {
+		Self {
+			filter: Vec::new(),
+			output: Box::new(output),
+			capacity: None,
+			records: VecDeque::new(),
+			encoder: Encoder::default(),
+			error: None,
+			before: (Vec::new(), 0, 0),
+		}
+	}
+
+	/// Keeps the last `capacity` records, written to `output` by [`Tracer::finish`]
+	pub fn last(capacity: usize, output: impl Write + 'static) -> Self {
+		let mut tracer = Self::new(output);
+		tracer.capacity = Some(capacity.max(1));
+		tracer
+	}
+
+	/// Records kept for the post-mortem, empty when streaming
+	pub fn records(&self) -> impl Iterator<Item = &Record> {
+		self.records.iter()
+	}
+
+	/// Writes out what is still buffered, and the kept records when keeping the last few
+	pub fn finish(&mut self) -> io::Result<()> {
+		for record in std::mem::take(&mut self.records) {
+			self.encoder.record(&record);
+		}
+		self.flush();
+		if let Some(error) = self.error.take() {
+			return Err(error);
+		}
+		self.output.flush()
+	}
+
+	fn flush(&mut self) {
+		if self.error.is_none() {
+			if let Err(error) = self.output.write_all(&self.encoder.bytes) {
+				self.error = Some(error);
+			}
+		}
+		self.encoder.bytes.clear();
+	}
+
+	/// Remembers what a step can change, called by [`Cpu::step`] before the step
+	pub(crate) fn begin(&mut self, registers: &[u8], sreg: u8, sp: u16) {
+		self.before.0.clear();
+		self.before.0.extend_from_slice(registers);
+		(self.before.1, self.before.2) = (sreg, sp);
+	}
+
+	/// Records the step that started at `pc` and `cycles`, called by [`Cpu::step`]
+	pub(crate) fn end(&mut self, cpu: &mut Cpu, event: Event, cycles: usize, pc: u16) {
+		if !self.filter.is_empty() && !self.filter.iter().any(|range| range.contains(&pc)) {
+			return;
+		}
+
+		let memory = &mut cpu.system.program_memory;
+		let opcode = memory.read(pc);
+		let operand = (event == Event::Instruction && is_two_words(opcode))
+			.then(|| memory.read(pc.wrapping_add(1)));
+
+		let (registers, sreg, sp) = &self.before;
+		let record = Record {
+			event,
+			cycles,
+			pc,
+			opcode,
+			operand,
+			registers: (0..)
+				.zip(registers.iter().zip(&cpu.sram.registers))
+				.filter(|(_, (before, after))| before != after)
+				.map(|(index, (_, after))| (index, *after))
+				.collect(),
+			sreg: Some(cpu.status.byte()).filter(|value| value != sreg),
+			sp: Some(cpu.sp).filter(|value| value != sp),
+			writes: (cpu.accesses.iter())
+				// registers are covered above
+				.filter(|access| access.kind == AccessKind::Write && access.address >= 0x20)
+				.map(|access| (access.address, access.value))
+				.collect(),
+		};
+
+		match self.capacity {
+			Some(capacity) => {
+				if self.records.len() == capacity {
+					self.records.pop_front();
+				}
+				self.records.push_back(record);
+			}
+			None => {
+				self.encoder.record(&record);
+				if self.encoder.bytes.len() >= BUFFER_SIZE {
+					self.flush();
+				}
+			}
+		}
+	}
+}
+
+/// Writes out what was recorded when the tracer goes away, so that a panic unwinding
+/// the emulator still leaves the post-mortem behind
+impl Drop for Tracer {
+	fn drop(&mut self) {
+		let _ = self.finish();
+	}
+}
+
+/// Binary encoding of records. Cycle counts are stored as the difference to the
+/// previous record and counts as LEB128, everything else little endian.
+struct Encoder {
+	bytes: Vec<u8>,
+	cycles: usize,
+}
+
+impl Default for Encoder {
+	fn default() -> Self {
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend_from_slice(&VERSION.to_le_bytes());
+		Self { bytes, cycles: 0 }
+	}
+}
+
+impl Encoder {
+	fn record(&mut self, record: &Record) {
+		let mut flags = Event::ALL
+			.iter()
+			.position(|event| *event == record.event)
+			.unwrap() as u8;
+		if record.operand.is_some() {
+			flags |= HAS_OPERAND;
+		}
+		if record.sreg.is_some() {
+			flags |= HAS_SREG;
+		}
+		if record.sp.is_some() {
+			flags |= HAS_SP;
+		}
+		self.bytes.push(flags);
+		self.leb128(record.cycles.wrapping_sub(self.cycles) as u64);
+		self.cycles = record.cycles;
+
+		self.u16(record.pc);
+		self.u16(record.opcode);
+		if let Some(operand) = record.operand {
+			self.u16(operand);
+		}
+		if let Some(sreg) = record.sreg {
+			self.bytes.push(sreg);
+		}
+		if let Some(sp) = record.sp {
+			self.u16(sp);
+		}
+		self.bytes.push(record.registers.len() as u8);
+		for (register, value) in &record.registers {
+			self.bytes.extend_from_slice(&[*register, *value]);
+		}
+		self.leb128(record.writes.len() as u64);
+		for (address, value) in &record.writes {
+			self.u16(*address);
+			self.bytes.push(*value);
+		}
+	}
+
+	fn u16(&mut self, value: u16) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn leb128(&mut self, mut value: u64) {
+		loop {
+			let byte = (value & 0x7F) as u8;
+			value >>= 7;
+			if value == 0 {
+				self.bytes.push(byte);
+				return;
+			}
+			self.bytes.push(byte | 0x80);
+		}
+	}
+}
+
+/// Decodes the records of a trace one at a time
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+	bytes: &'a [u8],
+	position: usize,
+	cycles: usize,
+}
+
+impl<'a> Records<'a> {
+	/// Checks the header of a trace, the records follow from the iterator
+	pub fn new(bytes: &'a [u8]) -> Result<Self> {
+		if !bytes.starts_with(MAGIC) {
+			return Err(Error::NotTrace);
+		}
+		let mut records = Self {
+			bytes,
+			position: MAGIC.len(),
+			cycles: 0,
+		};
+		let version = records.u16()?;
+		if version == 0 || version > VERSION {
+			return Err(Error::UnsupportedVersion(version));
+		}
+		Ok(records)
+	}
+
+	fn u8(&mut self) -> Result<u8> {
+		let byte = *self.bytes.get(self.position).ok_or(Error::Truncated)?;
+		self.position += 1;
+		Ok(byte)
+	}
+
+	fn u16(&mut self) -> Result<u16> {
+		Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+	}
+
+	fn leb128(&mut self) -> Result<u64> {
+		let mut value = 0u64;
+		for shift in (0..64).step_by(7) {
+			let byte = self.u8()?;
+			value |= ((byte & 0x7F) as u64) << shift;
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+		Err(Error::Invalid("number"))
+	}
+
+	fn record(&mut self) -> Result<Record> {
+		let flags = self.u8()?;
+		let event = *Event::ALL
+			.get((flags & EVENT_MASK) as usize)
+			.ok_or(Error::Invalid("event"))?;
+		let delta = usize::try_from(self.leb128()?).map_err(|_| Error::Invalid("cycle count"))?;
+		self.cycles = self.cycles.wrapping_add(delta);
+
+		let (pc, opcode) = (self.u16()?, self.u16()?);
+		let operand = match flags & HAS_OPERAND {
+			0 => None,
+			_ => Some(self.u16()?),
+		};
+		let sreg = match flags & HAS_SREG {
+			0 => None,
+			_ => Some(self.u8()?),
+		};
+		let sp = match flags & HAS_SP {
+			0 => None,
+			_ => Some(self.u16()?),
+		};
+
+		let count = self.u8()?;
+		if count > 32 {
+			return Err(Error::Invalid("register count"));
+		}
+		let mut registers = Vec::with_capacity(count as usize);
+		for _ in 0..count {
+			let register = self.u8()?;
+			if register >= 32 {
+				return Err(Error::Invalid("register"));
+			}
+			registers.push((register, self.u8()?));
+		}
+
+		let count = self.leb128()?;
+		let mut writes = Vec::new();
+		for _ in 0..count {
+			writes.push((self.u16()?, self.u8()?));
+		}
+
+		Ok(Record {
+			event,
+			cycles: self.cycles,
+			pc,
+			opcode,
+			operand,
+			registers,
+			sreg,
+			sp,
+			writes,
+		})
+	}
+}
+
+impl Iterator for Records<'_> {
+	type Item = Result<Record>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.position == self.bytes.len() {
+			return None;
+		}
+		let record = self.record();
+		if record.is_err() {
+			// nothing after a broken record can be trusted
+			self.position = self.bytes.len();
+		}
+		Some(record)
+	}
+}
+
+/// Writes a trace as text, one line per record with its cycle count, address,
+/// disassembly and changes. `symbols` name the functions and jump targets.
+pub fn export(bytes: &[u8], symbols: &SymbolTable, output: &mut impl Write) -> io::Result<()> {
+	let invalid = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+	let mut disassembler = Disassembler::default();
+
+	for record in Records::new(bytes).map_err(invalid)? {
+		let record = record.map_err(invalid)?;
+		let (instruction, operands) = match record.event {
+			Event::Instruction => {
+				let instruction =
+					disassembler.decode(record.pc, record.opcode, record.operand, symbols);
+				(instruction.instruction, instruction.operands)
+			}
+			Event::Interrupt => ("(interrupt)".to_string(), String::new()),
+			Event::Sleep => ("(asleep)".to_string(), String::new()),
+		};
+		let operand = record
+			.operand
+			.map_or(String::new(), |word| format!("{:04X}", word));
+		let location = symbols.code_location(record.pc).unwrap_or_default();
+
+		let mut line = format!(
+			"{:>10}  {:04X}  {:04X} {:4}  {:<7} {:<24} {:<20}",
+			record.cycles, record.pc, record.opcode, operand, instruction, operands, location
+		);
+		for (register, value) in &record.registers {
+			line.push_str(&format!(" r{}={:02X}", register, value));
+		}
+		if let Some(sreg) = record.sreg {
+			line.push_str(&format!(" SREG={:02X}", sreg));
+		}
+		if let Some(sp) = record.sp {
+			line.push_str(&format!(" SP={:04X}", sp));
+		}
+		for (address, value) in &record.writes {
+			line.push_str(&format!(" [{:04X}]={:02X}", address, value));
+		}
+		writeln!(output, "{}", line.trim_end())?;
+	}
+	Ok(())
+}
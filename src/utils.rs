@@ -36,7 +36,7 @@ pub fn low_nibble(value: u8) -> u8 {
 }
 
 pub fn high_nibble(value: u8) -> u8 {
-	(value >> 4 & 0xF) as u8
+	value >> 4 & 0xF
 }
 
 pub fn bit(value: u8, bit: u8) -> u8 {